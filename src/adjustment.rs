@@ -0,0 +1,239 @@
+use crate::audit;
+use crate::database;
+use crate::domain;
+
+/// One row of an adjustment or promo code, before its discount is checked.
+pub struct AdjustmentRow {
+    pub id: i32,
+    pub item_id: Option<i32>,
+    pub item_name: Option<String>,
+    pub kind: String,
+    pub value: Option<i32>,
+    pub buy: Option<i32>,
+    pub get: Option<i32>,
+}
+
+impl AdjustmentRow {
+    pub fn target(&self) -> domain::Target {
+        match (self.item_id, &self.item_name) {
+            (Some(item_id), _) => domain::Target::Item(item_id),
+            (None, Some(name)) => domain::Target::Named(name.clone()),
+            (None, None) => domain::Target::Bill,
+        }
+    }
+
+    pub fn discount(&self) -> Result<domain::Discount, String> {
+        domain::Discount::from_parts(&self.kind, self.value, self.buy, self.get)
+            .map_err(|e| format!("Discount {} is not valid: {}", self.id, e))
+    }
+}
+
+/// The columns a target is kept in, `(item_id, item_name)`.
+pub fn target_parts(target: &domain::Target) -> (Option<i32>, Option<&str>) {
+    match target {
+        domain::Target::Bill => (None, None),
+        domain::Target::Item(item_id) => (Some(*item_id), None),
+        domain::Target::Named(name) => (None, Some(name.as_str())),
+    }
+}
+
+/// The adjustments on a table's bill, in the order they were added.
+pub fn find(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: i32,
+) -> Result<Vec<domain::Adjustment>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select id, item_id, item_name, kind, value, buy, get, reason, code, added_by, added_at
+            from adjustment where restaurant_id = $1 and table_id = $2 order by id;",
+    );
+    let results = statement.query_map([&restaurant_id, &table_id], |row| {
+        Ok((
+            AdjustmentRow {
+                id: row.get(0)?,
+                item_id: row.get(1)?,
+                item_name: row.get(2)?,
+                kind: row.get(3)?,
+                value: row.get(4)?,
+                buy: row.get(5)?,
+                get: row.get(6)?,
+            },
+            row.get::<_, String>(7)?,
+            row.get::<_, Option<String>>(8)?,
+            row.get::<_, i32>(9)?,
+            row.get::<_, i64>(10)?,
+        ))
+    });
+    let rows = match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| format!("Failed with error: {}", e))?,
+        Err(e) => return Err(format!("Failed with error: {}", e)),
+    };
+    let mut adjustments = Vec::new();
+    for (row, reason, code, added_by, added_at) in rows {
+        adjustments.push(domain::Adjustment {
+            id: row.id,
+            target: row.target(),
+            discount: row.discount()?,
+            reason,
+            code,
+            added_by,
+            added_at,
+        });
+    }
+    Ok(adjustments)
+}
+
+/// Adds an adjustment to a table's bill and returns its id, the id it is given is ignored.
+/// Adds an adjustment to a table's bill and records it in the audit log in one transaction.
+pub fn add(
+    connection: &mut rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: i32,
+    adjustment: &domain::Adjustment,
+    change: audit::Change,
+) -> Result<i32, String> {
+    let transaction = connection
+        .transaction()
+        .map_err(|e| format!("Failed to add adjustment: {}", e))?;
+    let id = insert(&transaction, restaurant_id, table_id, adjustment)
+        .map_err(|e| format!("Failed to add adjustment: {}", e))?;
+    audit::insert(&transaction, &change)?;
+    transaction
+        .commit()
+        .map_err(|e| format!("Failed to add adjustment: {}", e))?;
+    audit::logged(&change);
+    Ok(id)
+}
+
+/// Counts a use of a promo code, puts its adjustment on a table's bill and records it in the
+/// audit log in one transaction, so none of them are kept without the others.
+pub fn redeem(
+    connection: &mut rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: i32,
+    promo_code_id: i32,
+    adjustment: &domain::Adjustment,
+    change: audit::Change,
+) -> Result<domain::Redemption, String> {
+    let transaction = connection
+        .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+        .map_err(|e| format!("Failed to redeem promo code: {}", e))?;
+    // Dropping the transaction rolls back the use when the code is not redeemed.
+    if !use_promo_code(&transaction, promo_code_id, adjustment.added_at)? {
+        return Ok(domain::Redemption::UsedUp);
+    }
+    let id = match insert(&transaction, restaurant_id, table_id, adjustment) {
+        Ok(id) => id,
+        Err(rusqlite::Error::SqliteFailure(error, _))
+            if error.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            return Ok(domain::Redemption::AlreadyOnBill);
+        }
+        Err(e) => return Err(format!("Failed to add adjustment: {}", e)),
+    };
+    audit::insert(&transaction, &change)?;
+    transaction
+        .commit()
+        .map_err(|e| format!("Failed to redeem promo code: {}", e))?;
+    audit::logged(&change);
+    Ok(domain::Redemption::Redeemed(id))
+}
+
+fn insert(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: i32,
+    adjustment: &domain::Adjustment,
+) -> rusqlite::Result<i32> {
+    let (item_id, item_name) = target_parts(&adjustment.target);
+    let (kind, value, buy, get) = adjustment.discount.parts();
+    connection.execute(
+        "insert into adjustment (restaurant_id, table_id, item_id, item_name, kind, value,
+                    buy, get, reason, code, added_by, added_at)
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12);",
+        rusqlite::params![
+            restaurant_id,
+            table_id,
+            item_id,
+            item_name,
+            kind,
+            value,
+            buy,
+            get,
+            adjustment.reason,
+            adjustment.code,
+            adjustment.added_by,
+            adjustment.added_at
+        ],
+    )?;
+    Ok(connection.last_insert_rowid() as i32)
+}
+
+pub fn find_promo_code(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    code: &str,
+) -> Result<Option<domain::PromoCode>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select id, item_name, kind, value, buy, get, code, max_uses, uses, expires_at
+            from promo_code where restaurant_id = $1 and code = $2;",
+    );
+    let results = statement.query_map(rusqlite::params![restaurant_id, code], |row| {
+        Ok((
+            AdjustmentRow {
+                id: row.get(0)?,
+                item_id: None,
+                item_name: row.get(1)?,
+                kind: row.get(2)?,
+                value: row.get(3)?,
+                buy: row.get(4)?,
+                get: row.get(5)?,
+            },
+            row.get::<_, String>(6)?,
+            row.get::<_, Option<i32>>(7)?,
+            row.get::<_, i32>(8)?,
+            row.get::<_, Option<i64>>(9)?,
+        ))
+    });
+    let found = match results {
+        Ok(mut rows) => match rows.next() {
+            Some(Ok(found)) => found,
+            Some(Err(e)) => return Err(format!("Failed with error: {}", e)),
+            None => return Ok(None),
+        },
+        Err(e) => return Err(format!("Failed with error: {}", e)),
+    };
+    let (row, code, max_uses, uses, expires_at) = found;
+    Ok(Some(domain::PromoCode {
+        id: row.id,
+        code,
+        target: row.target(),
+        discount: row.discount()?,
+        max_uses,
+        uses,
+        expires_at,
+    }))
+}
+
+/// Counts a use of a promo code, returns false when it has expired or been used up by
+/// the time it is counted.
+fn use_promo_code(
+    connection: &rusqlite::Connection,
+    promo_code_id: i32,
+    now: i64,
+) -> Result<bool, String> {
+    let updated = connection.execute(
+        "update promo_code set uses = uses + 1
+            where id = $1 and (max_uses is null or uses < max_uses)
+            and (expires_at is null or expires_at > $2);",
+        rusqlite::params![promo_code_id, now],
+    );
+    match updated {
+        Ok(updated) => Ok(updated == 1),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+
+use rocket::fairing::AdHoc;
+
+use crate::database;
+use crate::domain;
+
+/// What is done with an order for an item someone at the table is allergic to, set with
+/// `allergy_conflicts`. Flagged items are ordered with a warning for the kitchen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    Flag,
+    Reject,
+}
+
+pub fn policy_fairing() -> AdHoc {
+    AdHoc::on_attach("Allergy Policy", |rocket| {
+        let name = rocket
+            .config()
+            .get_str("allergy_conflicts")
+            .unwrap_or("flag")
+            .to_string();
+        let policy = match name.as_str() {
+            "flag" => Policy::Flag,
+            "reject" => Policy::Reject,
+            _ => {
+                error!("Unknown allergy_conflicts {}, use flag or reject.", name);
+                return Err(rocket);
+            }
+        };
+        info!("Orders with allergy conflicts are {:?}.", policy);
+        Ok(rocket.manage(policy))
+    })
+}
+
+/// The allergies declared at each of a restaurant's tables, by table id.
+pub fn all(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<BTreeMap<i32, Vec<domain::Allergy>>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select table_id, seat, allergen from table_allergy
+            where restaurant_id = $1 order by table_id, seat, allergen;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok((
+            row.get::<_, i32>(0)?,
+            domain::Allergy {
+                seat: row.get(1)?,
+                allergen: row.get(2)?,
+            },
+        ))
+    });
+    let rows = match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<(i32, domain::Allergy)>>>()
+            .map_err(|e| format!("Failed with error: {}", e))?,
+        Err(e) => return Err(format!("Failed with error: {}", e)),
+    };
+    let mut tables: BTreeMap<i32, Vec<domain::Allergy>> = BTreeMap::new();
+    for (table_id, allergy) in rows {
+        tables.entry(table_id).or_default().push(allergy);
+    }
+    Ok(tables)
+}
+
+/// Replaces the allergies declared at a table.
+pub fn replace(
+    connection: &mut rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: i32,
+    allergies: &[domain::Allergy],
+) -> Result<(), String> {
+    let transaction = connection
+        .transaction()
+        .map_err(|e| format!("Failed to record allergies: {}", e))?;
+    transaction
+        .execute(
+            "delete from table_allergy where restaurant_id = $1 and table_id = $2;",
+            rusqlite::params![restaurant_id, table_id],
+        )
+        .map_err(|e| format!("Failed to record allergies: {}", e))?;
+    for allergy in allergies {
+        transaction
+            .execute(
+                "insert into table_allergy (restaurant_id, table_id, seat, allergen)
+                    values ($1, $2, $3, $4);",
+                rusqlite::params![restaurant_id, table_id, allergy.seat, allergy.allergen],
+            )
+            .map_err(|e| format!("Failed to record allergies: {}", e))?;
+    }
+    transaction
+        .commit()
+        .map_err(|e| format!("Failed to record allergies: {}", e))
+}
@@ -0,0 +1,50 @@
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Rocket, Route};
+
+use crate::domain;
+
+pub mod v1;
+pub mod v2;
+
+/// A set of routes served under its own base path with its own json types.
+pub struct Version {
+    pub base: &'static str,
+    pub routes: fn() -> Vec<Route>,
+}
+
+/// Every version still served, old versions stay here until no client uses them.
+pub const VERSIONS: &[Version] = &[
+    Version {
+        base: v1::BASE,
+        routes: v1::routes,
+    },
+    Version {
+        base: v2::BASE,
+        routes: v2::routes,
+    },
+];
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    VERSIONS.iter().fold(rocket, |rocket, version| {
+        rocket.mount(version.base, (version.routes)())
+    })
+}
+
+/// Every version answers a failed change with the same status codes.
+impl From<domain::Error> for status::Custom<String> {
+    fn from(error: domain::Error) -> status::Custom<String> {
+        match error {
+            domain::Error::Invalid(message) => status::Custom(Status::BadRequest, message),
+            domain::Error::NotFound(message) => status::Custom(Status::NotFound, message),
+            domain::Error::PreconditionFailed(message) => {
+                status::Custom(Status::PreconditionFailed, message)
+            }
+            domain::Error::Conflict(message) => status::Custom(Status::Conflict, message),
+            domain::Error::Forbidden(message) => status::Custom(Status::Forbidden, message),
+            domain::Error::Internal(message) => {
+                status::Custom(Status::InternalServerError, message)
+            }
+        }
+    }
+}
@@ -0,0 +1,902 @@
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+
+use crate::allergy;
+use crate::audit;
+use crate::auth;
+use crate::data;
+use crate::domain;
+use crate::etag;
+use crate::idempotency;
+use crate::kitchen;
+use crate::repository;
+use crate::service;
+
+pub const BASE: &str = "/api/v1";
+
+#[get("/restaurant")]
+fn get_restaurant(
+    staff_members: State<repository::StaffMembers>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<Json<data::Restaurant>>, String> {
+    let restaurant_id = staff.staff.restaurant_id;
+    info!("Getting restaurant {}.", restaurant_id);
+    let found = staff_members.restaurant(restaurant_id)?;
+    Ok(found.map(|restaurant| {
+        Json(data::Restaurant {
+            id: restaurant.id,
+            name: restaurant.name,
+        })
+    }))
+}
+
+#[get("/menu")]
+fn get_menu(
+    menus: State<repository::Menus>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Json<Vec<data::MenuCategory>>, String> {
+    info!("Getting menu.");
+    let found = menus.menu(staff.staff.restaurant_id)?;
+    Ok(Json(
+        found
+            .categories
+            .iter()
+            .map(data::MenuCategory::from)
+            .collect(),
+    ))
+}
+
+/// Marks a menu item as run out, or as available again. An item that ran out can be given
+/// the time it is expected back, it can be ordered again from then on.
+#[put("/menu/<item>/availability", format = "json", data = "<request>")]
+fn set_availability(
+    item: i32,
+    menus: State<repository::Menus>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::ChangeAvailability>,
+    request: Json<data::AvailabilityRequest>,
+) -> Result<Option<Json<data::MenuItem>>, status::Custom<String>> {
+    info!(
+        "Setting availability of menu item {} to {} until {:?}.",
+        item, request.available, request.available_at
+    );
+    let restaurant_id = staff.staff.restaurant_id;
+    let available_at = if request.available {
+        None
+    } else {
+        request.available_at
+    };
+    if available_at.map_or(false, |available_at| available_at <= audit::now()) {
+        return Err(domain::Error::Invalid(String::from(
+            "The time an item is available again has to be in the future.",
+        ))
+        .into());
+    }
+    let find_menu_item = || -> Result<Option<data::MenuItem>, status::Custom<String>> {
+        let found = menus.menu(restaurant_id).map_err(internal_error)?;
+        let menu_item = found
+            .categories
+            .iter()
+            .flat_map(|category| category.items.iter())
+            .find(|menu_item| menu_item.id == item);
+        Ok(menu_item.map(data::MenuItem::from))
+    };
+    let before = match find_menu_item()? {
+        Some(before) => before,
+        None => return Ok(None),
+    };
+    let updated = menus
+        .set_availability(restaurant_id, item, request.available, available_at)
+        .map_err(internal_error)?;
+    if !updated {
+        return Ok(None);
+    }
+    let after = find_menu_item()?;
+    audit_log
+        .record(audit::Change {
+            restaurant_id,
+            actor_id: staff.staff.id,
+            action: "set_availability",
+            table_id: None,
+            item_id: None,
+            before: audit::snapshot(&before),
+            after: after.as_ref().and_then(audit::snapshot),
+        })
+        .map_err(internal_error)?;
+    Ok(after.map(Json))
+}
+
+#[get("/inventory")]
+fn get_inventory(
+    stock: State<repository::Stock>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Json<Vec<data::Ingredient>>, String> {
+    info!("Getting inventory.");
+    let ingredients = stock.ingredients(staff.staff.restaurant_id)?;
+    Ok(Json(
+        ingredients.iter().map(data::Ingredient::from).collect(),
+    ))
+}
+
+/// Adds an ingredient to the restaurant's stock.
+#[post("/inventory", format = "json", data = "<request>")]
+fn add_ingredient(
+    stock: State<repository::Stock>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::ManageInventory>,
+    request: Json<data::IngredientRequest>,
+) -> Result<Json<data::Ingredient>, status::Custom<String>> {
+    info!("Adding ingredient {:?}.", request.name);
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(domain::Error::Invalid(String::from("An ingredient needs a name.")).into());
+    }
+    if request.stock < 0 || request.low_stock < 0 {
+        let message = "An ingredient cannot have less than nothing in stock.";
+        return Err(domain::Error::Invalid(String::from(message)).into());
+    }
+    let restaurant_id = staff.staff.restaurant_id;
+    let added = stock
+        .add_ingredient(restaurant_id, name, request.stock, request.low_stock)
+        .map_err(internal_error)?;
+    let added = data::Ingredient::from(&added);
+    record_stock_change(&audit_log, &staff.staff, "add_ingredient", None, &added)?;
+    Ok(Json(added))
+}
+
+/// Adds a delivery of an ingredient to its stock.
+#[post("/inventory/<ingredient>/restock", format = "json", data = "<request>")]
+fn restock(
+    ingredient: i32,
+    stock: State<repository::Stock>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::ManageInventory>,
+    request: Json<data::RestockRequest>,
+) -> Result<Option<Json<data::Ingredient>>, status::Custom<String>> {
+    info!(
+        "Restocking ingredient {} with {}.",
+        ingredient, request.quantity
+    );
+    if request.quantity <= 0 {
+        let message = "A restock has to add more than nothing.";
+        return Err(domain::Error::Invalid(String::from(message)).into());
+    }
+    let restaurant_id = staff.staff.restaurant_id;
+    let before = match find_ingredient(&stock, restaurant_id, ingredient)? {
+        Some(before) => before,
+        None => return Ok(None),
+    };
+    let after = stock
+        .restock(restaurant_id, ingredient, request.quantity)
+        .map_err(internal_error)?;
+    let after = match after {
+        Some(after) => data::Ingredient::from(&after),
+        None => return Ok(None),
+    };
+    record_stock_change(&audit_log, &staff.staff, "restock", Some(&before), &after)?;
+    Ok(Some(Json(after)))
+}
+
+/// Changes how low an ingredient's stock gets before it is running low.
+#[put(
+    "/inventory/<ingredient>/low_stock",
+    format = "json",
+    data = "<request>"
+)]
+fn set_low_stock(
+    ingredient: i32,
+    stock: State<repository::Stock>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::ManageInventory>,
+    request: Json<data::LowStockRequest>,
+) -> Result<Option<Json<data::Ingredient>>, status::Custom<String>> {
+    info!(
+        "Setting low stock of ingredient {} to {}.",
+        ingredient, request.low_stock
+    );
+    if request.low_stock < 0 {
+        let message = "Low stock cannot be less than nothing.";
+        return Err(domain::Error::Invalid(String::from(message)).into());
+    }
+    let restaurant_id = staff.staff.restaurant_id;
+    let before = match find_ingredient(&stock, restaurant_id, ingredient)? {
+        Some(before) => before,
+        None => return Ok(None),
+    };
+    let after = stock
+        .set_low_stock(restaurant_id, ingredient, request.low_stock)
+        .map_err(internal_error)?;
+    let after = match after {
+        Some(after) => data::Ingredient::from(&after),
+        None => return Ok(None),
+    };
+    record_stock_change(
+        &audit_log,
+        &staff.staff,
+        "set_low_stock",
+        Some(&before),
+        &after,
+    )?;
+    Ok(Some(Json(after)))
+}
+
+/// Replaces how much of each ingredient making one of a menu item uses.
+#[put("/menu/<item>/recipe", format = "json", data = "<request>")]
+fn set_recipe(
+    item: i32,
+    menus: State<repository::Menus>,
+    stock: State<repository::Stock>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::ManageInventory>,
+    request: Json<data::Recipe>,
+) -> Result<Option<Json<data::Recipe>>, status::Custom<String>> {
+    info!("Setting recipe of menu item {}.", item);
+    let restaurant_id = staff.staff.restaurant_id;
+    let found = menus.menu(restaurant_id).map_err(internal_error)?;
+    let before = found
+        .categories
+        .iter()
+        .flat_map(|category| category.items.iter())
+        .find(|menu_item| menu_item.id == item)
+        .map(|menu_item| data::Recipe::from(&menu_item.recipe[..]));
+    let before = match before {
+        Some(before) => before,
+        None => return Ok(None),
+    };
+    let recipe = request
+        .lines
+        .iter()
+        .map(|line| domain::RecipeLine {
+            ingredient_id: line.ingredient_id,
+            quantity: line.quantity,
+        })
+        .collect::<Vec<domain::RecipeLine>>();
+    let ingredients = stock.ingredients(restaurant_id).map_err(internal_error)?;
+    service::check_recipe(&recipe, &ingredients)?;
+    let updated = menus
+        .set_recipe(restaurant_id, item, &recipe)
+        .map_err(internal_error)?;
+    if !updated {
+        return Ok(None);
+    }
+    let after = data::Recipe::from(&recipe[..]);
+    audit_log
+        .record(audit::Change {
+            restaurant_id,
+            actor_id: staff.staff.id,
+            action: "set_recipe",
+            table_id: None,
+            item_id: None,
+            before: audit::snapshot(&before),
+            after: audit::snapshot(&after),
+        })
+        .map_err(internal_error)?;
+    Ok(Some(Json(after)))
+}
+
+fn find_ingredient(
+    stock: &repository::Stock,
+    restaurant_id: i32,
+    ingredient_id: i32,
+) -> Result<Option<data::Ingredient>, status::Custom<String>> {
+    let ingredients = stock.ingredients(restaurant_id).map_err(internal_error)?;
+    Ok(ingredients
+        .iter()
+        .find(|ingredient| ingredient.id == ingredient_id)
+        .map(data::Ingredient::from))
+}
+
+/// Ingredients are not on any table, so their changes are not either.
+fn record_stock_change(
+    audit_log: &repository::AuditLog,
+    staff: &auth::Staff,
+    action: &'static str,
+    before: Option<&data::Ingredient>,
+    after: &data::Ingredient,
+) -> Result<(), status::Custom<String>> {
+    audit_log
+        .record(audit::Change {
+            restaurant_id: staff.restaurant_id,
+            actor_id: staff.id,
+            action,
+            table_id: None,
+            item_id: None,
+            before: before.and_then(audit::snapshot),
+            after: audit::snapshot(after),
+        })
+        .map_err(internal_error)
+}
+
+#[get("/tables")]
+fn get_all_tables(
+    tables: State<repository::Tables>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Json<Vec<data::Table>>, String> {
+    info!("Getting all tables.");
+    let all_tables: Vec<data::Table> = tables
+        .all(staff.staff.restaurant_id)?
+        .iter()
+        .map(data::Table::from)
+        .collect();
+    debug!("Finished getting all Tables {:?}.", all_tables);
+    Ok(Json(all_tables))
+}
+
+#[get("/tables/<table>")]
+fn get_items_for_table(
+    table: String,
+    tables: State<repository::Tables>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<etag::Tagged<Json<data::Table>>>, String> {
+    info!("Getting items for table {}.", table);
+    let found = match tables.find(staff.staff.restaurant_id, &table)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let table = data::Table::from(&found);
+    debug!("Finished getting items {:?}.", table);
+    Ok(Some(etag::Tagged(Json(table), found.version)))
+}
+
+#[get("/tables/<table>/<item>")]
+fn get_item_for_table(
+    table: String,
+    item: String,
+    tables: State<repository::Tables>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<Json<data::Item>>, String> {
+    info!("Getting item {:?} for table {}.", item, table);
+    let found = match tables.find(staff.staff.restaurant_id, &table)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    debug!("Found table: {:?}", found);
+    let item = found.ordered_item(&item).map(data::Item::from);
+    debug!("Found items {:?}", item);
+    Ok(item.map(Json))
+}
+
+impl From<&domain::Item> for data::Item {
+    fn from(item: &domain::Item) -> data::Item {
+        let later_course = item.course > 1;
+        data::Item {
+            id: item.id,
+            time_to_prepare: item.time_to_prepare,
+            name: item.name.clone(),
+            modifiers: item.modifiers.iter().map(data::Modifier::from).collect(),
+            course: Some(item.course).filter(|_| later_course),
+            held: Some(item.held).filter(|_| later_course),
+        }
+    }
+}
+
+impl From<&domain::Modifier> for data::Modifier {
+    fn from(modifier: &domain::Modifier) -> data::Modifier {
+        data::Modifier {
+            id: modifier.id,
+            name: modifier.name.clone(),
+            price_delta: modifier.price_delta,
+        }
+    }
+}
+
+impl From<&domain::ModifierGroup> for data::ModifierGroup {
+    fn from(group: &domain::ModifierGroup) -> data::ModifierGroup {
+        data::ModifierGroup {
+            id: group.id,
+            name: group.name.clone(),
+            min_selected: group.min_selected,
+            max_selected: group.max_selected,
+            modifiers: group.modifiers.iter().map(data::Modifier::from).collect(),
+        }
+    }
+}
+
+impl From<&domain::MenuItem> for data::MenuItem {
+    fn from(item: &domain::MenuItem) -> data::MenuItem {
+        data::MenuItem {
+            id: item.id,
+            name: item.name.clone(),
+            price: item.price,
+            regular_price: Some(item.regular_price).filter(|price| *price != item.price),
+            modifier_groups: item
+                .modifier_groups
+                .iter()
+                .map(data::ModifierGroup::from)
+                .collect(),
+            components: item.components.clone(),
+            available: item.availability == domain::Availability::Available,
+            available_at: match item.availability {
+                domain::Availability::Unavailable { until } => until,
+                domain::Availability::Available => None,
+            },
+            allergens: item.allergens.clone(),
+            dietary: item.dietary.clone(),
+        }
+    }
+}
+
+impl From<&domain::Ingredient> for data::Ingredient {
+    fn from(ingredient: &domain::Ingredient) -> data::Ingredient {
+        data::Ingredient {
+            id: ingredient.id,
+            name: ingredient.name.clone(),
+            stock: ingredient.stock,
+            low_stock: ingredient.low_stock,
+            low: ingredient.is_low(),
+        }
+    }
+}
+
+impl From<&[domain::RecipeLine]> for data::Recipe {
+    fn from(recipe: &[domain::RecipeLine]) -> data::Recipe {
+        data::Recipe {
+            lines: recipe
+                .iter()
+                .map(|line| data::RecipeLine {
+                    ingredient_id: line.ingredient_id,
+                    quantity: line.quantity,
+                })
+                .collect(),
+        }
+    }
+}
+
+const DAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+fn time_of_day(minutes: i32) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+impl From<&domain::Window> for data::Schedule {
+    fn from(window: &domain::Window) -> data::Schedule {
+        data::Schedule {
+            days: (0..DAYS.len())
+                .filter(|day| window.days & (1 << day) != 0)
+                .map(|day| String::from(DAYS[day]))
+                .collect(),
+            starts: time_of_day(window.starts),
+            ends: time_of_day(window.ends),
+        }
+    }
+}
+
+impl From<&domain::Category> for data::MenuCategory {
+    fn from(category: &domain::Category) -> data::MenuCategory {
+        data::MenuCategory {
+            id: category.id,
+            name: category.name.clone(),
+            schedules: category
+                .schedules
+                .iter()
+                .map(data::Schedule::from)
+                .collect(),
+            items: category.items.iter().map(data::MenuItem::from).collect(),
+        }
+    }
+}
+
+impl From<&data::ItemOrder> for domain::OrderLine {
+    fn from(order: &data::ItemOrder) -> domain::OrderLine {
+        match order {
+            data::ItemOrder::Name(name) => domain::OrderLine {
+                name: name.clone(),
+                modifiers: Vec::new(),
+                course: 1,
+            },
+            data::ItemOrder::WithModifiers {
+                name,
+                modifiers,
+                course,
+            } => domain::OrderLine {
+                name: name.clone(),
+                modifiers: modifiers.clone(),
+                course: course.unwrap_or(1),
+            },
+        }
+    }
+}
+
+/// Version 1 only ever shows the items still on the table.
+impl From<&domain::Table> for data::Table {
+    fn from(table: &domain::Table) -> data::Table {
+        data::Table {
+            id: table.id,
+            items: table.ordered_items().map(data::Item::from).collect(),
+        }
+    }
+}
+
+impl From<&domain::Void> for data::Void {
+    fn from(void: &domain::Void) -> data::Void {
+        data::Void {
+            reason: void.reason.clone(),
+            voided_by: void.voided_by,
+            voided_at: void.voided_at,
+        }
+    }
+}
+
+#[post("/tables", format = "json", data = "<request>")]
+#[allow(clippy::too_many_arguments)]
+fn add_item(
+    tables: State<repository::Tables>,
+    items: State<repository::Items>,
+    stock: State<repository::Stock>,
+    menus: State<repository::Menus>,
+    allergies: State<repository::Allergies>,
+    kitchen: State<repository::Kitchen>,
+    idempotency_keys: State<repository::IdempotencyKeys>,
+    policy: State<allergy::Policy>,
+    staff: auth::Authorized<auth::AddItems>,
+    idempotency_key: idempotency::IdempotencyKey,
+    window: State<idempotency::Window>,
+    if_match: etag::IfMatch,
+    request: Json<data::ItemRequest>,
+) -> Result<Json<data::StatusMessage>, status::Custom<String>> {
+    info!(
+        "Adding item {:?} in Table {}.",
+        request.items, request.table_id
+    );
+    let restaurant_id = staff.staff.restaurant_id;
+    let repositories = Repositories {
+        tables: tables.inner().as_ref(),
+        items: items.inner().as_ref(),
+        stock: stock.inner().as_ref(),
+        menus: menus.inner().as_ref(),
+        allergies: allergies.inner().as_ref(),
+        kitchen: kitchen.inner().as_ref(),
+    };
+    let key = match idempotency_key.0 {
+        Some(key) => key,
+        None => {
+            return insert_items(&repositories, *policy, &staff.staff, &request, if_match.0)
+                .map(Json)
+        }
+    };
+    let request_body =
+        serde_json::to_string(&*request).map_err(|e| internal_error(e.to_string()))?;
+    let attempt = idempotency_keys
+        .begin(restaurant_id, &key, &request_body, window.inner())
+        .map_err(internal_error)?;
+    match attempt {
+        idempotency::Attempt::New => {}
+        idempotency::Attempt::Replay(response) => {
+            info!("Replaying response for idempotency key {}.", key);
+            return serde_json::from_str(&response)
+                .map(Json)
+                .map_err(|e| internal_error(e.to_string()));
+        }
+        idempotency::Attempt::Mismatch => {
+            return Err(status::Custom(
+                Status::UnprocessableEntity,
+                format!("Idempotency key {} was used for a different request.", key),
+            ))
+        }
+        idempotency::Attempt::InProgress => {
+            return Err(status::Custom(
+                Status::Conflict,
+                format!("A request with idempotency key {} is still running.", key),
+            ))
+        }
+    }
+    match insert_items(&repositories, *policy, &staff.staff, &request, if_match.0) {
+        Ok(message) => {
+            let response =
+                serde_json::to_string(&message).map_err(|e| internal_error(e.to_string()))?;
+            // The order is in, so a failure here must not make the client retry it. The key
+            // is given up once its lease runs out.
+            if let Err(e) = idempotency_keys.complete(restaurant_id, &key, &response) {
+                warn!(
+                    "Failed to store response for idempotency key {}: {}",
+                    key, e
+                );
+            }
+            Ok(Json(message))
+        }
+        Err(e) => {
+            idempotency_keys.abandon(restaurant_id, &key);
+            Err(e)
+        }
+    }
+}
+
+/// The repositories an order is written to.
+struct Repositories<'a> {
+    tables: &'a dyn repository::TableRepository,
+    items: &'a dyn repository::ItemRepository,
+    stock: &'a dyn repository::StockRepository,
+    menus: &'a dyn repository::MenuRepository,
+    allergies: &'a dyn repository::AllergyRepository,
+    kitchen: &'a dyn repository::KitchenRepository,
+}
+
+fn insert_items(
+    repositories: &Repositories,
+    policy: allergy::Policy,
+    staff: &auth::Staff,
+    request: &data::ItemRequest,
+    expected_version: Option<i64>,
+) -> Result<data::StatusMessage, status::Custom<String>> {
+    let menu = repositories
+        .menus
+        .menu(staff.restaurant_id)
+        .map_err(internal_error)?;
+    let lines: Vec<domain::OrderLine> = request.items.iter().map(domain::OrderLine::from).collect();
+    if let (allergy::Policy::Reject, Ok(table_id)) = (policy, request.table_id.parse::<i32>()) {
+        let allergies = repositories
+            .allergies
+            .find(staff.restaurant_id, table_id)
+            .map_err(internal_error)?;
+        service::check_allergies(&menu, &allergies, &lines)?;
+    }
+    let ordered_at = audit::now();
+    let (table_id, _, new_items) = service::add_items(
+        repositories.tables,
+        repositories.items,
+        repositories.stock,
+        &menu,
+        staff.restaurant_id,
+        &request.table_id,
+        &lines,
+        ordered_at,
+        expected_version,
+        &|table_id, created, new_items| {
+            let mut changes = Vec::new();
+            if created {
+                let created_table = data::Table {
+                    id: table_id,
+                    items: new_items.iter().map(data::Item::from).collect(),
+                };
+                changes.push(audit::Change {
+                    restaurant_id: staff.restaurant_id,
+                    actor_id: staff.id,
+                    action: "create_table",
+                    table_id: Some(table_id),
+                    item_id: None,
+                    before: None,
+                    after: audit::snapshot(&created_table),
+                });
+            }
+            changes.extend(new_items.iter().map(|item| audit::Change {
+                restaurant_id: staff.restaurant_id,
+                actor_id: staff.id,
+                action: "add_item",
+                table_id: Some(table_id),
+                item_id: Some(item.id),
+                before: None,
+                after: audit::snapshot(&data::Item::from(item)),
+            }));
+            changes
+        },
+    )?;
+    let table = table_id.to_string();
+    for item in new_items.iter() {
+        repositories
+            .kitchen
+            .record(
+                staff.restaurant_id,
+                &table,
+                item,
+                kitchen::FIRED,
+                ordered_at,
+            )
+            .map_err(internal_error)?;
+    }
+    info!("Finished adding items to Table.");
+    // Version 1 reports the one table row that was written.
+    Ok(data::StatusMessage {
+        message: String::from("1 rows inserted."),
+    })
+}
+
+#[delete("/tables/<table>/<item>?<reason>")]
+#[allow(clippy::too_many_arguments)]
+fn remove_item(
+    table: String,
+    item: String,
+    tables: State<repository::Tables>,
+    items: State<repository::Items>,
+    stock: State<repository::Stock>,
+    kitchen: State<repository::Kitchen>,
+    staff: auth::Authorized<auth::RemoveItems>,
+    if_match: etag::IfMatch,
+    reason: Option<String>,
+) -> Result<Json<data::StatusMessage>, status::Custom<String>> {
+    info!(
+        "Voiding item {}, from table {} because {:?}.",
+        item, table, reason
+    );
+    // Without a reason the void is turned down like a blank one.
+    let void = domain::Void {
+        reason: reason.unwrap_or_default(),
+        voided_by: staff.staff.id,
+        voided_at: audit::now(),
+    };
+    let removed = service::void_item(
+        tables.inner().as_ref(),
+        items.inner().as_ref(),
+        stock.inner().as_ref(),
+        staff.staff.restaurant_id,
+        &table,
+        &item,
+        &void,
+        if_match.0,
+        staff.staff.role.can(auth::Action::VoidPaidItems),
+        &|table_id, removed| audit::Change {
+            restaurant_id: staff.staff.restaurant_id,
+            actor_id: staff.staff.id,
+            action: "void_item",
+            table_id: Some(table_id),
+            item_id: Some(removed.id),
+            before: audit::snapshot(&data::Item::from(removed)),
+            after: audit::snapshot(&data::Void::from(&void)),
+        },
+    )?;
+    kitchen
+        .record(
+            staff.staff.restaurant_id,
+            &table,
+            &removed,
+            kitchen::VOIDED,
+            void.voided_at,
+        )
+        .map_err(internal_error)?;
+    Ok(Json(data::StatusMessage {
+        message: String::from("1 rows voided."),
+    }))
+}
+
+#[post("/tables/<table>/<item>/restore")]
+#[allow(clippy::too_many_arguments)]
+fn restore_item(
+    table: String,
+    item: String,
+    tables: State<repository::Tables>,
+    items: State<repository::Items>,
+    stock: State<repository::Stock>,
+    kitchen: State<repository::Kitchen>,
+    staff: auth::Authorized<auth::RestoreItems>,
+    if_match: etag::IfMatch,
+) -> Result<Json<data::StatusMessage>, status::Custom<String>> {
+    info!("Restoring item {} to table {}.", item, table);
+    let (restored, _) = service::restore_item(
+        tables.inner().as_ref(),
+        items.inner().as_ref(),
+        stock.inner().as_ref(),
+        staff.staff.restaurant_id,
+        &table,
+        &item,
+        if_match.0,
+        &|table_id, restored, void| audit::Change {
+            restaurant_id: staff.staff.restaurant_id,
+            actor_id: staff.staff.id,
+            action: "restore_item",
+            table_id: Some(table_id),
+            item_id: Some(restored.id),
+            before: audit::snapshot(&data::Void::from(void)),
+            after: audit::snapshot(&data::Item::from(restored)),
+        },
+    )?;
+    kitchen
+        .record(
+            staff.staff.restaurant_id,
+            &table,
+            &restored,
+            kitchen::FIRED,
+            audit::now(),
+        )
+        .map_err(internal_error)?;
+    Ok(Json(data::StatusMessage {
+        message: format!("Restored item {} to table {}.", item, table),
+    }))
+}
+
+#[post("/tables/<table>/courses/<course>/fire")]
+fn fire_course(
+    table: String,
+    course: i32,
+    tables: State<repository::Tables>,
+    kitchen: State<repository::Kitchen>,
+    staff: auth::Authorized<auth::AddItems>,
+    if_match: etag::IfMatch,
+) -> Result<Json<data::StatusMessage>, status::Custom<String>> {
+    info!("Firing course {} of table {}.", course, table);
+    let fired_at = audit::now();
+    let fired = service::fire_course(
+        tables.inner().as_ref(),
+        staff.staff.restaurant_id,
+        &table,
+        course,
+        fired_at,
+        if_match.0,
+        &|table_id, fired| {
+            fired
+                .iter()
+                .map(|item| audit::Change {
+                    restaurant_id: staff.staff.restaurant_id,
+                    actor_id: staff.staff.id,
+                    action: "fire_course",
+                    table_id: Some(table_id),
+                    item_id: Some(item.id),
+                    before: None,
+                    after: audit::snapshot(&data::Item::from(item)),
+                })
+                .collect()
+        },
+    )?;
+    for item in fired.iter() {
+        kitchen
+            .record(
+                staff.staff.restaurant_id,
+                &table,
+                item,
+                kitchen::FIRED,
+                fired_at,
+            )
+            .map_err(internal_error)?;
+    }
+    Ok(Json(data::StatusMessage {
+        message: format!("{} rows fired.", fired.len()),
+    }))
+}
+
+fn internal_error(message: String) -> status::Custom<String> {
+    status::Custom(Status::InternalServerError, message)
+}
+
+#[get("/audit?<table>&<actor>&<from>&<to>")]
+fn get_audit(
+    table: Option<i32>,
+    actor: Option<i32>,
+    from: Option<i64>,
+    to: Option<i64>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::ViewAudit>,
+) -> Result<Json<Vec<data::AuditEntry>>, String> {
+    info!(
+        "Getting audit log for table {:?} and staff {:?}.",
+        table, actor
+    );
+    let filter = audit::Filter {
+        restaurant_id: staff.staff.restaurant_id,
+        table_id: table,
+        actor_id: actor,
+        from,
+        to,
+    };
+    let entries = audit_log.search(&filter)?;
+    debug!("Found {} audit entries.", entries.len());
+    Ok(Json(entries))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_restaurant,
+        get_menu,
+        set_availability,
+        set_recipe,
+        get_inventory,
+        add_ingredient,
+        restock,
+        set_low_stock,
+        get_all_tables,
+        add_item,
+        remove_item,
+        restore_item,
+        fire_course,
+        get_item_for_table,
+        get_items_for_table,
+        get_audit
+    ]
+}
@@ -0,0 +1,963 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::http::ContentType;
+use rocket::response::{content, status};
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::auth;
+use crate::domain;
+use crate::estimate;
+use crate::etag;
+use crate::kitchen;
+use crate::repository;
+use crate::service;
+
+pub const BASE: &str = "/api/v2";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TableStatus {
+    Open,
+    Empty,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    Ordered,
+    Held,
+    Voided,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Void {
+    pub reason: String,
+    pub voided_by: i32,
+    pub voided_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Modifier {
+    pub id: i32,
+    pub name: String,
+    pub price_delta: i32,
+}
+
+/// Something the kitchen makes for a bundle.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Component {
+    pub id: i32,
+    pub name: String,
+    pub preparation_minutes: i32,
+}
+
+/// The price is in cents, as it was when the item was ordered. A held item waits for its
+/// course to be fired, `fired_at` is when the kitchen got it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Item {
+    pub id: i32,
+    pub name: String,
+    pub preparation_minutes: i32,
+    pub price: i32,
+    pub course: i32,
+    pub status: ItemStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fired_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub void: Option<Void>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<Component>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Order {
+    pub items: Vec<Item>,
+}
+
+/// One thing for the kitchen to make, components name the bundle they were ordered in.
+/// Its preparation starts at `fired_at` at its station and it is done at `ready_at`. There
+/// is a warning for each allergy at the table the item has the allergen of.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Ticket {
+    pub table_id: i32,
+    pub item_id: i32,
+    pub name: String,
+    pub preparation_minutes: i32,
+    pub course: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub station: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fired_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allergy_warnings: Vec<String>,
+}
+
+/// Where in the kitchen items are made, like the grill or the bar.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Station {
+    pub id: i32,
+    pub name: String,
+}
+
+/// Something that happened to a ticket at a station, the data of an event in its stream.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KitchenEvent {
+    pub id: i64,
+    pub kind: String,
+    pub table_id: i32,
+    pub item_id: i32,
+    pub name: String,
+    pub at: i64,
+}
+
+/// How long a menu item's latest preparations were estimated to take against how long they
+/// took, in minutes. `current_minutes` is its estimate if it was ordered now, when it was
+/// made often enough to have one.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PrepEstimate {
+    pub name: String,
+    pub samples: i32,
+    pub estimated_minutes: i32,
+    pub median_minutes: i32,
+    pub p90_minutes: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_minutes: Option<i32>,
+}
+
+/// An allergy declared at a table, for one seat or for everyone at it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Allergy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seat: Option<i32>,
+    pub allergen: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Allergies {
+    pub allergies: Vec<Allergy>,
+}
+
+/// A line for each item still on the table, a bundle is one line at the bundle's price.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BillLine {
+    pub item_id: i32,
+    pub name: String,
+    pub price: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<String>,
+}
+
+/// One of `domain::Discount::KINDS`. `value` is the percentage off or the amount off in
+/// cents, buy x get y gives `get` free for every `buy`, and a comp makes its lines free.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Discount {
+    pub kind: String,
+    #[serde(default)]
+    pub value: Option<i32>,
+    #[serde(default)]
+    pub buy: Option<i32>,
+    #[serde(default)]
+    pub get: Option<i32>,
+}
+
+/// Something taken off a bill, for one item, the items ordered with a name or the whole
+/// bill when it has neither. `amount` is what it takes off in cents.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Adjustment {
+    pub id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_id: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_name: Option<String>,
+    pub discount: Discount,
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub added_by: i32,
+    pub added_at: i64,
+    pub amount: i32,
+}
+
+/// A comp or discount a manager gives, with the reason for it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdjustmentRequest {
+    #[serde(default)]
+    pub item_id: Option<i32>,
+    #[serde(default)]
+    pub item_name: Option<String>,
+    pub discount: Discount,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PromoCodeRequest {
+    pub code: String,
+}
+
+/// Prices are in cents, the total is the subtotal of the lines with the adjustments taken
+/// off. `paid_at` is when the bill was settled.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Bill {
+    pub table_id: i32,
+    pub lines: Vec<BillLine>,
+    pub subtotal: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adjustments: Vec<Adjustment>,
+    pub total: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paid_at: Option<i64>,
+}
+
+/// Unlike version 1 the table keeps its voided items, marked with their status. It is ready
+/// once every station has what it was sent for the table ready.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Table {
+    pub id: i32,
+    pub version: i64,
+    pub status: TableStatus,
+    pub ready: bool,
+    pub order: Order,
+}
+
+impl From<&domain::Item> for Item {
+    fn from(item: &domain::Item) -> Item {
+        let (status, void) = match &item.status {
+            domain::ItemStatus::Ordered if item.held => (ItemStatus::Held, None),
+            domain::ItemStatus::Ordered => (ItemStatus::Ordered, None),
+            domain::ItemStatus::Voided(void) => (
+                ItemStatus::Voided,
+                Some(Void {
+                    reason: void.reason.clone(),
+                    voided_by: void.voided_by,
+                    voided_at: void.voided_at,
+                }),
+            ),
+        };
+        Item {
+            id: item.id,
+            name: item.name.clone(),
+            preparation_minutes: item.time_to_prepare,
+            price: item.price,
+            course: item.course,
+            status,
+            fired_at: item.fired_at,
+            void,
+            modifiers: item.modifiers.iter().map(Modifier::from).collect(),
+            components: item
+                .components
+                .iter()
+                .map(|component| Component {
+                    id: component.id,
+                    name: component.name.clone(),
+                    preparation_minutes: component.time_to_prepare,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&domain::Modifier> for Modifier {
+    fn from(modifier: &domain::Modifier) -> Modifier {
+        Modifier {
+            id: modifier.id,
+            name: modifier.name.clone(),
+            price_delta: modifier.price_delta,
+        }
+    }
+}
+
+impl From<&domain::Ticket<'_>> for Ticket {
+    fn from(ticket: &domain::Ticket) -> Ticket {
+        Ticket {
+            table_id: ticket.table_id,
+            item_id: ticket.item.id,
+            name: ticket.item.name.clone(),
+            preparation_minutes: ticket.item.time_to_prepare,
+            course: ticket.item.course,
+            station: None,
+            fired_at: ticket.item.fired_at,
+            ready_at: ticket.item.ready_at,
+            modifiers: ticket.item.modifiers.iter().map(Modifier::from).collect(),
+            bundle: ticket.bundle.map(|bundle| bundle.name.clone()),
+            allergy_warnings: Vec::new(),
+        }
+    }
+}
+
+impl From<&domain::Station> for Station {
+    fn from(station: &domain::Station) -> Station {
+        Station {
+            id: station.id,
+            name: station.name.clone(),
+        }
+    }
+}
+
+impl From<&domain::KitchenEvent> for KitchenEvent {
+    fn from(event: &domain::KitchenEvent) -> KitchenEvent {
+        KitchenEvent {
+            id: event.id,
+            kind: event.kind.clone(),
+            table_id: event.table_id,
+            item_id: event.item_id,
+            name: event.name.clone(),
+            at: event.at,
+        }
+    }
+}
+
+impl From<&[domain::Allergy]> for Allergies {
+    fn from(allergies: &[domain::Allergy]) -> Allergies {
+        Allergies {
+            allergies: allergies
+                .iter()
+                .map(|allergy| Allergy {
+                    seat: allergy.seat,
+                    allergen: allergy.allergen.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&domain::Discount> for Discount {
+    fn from(discount: &domain::Discount) -> Discount {
+        let (kind, value, buy, get) = discount.parts();
+        Discount {
+            kind: kind.to_string(),
+            value,
+            buy,
+            get,
+        }
+    }
+}
+
+impl Bill {
+    fn new(table: &domain::Table, adjustments: &[domain::Adjustment]) -> Bill {
+        let lines: Vec<BillLine> = table
+            .ordered_items()
+            .map(|item| BillLine {
+                item_id: item.id,
+                name: item.name.clone(),
+                price: item.price,
+                modifiers: item.modifiers.iter().map(Modifier::from).collect(),
+                components: item
+                    .components
+                    .iter()
+                    .map(|component| component.name.clone())
+                    .collect(),
+            })
+            .collect();
+        let subtotal = lines.iter().map(|line| line.price).sum::<i32>();
+        let amounts = service::adjust(table, adjustments);
+        let adjustments = adjustments
+            .iter()
+            .zip(amounts)
+            .map(|(adjustment, amount)| {
+                let (item_id, item_name) = match &adjustment.target {
+                    domain::Target::Bill => (None, None),
+                    domain::Target::Item(item_id) => (Some(*item_id), None),
+                    domain::Target::Named(name) => (None, Some(name.clone())),
+                };
+                Adjustment {
+                    id: adjustment.id,
+                    item_id,
+                    item_name,
+                    discount: Discount::from(&adjustment.discount),
+                    reason: adjustment.reason.clone(),
+                    code: adjustment.code.clone(),
+                    added_by: adjustment.added_by,
+                    added_at: adjustment.added_at,
+                    amount,
+                }
+            })
+            .collect::<Vec<Adjustment>>();
+        let taken_off = adjustments
+            .iter()
+            .map(|adjustment| adjustment.amount)
+            .sum::<i32>();
+        Bill {
+            table_id: table.id,
+            lines,
+            subtotal,
+            total: subtotal - taken_off,
+            adjustments,
+            paid_at: table.paid_at,
+        }
+    }
+}
+
+impl From<&domain::Table> for Table {
+    fn from(table: &domain::Table) -> Table {
+        let status = match table.ordered_items().next() {
+            Some(_) => TableStatus::Open,
+            None => TableStatus::Empty,
+        };
+        Table {
+            id: table.id,
+            version: table.version,
+            status,
+            ready: table.is_ready(),
+            order: Order {
+                items: table.items.iter().map(Item::from).collect(),
+            },
+        }
+    }
+}
+
+#[get("/tables")]
+fn get_tables(
+    tables: State<repository::Tables>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Json<Vec<Table>>, String> {
+    info!("Getting all tables.");
+    let all_tables = tables.all(staff.staff.restaurant_id)?;
+    Ok(Json(all_tables.iter().map(Table::from).collect()))
+}
+
+#[get("/tables/<table>")]
+fn get_table(
+    table: i32,
+    tables: State<repository::Tables>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<etag::Tagged<Json<Table>>>, String> {
+    info!("Getting table {}.", table);
+    let found = tables.find(staff.staff.restaurant_id, &table.to_string())?;
+    Ok(found.map(|table| etag::Tagged(Json(Table::from(&table)), table.version)))
+}
+
+#[get("/tables/<table>/bill")]
+fn get_bill(
+    table: i32,
+    tables: State<repository::Tables>,
+    adjustments: State<repository::Adjustments>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<Json<Bill>>, String> {
+    info!("Getting bill for table {}.", table);
+    let restaurant_id = staff.staff.restaurant_id;
+    let found = match tables.find(restaurant_id, &table.to_string())? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let adjustments = adjustments.adjustments(restaurant_id, table)?;
+    Ok(Some(Json(Bill::new(&found, &adjustments))))
+}
+
+/// Settles a table's bill once it has been paid and returns it. Only a manager can void
+/// items from it after that.
+#[post("/tables/<table>/bill/settle")]
+fn settle_bill(
+    table: i32,
+    tables: State<repository::Tables>,
+    adjustments: State<repository::Adjustments>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::SettleBills>,
+) -> Result<Json<Bill>, status::Custom<String>> {
+    info!("Settling bill for table {}.", table);
+    let restaurant_id = staff.staff.restaurant_id;
+    let table_id = table.to_string();
+    let settled = service::settle_bill(
+        tables.inner().as_ref(),
+        restaurant_id,
+        &table_id,
+        audit::now(),
+    )?;
+    let adjustments = adjustments
+        .adjustments(restaurant_id, table)
+        .map_err(domain::Error::from)?;
+    let bill = Bill::new(&settled, &adjustments);
+    audit_log
+        .record(audit::Change {
+            restaurant_id,
+            actor_id: staff.staff.id,
+            action: "settle_bill",
+            table_id: Some(table),
+            item_id: None,
+            before: None,
+            after: audit::snapshot(&bill),
+        })
+        .map_err(domain::Error::from)?;
+    Ok(Json(bill))
+}
+
+/// Gives a comp or discount on a table's bill and returns the bill with it.
+#[post("/tables/<table>/adjustments", format = "json", data = "<request>")]
+fn add_adjustment(
+    table: i32,
+    tables: State<repository::Tables>,
+    adjustments: State<repository::Adjustments>,
+    staff: auth::Authorized<auth::AdjustBills>,
+    request: Json<AdjustmentRequest>,
+) -> Result<Option<Json<Bill>>, status::Custom<String>> {
+    info!(
+        "Adjusting bill for table {} with {:?} because {:?}.",
+        table, request.discount, request.reason
+    );
+    let restaurant_id = staff.staff.restaurant_id;
+    let found = tables
+        .find(restaurant_id, &table.to_string())
+        .map_err(domain::Error::from)?;
+    let found = match found {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let target = match (request.item_id, &request.item_name) {
+        (Some(_), Some(_)) => {
+            let message = "An adjustment is for an item or for the items with a name, not both.";
+            return Err(domain::Error::Invalid(String::from(message)).into());
+        }
+        (Some(item_id), None) => domain::Target::Item(item_id),
+        (None, Some(name)) => domain::Target::Named(name.clone()),
+        (None, None) => domain::Target::Bill,
+    };
+    service::check_target(&found, &target)?;
+    let discount = &request.discount;
+    let discount =
+        domain::Discount::from_parts(&discount.kind, discount.value, discount.buy, discount.get)
+            .map_err(domain::Error::Invalid)?;
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return Err(domain::Error::Invalid(String::from("An adjustment needs a reason.")).into());
+    }
+    let given = domain::Adjustment {
+        id: 0,
+        target,
+        discount,
+        reason: reason.to_string(),
+        code: None,
+        added_by: staff.staff.id,
+        added_at: audit::now(),
+    };
+    let change = audit::Change {
+        restaurant_id,
+        actor_id: staff.staff.id,
+        action: "adjust_bill",
+        table_id: Some(table),
+        item_id: request.item_id,
+        before: None,
+        after: audit::snapshot(&*request),
+    };
+    adjustments
+        .add(restaurant_id, table, &given, change)
+        .map_err(domain::Error::from)?;
+    let adjustments = adjustments
+        .adjustments(restaurant_id, table)
+        .map_err(domain::Error::from)?;
+    Ok(Some(Json(Bill::new(&found, &adjustments))))
+}
+
+/// Redeems a promo code for a table and returns the bill with it.
+#[post("/tables/<table>/promo_codes", format = "json", data = "<request>")]
+fn redeem_promo_code(
+    table: i32,
+    tables: State<repository::Tables>,
+    adjustments: State<repository::Adjustments>,
+    staff: auth::Authorized<auth::AddItems>,
+    request: Json<PromoCodeRequest>,
+) -> Result<Option<Json<Bill>>, status::Custom<String>> {
+    info!(
+        "Redeeming promo code {:?} for table {}.",
+        request.code, table
+    );
+    let restaurant_id = staff.staff.restaurant_id;
+    let found = tables
+        .find(restaurant_id, &table.to_string())
+        .map_err(domain::Error::from)?;
+    let found = match found {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let code = request.code.trim();
+    let promo_code = adjustments
+        .find_promo_code(restaurant_id, code)
+        .map_err(domain::Error::from)?
+        .ok_or_else(|| domain::Error::Invalid(format!("There is no promo code {}.", code)))?;
+    let found_adjustments = adjustments
+        .adjustments(restaurant_id, table)
+        .map_err(domain::Error::from)?;
+    let now = audit::now();
+    service::check_promo_code(&promo_code, &found_adjustments, now)?;
+    let code = promo_code.code.clone();
+    let redeemed = domain::Adjustment {
+        id: 0,
+        target: promo_code.target,
+        discount: promo_code.discount,
+        reason: format!("Promo code {}", code),
+        code: Some(code.clone()),
+        added_by: staff.staff.id,
+        added_at: now,
+    };
+    let change = audit::Change {
+        restaurant_id,
+        actor_id: staff.staff.id,
+        action: "redeem_promo_code",
+        table_id: Some(table),
+        item_id: None,
+        before: None,
+        after: audit::snapshot(&*request),
+    };
+    let redemption = adjustments
+        .redeem(restaurant_id, table, promo_code.id, &redeemed, change)
+        .map_err(domain::Error::from)?;
+    match redemption {
+        domain::Redemption::Redeemed(_) => {}
+        domain::Redemption::UsedUp => {
+            let message = format!("Promo code {} has been used up.", code);
+            return Err(domain::Error::Conflict(message).into());
+        }
+        domain::Redemption::AlreadyOnBill => {
+            let message = format!("Promo code {} is already on the bill.", code);
+            return Err(domain::Error::Conflict(message).into());
+        }
+    }
+    let adjustments = adjustments
+        .adjustments(restaurant_id, table)
+        .map_err(domain::Error::from)?;
+    Ok(Some(Json(Bill::new(&found, &adjustments))))
+}
+
+#[get("/tables/<table>/allergies")]
+fn get_allergies(
+    table: i32,
+    tables: State<repository::Tables>,
+    allergies: State<repository::Allergies>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<Json<Allergies>>, String> {
+    info!("Getting allergies at table {}.", table);
+    let restaurant_id = staff.staff.restaurant_id;
+    if tables.find(restaurant_id, &table.to_string())?.is_none() {
+        return Ok(None);
+    }
+    let allergies = allergies.find(restaurant_id, table)?;
+    Ok(Some(Json(Allergies::from(&allergies[..]))))
+}
+
+/// Replaces the allergies the guests at a table declared.
+#[put("/tables/<table>/allergies", format = "json", data = "<request>")]
+fn set_allergies(
+    table: i32,
+    tables: State<repository::Tables>,
+    allergies: State<repository::Allergies>,
+    audit_log: State<repository::AuditLog>,
+    staff: auth::Authorized<auth::AddItems>,
+    request: Json<Allergies>,
+) -> Result<Option<Json<Allergies>>, status::Custom<String>> {
+    info!(
+        "Setting allergies at table {} to {:?}.",
+        table, request.allergies
+    );
+    let restaurant_id = staff.staff.restaurant_id;
+    let found = tables
+        .find(restaurant_id, &table.to_string())
+        .map_err(domain::Error::from)?;
+    if found.is_none() {
+        return Ok(None);
+    }
+    let declared = request
+        .allergies
+        .iter()
+        .map(|allergy| domain::Allergy {
+            seat: allergy.seat,
+            allergen: allergy.allergen.trim().to_string(),
+        })
+        .collect::<Vec<domain::Allergy>>();
+    if declared.iter().any(|allergy| allergy.allergen.is_empty()) {
+        return Err(domain::Error::Invalid(String::from("An allergy needs an allergen.")).into());
+    }
+    let before = allergies
+        .find(restaurant_id, table)
+        .map_err(domain::Error::from)?;
+    allergies
+        .replace(restaurant_id, table, &declared)
+        .map_err(domain::Error::from)?;
+    let after = Allergies::from(&declared[..]);
+    audit_log
+        .record(audit::Change {
+            restaurant_id,
+            actor_id: staff.staff.id,
+            action: "set_allergies",
+            table_id: Some(table),
+            item_id: None,
+            before: audit::snapshot(&Allergies::from(&before[..])),
+            after: audit::snapshot(&after),
+        })
+        .map_err(domain::Error::from)?;
+    Ok(Some(Json(after)))
+}
+
+/// The repositories the kitchen's tickets are put together from.
+struct KitchenRepositories<'a> {
+    tables: &'a dyn repository::TableRepository,
+    menus: &'a dyn repository::MenuRepository,
+    allergies: &'a dyn repository::AllergyRepository,
+    kitchen: &'a dyn repository::KitchenRepository,
+}
+
+/// The tickets of every table the kitchen has to make and `keep` keeps, table by table in the
+/// order the items were added, with the station each is made at and allergy warnings.
+fn kitchen_tickets(
+    repositories: &KitchenRepositories,
+    restaurant_id: i32,
+    keep: impl Fn(&domain::Ticket) -> bool,
+) -> Result<Vec<Ticket>, String> {
+    let menu = repositories.menus.menu(restaurant_id)?;
+    let allergies = repositories.allergies.all(restaurant_id)?;
+    let stations = repositories.kitchen.stations(restaurant_id)?;
+    let mut tickets = Vec::new();
+    for table in repositories.tables.all(restaurant_id)? {
+        let at_table = allergies.get(&table.id).map_or(&[][..], |found| &found[..]);
+        for ticket in table.tickets().iter().filter(|ticket| keep(ticket)) {
+            let station = stations
+                .iter()
+                .find(|station| Some(station.id) == ticket.item.station_id);
+            tickets.push(Ticket {
+                station: station.map(|station| station.name.clone()),
+                allergy_warnings: service::allergy_warnings(&menu, at_table, &ticket.item.name),
+                ..Ticket::from(ticket)
+            });
+        }
+    }
+    Ok(tickets)
+}
+
+/// Every ticket of every table, table by table in the order the items were added.
+#[get("/kitchen")]
+fn get_kitchen(
+    tables: State<repository::Tables>,
+    menus: State<repository::Menus>,
+    allergies: State<repository::Allergies>,
+    kitchen: State<repository::Kitchen>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Json<Vec<Ticket>>, String> {
+    info!("Getting kitchen tickets.");
+    let repositories = KitchenRepositories {
+        tables: tables.inner().as_ref(),
+        menus: menus.inner().as_ref(),
+        allergies: allergies.inner().as_ref(),
+        kitchen: kitchen.inner().as_ref(),
+    };
+    let tickets = kitchen_tickets(&repositories, staff.staff.restaurant_id, |_| true)?;
+    Ok(Json(tickets))
+}
+
+/// Estimated against actual preparation times of every menu item made lately.
+#[get("/kitchen/estimates")]
+fn get_estimates(
+    menus: State<repository::Menus>,
+    items: State<repository::Items>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Json<Vec<PrepEstimate>>, String> {
+    info!("Getting preparation estimates.");
+    let restaurant_id = staff.staff.restaurant_id;
+    let menu = menus.menu(restaurant_id)?;
+    let now = audit::now();
+    let preparations = items.preparations(restaurant_id, now - estimate::HISTORY_SECONDS)?;
+    let estimator = estimate::Estimator::new(&preparations, now);
+    let estimates = estimator
+        .stats()
+        .iter()
+        .map(|stats| PrepEstimate {
+            name: stats.name.clone(),
+            samples: stats.samples,
+            estimated_minutes: stats.estimated_minutes,
+            median_minutes: stats.median_minutes,
+            p90_minutes: stats.p90_minutes,
+            current_minutes: estimator.estimate(&stats.name, menu.station(&stats.name)),
+        })
+        .collect();
+    Ok(Json(estimates))
+}
+
+#[get("/stations")]
+fn get_stations(
+    kitchen: State<repository::Kitchen>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Json<Vec<Station>>, String> {
+    info!("Getting stations.");
+    let stations = kitchen.stations(staff.staff.restaurant_id)?;
+    Ok(Json(stations.iter().map(Station::from).collect()))
+}
+
+/// The queue of a station, the tickets it still has to get ready.
+#[get("/stations/<station>/tickets")]
+fn get_station_tickets(
+    station: i32,
+    tables: State<repository::Tables>,
+    menus: State<repository::Menus>,
+    allergies: State<repository::Allergies>,
+    kitchen: State<repository::Kitchen>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<Json<Vec<Ticket>>>, String> {
+    info!("Getting tickets of station {}.", station);
+    let restaurant_id = staff.staff.restaurant_id;
+    if kitchen.find_station(restaurant_id, station)?.is_none() {
+        return Ok(None);
+    }
+    let repositories = KitchenRepositories {
+        tables: tables.inner().as_ref(),
+        menus: menus.inner().as_ref(),
+        allergies: allergies.inner().as_ref(),
+        kitchen: kitchen.inner().as_ref(),
+    };
+    let tickets = kitchen_tickets(&repositories, restaurant_id, |ticket| {
+        ticket.item.station_id == Some(station) && ticket.item.ready_at.is_none()
+    })?;
+    Ok(Some(Json(tickets)))
+}
+
+/// A polled server-sent event stream of what happened at a station after the event the client
+/// saw last. When nothing has happened yet the request is held until something does or the
+/// event wait runs out, which is at once unless one is set. The response then ends with the
+/// events so far and the client reconnects after the retry interval, sending the id of the
+/// last event it got as `Last-Event-ID`.
+#[get("/stations/<station>/events?<after>")]
+fn get_station_events(
+    station: i32,
+    after: Option<i64>,
+    last_event_id: kitchen::LastEventId,
+    kitchen: State<repository::Kitchen>,
+    wait: State<kitchen::EventWait>,
+    staff: auth::Authorized<auth::ViewTables>,
+) -> Result<Option<content::Content<String>>, String> {
+    info!("Getting events of station {}.", station);
+    let restaurant_id = staff.staff.restaurant_id;
+    if kitchen.find_station(restaurant_id, station)?.is_none() {
+        return Ok(None);
+    }
+    let after = last_event_id.0.or(after).unwrap_or(0);
+    let until = Instant::now() + Duration::from_secs(wait.0.max(0) as u64);
+    let mut events = kitchen.events(restaurant_id, station, after)?;
+    while events.is_empty() && Instant::now() < until {
+        thread::sleep(Duration::from_millis(kitchen::POLL_MILLIS));
+        events = kitchen.events(restaurant_id, station, after)?;
+    }
+    let mut body = format!("retry: {}\n\n", kitchen::RETRY_MILLIS);
+    for event in events {
+        let data = serde_json::to_string(&KitchenEvent::from(&event))
+            .map_err(|e| format!("Failed to write event: {}", e))?;
+        body.push_str(&format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            event.id, event.kind, data
+        ));
+    }
+    let event_stream = ContentType::new("text", "event-stream");
+    Ok(Some(content::Content(event_stream, body)))
+}
+
+/// Marks a ticket of a table ready and returns the table.
+#[post("/tables/<table>/items/<item>/ready")]
+fn mark_ready(
+    table: i32,
+    item: i32,
+    tables: State<repository::Tables>,
+    kitchen: State<repository::Kitchen>,
+    staff: auth::Authorized<auth::ChangeItemStatus>,
+) -> Result<Json<Table>, status::Custom<String>> {
+    info!("Marking item {} of table {} ready.", item, table);
+    let restaurant_id = staff.staff.restaurant_id;
+    let table_id = table.to_string();
+    let ready_at = audit::now();
+    let found = service::mark_ready(
+        tables.inner().as_ref(),
+        restaurant_id,
+        &table_id,
+        &item.to_string(),
+        ready_at,
+        &|ticket| audit::Change {
+            restaurant_id,
+            actor_id: staff.staff.id,
+            action: "mark_ready",
+            table_id: Some(table),
+            item_id: Some(item),
+            before: None,
+            after: audit::snapshot(&Ticket::from(ticket)),
+        },
+    )?;
+    let tickets = found.tickets();
+    let ticket = tickets
+        .iter()
+        .find(|ticket| ticket.item.id == item)
+        .ok_or_else(|| domain::Error::Internal(format!("Item {} went missing.", item)))?;
+    kitchen
+        .record(
+            restaurant_id,
+            &table_id,
+            ticket.item,
+            kitchen::READY,
+            ready_at,
+        )
+        .map_err(domain::Error::from)?;
+    Ok(Json(Table::from(&found)))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_tables,
+        get_table,
+        get_bill,
+        settle_bill,
+        add_adjustment,
+        redeem_promo_code,
+        get_allergies,
+        set_allergies,
+        get_kitchen,
+        get_estimates,
+        get_stations,
+        get_station_tickets,
+        get_station_events,
+        mark_ready
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_voided_items_with_their_status() {
+        let table = domain::Table {
+            id: 3,
+            version: 4,
+            paid_at: None,
+            items: vec![domain::Item {
+                id: 12,
+                name: String::from("pizza"),
+                time_to_prepare: 8,
+                status: domain::ItemStatus::Voided(domain::Void {
+                    reason: String::from("sent back"),
+                    voided_by: 9002,
+                    voided_at: 1700000000,
+                }),
+                modifiers: Vec::new(),
+                price: 1100,
+                components: Vec::new(),
+                course: 1,
+                held: false,
+                fired_at: Some(1700000000),
+                station_id: None,
+                ready_at: None,
+            }],
+        };
+        let table = Table::from(&table);
+        assert_eq!(table.status, TableStatus::Empty);
+        assert_eq!(table.order.items[0].status, ItemStatus::Voided);
+        assert_eq!(
+            serde_json::to_string(&table).unwrap(),
+            "{\"id\":3,\"version\":4,\"status\":\"empty\",\"ready\":false,\"order\":{\"items\":[{\"id\":12,\"name\":\"pizza\",\"preparation_minutes\":8,\"price\":1100,\"course\":1,\"status\":\"voided\",\"fired_at\":1700000000,\"void\":{\"reason\":\"sent back\",\"voided_by\":9002,\"voided_at\":1700000000}}]}}"
+        );
+    }
+}
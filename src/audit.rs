@@ -0,0 +1,147 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::data;
+use crate::database;
+
+/// A single mutation made by a member of staff, written to the append-only `audit_log` table.
+/// Changes to the menu are not on any table.
+pub struct Change {
+    pub restaurant_id: i32,
+    pub actor_id: i32,
+    pub action: &'static str,
+    pub table_id: Option<i32>,
+    pub item_id: Option<i32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Entries are only ever searched within one restaurant.
+pub struct Filter {
+    pub restaurant_id: i32,
+    pub table_id: Option<i32>,
+    pub actor_id: Option<i32>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+pub fn record(connection: &rusqlite::Connection, change: Change) -> Result<(), String> {
+    insert(connection, &change)?;
+    logged(&change);
+    Ok(())
+}
+
+/// Writes a change to the audit log without logging it, for changes recorded in a
+/// transaction that is not committed yet.
+pub fn insert(connection: &rusqlite::Connection, change: &Change) -> Result<(), String> {
+    let mut statement = database::build_statement(
+        connection,
+        "insert into audit_log
+            (id, actor_id, action, table_id, item_id, before, after, created_at, restaurant_id)
+            values (null, $1, $2, $3, $4, $5, $6, $7, $8);",
+    );
+    let results = statement.execute(rusqlite::params![
+        change.actor_id,
+        change.action,
+        change.table_id,
+        change.item_id,
+        change.before,
+        change.after,
+        now(),
+        change.restaurant_id,
+    ]);
+    match results {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to record audit entry: {}", e)),
+    }
+}
+
+/// Logs a change once it is in the audit log.
+pub fn logged(change: &Change) {
+    match change.table_id {
+        Some(table_id) => info!(
+            "Recorded {} on table {} by staff {}.",
+            change.action, table_id, change.actor_id
+        ),
+        None => info!("Recorded {} by staff {}.", change.action, change.actor_id),
+    }
+}
+
+pub fn search(
+    connection: &rusqlite::Connection,
+    filter: &Filter,
+) -> Result<Vec<data::AuditEntry>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select id, actor_id, action, table_id, item_id, before, after, created_at
+            from audit_log
+            where restaurant_id = $1
+            and ($2 is null or table_id = $2)
+            and ($3 is null or actor_id = $3)
+            and ($4 is null or created_at >= $4)
+            and ($5 is null or created_at <= $5)
+            order by id;",
+    );
+    let results = statement.query_map(
+        rusqlite::params![
+            filter.restaurant_id,
+            filter.table_id,
+            filter.actor_id,
+            filter.from,
+            filter.to
+        ],
+        |row| {
+            Ok(database::AuditData {
+                id: row.get(0)?,
+                actor_id: row.get(1)?,
+                action: row.get(2)?,
+                table_id: row.get(3)?,
+                item_id: row.get(4)?,
+                before: row.get(5)?,
+                after: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        },
+    );
+    match results {
+        Ok(rows) => {
+            let collection: rusqlite::Result<Vec<database::AuditData>> = rows.collect();
+            match collection {
+                Ok(entries) => Ok(entries.into_iter().map(entry).collect()),
+                Err(_) => Err("Could not collect audit entries.".into()),
+            }
+        }
+        Err(_) => Err("Failed to fetch audit entries.".into()),
+    }
+}
+
+/// An entry as it is sent, with its snapshots parsed back into JSON.
+pub fn entry(entry: database::AuditData) -> data::AuditEntry {
+    data::AuditEntry {
+        id: entry.id,
+        actor_id: entry.actor_id,
+        action: entry.action,
+        table_id: entry.table_id,
+        item_id: entry.item_id,
+        before: entry
+            .before
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        after: entry
+            .after
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        timestamp: entry.created_at,
+    }
+}
+
+/// Serializes the state of a row so it can be stored as the before or after of a change.
+pub fn snapshot<T: Serialize>(value: &T) -> Option<String> {
+    serde_json::to_string(value).ok()
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
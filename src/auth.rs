@@ -0,0 +1,305 @@
+use std::marker::PhantomData;
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::{Outcome, State};
+
+use crate::database;
+use crate::repository;
+
+pub const STAFF_HEADER: &str = "X-Staff-Id";
+pub const RESTAURANT_HEADER: &str = "X-Restaurant-Id";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Server,
+    Cook,
+    Manager,
+}
+
+pub const ROLES: [Role; 3] = [Role::Server, Role::Cook, Role::Manager];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    ViewTables,
+    AddItems,
+    RemoveItems,
+    RestoreItems,
+    ViewAudit,
+    ChangeAvailability,
+    AdjustBills,
+    ChangeItemStatus,
+    ManageInventory,
+    SettleBills,
+    VoidPaidItems,
+}
+
+impl Role {
+    pub fn from_name(name: &str) -> Option<Role> {
+        match name {
+            "server" => Some(Role::Server),
+            "cook" => Some(Role::Cook),
+            "manager" => Some(Role::Manager),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Server => "server",
+            Role::Cook => "cook",
+            Role::Manager => "manager",
+        }
+    }
+
+    /// Managers can do everything, everyone else only what their job needs. Servers void
+    /// items until the bill is settled, after that it takes a manager.
+    pub fn can(&self, action: Action) -> bool {
+        matches!(
+            (self, action),
+            (Role::Manager, _)
+                | (_, Action::ViewTables)
+                | (Role::Server, Action::AddItems)
+                | (Role::Server, Action::RemoveItems)
+                | (Role::Server, Action::SettleBills)
+                | (Role::Cook, Action::ChangeAvailability)
+                | (Role::Cook, Action::ChangeItemStatus)
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Staff {
+    pub id: i32,
+    pub name: String,
+    pub role: Role,
+    /// Everything the staff member sees or changes belongs to this restaurant.
+    pub restaurant_id: i32,
+}
+
+/// Looks up the staff member making the request from the `X-Staff-Id` header. An
+/// `X-Restaurant-Id` header is optional, when it is sent it has to be the staff member's
+/// own restaurant.
+impl<'a, 'r> FromRequest<'a, 'r> for Staff {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Staff, String> {
+        let staff_id = match request.headers().get_one(STAFF_HEADER) {
+            Some(id) => id,
+            None => return Outcome::Failure((Status::Unauthorized, "Missing staff id.".into())),
+        };
+        let staff_members = match request.guard::<State<repository::StaffMembers>>() {
+            Outcome::Success(staff_members) => staff_members,
+            _ => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    "No database configured.".into(),
+                ))
+            }
+        };
+        let staff = match staff_members.find(staff_id) {
+            Ok(Some(staff)) => staff,
+            Ok(None) => {
+                return Outcome::Failure((
+                    Status::Unauthorized,
+                    format!("Unknown staff id {}.", staff_id),
+                ))
+            }
+            Err(e) => return Outcome::Failure((Status::InternalServerError, e)),
+        };
+        match request.headers().get_one(RESTAURANT_HEADER) {
+            Some(restaurant) if restaurant != staff.restaurant_id.to_string() => {
+                warn!(
+                    "Staff {} of restaurant {} asked for restaurant {}.",
+                    staff.id, staff.restaurant_id, restaurant
+                );
+                Outcome::Failure((
+                    Status::Forbidden,
+                    format!(
+                        "Staff {} does not work at restaurant {}.",
+                        staff.id, restaurant
+                    ),
+                ))
+            }
+            _ => Outcome::Success(staff),
+        }
+    }
+}
+
+pub fn find_staff(
+    connection: &rusqlite::Connection,
+    staff_id: &str,
+) -> Result<Option<Staff>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select id, name, role, restaurant_id from staff where id = $1;",
+    );
+    let results = statement.query_map([&staff_id], |row| {
+        Ok(database::StaffData {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            role: row.get(2)?,
+            restaurant_id: row.get(3)?,
+        })
+    });
+    match results {
+        Ok(rows) => Ok(rows.filter_map(|row| row.ok()).find_map(|staff_data| {
+            Role::from_name(&staff_data.role).map(|role| Staff {
+                id: staff_data.id,
+                name: staff_data.name,
+                role,
+                restaurant_id: staff_data.restaurant_id,
+            })
+        })),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// Marker for an action a route requires, used as `Authorized<AddItems>`.
+pub trait Permission {
+    const ACTION: Action;
+}
+
+pub struct ViewTables;
+pub struct AddItems;
+pub struct RemoveItems;
+pub struct RestoreItems;
+pub struct ViewAudit;
+pub struct ChangeAvailability;
+pub struct AdjustBills;
+pub struct ChangeItemStatus;
+pub struct ManageInventory;
+pub struct SettleBills;
+
+impl Permission for ViewTables {
+    const ACTION: Action = Action::ViewTables;
+}
+
+impl Permission for AddItems {
+    const ACTION: Action = Action::AddItems;
+}
+
+impl Permission for RemoveItems {
+    const ACTION: Action = Action::RemoveItems;
+}
+
+impl Permission for RestoreItems {
+    const ACTION: Action = Action::RestoreItems;
+}
+
+impl Permission for ViewAudit {
+    const ACTION: Action = Action::ViewAudit;
+}
+
+impl Permission for ChangeAvailability {
+    const ACTION: Action = Action::ChangeAvailability;
+}
+
+impl Permission for AdjustBills {
+    const ACTION: Action = Action::AdjustBills;
+}
+
+impl Permission for ChangeItemStatus {
+    const ACTION: Action = Action::ChangeItemStatus;
+}
+
+impl Permission for ManageInventory {
+    const ACTION: Action = Action::ManageInventory;
+}
+
+impl Permission for SettleBills {
+    const ACTION: Action = Action::SettleBills;
+}
+
+/// Request guard that only succeeds when the caller's role allows `P`.
+pub struct Authorized<P: Permission> {
+    pub staff: Staff,
+    permission: PhantomData<P>,
+}
+
+impl<'a, 'r, P: Permission> FromRequest<'a, 'r> for Authorized<P> {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Authorized<P>, String> {
+        let staff = request.guard::<Staff>()?;
+        if staff.role.can(P::ACTION) {
+            Outcome::Success(Authorized {
+                staff,
+                permission: PhantomData,
+            })
+        } else {
+            warn!(
+                "Staff {} ({}) is not allowed to {:?}.",
+                staff.id,
+                staff.role.name(),
+                P::ACTION
+            );
+            Outcome::Failure((
+                Status::Forbidden,
+                format!("A {} cannot {:?}.", staff.role.name(), P::ACTION),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_roles() {
+        for role in [Role::Server, Role::Cook, Role::Manager] {
+            assert_eq!(Role::from_name(role.name()), Some(role));
+        }
+        assert_eq!(Role::from_name("owner"), None);
+    }
+
+    #[test]
+    fn should_allow_manager_everything() {
+        for action in [
+            Action::ViewTables,
+            Action::AddItems,
+            Action::RemoveItems,
+            Action::RestoreItems,
+            Action::ViewAudit,
+            Action::ChangeAvailability,
+            Action::AdjustBills,
+            Action::ChangeItemStatus,
+            Action::ManageInventory,
+            Action::SettleBills,
+            Action::VoidPaidItems,
+        ] {
+            assert!(Role::Manager.can(action));
+        }
+    }
+
+    #[test]
+    fn should_allow_server_to_add_remove_and_settle() {
+        assert!(Role::Server.can(Action::ViewTables));
+        assert!(Role::Server.can(Action::AddItems));
+        assert!(Role::Server.can(Action::RemoveItems));
+        assert!(!Role::Server.can(Action::RestoreItems));
+        assert!(!Role::Server.can(Action::ViewAudit));
+        assert!(!Role::Server.can(Action::ChangeAvailability));
+        assert!(!Role::Server.can(Action::AdjustBills));
+        assert!(!Role::Server.can(Action::ChangeItemStatus));
+        assert!(!Role::Server.can(Action::ManageInventory));
+        assert!(Role::Server.can(Action::SettleBills));
+        assert!(!Role::Server.can(Action::VoidPaidItems));
+    }
+
+    #[test]
+    fn should_only_allow_cook_to_view_change_availability_and_item_status() {
+        assert!(Role::Cook.can(Action::ViewTables));
+        assert!(!Role::Cook.can(Action::AddItems));
+        assert!(!Role::Cook.can(Action::RemoveItems));
+        assert!(!Role::Cook.can(Action::RestoreItems));
+        assert!(!Role::Cook.can(Action::ViewAudit));
+        assert!(Role::Cook.can(Action::ChangeAvailability));
+        assert!(!Role::Cook.can(Action::AdjustBills));
+        assert!(Role::Cook.can(Action::ChangeItemStatus));
+        assert!(!Role::Cook.can(Action::ManageInventory));
+        assert!(!Role::Cook.can(Action::SettleBills));
+        assert!(!Role::Cook.can(Action::VoidPaidItems));
+    }
+}
@@ -1,10 +1,28 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+/// A modifier chosen on an item or offered on the menu, the price delta is in cents.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Modifier {
+    pub id: i32,
+    pub name: String,
+    pub price_delta: i32,
+}
+
+/// Items ordered without modifiers look the same as they did before there were any, and
+/// so do items in the first course.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Item {
     pub id: i32,
     pub time_to_prepare: i32,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub course: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub held: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,13 +31,164 @@ pub struct Table {
     pub items: Vec<Item>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Restaurant {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModifierGroup {
+    pub id: i32,
+    pub name: String,
+    pub min_selected: i32,
+    pub max_selected: i32,
+    pub modifiers: Vec<Modifier>,
+}
+
+/// Something on the menu, the price is in cents. While a price rule takes something off,
+/// `regular_price` is what it costs otherwise. A bundle names the items it is made of.
+/// `allergens` are what it contains and `dietary` the diets it suits.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MenuItem {
+    pub id: i32,
+    pub name: String,
+    pub price: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regular_price: Option<i32>,
+    pub modifier_groups: Vec<ModifierGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<String>,
+    pub available: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allergens: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dietary: Vec<String>,
+}
+
+/// An ingredient and how much of it is left, `low` once it is down to `low_stock`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Ingredient {
+    pub id: i32,
+    pub name: String,
+    pub stock: i32,
+    pub low_stock: i32,
+    pub low: bool,
+}
+
+/// A new ingredient with the stock there is of it to start with.
+#[derive(Serialize, Deserialize)]
+pub struct IngredientRequest {
+    pub name: String,
+    pub stock: i32,
+    pub low_stock: i32,
+}
+
+/// How much of an ingredient was delivered, it is added to the stock.
+#[derive(Serialize, Deserialize)]
+pub struct RestockRequest {
+    pub quantity: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LowStockRequest {
+    pub low_stock: i32,
+}
+
+/// What making one of a menu item uses, how much of each ingredient.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recipe {
+    pub lines: Vec<RecipeLine>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecipeLine {
+    pub ingredient_id: i32,
+    pub quantity: i32,
+}
+
+/// When a category can be ordered from, on the days named from `starts` to `ends` as
+/// hours and minutes in the restaurant's time.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Schedule {
+    pub days: Vec<String>,
+    pub starts: String,
+    pub ends: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MenuCategory {
+    pub id: i32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<Schedule>,
+    pub items: Vec<MenuItem>,
+}
+
+/// An item in an order, either just its name or its name with the modifiers chosen and
+/// the course it is in, the first course when none is given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ItemOrder {
+    Name(String),
+    WithModifiers {
+        name: String,
+        #[serde(default)]
+        modifiers: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        course: Option<i32>,
+    },
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ItemRequest {
     pub table_id: String,
-    pub items: Vec<String>,
+    pub items: Vec<ItemOrder>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct StatusMessage {
     pub message: String,
 }
+
+/// Marks a menu item as run out or back, `available_at` is when an item that ran out is
+/// expected back as unix seconds.
+#[derive(Serialize, Deserialize)]
+pub struct AvailabilityRequest {
+    pub available: bool,
+    #[serde(default)]
+    pub available_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Void {
+    pub reason: String,
+    pub voided_by: i32,
+    pub voided_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor_id: i32,
+    pub action: String,
+    pub table_id: Option<i32>,
+    pub item_id: Option<i32>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ComponentHealth {
+    pub status: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HealthReport {
+    pub status: String,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
@@ -1,3 +1,4 @@
+use rocket::Config;
 use rusqlite::Result;
 
 #[derive(Debug)]
@@ -5,16 +6,328 @@ pub struct ItemData {
     pub id: i32,
     pub time_to_prepare: i32,
     pub name: String,
+    pub price: i32,
+    pub course: i32,
+    pub held: bool,
+    pub fired_at: Option<i64>,
+    pub station_id: Option<i32>,
+    pub ready_at: Option<i64>,
 }
 
 #[derive(Debug)]
 pub struct TableData {
     pub id: i32,
     pub item_ids: String,
+    pub version: i64,
+    pub paid_at: Option<i64>,
 }
 
-pub fn setup_db() -> Result<String, String> {
-    let db_connection = match rusqlite::Connection::open("data.sqlite") {
+#[derive(Debug)]
+pub struct VoidedItemData {
+    pub item: ItemData,
+    pub reason: String,
+    pub voided_by: i32,
+    pub voided_at: i64,
+}
+
+#[derive(Debug)]
+pub struct ModifierData {
+    pub id: i32,
+    pub name: String,
+    pub price_delta: i32,
+}
+
+#[derive(Debug)]
+pub struct StaffData {
+    pub id: i32,
+    pub name: String,
+    pub role: String,
+    pub restaurant_id: i32,
+}
+
+#[derive(Debug)]
+pub struct RestaurantData {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct AuditData {
+    pub id: i64,
+    pub actor_id: i32,
+    pub action: String,
+    pub table_id: Option<i32>,
+    pub item_id: Option<i32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub created_at: i64,
+}
+
+pub const DEFAULT_PATH: &str = "data.sqlite";
+
+/// The sqlite file everything is kept in, set with `database` in Rocket.toml.
+#[derive(Debug, Clone)]
+pub struct Database {
+    path: String,
+}
+
+impl Database {
+    pub fn new(path: &str) -> Database {
+        Database {
+            path: path.to_string(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn connect(&self) -> rusqlite::Connection {
+        rusqlite::Connection::open(&self.path).expect("Failed to get db connection.")
+    }
+
+    /// Opens the database without creating it, so a missing file is reported instead of hidden.
+    pub fn open_existing(&self) -> Result<rusqlite::Connection, String> {
+        rusqlite::Connection::open_with_flags(
+            &self.path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE,
+        )
+        .map_err(|e| format!("Cannot connect to database: {}", e))
+    }
+}
+
+/// Creates and migrates the database set with `database`, like postgres the server does not
+/// start when this fails.
+pub fn open(config: &Config) -> Result<Database, String> {
+    let path = config.get_str("database").unwrap_or(DEFAULT_PATH);
+    let database = Database::new(path);
+    let message =
+        setup_db(&database).map_err(|e| format!("Failed to set up database {}: {}", path, e))?;
+    info!("{} Using {}.", message, path);
+    Ok(database)
+}
+
+/// Schema changes applied in order after the tables are created, tracked with `user_version`.
+/// The postgres repository has its own copy of each one in `repository::postgres`.
+pub const MIGRATIONS: &[&str] = &[
+    "alter table restaurant_table add column version integer not null default 1;",
+    // Everything that was there before belongs to the first restaurant.
+    "create table if not exists restaurant (
+        id integer primary key,
+        name varchar(64) not null
+    );
+    insert into restaurant (id, name) values (1, 'Restaurant');
+    alter table restaurant_table add column restaurant_id integer not null default 1;
+    alter table item add column restaurant_id integer not null default 1;
+    alter table staff add column restaurant_id integer not null default 1;
+    alter table audit_log add column restaurant_id integer not null default 1;",
+    // Items are ordered by name, so a name is only on a restaurant's menu once. Ordered
+    // items keep the modifiers chosen for them as they were at the time.
+    "create table if not exists menu_category (
+        id integer primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null
+    );
+    create table if not exists menu_item (
+        id integer primary key,
+        restaurant_id integer not null,
+        category_id integer not null,
+        name varchar(64) not null,
+        price integer not null
+    );
+    create unique index if not exists menu_item_name on menu_item (restaurant_id, name);
+    create table if not exists modifier_group (
+        id integer primary key,
+        menu_item_id integer not null,
+        name varchar(64) not null,
+        min_selected integer not null default 0,
+        max_selected integer not null default 1
+    );
+    create table if not exists modifier (
+        id integer primary key,
+        group_id integer not null,
+        name varchar(64) not null,
+        price_delta integer not null default 0
+    );
+    create table if not exists item_modifier (
+        item_id integer not null,
+        position integer not null,
+        modifier_id integer not null,
+        name varchar(64) not null,
+        price_delta integer not null
+    );",
+    // A menu item with components is a bundle. The components ordered for a bundle point
+    // at it with bundle_item_id and are not put on the table themselves.
+    "create table if not exists bundle_component (
+        bundle_id integer not null,
+        position integer not null,
+        menu_item_id integer not null
+    );
+    alter table item add column price integer not null default 0;
+    alter table item add column bundle_item_id integer;",
+    // Ingredients are kept with the menu. A recipe says how much of each ingredient one
+    // of a menu item uses.
+    "create table if not exists ingredient (
+        id integer primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null,
+        stock integer not null default 0,
+        low_stock integer not null default 0
+    );
+    create table if not exists recipe (
+        menu_item_id integer not null,
+        ingredient_id integer not null,
+        quantity integer not null
+    );",
+    // A menu item that has run out is not available, until available_at when that is set.
+    "alter table menu_item add column available integer not null default 1;
+    alter table menu_item add column available_at bigint;",
+    // A tag is an allergen the menu item contains or a diet it suits. Allergies are the
+    // ones guests declared at a table, for one seat or for everyone at it when seat is null.
+    "create table if not exists menu_item_tag (
+        menu_item_id integer not null,
+        kind varchar(16) not null,
+        tag varchar(64) not null
+    );
+    create table if not exists table_allergy (
+        restaurant_id integer not null,
+        table_id integer not null,
+        seat integer,
+        allergen varchar(64) not null
+    );",
+    // Schedules and price rules are in the restaurant's time, utc_offset seconds ahead of
+    // UTC. Their days have a bit for each day they are on, 1 for Monday up to 64 for
+    // Sunday, and their times are minutes after midnight. A category with schedules can
+    // only be ordered from while one is open.
+    "alter table restaurant add column utc_offset integer not null default 0;
+    create table if not exists menu_schedule (
+        category_id integer not null,
+        days integer not null default 127,
+        starts integer not null,
+        ends integer not null
+    );
+    create table if not exists price_rule (
+        id integer primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null,
+        category_id integer,
+        menu_item_id integer,
+        days integer not null default 127,
+        starts integer not null default 0,
+        ends integer not null default 1440,
+        valid_from bigint,
+        valid_until bigint,
+        percent_off integer not null
+    );",
+    // Adjustments take something off a table's bill, for one ordered item when item_id is
+    // set, the items ordered with item_name when that is and the whole bill otherwise.
+    // value is the percentage or the amount in cents taken off, buy and get are for buy x
+    // get y. Promo codes are set up in the database and each redeemed one is an adjustment.
+    "create table if not exists promo_code (
+        id integer primary key,
+        restaurant_id integer not null,
+        code varchar(32) not null,
+        item_name varchar(64),
+        kind varchar(16) not null,
+        value integer,
+        buy integer,
+        get integer,
+        max_uses integer,
+        uses integer not null default 0,
+        expires_at bigint
+    );
+    create unique index if not exists promo_code_code on promo_code (restaurant_id, code);
+    create table if not exists adjustment (
+        id integer primary key,
+        restaurant_id integer not null,
+        table_id integer not null,
+        item_id integer,
+        item_name varchar(64),
+        kind varchar(16) not null,
+        value integer,
+        buy integer,
+        get integer,
+        reason text not null,
+        code varchar(32),
+        added_by integer not null,
+        added_at bigint not null
+    );",
+    // Items are ordered in courses. A held item waits for its course to be fired, fired_at
+    // is when it went to the kitchen.
+    "alter table item add column course integer not null default 1;
+    alter table item add column held integer not null default 0;
+    alter table item add column fired_at bigint;",
+    // Items are made at stations, a menu item without a station of its own is made at its
+    // category's. Ordered items keep the station they were sent to and when it had them
+    // ready, and each station has a log of what happened to its tickets for its stream.
+    "create table if not exists station (
+        id integer primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null
+    );
+    alter table menu_category add column station_id integer;
+    alter table menu_item add column station_id integer;
+    alter table item add column station_id integer;
+    alter table item add column ready_at bigint;
+    create table if not exists kitchen_event (
+        id integer primary key,
+        restaurant_id integer not null,
+        station_id integer,
+        table_id integer not null,
+        item_id integer not null,
+        name varchar(64) not null,
+        kind varchar(16) not null,
+        at bigint not null
+    );",
+    // Changes to the menu are not on any table. Sqlite can't drop a not null constraint, so
+    // the audit log is copied into a table without it.
+    "create table audit_log_new (
+        id integer primary key,
+        actor_id integer not null,
+        action varchar(32) not null,
+        table_id integer,
+        item_id integer,
+        before text,
+        after text,
+        created_at integer not null,
+        restaurant_id integer not null default 1
+    );
+    insert into audit_log_new
+        (id, actor_id, action, table_id, item_id, before, after, created_at, restaurant_id)
+        select id, actor_id, action, table_id, item_id, before, after, created_at, restaurant_id
+        from audit_log;
+    drop table audit_log;
+    alter table audit_log_new rename to audit_log;
+    create trigger audit_log_no_update before update on audit_log
+    begin
+        select raise(abort, 'The audit log is append-only.');
+    end;
+    create trigger audit_log_no_delete before delete on audit_log
+    begin
+        select raise(abort, 'The audit log is append-only.');
+    end;",
+    // A promo code is only redeemed once for a table, even when two redeem it at once.
+    "create unique index if not exists adjustment_table_code on adjustment (table_id, code);",
+    // A table's bill is settled once it has been paid, at paid_at.
+    "alter table restaurant_table add column paid_at bigint;",
+    // Ordered items keep what their recipe used from stock, so voiding and restoring them
+    // gives back and takes the same even after the recipe changed. Items ordered before
+    // this are taken to have used what their recipe uses now.
+    "create table if not exists item_ingredient (
+        item_id integer not null,
+        ingredient_id integer not null,
+        quantity integer not null
+    );
+    insert into item_ingredient (item_id, ingredient_id, quantity)
+        select item.id, recipe.ingredient_id, recipe.quantity
+        from item
+        join menu_item on menu_item.name = item.name
+            and menu_item.restaurant_id = item.restaurant_id
+        join recipe on recipe.menu_item_id = menu_item.id;",
+];
+
+pub fn setup_db(database: &Database) -> Result<String, String> {
+    let mut db_connection = match rusqlite::Connection::open(database.path()) {
         Ok(connection) => connection,
         Err(_) => {
             return Err("Cannot connect to database.".into());
@@ -29,52 +342,433 @@ pub fn setup_db() -> Result<String, String> {
             create table if not exists restaurant_table (
                 id integer primary key,
                 items varchar(64) not null
-            );",
+            );
+            create table if not exists staff (
+                id integer primary key,
+                name varchar(64) not null,
+                role varchar(16) not null
+            );
+            create table if not exists voided_item (
+                item_id integer primary key,
+                table_id integer not null,
+                reason text not null,
+                voided_by integer not null,
+                voided_at integer not null
+            );
+            create table if not exists idempotency_key (
+                key varchar(255) primary key,
+                request text not null,
+                response text,
+                created_at integer not null
+            );
+            create table if not exists audit_log (
+                id integer primary key,
+                actor_id integer not null,
+                action varchar(32) not null,
+                table_id integer not null,
+                item_id integer,
+                before text,
+                after text,
+                created_at integer not null
+            );
+            create trigger if not exists audit_log_no_update before update on audit_log
+            begin
+                select raise(abort, 'The audit log is append-only.');
+            end;
+            create trigger if not exists audit_log_no_delete before delete on audit_log
+            begin
+                select raise(abort, 'The audit log is append-only.');
+            end;",
     ) {
-        Ok(_) => Ok("Successfully created database tables.".into()),
+        Ok(_) => {}
         Err(_) => return Err("Could not run create table sql".into()),
     }
+    match migrate(&mut db_connection) {
+        Ok(_) => Ok("Successfully created database tables.".into()),
+        Err(e) => Err(e),
+    }
 }
 
-pub fn get_connection() -> rusqlite::Connection {
-    rusqlite::Connection::open("data.sqlite").expect("Failed to get db connection.")
+fn migrate(connection: &mut rusqlite::Connection) -> Result<(), String> {
+    let transaction = connection
+        .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+        .map_err(|e| format!("Could not start migration: {}", e))?;
+    let version = schema_version(&transaction)?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Running migration {}.", index + 1);
+        transaction
+            .execute_batch(migration)
+            .map_err(|e| format!("Migration {} failed: {}", index + 1, e))?;
+        transaction
+            .execute_batch(&format!("pragma user_version = {};", index + 1))
+            .map_err(|e| format!("Could not update schema version: {}", e))?;
+    }
+    transaction
+        .commit()
+        .map_err(|e| format!("Could not commit migrations: {}", e))
 }
 
-pub fn build_statement<'a>(connection: &'a rusqlite::Connection, statement:&str) -> rusqlite::Statement<'a> {
-    connection.prepare(statement).expect("Failed to prepare query.")
+/// The `user_version` a fully migrated database has.
+pub fn current_schema_version() -> i64 {
+    MIGRATIONS.len() as i64
 }
 
-pub fn setup_test_db(statement: &str) {
-    setup_db().expect("Set up database.");
-    add_test_items(statement);
+pub fn schema_version(connection: &rusqlite::Connection) -> Result<i64, String> {
+    connection
+        .query_row("pragma user_version;", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| format!("Could not read schema version: {}", e))
 }
 
-pub fn close_test_db(statement: &str) {
-    delete_test_items(statement);
+pub fn find_table(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: &str,
+) -> Result<Option<TableData>, String> {
+    let mut statement = build_statement(
+        connection,
+        "select id, items, version, paid_at from restaurant_table
+            where id = $1 and restaurant_id = $2;",
+    );
+    let results = statement.query_map(rusqlite::params![table_id, restaurant_id], |row| {
+        Ok(TableData {
+            id: row.get(0)?,
+            item_ids: row.get(1)?,
+            version: row.get(2)?,
+            paid_at: row.get(3)?,
+        })
+    });
+    match results {
+        Ok(mut rows) => match rows.next() {
+            Some(Ok(table_data)) => Ok(Some(table_data)),
+            Some(Err(e)) => Err(format!("Failed with error: {}", e)),
+            None => Ok(None),
+        },
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
 }
 
-fn add_test_items(statement: &str) {
-    let db_connection = rusqlite::Connection::open("data.sqlite").expect("Failed to get db.");
-    match db_connection.execute_batch(statement) {
-        Ok(_) => {
-            println!("Inserted test values")
-        }
-        Err(e) => {
-            println!("Failed to inserted test values {}", e)
-        }
-    };
-    println!("setup test db so we can test now");
+pub fn find_tables(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Vec<TableData>, String> {
+    let mut statement = build_statement(
+        connection,
+        "select id, items, version, paid_at from restaurant_table where restaurant_id = $1;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok(TableData {
+            id: row.get(0)?,
+            item_ids: row.get(1)?,
+            version: row.get(2)?,
+            paid_at: row.get(3)?,
+        })
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<Result<Vec<TableData>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
 }
 
-fn delete_test_items(statement: &str) {
-    let db_connection = rusqlite::Connection::open("data.sqlite").expect("Failed to get db.");
-    match db_connection.execute_batch(statement) {
-        Ok(_) => {
-            println!("Deleted test values")
-        }
-        Err(e) => {
-            println!("Failed to delete test values {}", e)
+/// Finds an item of the restaurant that has not been voided.
+pub fn find_ordered_item(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    item_id: &str,
+) -> Result<Option<ItemData>, String> {
+    let mut statement = build_statement(
+        connection,
+        "select id, name, preperation_time, price, course, held, fired_at, station_id, ready_at
+            from item
+            where id = $1 and restaurant_id = $2
+            and id not in (select item_id from voided_item);",
+    );
+    let results = statement.query_map(rusqlite::params![item_id, restaurant_id], |row| {
+        Ok(ItemData {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            time_to_prepare: row.get(2)?,
+            price: row.get(3)?,
+            course: row.get(4)?,
+            held: row.get(5)?,
+            fired_at: row.get(6)?,
+            station_id: row.get(7)?,
+            ready_at: row.get(8)?,
+        })
+    });
+    match results {
+        Ok(mut rows) => match rows.next() {
+            Some(Ok(item_data)) => Ok(Some(item_data)),
+            Some(Err(e)) => Err(format!("Failed with error: {}", e)),
+            None => Ok(None),
+        },
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// The components ordered for a bundle, in the order they are listed on the menu.
+pub fn find_components(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    bundle_item_id: i32,
+) -> Result<Vec<ItemData>, String> {
+    let mut statement = build_statement(
+        connection,
+        "select id, name, preperation_time, price, course, held, fired_at, station_id, ready_at
+            from item
+            where bundle_item_id = $1 and restaurant_id = $2 order by id;",
+    );
+    let results = statement.query_map([&bundle_item_id, &restaurant_id], |row| {
+        Ok(ItemData {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            time_to_prepare: row.get(2)?,
+            price: row.get(3)?,
+            course: row.get(4)?,
+            held: row.get(5)?,
+            fired_at: row.get(6)?,
+            station_id: row.get(7)?,
+            ready_at: row.get(8)?,
+        })
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<Result<Vec<ItemData>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// The modifiers chosen for an ordered item, in the order they were asked for.
+pub fn find_item_modifiers(
+    connection: &rusqlite::Connection,
+    item_id: i32,
+) -> Result<Vec<ModifierData>, String> {
+    let mut statement = build_statement(
+        connection,
+        "select modifier_id, name, price_delta from item_modifier
+            where item_id = $1 order by position;",
+    );
+    let results = statement.query_map([&item_id], |row| {
+        Ok(ModifierData {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            price_delta: row.get(2)?,
+        })
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<Result<Vec<ModifierData>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+pub fn find_voided_items(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: i32,
+) -> Result<Vec<VoidedItemData>, String> {
+    let mut statement = build_statement(
+        connection,
+        "select item.id, item.name, item.preperation_time, item.price,
+                item.course, item.held, item.fired_at, item.station_id, item.ready_at,
+                voided_item.reason, voided_item.voided_by, voided_item.voided_at
+            from voided_item join item on item.id = voided_item.item_id
+            where voided_item.table_id = $1 and item.restaurant_id = $2
+            order by voided_item.voided_at, item.id;",
+    );
+    let results = statement.query_map([&table_id, &restaurant_id], |row| {
+        Ok(VoidedItemData {
+            item: ItemData {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                time_to_prepare: row.get(2)?,
+                price: row.get(3)?,
+                course: row.get(4)?,
+                held: row.get(5)?,
+                fired_at: row.get(6)?,
+                station_id: row.get(7)?,
+                ready_at: row.get(8)?,
+            },
+            reason: row.get(9)?,
+            voided_by: row.get(10)?,
+            voided_at: row.get(11)?,
+        })
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<Result<Vec<VoidedItemData>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// Writes a table's items only if nobody else changed it since `version` was read,
+/// returning how many rows were updated.
+pub fn update_table_items(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: &str,
+    item_ids: &str,
+    version: i64,
+) -> Result<usize, String> {
+    let mut statement = build_statement(
+        connection,
+        "update restaurant_table set items = $1, version = version + 1
+            where id = $2 and version = $3 and restaurant_id = $4;",
+    );
+    match statement.execute(rusqlite::params![
+        item_ids,
+        table_id,
+        version,
+        restaurant_id
+    ]) {
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+pub fn find_restaurant(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Option<RestaurantData>, String> {
+    let mut statement =
+        build_statement(connection, "select id, name from restaurant where id = $1;");
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok(RestaurantData {
+            id: row.get(0)?,
+            name: row.get(1)?,
+        })
+    });
+    match results {
+        Ok(mut rows) => match rows.next() {
+            Some(Ok(restaurant_data)) => Ok(Some(restaurant_data)),
+            Some(Err(e)) => Err(format!("Failed with error: {}", e)),
+            None => Ok(None),
+        },
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// Splits the comma separated `items` column of `restaurant_table` into item ids.
+pub fn split_item_ids(item_ids: &str) -> Vec<String> {
+    item_ids
+        .split(",")
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .collect()
+}
+
+pub fn join_item_ids(item_ids: &[String]) -> String {
+    item_ids.join(",")
+}
+
+pub fn append_item_ids(item_ids: &str, new_item_ids: &str) -> String {
+    let mut ids = split_item_ids(item_ids);
+    ids.extend(split_item_ids(new_item_ids));
+    join_item_ids(&ids)
+}
+
+/// Removes `item_id` from a table's items, or returns `None` when it is not on the table.
+pub fn remove_item_id(item_ids: &str, item_id: &str) -> Option<String> {
+    let mut ids = split_item_ids(item_ids);
+    let position = ids.iter().position(|id| id == item_id)?;
+    ids.remove(position);
+    Some(join_item_ids(&ids))
+}
+
+pub fn build_statement<'a>(
+    connection: &'a rusqlite::Connection,
+    statement: &str,
+) -> rusqlite::Statement<'a> {
+    connection
+        .prepare(statement)
+        .expect("Failed to prepare query.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const TABLES: usize = 3;
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Add(usize, u16),
+        Remove(usize, u16),
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            (0..TABLES, 1..40u16).prop_map(|(table, item)| Operation::Add(table, item)),
+            (0..TABLES, 1..40u16).prop_map(|(table, item)| Operation::Remove(table, item)),
+        ]
+    }
+
+    #[test]
+    fn should_keep_separators_when_removing() {
+        assert_eq!(
+            remove_item_id("1007,1008,1009", "1008"),
+            Some(String::from("1007,1009"))
+        );
+        assert_eq!(
+            remove_item_id("1007,1008,1009", "1007"),
+            Some(String::from("1008,1009"))
+        );
+        assert_eq!(remove_item_id("1007", "1007"), Some(String::new()));
+    }
+
+    #[test]
+    fn should_not_remove_missing_item() {
+        assert_eq!(remove_item_id("1007,1008", "100"), None);
+        assert_eq!(remove_item_id("", "1007"), None);
+    }
+
+    #[test]
+    fn should_append_to_empty_table() {
+        assert_eq!(append_item_ids("", "1007"), "1007");
+        assert_eq!(append_item_ids("1007", "1008,1009"), "1007,1008,1009");
+    }
+
+    proptest! {
+        #[test]
+        fn should_match_model_for_any_adds_and_removes(
+            operations in prop::collection::vec(operation(), 0..100)
+        ) {
+            let mut tables = vec![String::new(); TABLES];
+            let mut model: Vec<Vec<String>> = vec![Vec::new(); TABLES];
+            for operation in operations {
+                match operation {
+                    Operation::Add(table, item) => {
+                        let item = item.to_string();
+                        // An item row only ever belongs to one table.
+                        if model.iter().any(|items| items.contains(&item)) {
+                            continue;
+                        }
+                        tables[table] = append_item_ids(&tables[table], &item);
+                        model[table].push(item);
+                    }
+                    Operation::Remove(table, item) => {
+                        let item = item.to_string();
+                        let removed = remove_item_id(&tables[table], &item);
+                        match model[table].iter().position(|id| id == &item) {
+                            Some(position) => {
+                                model[table].remove(position);
+                                prop_assert_eq!(&removed, &Some(join_item_ids(&model[table])));
+                                tables[table] = removed.unwrap();
+                            }
+                            None => prop_assert_eq!(removed, None),
+                        }
+                    }
+                }
+                for (table, items) in model.iter().enumerate() {
+                    prop_assert_eq!(&split_item_ids(&tables[table]), items);
+                }
+            }
         }
-    };
-    println!("Deleted test items from db.");
+    }
 }
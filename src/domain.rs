@@ -0,0 +1,519 @@
+/// Why, when and by whom an item was taken off a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Void {
+    pub reason: String,
+    pub voided_by: i32,
+    pub voided_at: i64,
+}
+
+/// What has happened to an item since it was ordered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemStatus {
+    Ordered,
+    Voided(Void),
+}
+
+/// A choice on a menu item, the price delta is in cents and is added to the item's price.
+/// Ordered items keep a copy of the ones chosen for them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modifier {
+    pub id: i32,
+    pub name: String,
+    pub price_delta: i32,
+}
+
+/// An ordered item, the price is what it cost in cents when it was ordered. A bundle is
+/// one item on the table and the bill, its components are what the kitchen makes for it
+/// and share its status and course. A held item waits for its course to be fired before
+/// the kitchen sees it, `fired_at` is when the kitchen got it and is not known for items
+/// ordered before courses were kept. The station is the one of the menu item when it was
+/// ordered, `ready_at` is when the station had it ready.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub id: i32,
+    pub name: String,
+    pub time_to_prepare: i32,
+    pub status: ItemStatus,
+    pub modifiers: Vec<Modifier>,
+    pub price: i32,
+    pub components: Vec<Item>,
+    pub course: i32,
+    pub held: bool,
+    pub fired_at: Option<i64>,
+    pub station_id: Option<i32>,
+    pub ready_at: Option<i64>,
+}
+
+impl Item {
+    /// What the kitchen makes for the item, the components of a bundle or the item itself.
+    pub fn kitchen_items(&self) -> Vec<&Item> {
+        if self.components.is_empty() {
+            vec![self]
+        } else {
+            self.components.iter().collect()
+        }
+    }
+}
+
+/// An item about to be ordered, `bundle_id` is the ordered bundle it is a component of.
+/// `recipe` is what its menu item's own recipe uses at the time it is ordered, a bundle's
+/// components keep their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewItem {
+    pub name: String,
+    pub time_to_prepare: i32,
+    pub price: i32,
+    pub modifiers: Vec<Modifier>,
+    pub bundle_id: Option<i32>,
+    pub course: i32,
+    pub held: bool,
+    pub fired_at: Option<i64>,
+    pub station_id: Option<i32>,
+    pub recipe: Vec<RecipeLine>,
+}
+
+/// Something for the kitchen to make, with the bundle it was ordered in.
+#[derive(Debug, PartialEq)]
+pub struct Ticket<'a> {
+    pub table_id: i32,
+    pub item: &'a Item,
+    pub bundle: Option<&'a Item>,
+}
+
+/// A table with everything ordered on it, the API versions each map this to their own json.
+/// `paid_at` is when its bill was settled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub id: i32,
+    pub version: i64,
+    pub paid_at: Option<i64>,
+    pub items: Vec<Item>,
+}
+
+impl Table {
+    /// Items still on the table, in the order they were added.
+    pub fn ordered_items(&self) -> impl Iterator<Item = &Item> {
+        self.items
+            .iter()
+            .filter(|item| item.status == ItemStatus::Ordered)
+    }
+
+    /// Finds an item still on the table by the id given in a request.
+    pub fn ordered_item(&self, item_id: &str) -> Option<&Item> {
+        self.ordered_items()
+            .find(|item| item.id.to_string() == item_id)
+    }
+
+    /// What the kitchen has to make for the table, bundles are split into their components.
+    /// Held items are left out until their course is fired.
+    pub fn tickets(&self) -> Vec<Ticket> {
+        let mut tickets = Vec::new();
+        for item in self.ordered_items().filter(|item| !item.held) {
+            for kitchen_item in item.kitchen_items() {
+                tickets.push(Ticket {
+                    table_id: self.id,
+                    item: kitchen_item,
+                    bundle: Some(item).filter(|_| !item.components.is_empty()),
+                });
+            }
+        }
+        tickets
+    }
+
+    /// Whether every station has everything the kitchen has been sent for the table ready,
+    /// a table the kitchen has nothing for is not.
+    pub fn is_ready(&self) -> bool {
+        let tickets = self.tickets();
+        !tickets.is_empty() && tickets.iter().all(|ticket| ticket.item.ready_at.is_some())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Restaurant {
+    pub id: i32,
+    pub name: String,
+}
+
+/// Where in the kitchen items are made, like the grill or the bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Station {
+    pub id: i32,
+    pub name: String,
+}
+
+/// Something that happened to a ticket at a station, `kind` is one of `kitchen::KINDS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KitchenEvent {
+    pub id: i64,
+    pub kind: String,
+    pub station_id: Option<i32>,
+    pub table_id: i32,
+    pub item_id: i32,
+    pub name: String,
+    pub at: i64,
+}
+
+/// One ticket the kitchen was sent, estimated to take `time_to_prepare` minutes. It has no
+/// `ready_at` while the kitchen is still making it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preparation {
+    pub name: String,
+    pub station_id: Option<i32>,
+    pub time_to_prepare: i32,
+    pub fired_at: i64,
+    pub ready_at: Option<i64>,
+}
+
+impl Preparation {
+    /// How long it actually took to the nearest minute, at least one.
+    pub fn minutes(&self) -> Option<i32> {
+        self.ready_at
+            .map(|ready_at| ((ready_at - self.fired_at + 30) / 60).max(1) as i32)
+    }
+}
+
+/// How long a menu item's latest preparations were estimated to take and took, in minutes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrepStats {
+    pub name: String,
+    pub samples: i32,
+    pub estimated_minutes: i32,
+    pub median_minutes: i32,
+    pub p90_minutes: i32,
+}
+
+/// How much an adjustment takes off the lines of a bill it is for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discount {
+    /// A percentage off each line.
+    Percentage(i32),
+    /// An amount in cents off the lines together.
+    Amount(i32),
+    /// Of every `buy` plus `get` lines, the `get` cheapest are free.
+    BuyGet { buy: i32, get: i32 },
+    /// The lines are on the house.
+    Comp,
+}
+
+impl Discount {
+    pub const KINDS: [&'static str; 4] = ["percentage", "amount", "buy_get", "comp"];
+
+    /// A discount from the columns it is kept in, `value` is the percentage or the amount
+    /// in cents. Fails with why the columns are not a valid discount.
+    pub fn from_parts(
+        kind: &str,
+        value: Option<i32>,
+        buy: Option<i32>,
+        get: Option<i32>,
+    ) -> Result<Discount, String> {
+        match (kind, value, buy, get) {
+            ("percentage", Some(percent), _, _) if percent > 0 && percent <= 100 => {
+                Ok(Discount::Percentage(percent))
+            }
+            ("percentage", _, _, _) => Err(String::from("A percentage is from 1 to 100.")),
+            ("amount", Some(cents), _, _) if cents > 0 => Ok(Discount::Amount(cents)),
+            ("amount", _, _, _) => Err(String::from("An amount is at least 1 cent.")),
+            ("buy_get", _, Some(buy), Some(get)) if buy > 0 && get > 0 => {
+                Ok(Discount::BuyGet { buy, get })
+            }
+            ("buy_get", _, _, _) => Err(String::from("Buy and get are both at least 1.")),
+            ("comp", _, _, _) => Ok(Discount::Comp),
+            _ => Err(format!(
+                "Unknown discount {}, use one of {}.",
+                kind,
+                Discount::KINDS.join(", ")
+            )),
+        }
+    }
+
+    /// The kind of the discount with its value, buy and get, as they are kept.
+    pub fn parts(&self) -> (&'static str, Option<i32>, Option<i32>, Option<i32>) {
+        match self {
+            Discount::Percentage(percent) => ("percentage", Some(*percent), None, None),
+            Discount::Amount(cents) => ("amount", Some(*cents), None, None),
+            Discount::BuyGet { buy, get } => ("buy_get", None, Some(*buy), Some(*get)),
+            Discount::Comp => ("comp", None, None, None),
+        }
+    }
+}
+
+/// The lines of a bill an adjustment is for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Bill,
+    Item(i32),
+    /// Every item ordered with this name.
+    Named(String),
+}
+
+/// Something taken off a table's bill, a comp or discount a manager gave with the reason
+/// for it or a promo code redeemed for the table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adjustment {
+    pub id: i32,
+    pub target: Target,
+    pub discount: Discount,
+    pub reason: String,
+    pub code: Option<String>,
+    pub added_by: i32,
+    pub added_at: i64,
+}
+
+/// A code guests can give for a discount. It can be redeemed `max_uses` times when that is
+/// set and until `expires_at`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromoCode {
+    pub id: i32,
+    pub code: String,
+    pub target: Target,
+    pub discount: Discount,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<i64>,
+}
+
+/// What came of redeeming a promo code for a table. Nothing is kept unless it was redeemed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Redemption {
+    Redeemed(i32),
+    /// The code expired or was used up by the time its use was counted.
+    UsedUp,
+    /// The code was put on the table's bill by someone else first.
+    AlreadyOnBill,
+}
+
+/// Modifiers of a menu item to pick from, an order has to pick between `min_selected` and
+/// `max_selected` of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModifierGroup {
+    pub id: i32,
+    pub name: String,
+    pub min_selected: i32,
+    pub max_selected: i32,
+    pub modifiers: Vec<Modifier>,
+}
+
+/// How much of an ingredient making one of a menu item uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecipeLine {
+    pub ingredient_id: i32,
+    pub quantity: i32,
+}
+
+/// Whether a menu item can be ordered. An item that has run out can have the time it is
+/// expected back, as unix seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Availability {
+    Available,
+    Unavailable { until: Option<i64> },
+}
+
+impl Availability {
+    /// The availability at `now` of an item marked as `available` or not, an item is
+    /// available again once `available_at` has passed.
+    pub fn at(available: bool, available_at: Option<i64>, now: i64) -> Availability {
+        match available_at {
+            _ if available => Availability::Available,
+            Some(until) if until <= now => Availability::Available,
+            until => Availability::Unavailable { until },
+        }
+    }
+}
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// A window of time every week, on the `days` it is on from `starts` to `ends` minutes
+/// after midnight. `days` has a bit for each day, 1 for Monday up to 64 for Sunday, and a
+/// window that ends before it starts runs past midnight into the next day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub days: i32,
+    pub starts: i32,
+    pub ends: i32,
+}
+
+impl Window {
+    /// Whether the window is open at `local`, unix seconds in the restaurant's time.
+    pub fn contains(&self, local: i64) -> bool {
+        let day = local.div_euclid(DAY);
+        let minute = (local.rem_euclid(DAY) / 60) as i32;
+        if self.starts <= self.ends {
+            self.is_on(day) && self.starts <= minute && minute < self.ends
+        } else {
+            (self.is_on(day) && minute >= self.starts)
+                || (self.is_on(day - 1) && minute < self.ends)
+        }
+    }
+
+    /// When the window next opens after `local`, in the restaurant's time, or None when it
+    /// is on no day at all.
+    pub fn next_start(&self, local: i64) -> Option<i64> {
+        let today = local.div_euclid(DAY);
+        (today..=today + 7)
+            .filter(|day| self.is_on(*day))
+            .map(|day| day * DAY + self.starts as i64 * 60)
+            .find(|start| *start > local)
+    }
+
+    fn is_on(&self, day: i64) -> bool {
+        // Day 0, the first of January 1970, was a Thursday.
+        let weekday = (day + 3).rem_euclid(7);
+        self.days & (1 << weekday) != 0
+    }
+}
+
+/// Takes `percent_off` the price of a menu item, of every item in a category when it is
+/// not for one item, or of the whole menu when it is for neither. It is active while its
+/// window is open, and only from `valid_from` until `valid_until` when those are set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceRule {
+    pub id: i32,
+    pub name: String,
+    pub category_id: Option<i32>,
+    pub menu_item_id: Option<i32>,
+    pub window: Window,
+    pub valid_from: Option<i64>,
+    pub valid_until: Option<i64>,
+    pub percent_off: i32,
+}
+
+impl PriceRule {
+    /// Whether the rule is active at `now`, in a restaurant `utc_offset` seconds ahead of
+    /// UTC.
+    pub fn is_active(&self, now: i64, utc_offset: i64) -> bool {
+        self.valid_from.map_or(true, |from| from <= now)
+            && self.valid_until.map_or(true, |until| now < until)
+            && self.window.contains(now + utc_offset)
+    }
+
+    pub fn applies_to(&self, category_id: i32, menu_item_id: i32) -> bool {
+        self.category_id.map_or(true, |id| id == category_id)
+            && self.menu_item_id.map_or(true, |id| id == menu_item_id)
+    }
+}
+
+/// Something on the menu, the price is in cents. `price` is what it costs now, with any
+/// active price rule taken off `regular_price`. An item with components is a bundle, it
+/// is sold at its own price and the components are made by the kitchen. `allergens` are
+/// what it contains, a bundle's include those of its components, and `dietary` the diets
+/// it suits, like vegan or gluten free. It is made at its own station or else at its
+/// category's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuItem {
+    pub id: i32,
+    pub name: String,
+    pub price: i32,
+    pub regular_price: i32,
+    pub modifier_groups: Vec<ModifierGroup>,
+    pub components: Vec<String>,
+    pub recipe: Vec<RecipeLine>,
+    pub availability: Availability,
+    pub allergens: Vec<String>,
+    pub dietary: Vec<String>,
+    pub station_id: Option<i32>,
+}
+
+/// A category of the menu. One with schedules, like breakfast, can only be ordered from
+/// while one of them is open.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+    pub schedules: Vec<Window>,
+    pub items: Vec<MenuItem>,
+}
+
+/// The menu of one restaurant.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Menu {
+    pub categories: Vec<Category>,
+}
+
+impl Menu {
+    /// Finds a menu item by the name it is ordered with.
+    pub fn item(&self, name: &str) -> Option<&MenuItem> {
+        self.categories
+            .iter()
+            .flat_map(|category| category.items.iter())
+            .find(|item| item.name == name)
+    }
+
+    /// The station the item with this name is made at, items that are not on the menu and
+    /// items without a station are not made at any.
+    pub fn station(&self, name: &str) -> Option<i32> {
+        self.item(name).and_then(|menu_item| menu_item.station_id)
+    }
+
+    /// The ingredients used to make one of the item with this name, a bundle uses those of
+    /// its components as well as its own. Items that are not on the menu use nothing.
+    pub fn recipe(&self, name: &str) -> Vec<RecipeLine> {
+        let menu_item = match self.item(name) {
+            Some(menu_item) => menu_item,
+            None => return Vec::new(),
+        };
+        let components = menu_item
+            .components
+            .iter()
+            .filter_map(|component| self.item(component));
+        let mut recipe = menu_item.recipe.clone();
+        for component in components {
+            recipe.extend(component.recipe.iter().cloned());
+        }
+        recipe
+    }
+}
+
+/// An allergy a guest declared, for one seat at a table or for everyone at it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allergy {
+    pub seat: Option<i32>,
+    pub allergen: String,
+}
+
+/// Something recipes are made from, counted in whatever unit suits it, like grams or
+/// pieces. It is running low once the stock is down to `low_stock`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ingredient {
+    pub id: i32,
+    pub name: String,
+    pub stock: i32,
+    pub low_stock: i32,
+}
+
+impl Ingredient {
+    pub fn is_low(&self) -> bool {
+        self.stock <= self.low_stock
+    }
+}
+
+/// An item as it is asked for in an order, by name with the names of the modifiers chosen.
+/// Courses start at 1, the first course goes to the kitchen straight away and later ones
+/// are held until they are fired.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderLine {
+    pub name: String,
+    pub modifiers: Vec<String>,
+    pub course: i32,
+}
+
+/// Why a change to the tables could not be made.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Invalid(String),
+    NotFound(String),
+    PreconditionFailed(String),
+    /// The change is valid but cannot be made as things stand, like ordering something
+    /// there is not enough stock to make.
+    Conflict(String),
+    /// The staff member's role does not allow the change as things stand, like voiding an
+    /// item from a bill that was settled.
+    Forbidden(String),
+    Internal(String),
+}
+
+/// Repositories report failures as strings, anything they fail at is on our side.
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Internal(message)
+    }
+}
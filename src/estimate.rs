@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use crate::domain;
+
+/// How far back the kitchen's preparations are looked at, in seconds.
+pub const HISTORY_SECONDS: i64 = 30 * 24 * 60 * 60;
+/// How many of a menu item's latest preparations its statistics are rolled over.
+pub const SAMPLES: usize = 50;
+/// An item is only estimated from its history once it was made this many times.
+pub const MIN_SAMPLES: usize = 3;
+/// Tickets fired longer ago than this that were never marked ready are not counted as load.
+pub const LOAD_SECONDS: i64 = 60 * 60;
+/// How much longer an item takes for each ticket its station still has to get ready, in
+/// percent.
+pub const LOAD_PERCENT: i32 = 5;
+
+/// Estimates how long menu items take from how long they took lately and how busy the
+/// kitchen is now.
+pub struct Estimator {
+    stats: Vec<domain::PrepStats>,
+    load: BTreeMap<Option<i32>, i32>,
+}
+
+impl Estimator {
+    /// Learns from the kitchen's preparations, oldest first, as they are at `now`.
+    pub fn new(preparations: &[domain::Preparation], now: i64) -> Estimator {
+        let mut history: BTreeMap<&str, Vec<&domain::Preparation>> = BTreeMap::new();
+        let mut load: BTreeMap<Option<i32>, i32> = BTreeMap::new();
+        for preparation in preparations {
+            if preparation.ready_at.is_some() {
+                history
+                    .entry(&preparation.name)
+                    .or_default()
+                    .push(preparation);
+            } else if preparation.fired_at >= now - LOAD_SECONDS {
+                *load.entry(preparation.station_id).or_default() += 1;
+            }
+        }
+        let stats = history
+            .into_iter()
+            .map(|(name, made)| stats(name, &made[made.len().saturating_sub(SAMPLES)..]))
+            .collect();
+        Estimator { stats, load }
+    }
+
+    /// The statistics of every menu item made lately, by name.
+    pub fn stats(&self) -> &[domain::PrepStats] {
+        &self.stats
+    }
+
+    /// How many minutes an item ordered now is expected to take at its station, the median
+    /// of its latest preparations made longer by the tickets waiting there. `None` until it
+    /// was made `MIN_SAMPLES` times.
+    pub fn estimate(&self, name: &str, station_id: Option<i32>) -> Option<i32> {
+        let found = self.stats.iter().find(|stats| stats.name == name)?;
+        if (found.samples as usize) < MIN_SAMPLES {
+            return None;
+        }
+        let waiting = self.load.get(&station_id).copied().unwrap_or(0);
+        Some((found.median_minutes * (100 + LOAD_PERCENT * waiting) + 50) / 100)
+    }
+}
+
+fn stats(name: &str, made: &[&domain::Preparation]) -> domain::PrepStats {
+    let mut estimated = made
+        .iter()
+        .map(|preparation| preparation.time_to_prepare)
+        .collect::<Vec<i32>>();
+    let mut took = made
+        .iter()
+        .filter_map(|preparation| preparation.minutes())
+        .collect::<Vec<i32>>();
+    estimated.sort_unstable();
+    took.sort_unstable();
+    domain::PrepStats {
+        name: name.to_string(),
+        samples: made.len() as i32,
+        estimated_minutes: percentile(&estimated, 50),
+        median_minutes: percentile(&took, 50),
+        p90_minutes: percentile(&took, 90),
+    }
+}
+
+/// The nearest-rank percentile of sorted values, the smallest value at least `percent` of
+/// them are no bigger than.
+fn percentile(sorted: &[i32], percent: usize) -> i32 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * percent + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1700000000;
+
+    fn made(name: &str, minutes: i64, estimated: i32) -> domain::Preparation {
+        domain::Preparation {
+            name: name.to_string(),
+            station_id: Some(1),
+            time_to_prepare: estimated,
+            fired_at: NOW - 7200,
+            ready_at: Some(NOW - 7200 + minutes * 60),
+        }
+    }
+
+    fn waiting(station_id: Option<i32>, fired_at: i64) -> domain::Preparation {
+        domain::Preparation {
+            name: String::from("soup"),
+            station_id,
+            time_to_prepare: 10,
+            fired_at,
+            ready_at: None,
+        }
+    }
+
+    #[test]
+    fn should_take_nearest_rank_percentiles() {
+        let values = (1..=10).collect::<Vec<i32>>();
+        assert_eq!(percentile(&values, 50), 5);
+        assert_eq!(percentile(&values, 90), 9);
+        assert_eq!(percentile(&[7], 90), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+
+    #[test]
+    fn should_roll_statistics_over_the_latest_preparations() {
+        let mut preparations = (0..SAMPLES)
+            .map(|_| made("steak", 30, 10))
+            .collect::<Vec<_>>();
+        for minutes in 1..=10 {
+            preparations.push(made("steak", minutes, 12));
+        }
+        let estimator = Estimator::new(&preparations, NOW);
+        let expected = domain::PrepStats {
+            name: String::from("steak"),
+            samples: SAMPLES as i32,
+            estimated_minutes: 10,
+            median_minutes: 30,
+            p90_minutes: 30,
+        };
+        assert_eq!(estimator.stats(), &[expected][..]);
+        let latest = &preparations[SAMPLES - 5..];
+        let estimator = Estimator::new(latest, NOW);
+        let stats = &estimator.stats()[0];
+        assert_eq!(stats.samples, 15);
+        assert_eq!(stats.estimated_minutes, 12);
+        assert_eq!(stats.median_minutes, 8);
+        assert_eq!(stats.p90_minutes, 30);
+    }
+
+    #[test]
+    fn should_only_estimate_items_made_often_enough() {
+        let preparations = vec![made("steak", 20, 10), made("steak", 22, 10)];
+        let estimator = Estimator::new(&preparations, NOW);
+        assert_eq!(estimator.estimate("steak", Some(1)), None);
+        assert_eq!(estimator.estimate("soup", Some(1)), None);
+        let preparations = vec![
+            made("steak", 20, 10),
+            made("steak", 22, 10),
+            made("steak", 30, 10),
+        ];
+        let estimator = Estimator::new(&preparations, NOW);
+        assert_eq!(estimator.estimate("steak", Some(1)), Some(22));
+    }
+
+    #[test]
+    fn should_take_longer_for_each_ticket_waiting_at_the_station() {
+        let preparations = vec![
+            made("steak", 20, 10),
+            made("steak", 20, 10),
+            made("steak", 20, 10),
+            waiting(Some(1), NOW - 60),
+            waiting(Some(1), NOW - 600),
+            waiting(Some(1), NOW - LOAD_SECONDS - 1),
+            waiting(Some(2), NOW - 60),
+        ];
+        let estimator = Estimator::new(&preparations, NOW);
+        assert_eq!(estimator.estimate("steak", Some(1)), Some(22));
+        assert_eq!(estimator.estimate("steak", Some(2)), Some(21));
+        assert_eq!(estimator.estimate("steak", None), Some(20));
+    }
+}
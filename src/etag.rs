@@ -0,0 +1,64 @@
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::Outcome;
+
+pub const IF_MATCH_HEADER: &str = "If-Match";
+
+pub fn format_etag(version: i64) -> String {
+    format!("\"{}\"", version)
+}
+
+/// Parses an `If-Match` value, `*` matches any version so it is treated as no condition.
+pub fn parse_etag(value: &str) -> Option<Option<i64>> {
+    let value = value.trim();
+    if value == "*" {
+        return Some(None);
+    }
+    let value = value.strip_prefix("W/").unwrap_or(value);
+    value.trim_matches('"').parse::<i64>().ok().map(Some)
+}
+
+/// A response sent with the version of the table it came from as its `ETag`.
+pub struct Tagged<R>(pub R, pub i64);
+
+impl<'r, R: Responder<'r>> Responder<'r> for Tagged<R> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        Response::build_from(self.0.respond_to(request)?)
+            .raw_header("ETag", format_etag(self.1))
+            .ok()
+    }
+}
+
+/// The table version a client expects to be changing, from the `If-Match` header.
+pub struct IfMatch(pub Option<i64>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfMatch {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<IfMatch, String> {
+        match request.headers().get_one(IF_MATCH_HEADER) {
+            None => Outcome::Success(IfMatch(None)),
+            Some(value) => match parse_etag(value) {
+                Some(version) => Outcome::Success(IfMatch(version)),
+                None => Outcome::Failure((
+                    Status::BadRequest,
+                    format!("Invalid If-Match header {}.", value),
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_etags() {
+        assert_eq!(parse_etag(&format_etag(3)), Some(Some(3)));
+        assert_eq!(parse_etag("W/\"4\""), Some(Some(4)));
+        assert_eq!(parse_etag("*"), Some(None));
+        assert_eq!(parse_etag("\"abc\""), None);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use crate::data;
+use crate::database;
+use crate::repository::postgres;
+
+pub const UP: &str = "up";
+pub const DOWN: &str = "down";
+
+/// The process is answering requests, nothing else is checked.
+pub fn liveness() -> data::HealthReport {
+    report(BTreeMap::new())
+}
+
+/// Checks the database can be opened, is fully migrated and answers a query.
+pub fn readiness(connection: Result<rusqlite::Connection, String>) -> data::HealthReport {
+    let mut components = BTreeMap::new();
+    match connection {
+        Ok(connection) => {
+            components.insert(String::from("database"), up(None));
+            components.insert(String::from("schema"), check_schema(&connection));
+            components.insert(String::from("query"), check_query(&connection));
+        }
+        Err(e) => {
+            let unreachable = "Database is unreachable.";
+            components.insert(String::from("database"), down(e));
+            components.insert(String::from("schema"), down(unreachable.into()));
+            components.insert(String::from("query"), down(unreachable.into()));
+        }
+    }
+    report(components)
+}
+
+/// Checks postgres can be reached, is fully migrated and answers a query.
+pub fn postgres_readiness(client: Result<&mut ::postgres::Client, String>) -> data::HealthReport {
+    let mut components = BTreeMap::new();
+    match client {
+        Ok(client) => {
+            components.insert(String::from("postgres"), up(None));
+            components.insert(String::from("schema"), check_postgres_schema(client));
+            components.insert(String::from("query"), check_postgres_query(client));
+        }
+        Err(e) => {
+            let unreachable = "Postgres is unreachable.";
+            components.insert(String::from("postgres"), down(e));
+            components.insert(String::from("schema"), down(unreachable.into()));
+            components.insert(String::from("query"), down(unreachable.into()));
+        }
+    }
+    report(components)
+}
+
+pub fn is_up(report: &data::HealthReport) -> bool {
+    report.status == UP
+}
+
+fn check_schema(connection: &rusqlite::Connection) -> data::ComponentHealth {
+    let expected = database::current_schema_version();
+    match database::schema_version(connection) {
+        Ok(version) if version == expected => up(Some(format!("Schema version {}.", version))),
+        Ok(version) => down(format!(
+            "Schema version {} but expected {}.",
+            version, expected
+        )),
+        Err(e) => down(e),
+    }
+}
+
+fn check_query(connection: &rusqlite::Connection) -> data::ComponentHealth {
+    let tables = connection.query_row("select count(*) from restaurant_table;", [], |row| {
+        row.get::<_, i64>(0)
+    });
+    match tables {
+        Ok(_) => up(None),
+        Err(e) => down(format!("Query failed: {}", e)),
+    }
+}
+
+fn check_postgres_schema(client: &mut ::postgres::Client) -> data::ComponentHealth {
+    let expected = postgres::current_schema_version();
+    match client.query_opt("select version from schema_migration;", &[]) {
+        Ok(Some(row)) => {
+            let version = row.get::<_, i32>(0) as i64;
+            if version == expected {
+                up(Some(format!("Schema version {}.", version)))
+            } else {
+                down(format!(
+                    "Schema version {} but expected {}.",
+                    version, expected
+                ))
+            }
+        }
+        Ok(None) => down(String::from("Schema version is missing.")),
+        Err(e) => down(format!("Failed to read schema version: {}", e)),
+    }
+}
+
+fn check_postgres_query(client: &mut ::postgres::Client) -> data::ComponentHealth {
+    match client.query_one("select count(*) from restaurant_table;", &[]) {
+        Ok(_) => up(None),
+        Err(e) => down(format!("Query failed: {}", e)),
+    }
+}
+
+fn report(components: BTreeMap<String, data::ComponentHealth>) -> data::HealthReport {
+    let healthy = components.values().all(|component| component.status == UP);
+    if !healthy {
+        warn!("Not ready: {:?}", components);
+    }
+    data::HealthReport {
+        status: String::from(if healthy { UP } else { DOWN }),
+        components,
+    }
+}
+
+fn up(detail: Option<String>) -> data::ComponentHealth {
+    data::ComponentHealth {
+        status: String::from(UP),
+        detail,
+    }
+}
+
+fn down(detail: String) -> data::ComponentHealth {
+    data::ComponentHealth {
+        status: String::from(DOWN),
+        detail: Some(detail),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_not_be_ready_without_database() {
+        let report = readiness(Err("Cannot connect to database.".into()));
+        assert!(!is_up(&report));
+        assert_eq!(report.components["database"].status, DOWN);
+    }
+
+    #[test]
+    fn should_not_be_ready_without_postgres() {
+        let report = postgres_readiness(Err("Cannot connect to database.".into()));
+        assert!(!is_up(&report));
+        assert_eq!(report.components["postgres"].status, DOWN);
+        assert_eq!(report.components["schema"].status, DOWN);
+    }
+
+    #[test]
+    fn should_not_be_ready_before_migrations() {
+        let connection = rusqlite::Connection::open_in_memory().expect("Open database.");
+        let report = readiness(Ok(connection));
+        assert!(!is_up(&report));
+        assert_eq!(report.components["database"].status, UP);
+        assert_eq!(report.components["schema"].status, DOWN);
+        assert_eq!(report.components["query"].status, DOWN);
+    }
+}
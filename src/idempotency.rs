@@ -0,0 +1,133 @@
+use rocket::fairing::AdHoc;
+use rocket::request::{self, FromRequest, Request};
+use rocket::Outcome;
+
+use crate::audit;
+use crate::database;
+
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// How long, in seconds, a stored response is replayed for. Set with `idempotency_window`.
+pub struct Window(pub i64);
+
+const DEFAULT_WINDOW: i64 = 24 * 60 * 60;
+
+/// How long, in seconds, a key is held for a request that has not finished. A claim older
+/// than this was left behind by a request that died before storing its response, so the
+/// next request with the key takes it over instead of waiting out the window.
+pub const LEASE: i64 = 60;
+
+pub fn window_fairing() -> AdHoc {
+    AdHoc::on_attach("Idempotency Window", |rocket| {
+        let window = rocket
+            .config()
+            .get_int("idempotency_window")
+            .unwrap_or(DEFAULT_WINDOW);
+        info!("Replaying idempotent requests for {} seconds.", window);
+        Ok(rocket.manage(Window(window)))
+    })
+}
+
+/// The optional `Idempotency-Key` header sent with a POST.
+pub struct IdempotencyKey(pub Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for IdempotencyKey {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<IdempotencyKey, ()> {
+        let key = request
+            .headers()
+            .get_one(IDEMPOTENCY_HEADER)
+            .map(|key| key.to_string());
+        Outcome::Success(IdempotencyKey(key))
+    }
+}
+
+pub enum Attempt {
+    /// First time the key has been seen, the request should be processed.
+    New,
+    /// The key was already used for this request, send back the stored response.
+    Replay(String),
+    /// The key was already used for a different request.
+    Mismatch,
+    /// The first request with this key has not finished yet and is still within its lease.
+    InProgress,
+}
+
+/// Keys are chosen by clients, so the same key sent to two restaurants is two keys.
+pub fn scoped(restaurant_id: i32, key: &str) -> String {
+    format!("{}:{}", restaurant_id, key)
+}
+
+/// Claims `key` for `request`, or finds out what happened the last time it was used.
+/// Keys older than the window and claims older than the lease are forgotten first.
+pub fn begin(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    key: &str,
+    request: &str,
+    window: &Window,
+) -> Result<Attempt, String> {
+    let key = &scoped(restaurant_id, key);
+    let now = audit::now();
+    let mut expire_statement = database::build_statement(
+        connection,
+        "delete from idempotency_key
+            where key = $1 and (created_at < $2 or (response is null and created_at < $3));",
+    );
+    expire_statement
+        .execute(rusqlite::params![key, now - window.0, now - LEASE])
+        .map_err(|e| format!("Failed with error: {}", e))?;
+    let mut insert_statement = database::build_statement(
+        connection,
+        "insert or ignore into idempotency_key (key, request, response, created_at)
+            values ($1, $2, null, $3);",
+    );
+    let inserted = insert_statement
+        .execute(rusqlite::params![key, request, now])
+        .map_err(|e| format!("Failed with error: {}", e))?;
+    if inserted == 1 {
+        return Ok(Attempt::New);
+    }
+    let mut statement = database::build_statement(
+        connection,
+        "select request, response from idempotency_key where key = $1;",
+    );
+    let stored = statement.query_row([&key], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+    });
+    match stored {
+        Ok((stored_request, _)) if stored_request != request => Ok(Attempt::Mismatch),
+        Ok((_, Some(response))) => Ok(Attempt::Replay(response)),
+        Ok((_, None)) => Ok(Attempt::InProgress),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// Stores the response sent for `key` so retries get exactly the same answer.
+pub fn complete(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    key: &str,
+    response: &str,
+) -> Result<(), String> {
+    let key = &scoped(restaurant_id, key);
+    let mut statement = database::build_statement(
+        connection,
+        "update idempotency_key set response = $1 where key = $2;",
+    );
+    match statement.execute(rusqlite::params![response, key]) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// Releases `key` after a failed request so the client can retry it.
+pub fn abandon(connection: &rusqlite::Connection, restaurant_id: i32, key: &str) {
+    let key = &scoped(restaurant_id, key);
+    let mut statement =
+        database::build_statement(connection, "delete from idempotency_key where key = $1;");
+    if let Err(e) = statement.execute([&key]) {
+        warn!("Failed to release idempotency key {}: {}", key, e);
+    }
+}
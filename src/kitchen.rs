@@ -0,0 +1,156 @@
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::Outcome;
+
+use crate::database;
+use crate::domain;
+
+pub const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+
+/// A ticket arrived at its station, when its course was fired or it was restored.
+pub const FIRED: &str = "fired";
+/// A ticket was marked ready by its station.
+pub const READY: &str = "ready";
+/// A ticket was taken off its station because its item was voided.
+pub const VOIDED: &str = "voided";
+pub const KINDS: [&str; 3] = [FIRED, READY, VOIDED];
+
+/// How long a client waits before asking a stream for what happened next, in milliseconds.
+pub const RETRY_MILLIS: i64 = 2000;
+
+/// How often a held events request looks for new events, in milliseconds.
+pub const POLL_MILLIS: u64 = 250;
+
+/// How long, in seconds, an events request is held open when there is nothing new. Set with
+/// `kitchen_event_wait`. A held request keeps one of Rocket's `workers` busy until it ends,
+/// so by default it is not held and the client comes back after the retry interval.
+pub struct EventWait(pub i64);
+
+const DEFAULT_EVENT_WAIT: i64 = 0;
+
+pub fn event_wait_fairing() -> AdHoc {
+    AdHoc::on_attach("Kitchen Event Wait", |rocket| {
+        let wait = rocket
+            .config()
+            .get_int("kitchen_event_wait")
+            .unwrap_or(DEFAULT_EVENT_WAIT);
+        if wait > 0 {
+            info!(
+                "Holding kitchen event requests for up to {} seconds, each one keeps a worker \
+                 busy while it waits.",
+                wait
+            );
+        }
+        Ok(rocket.manage(EventWait(wait)))
+    })
+}
+
+/// The restaurant's stations in the order they were added.
+pub fn stations(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Vec<domain::Station>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select id, name from station where restaurant_id = $1 order by id;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok(domain::Station {
+            id: row.get(0)?,
+            name: row.get(1)?,
+        })
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<domain::Station>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// Logs an event for each ticket of an item at the station it is made at, held items have
+/// no tickets yet so nothing is logged for them.
+pub fn record(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    table_id: &str,
+    item: &domain::Item,
+    kind: &str,
+    at: i64,
+) -> Result<(), String> {
+    if item.held {
+        return Ok(());
+    }
+    for ticket in item.kitchen_items() {
+        connection
+            .execute(
+                "insert into kitchen_event
+                    (restaurant_id, station_id, table_id, item_id, name, kind, at)
+                    values ($1, $2, $3, $4, $5, $6, $7);",
+                rusqlite::params![
+                    restaurant_id,
+                    ticket.station_id,
+                    table_id,
+                    ticket.id,
+                    ticket.name,
+                    kind,
+                    at
+                ],
+            )
+            .map_err(|e| format!("Failed to record kitchen event: {}", e))?;
+    }
+    Ok(())
+}
+
+/// What happened at a station after the event with id `after`, oldest first.
+pub fn events(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    station_id: i32,
+    after: i64,
+) -> Result<Vec<domain::KitchenEvent>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select id, kind, station_id, table_id, item_id, name, at from kitchen_event
+            where restaurant_id = $1 and station_id = $2 and id > $3 order by id;",
+    );
+    let results = statement.query_map(rusqlite::params![restaurant_id, station_id, after], |row| {
+        Ok(domain::KitchenEvent {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            station_id: row.get(2)?,
+            table_id: row.get(3)?,
+            item_id: row.get(4)?,
+            name: row.get(5)?,
+            at: row.get(6)?,
+        })
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<domain::KitchenEvent>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// The id of the last event a stream's client saw, from the `Last-Event-ID` header a
+/// browser sends when it reconnects.
+pub struct LastEventId(pub Option<i64>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for LastEventId {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<LastEventId, String> {
+        match request.headers().get_one(LAST_EVENT_ID_HEADER) {
+            None => Outcome::Success(LastEventId(None)),
+            Some(value) => match value.trim().parse::<i64>() {
+                Ok(id) => Outcome::Success(LastEventId(Some(id))),
+                Err(_) => Outcome::Failure((
+                    Status::BadRequest,
+                    format!("Invalid Last-Event-ID header {}.", value),
+                )),
+            },
+        }
+    }
+}
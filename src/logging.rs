@@ -0,0 +1,161 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use rocket::config::LoggingLevel;
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+use crate::audit;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+thread_local! {
+    /// Rocket handles a request on a single thread, so this tags every line logged for it.
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub struct Logger {
+    json: AtomicBool,
+}
+
+static LOGGER: Logger = Logger {
+    json: AtomicBool::new(false),
+};
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let request_id = REQUEST_ID.with(|id| id.borrow().clone());
+        let line = if self.json.load(Ordering::Relaxed) {
+            serde_json::json!({
+                "timestamp": audit::now(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "request_id": request_id,
+                "message": record.args().to_string(),
+            })
+            .to_string()
+        } else {
+            format!(
+                "{} {:<5} [{}] {}: {}",
+                audit::now(),
+                record.level(),
+                request_id.as_deref().unwrap_or("-"),
+                record.target(),
+                record.args()
+            )
+        };
+        let _ = writeln!(std::io::stdout().lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Installs the logger, this has to happen before Rocket is ignited or it will use its own.
+pub fn init() {
+    match log::set_logger(&LOGGER) {
+        Ok(_) => log::set_max_level(LevelFilter::Info),
+        Err(e) => eprintln!("Failed to install logger: {}", e),
+    }
+}
+
+/// Takes the verbosity from Rocket's `log` setting and the output from `log_format`,
+/// which is either `text` (the default) or `json`.
+pub fn config_fairing() -> AdHoc {
+    AdHoc::on_attach("Logging", |rocket| {
+        let level = match rocket.config().log_level {
+            LoggingLevel::Critical => LevelFilter::Warn,
+            LoggingLevel::Normal => LevelFilter::Info,
+            LoggingLevel::Debug => LevelFilter::Trace,
+            LoggingLevel::Off => LevelFilter::Off,
+        };
+        let json = match rocket.config().get_str("log_format") {
+            Ok(format) => format == "json",
+            Err(_) => false,
+        };
+        LOGGER.json.store(json, Ordering::Relaxed);
+        log::set_max_level(level);
+        Ok(rocket)
+    })
+}
+
+pub struct RequestId(pub String);
+
+/// Gives every request an id, taken from `X-Request-Id` when the client sent a usable one,
+/// and echoes it back on the response.
+pub struct RequestIdFairing;
+
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Id",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        let request_id = request
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .filter(|id| is_valid_request_id(id))
+            .map(|id| id.to_string())
+            .unwrap_or_else(new_request_id);
+        REQUEST_ID.with(|id| *id.borrow_mut() = Some(request_id.clone()));
+        request.local_cache(|| RequestId(request_id));
+        info!("{} {}", request.method(), request.uri());
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let request_id = request.local_cache(|| RequestId(new_request_id()));
+        info!(
+            "{} {} finished with {}",
+            request.method(),
+            request.uri(),
+            response.status()
+        );
+        response.set_raw_header(REQUEST_ID_HEADER, request_id.0.clone());
+        REQUEST_ID.with(|id| *id.borrow_mut() = None);
+    }
+}
+
+fn new_request_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_only_accept_safe_request_ids() {
+        assert!(is_valid_request_id("3f2a-91c0_b"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("id with spaces"));
+        assert!(!is_valid_request_id(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn should_generate_valid_request_ids() {
+        let id = new_request_id();
+        assert_eq!(id.len(), 16);
+        assert!(is_valid_request_id(&id));
+    }
+}
@@ -1,356 +1,166 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 
+#[macro_use]
+extern crate log;
 #[macro_use]
 extern crate rocket;
 
-use rand::Rng;
+use std::sync::Arc;
+
+use rocket::http::Status;
+use rocket::response::{content, status};
+use rocket::State;
 use rocket_contrib::json::Json;
-use rusqlite::Result;
 
+mod adjustment;
+mod allergy;
+mod api;
+mod audit;
+mod auth;
 mod data;
 mod database;
+mod domain;
+mod estimate;
+mod etag;
+mod health;
+mod idempotency;
+mod kitchen;
+mod logging;
+mod menu;
+mod metrics;
+mod openapi;
+mod repository;
+mod service;
+#[cfg(test)]
+mod testing;
 
-#[get("/api/v1/tables")]
-fn get_all_tables() -> Result<Json<Vec<data::Table>>, String> {
-    println!("Getting all tables.");    let connection = &database::get_connection();
-    let mut statement = database::build_statement(connection,  "select * from restaurant_table;");
-    println!("Prepared statement {:?}.", statement);
-    let results = statement.query_map([], |row| {
-        Ok(database::TableData {
-            id: row.get(0)?,
-            item_ids: row.get(1)?,
-        })
-    });
-    match results {
-        Ok(rows) => {
-            let collection: rusqlite::Result<Vec<_>> = rows.collect();
-            match collection {
-                Ok(items) => {
-                    let new_data = items
-                        .iter()
-                        .map(|table_data| data::Table {
-                            id: table_data.id,
-                            items: {
-                                let id_list = Vec::from_iter(
-                                    table_data.item_ids.split(",").map(|s| s.to_string()),
-                                );
-                                get_items(connection, id_list)
-                            },
-                        })
-                        .collect();
-                    println!("Finished getting all Tables {:?}.", new_data);
-                    Ok(Json(new_data))
-                }
-                Err(_) => Err("Could not collect items".into()),
-            }
-        }
-        Err(_) => Err("Failed to fetch items.".into()),
-    }
+#[get("/metrics")]
+fn get_metrics(
+    registry: State<Arc<metrics::Metrics>>,
+    status: State<repository::Status>,
+) -> content::Plain<String> {
+    content::Plain(registry.render(status.inner().as_ref()))
 }
 
-#[get("/api/v1/tables/<table>")]
-fn get_items_for_table(table: String) -> Result<Json<data::Table>, String> {
-    println!("Getting items for table {}.", table);    let connection = &database::get_connection();
-    let mut statement = database::build_statement(&connection, "select * from restaurant_table where id = $1;");
-    println!("Prepared statement {:?}.", statement);
-    let results = statement.query_map(&[&table], |row| {
-        Ok(database::TableData {
-            id: row.get(0)?,
-            item_ids: row.get(1)?,
-        })
-    });
-    match results {
-        Ok(rows) => {
-            let collection: rusqlite::Result<Vec<_>> = rows.collect();
-            match collection {
-                Ok(items) => {
-                    let table_data = items.first().expect("No tables found.");
-                    let table = data::Table {
-                        id: table_data.id,
-                        items: {
-                            let id_list = Vec::from_iter(
-                                table_data.item_ids.split(",").map(|s| s.to_string()),
-                            );
-                            get_items(&connection, id_list)
-                        },
-                    };
-                    println!("Finished getting items {:?}.", table);
-                    Ok(Json(table))
-                }
-                Err(_) => Err("Could not collect items".into()),
-            }
-        }
-        Err(_) => Err("Failed to fetch items.".into()),
-    }
+#[get("/api/v1/openapi.json")]
+fn get_openapi() -> Json<serde_json::Value> {
+    Json(openapi::document())
 }
 
-#[get("/api/v1/tables/<table>/<item>")]
-fn get_item_for_table(table: String, item: String) -> Result<Json<data::Item>, String> {
-    println!("Getting item {:?} for table {}.", item, table);
-    let connection = &database::get_connection();
-    let mut statement = database::build_statement(&connection, "select * from restaurant_table where id = $1;");
-    println!("Prepared statement {:?}.", statement);
-    let results = statement.query_map(&[&table], |row| {
-        Ok(database::TableData {
-            id: row.get(0)?,
-            item_ids: row.get(1)?,
-        })
-    });
-    match results {
-        Ok(rows) => {
-            let collection: rusqlite::Result<Vec<_>> = rows.collect();
-            match collection {
-                Ok(items) => {
-                    let table = items.first().expect("Table not found.");
-                    println!("Found table: {:?}", table);
-                    let item_ids = table
-                        .item_ids
-                        .split(",")
-                        .filter(|s| item.eq(&s.to_string()))
-                        .map(|s| s.to_string())
-                        .collect();
-                    println!("Getting items for ids: {:?}", item_ids);
-                    let item = get_items(&connection, item_ids).into_iter().next().expect("Item not found.");
-                    println!("Found items {:?}", item);
-                    Ok(Json(item))
-                }
-                Err(_) => Err("Could not collect items".into()),
-            }
-        }
-        Err(_) => Err("Failed to fetch items.".into()),
-    }
+#[get("/api/v1/docs")]
+fn get_docs() -> content::Html<&'static str> {
+    content::Html(openapi::DOCS_PAGE)
 }
 
-fn get_items(connection: &rusqlite::Connection, ids: Vec<String>) -> Vec<data::Item> {
-    println!("Getting items {:?}.", ids);
-    let mut items: Vec<data::Item> = Vec::new();
-    let mut iter = IntoIterator::into_iter(&ids);
-    while let Some(s) = iter.next() {
-        let mut statement = database::build_statement(&connection, "select * from item where id = $1;");
-        let results = statement.query_map(&[&s], |row| {
-            Ok(database::ItemData {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                time_to_prepare: row.get(2)?,
-            })
-        });
-        match results {
-            Ok(rows) => {
-                let collection: rusqlite::Result<Vec<database::ItemData>> = rows.collect();
-                match collection {
-                    Ok(data) => {
-                        for d in data.iter() {
-                            items.push(data::Item {
-                                id: d.id,
-                                name: d.name.clone(),
-                                time_to_prepare: d.time_to_prepare,
-                            });
-                        }
-                    }
-                    Err(_) => {}
-                }
-            }
-            Err(_) => {}
-        };
-    }
-    println!("Found items {:?}.", items);
-    items
+#[get("/api/v1/docs/swagger-ui.css")]
+fn get_docs_css() -> content::Css<&'static str> {
+    content::Css(openapi::SWAGGER_UI_CSS)
 }
 
-#[post("/api/v1/tables", format = "json", data = "<request>")]
-fn add_item(request: Json<data::ItemRequest>) -> Result<Json<data::StatusMessage>, String> {
-    println!(
-        "Adding item {:?} in Table {}.",
-        request.items, request.table_id
-    );
-    let connection = &database::get_connection();
-    let mut statement = database::build_statement(&connection, "insert into item (id, name, preperation_time) values (null, $1, $2);");
-    println!("Prepared statement {:?}.", statement);
-    let mut random = rand::thread_rng();
-    let item_ids = request
-        .items
-        .iter()
-        .map(|item| {
-            let preperation_time = random.gen_range(5..=15).to_string();
-            statement
-                .execute(&[&item, &preperation_time])
-                .expect("Failed to insert items");
-            connection.last_insert_rowid().to_string()
-        })
-        .collect::<Vec<String>>()
-        .join(",");
-    match add_item_to_table(&item_ids, &request.table_id.to_string()) {
-        Ok(result) => {
-            println!("Finished adding items to Table.");
-            Ok(Json(data::StatusMessage {
-                message: String::from(result),
-            }))
-        }
-        Err(_) => Err("Failed to insert into items.".into()),
-    }
+#[get("/api/v1/docs/swagger-ui-bundle.js")]
+fn get_docs_bundle() -> content::JavaScript<&'static str> {
+    content::JavaScript(openapi::SWAGGER_UI_BUNDLE)
 }
 
-pub fn add_item_to_table(item_id: &str, table_id: &str) -> Result<String, String> {
-    let connection = &database::get_connection();
-    let mut statement = database::build_statement(&connection, "select * from restaurant_table where id = :id;");
-    println!("Prepared statement {:?}.", statement);
-    let mut table_rows: rusqlite::Rows = statement
-        .query(rusqlite::named_params! { ":id": table_id })
-        .expect("Select item statement failed");
-    let mut items = String::from("");
-    while let Some(row) = table_rows.next().expect("Failed to select table.`") {
-        let values: String = row.get(1).expect("Failed to gets items from table.");
-        items.push_str(&values.to_string());
-        items.push_str(",");
-    }
-    if items.len() < 1 {
-        let mut insert_statement = match connection
-            .prepare("insert into restaurant_table (id,items) values (null, $1);")
-        {
-            Ok(statement) => statement,
-            Err(e) => return Err(format!("Failed with error: {}", e)),
-        };
-        items.push_str(&item_id.to_string());
-        let results = insert_statement.execute(&[&items]);
-        match results {
-            Ok(count) => return Ok(format!("{} rows inserted.", count)),
-            Err(e) => return Err(format!("Failed with error: {}", e)),
-        }
-    }
-    items.push_str(&item_id.to_string());
-
-    let mut update_statement = database::build_statement(&connection, "update restaurant_table set items = $1 where id = $2;");
-    println!("Prepared statement {:?}.", update_statement);
-    let results = update_statement.execute(&[&items, table_id]);
-    match results {
-        Ok(count) => Ok(format!("{} rows inserted.", count)),
-        Err(e) => Err(format!("Failed with error: {}", e)),
-    }
+#[get("/health")]
+fn get_health() -> Json<data::HealthReport> {
+    Json(health::liveness())
 }
 
-#[delete("/api/v1/tables/<table>/<item>")]
-fn remove_item(table: String, item: String) -> Result<Json<data::StatusMessage>, String> {
-    println!("Removing item {}, from table {}.", item, table);
-    let connection = &database::get_connection();
-    let mut statement = database::build_statement(&connection, "select * from restaurant_table where id = :id;");
-    println!("Prepared statement {:?}.", statement);
-    let table_rows = statement.query_map(&[&table], |row| {
-        Ok(database::TableData {
-            id: row.get(0)?,
-            item_ids: row.get(1)?,
-        })
-    });
-    let mut updated_items = String::new();
-    match table_rows {
-        Ok(rows) => {
-            let collection: rusqlite::Result<Vec<database::TableData>> = rows.collect();
-            match collection {
-                Ok(data) => {
-                    for d in data.iter() {
-                        println!("Found table {:?}", data);
-                        let table_items = d
-                            .item_ids
-                            .split(",")
-                            .filter(|s| s != &item)
-                            .collect::<String>();
-                        updated_items.push_str(&table_items);
-                        println!("New items are {:?}", table_items);
-                        break;
-                    }
-                }
-                Err(_) => {}
-            }
-        }
-        Err(_) => {}
+#[get("/ready")]
+fn get_ready(status: State<repository::Status>) -> status::Custom<Json<data::HealthReport>> {
+    let report = status.readiness();
+    let code = if health::is_up(&report) {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
     };
-    let mut update_statement = database::build_statement(&connection, "update restaurant_table set items = $1 where id = $2;");
-    println!("Prepared statement {:?}.", update_statement);
-    let results = update_statement.execute(&[&updated_items, &table]);
-    match results {
-        Ok(_) => {
-            println!("Updated table {} and removed item {}.", table, item);
-            let message = delete_item(&connection, item).unwrap();
-            Ok(Json(data::StatusMessage { message: message }))
-        }
-        Err(e) => Err(format!("Failed with error: {}", e)),
-    }
-}
-
-fn delete_item(connection: &rusqlite::Connection, id: String) -> Result<String, String> {
-    let mut statement = database::build_statement(&connection, "delete from item where id = $1;");
-    println!("Prepared statement {:?}.", statement);
-    let deleted_rows = statement.execute(&[&id]);
-    match deleted_rows {
-        Ok(rows_affected) => Ok(format!("{} rows deleted.", rows_affected)),
-        Err(_) => Err("Failed to delete into items.".into()),
-    }
+    status::Custom(code, Json(report))
 }
 
 fn main() {
-    database::setup_db().expect("Program failed to start");
+    logging::init();
     let rocket = luanch_server();
     rocket.launch();
 }
 
 fn luanch_server() -> rocket::Rocket {
-    rocket::ignite().mount(
-        "/",
-        routes![
-            get_all_tables,
-            add_item,
-            remove_item,
-            get_item_for_table,
-            get_items_for_table
-        ],
-    )
+    build_server(rocket::ignite())
+}
+
+/// Attaches everything the server needs, tests use it with their own configuration.
+fn build_server(rocket: rocket::Rocket) -> rocket::Rocket {
+    let rocket = rocket
+        .attach(logging::config_fairing())
+        .attach(logging::RequestIdFairing)
+        .attach(metrics::MetricsFairing::new())
+        .attach(idempotency::window_fairing())
+        .attach(allergy::policy_fairing())
+        .attach(kitchen::event_wait_fairing())
+        .attach(repository::fairing())
+        .mount(
+            "/",
+            routes![
+                get_metrics,
+                get_health,
+                get_ready,
+                get_openapi,
+                get_docs,
+                get_docs_css,
+                get_docs_bundle
+            ],
+        );
+    api::mount(rocket)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rocket::http::{ContentType, Status};
+    use crate::testing::{
+        TestServer, COOK, MANAGER, OTHER_MANAGER, OTHER_RESTAURANT, OTHER_SERVER, RESTAURANT,
+        SERVER,
+    };
+    use rocket::http::uri::Uri;
+    use rocket::http::{ContentType, Header, Method, Status};
     use rocket::local::Client;
 
     #[test]
     fn should_setup_db() {
-        let db_message = database::setup_db().unwrap();
+        let server = TestServer::new();
+        let db_message = database::setup_db(server.database()).unwrap();
         assert!(db_message.eq("Successfully created database tables."));
     }
 
     #[test]
     fn should_get_all() {
-        database::setup_test_db(
-            "INSERT INTO restaurant_table VALUES (999, '999,1000');
-                 INSERT INTO item VALUES (999, 'pizza', 5);
-                 INSERT INTO item VALUES (1000, 'cake', 9);",
-        );
-        let rocket = luanch_server();
-        let client = Client::new(rocket).expect("Failed to start server");
-        let mut response = client.get("/api/v1/tables").dispatch();
+        let server = TestServer::new();
+        let pizza = server.item("pizza").create();
+        let cake = server.item("cake").time_to_prepare(9).create();
+        server.table().items(&[pizza, cake]).create();
+        let mut response = server
+            .client
+            .get("/api/v1/tables")
+            .header(as_staff(COOK))
+            .dispatch();
         assert_eq!(response.status(), Status::Ok);
         assert_eq!(response.content_type(), Some(ContentType::JSON));
         let body = response.body_string().unwrap();
         let data =
             serde_json::from_str::<Vec<data::Table>>(&body).expect("Failed to convert json.");
-        assert_ne!(data.is_empty(), true);
-        database::close_test_db(
-            "
-        DELETE FROM restaurant_table WHERE id = 999;
-        DELETE FROM item WHERE id = 999;
-        DELETE FROM item WHERE id = 1000;",
-        );
+        assert!(!data.is_empty());
     }
 
     #[test]
     fn should_get_all_items_for_table() {
-        database::setup_test_db(
-            "INSERT INTO restaurant_table VALUES (1000, '1001,1002');
-                 INSERT INTO item VALUES (1001, 'pizza', 5);
-                 INSERT INTO item VALUES (1002, 'cake', 9);",
-        );
-        let rocket = luanch_server();
-        let client = Client::new(rocket).expect("Failed to start server");
-        let mut response = client.get("/api/v1/tables/1000").dispatch();
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1001).create();
+        let cake = server.item("cake").id(1002).time_to_prepare(9).create();
+        server.table().id(1000).items(&[pizza, cake]).create();
+        let mut response = server
+            .client
+            .get("/api/v1/tables/1000")
+            .header(as_staff(MANAGER))
+            .dispatch();
         assert_eq!(response.status(), Status::Ok);
         assert_eq!(response.content_type(), Some(ContentType::JSON));
         let body = response.body_string().unwrap();
@@ -358,7 +168,7 @@ mod tests {
         assert_eq!(table.id, 1000);
         let items = table.items;
         assert_eq!(items.len(), 2);
-        let item1 = items.get(0).unwrap();
+        let item1 = items.first().unwrap();
         assert_eq!(item1.id, 1001);
         assert_eq!(item1.time_to_prepare, 5);
         assert!(item1.name.eq("pizza"));
@@ -366,62 +176,52 @@ mod tests {
         assert_eq!(item2.id, 1002);
         assert_eq!(item2.time_to_prepare, 9);
         assert!(item2.name.eq("cake"));
-        database::close_test_db(
-            "
-        DELETE FROM restaurant_table WHERE id = 1000;
-        DELETE FROM item WHERE id = 1001;
-        DELETE FROM item WHERE id = 1002;",
-        );
     }
 
     #[test]
-    #[should_panic(expected = "No tables found.")]
     fn should_get_no_items_for_table() {
-        let rocket = luanch_server();
-        let client = Client::new(rocket).expect("Failed to start server");
-        client.get("/api/v1/tables/9999999").dispatch();
+        let server = TestServer::new();
+        let response = server
+            .client
+            .get("/api/v1/tables/9999999")
+            .header(as_staff(MANAGER))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn should_not_find_item_missing_from_table() {
+        let server = TestServer::new();
+        server.order(1030).item("pasta").place();
+        for uri in ["/api/v1/tables/1030/9999999", "/api/v1/tables/9999999/1"] {
+            let response = server.client.get(uri).header(as_staff(MANAGER)).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+        }
     }
 
     #[test]
     fn should_add_item() {
-        database::setup_test_db(
-            "INSERT INTO restaurant_table VALUES (1001, '10003,1004');
-                 INSERT INTO item VALUES (1003, 'pizza', 5);
-                 INSERT INTO item VALUES (1004, 'cake', 9);",
-        );
-        let rocket = luanch_server();
-        let client = Client::new(rocket).expect("Failed to start server");
-        let items = vec![String::from("pasta")];
-        let mut response = client
-            .post("/api/v1/tables")
-            .header(ContentType::JSON)
-            .body(get_item_json(String::from("999"), items))
-            .dispatch();
+        let server = TestServer::new();
+        let mut response = server.order(999).item("pasta").place();
         assert_eq!(response.status(), Status::Ok);
         assert_eq!(response.content_type(), Some(ContentType::JSON));
         let body = response.body_string().unwrap();
         let data =
             serde_json::from_str::<data::StatusMessage>(&body).expect("Failed to convert json.");
         assert!(data.message.eq("1 rows inserted."));
-        database::close_test_db(
-            "DELETE FROM restaurant_table WHERE id = 1001;
-        DELETE FROM item WHERE id = 1003;
-        DELETE FROM item WHERE id = 1004;",
-        );
     }
 
     #[test]
     fn should_get_item() {
-        database::setup_test_db(
-            "INSERT INTO restaurant_table VALUES (1002, '1005,1006');
-                 INSERT INTO item VALUES (1005, 'pizza', 5);
-                 INSERT INTO item VALUES (1006, 'cake', 9);",
-        );
-        let rocket = luanch_server();
-        let client = Client::new(rocket).expect("Failed to start server");
-        let mut response = client
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1005).create();
+        let cake = server.item("cake").time_to_prepare(9).create();
+        server.table().id(1002).items(&[pizza, cake]).create();
+        let mut response = server
+            .client
             .get("/api/v1/tables/1002/1005")
             .header(ContentType::JSON)
+            .header(as_staff(MANAGER))
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
         assert_eq!(response.content_type(), Some(ContentType::JSON));
@@ -431,33 +231,30 @@ mod tests {
         assert!(item.name.eq("pizza"));
         assert_eq!(item.id, 1005);
         assert_eq!(item.time_to_prepare, 5);
-        database::close_test_db(
-            "DELETE FROM restaurant_table WHERE id = 1002;
-                    DELETE FROM item WHERE id = 1005;
-                    DELETE FROM item WHERE id = 1006;",
-        );
     }
 
     #[test]
     fn should_delete_item() {
-        database::setup_test_db(
-            "INSERT INTO restaurant_table VALUES (1003, '1007,1008');
-                 INSERT INTO item VALUES (1007, 'pizza', 5);
-                 INSERT INTO item VALUES (1008, 'cake', 9);",
-        );
-        let rocket = luanch_server();
-        let client = Client::new(rocket).expect("Failed to start server");
-        let mut response = client
-            .delete("/api/v1/tables/1003/1007")
-            .header(ContentType::JSON)
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1007).create();
+        let cake = server.item("cake").id(1008).time_to_prepare(9).create();
+        server.table().id(1003).items(&[pizza, cake]).create();
+        let mut response = server
+            .client
+            .delete(void_path("/api/v1/tables/1003/1007", "sent back"))
+            .header(as_staff(SERVER))
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
         assert_eq!(response.content_type(), Some(ContentType::JSON));
         let response_body = response.body_string().unwrap();
         let status_message = serde_json::from_str::<data::StatusMessage>(&response_body)
             .expect("Failed to convert json.");
-        assert_eq!(status_message.message, "1 rows deleted.");
-        let mut response = client.get("/api/v1/tables/1003").dispatch();
+        assert_eq!(status_message.message, "1 rows voided.");
+        let mut response = server
+            .client
+            .get("/api/v1/tables/1003")
+            .header(as_staff(SERVER))
+            .dispatch();
         assert_eq!(response.status(), Status::Ok);
         assert_eq!(response.content_type(), Some(ContentType::JSON));
         let body = response.body_string().unwrap();
@@ -465,22 +262,2336 @@ mod tests {
         assert_eq!(table.id, 1003);
         let items = table.items;
         assert_eq!(items.len(), 1);
-        let item1 = items.get(0).unwrap();
+        let item1 = items.first().unwrap();
         assert_eq!(item1.id, 1008);
         assert_eq!(item1.time_to_prepare, 9);
         assert!(item1.name.eq("cake"));
-        database::close_test_db(
-            "DELETE FROM restaurant_table WHERE id = 1003;
-                    DELETE FROM item WHERE id = 1007;
-                    DELETE FROM item WHERE id = 1008;",
+    }
+
+    #[test]
+    fn should_reject_missing_staff() {
+        let server = TestServer::new();
+        let response = server.client.get("/api/v1/tables").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn should_reject_unknown_staff() {
+        let server = TestServer::new();
+        let response = server
+            .client
+            .get("/api/v1/tables")
+            .header(as_staff("8999"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn should_forbid_cook_adding_items() {
+        let server = TestServer::new();
+        let response = server
+            .client
+            .post("/api/v1/tables")
+            .header(ContentType::JSON)
+            .header(as_staff(COOK))
+            .body(server.order(999).item("pasta").json())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn should_forbid_cook_removing_items() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1009).create();
+        let cake = server.item("cake").time_to_prepare(9).create();
+        server.table().id(1004).items(&[pizza, cake]).create();
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1004/1009", "burnt"))
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let mut response = server
+            .client
+            .get("/api/v1/tables/1004")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        let table = serde_json::from_str::<data::Table>(&body).expect("Failed to convert json.");
+        assert_eq!(table.items.len(), 2);
+    }
+
+    #[test]
+    fn should_let_manager_remove_items() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1011).create();
+        let cake = server.item("cake").time_to_prepare(9).create();
+        server.table().id(1005).items(&[pizza, cake]).create();
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1005/1011", "comped"))
+            .header(as_staff(MANAGER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn should_audit_added_items() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").create();
+        server.table().id(1006).items(&[pizza]).create();
+        let response = server.order(1006).item("pasta").place();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = server
+            .client
+            .get("/api/v1/audit?table=1006")
+            .header(as_staff(MANAGER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        let entries =
+            serde_json::from_str::<Vec<data::AuditEntry>>(&body).expect("Failed to convert json.");
+        assert_eq!(entries.len(), 1);
+        let entry = entries.first().unwrap();
+        assert_eq!(entry.actor_id, 9002);
+        assert_eq!(entry.action, "add_item");
+        assert!(entry.before.is_none());
+        let after = serde_json::from_value::<data::Item>(entry.after.clone().unwrap()).unwrap();
+        assert!(after.name.eq("pasta"));
+    }
+
+    #[test]
+    fn should_audit_removed_items_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let pizza = server.item("pizza").id(1014).create();
+            let cake = server.item("cake").time_to_prepare(9).create();
+            server.table().id(1007).items(&[pizza, cake]).create();
+            let response = server
+                .client
+                .delete(void_path("/api/v1/tables/1007/1014", "customer left"))
+                .header(as_staff(SERVER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let mut response = server
+                .client
+                .get("/api/v1/audit?table=1007&actor=9002")
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let body = response.body_string().unwrap();
+            let entries = serde_json::from_str::<Vec<data::AuditEntry>>(&body)
+                .expect("Failed to convert json.");
+            assert_eq!(entries.len(), 1, "{}", backend);
+            let entry = entries.first().unwrap();
+            assert_eq!(entry.action, "void_item", "{}", backend);
+            assert_eq!(entry.item_id, Some(1014), "{}", backend);
+            let before =
+                serde_json::from_value::<data::Item>(entry.before.clone().unwrap()).unwrap();
+            assert!(before.name.eq("pizza"), "{}", backend);
+            let after = serde_json::from_value::<data::Void>(entry.after.clone().unwrap()).unwrap();
+            assert_eq!(after.reason, "customer left", "{}", backend);
+            assert_eq!(after.voided_by, 9002, "{}", backend);
+            let mut response = server
+                .client
+                .get("/api/v1/audit?table=1007&actor=9001")
+                .header(as_staff(MANAGER))
+                .dispatch();
+            let body = response.body_string().unwrap();
+            let entries = serde_json::from_str::<Vec<data::AuditEntry>>(&body)
+                .expect("Failed to convert json.");
+            assert!(entries.is_empty(), "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_audit_tables_menu_and_allergies_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let pizza = server.menu_item("pizza").create();
+            let response = server.order(777).item("pizza").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table_id = get_v2_tables(&server.client)[0].id;
+            assert_ne!(table_id, 777, "{}", backend);
+            assert!(
+                get_audit(&server.client, "?table=777").is_empty(),
+                "{}",
+                backend
+            );
+            let entries = get_audit(&server.client, &format!("?table={}", table_id));
+            let actions = entries
+                .iter()
+                .map(|entry| entry.action.as_str())
+                .collect::<Vec<&str>>();
+            assert_eq!(actions, vec!["create_table", "add_item"], "{}", backend);
+            let created = entries[0].after.clone().unwrap();
+            let created = serde_json::from_value::<data::Table>(created).unwrap();
+            assert_eq!(created.id, table_id, "{}", backend);
+            let response = server
+                .client
+                .put(format!("/api/v1/menu/{}/availability", pizza))
+                .header(ContentType::JSON)
+                .header(as_staff(COOK))
+                .body("{\"available\": false}")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let response = server
+                .client
+                .put(format!("/api/v2/tables/{}/allergies", table_id))
+                .header(ContentType::JSON)
+                .header(as_staff(SERVER))
+                .body("{\"allergies\": [{\"allergen\": \"peanuts\"}]}")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let entries = get_audit(&server.client, "");
+            let availability = &entries[2];
+            assert_eq!(availability.action, "set_availability", "{}", backend);
+            assert_eq!(availability.table_id, None, "{}", backend);
+            assert_eq!(availability.actor_id, 9003, "{}", backend);
+            let before = availability.before.clone().unwrap();
+            let before = serde_json::from_value::<data::MenuItem>(before).unwrap();
+            let after = availability.after.clone().unwrap();
+            let after = serde_json::from_value::<data::MenuItem>(after).unwrap();
+            assert_eq!((before.id, before.available), (pizza, true), "{}", backend);
+            assert_eq!((after.id, after.available), (pizza, false), "{}", backend);
+            let allergies = &entries[3];
+            assert_eq!(allergies.action, "set_allergies", "{}", backend);
+            assert_eq!(allergies.table_id, Some(table_id), "{}", backend);
+            let after = allergies.after.clone().unwrap();
+            let after = serde_json::from_value::<api::v2::Allergies>(after).unwrap();
+            assert_eq!(after.allergies[0].allergen, "peanuts", "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_only_let_manager_view_audit() {
+        let server = TestServer::new();
+        for staff in [SERVER, COOK] {
+            let response = server
+                .client
+                .get("/api/v1/audit")
+                .header(as_staff(staff))
+                .dispatch();
+            assert_eq!(response.status(), Status::Forbidden);
+        }
+        let response = server
+            .client
+            .get("/api/v1/audit?from=0&to=0")
+            .header(as_staff(MANAGER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn should_require_void_reason() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1016).create();
+        let cake = server.item("cake").time_to_prepare(9).create();
+        server.table().id(1008).items(&[pizza, cake]).create();
+        for path in [
+            "/api/v1/tables/1008/1016",
+            &void_path("/api/v1/tables/1008/1016", " "),
+        ] {
+            let response = server
+                .client
+                .delete(path)
+                .header(as_staff(SERVER))
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+        let table = get_test_table(&server.client, "1008");
+        assert_eq!(table.items.len(), 2);
+    }
+
+    #[test]
+    fn should_restore_voided_item() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1018).create();
+        let cake = server.item("cake").id(1019).time_to_prepare(9).create();
+        server.table().id(1009).items(&[pizza, cake]).create();
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1009/1018", "wrong table"))
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = server
+            .client
+            .post("/api/v1/tables/1009/1018/restore")
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = server
+            .client
+            .post("/api/v1/tables/1009/1018/restore")
+            .header(as_staff(MANAGER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let table = get_test_table(&server.client, "1009");
+        let mut ids = table.items.iter().map(|item| item.id).collect::<Vec<i32>>();
+        ids.sort();
+        assert_eq!(ids, vec![1018, 1019]);
+        let response = server
+            .client
+            .post("/api/v1/tables/1009/1018/restore")
+            .header(as_staff(MANAGER))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn should_keep_other_items_when_voiding_from_large_table() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1020).create();
+        let cake = server.item("cake").id(1021).time_to_prepare(9).create();
+        let pasta = server.item("pasta").id(1022).time_to_prepare(7).create();
+        server
+            .table()
+            .id(1010)
+            .items(&[pizza, cake, pasta])
+            .create();
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1010/1021", "sent back"))
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let table = get_test_table(&server.client, "1010");
+        let ids = table.items.iter().map(|item| item.id).collect::<Vec<i32>>();
+        assert_eq!(ids, vec![1020, 1022]);
+    }
+
+    #[test]
+    fn should_not_void_item_from_another_table() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").create();
+        let cake = server.item("cake").id(1024).time_to_prepare(9).create();
+        server.table().id(1011).items(&[pizza]).create();
+        server.table().id(1012).items(&[cake]).create();
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1011/1024", "sent back"))
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let table = get_test_table(&server.client, "1012");
+        let ids = table.items.iter().map(|item| item.id).collect::<Vec<i32>>();
+        assert_eq!(ids, vec![1024]);
+    }
+
+    #[test]
+    fn should_replay_retried_post_with_idempotency_key_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let pizza = server.item("pizza").create();
+            server.table().id(1013).items(&[pizza]).create();
+            let mut messages = Vec::new();
+            for _ in 0..3 {
+                let mut response = server
+                    .client
+                    .post("/api/v1/tables")
+                    .header(ContentType::JSON)
+                    .header(as_staff(SERVER))
+                    .header(idempotency_key("test-key-1013"))
+                    .body(server.order(1013).item("pasta").json())
+                    .dispatch();
+                assert_eq!(response.status(), Status::Ok, "{}", backend);
+                messages.push(response.body_string().unwrap());
+            }
+            assert_eq!(messages[0], messages[1], "{}", backend);
+            assert_eq!(messages[0], messages[2], "{}", backend);
+            let table = get_test_table(&server.client, "1013");
+            assert_eq!(table.items.len(), 2, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_take_over_idempotency_key_left_by_request_that_died_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let pizza = server.item("pizza").create();
+            server.table().id(1016).items(&[pizza]).create();
+            let body = server.order(1016).item("pasta").json();
+            let claim = |created_at: i64| {
+                server.execute(&format!(
+                    "delete from idempotency_key;
+                    insert into idempotency_key (key, request, response, created_at)
+                        values ('1:test-key-1016', '{}', null, {});",
+                    body, created_at
+                ))
+            };
+            let post = || {
+                server
+                    .client
+                    .post("/api/v1/tables")
+                    .header(ContentType::JSON)
+                    .header(as_staff(SERVER))
+                    .header(idempotency_key("test-key-1016"))
+                    .body(body.clone())
+                    .dispatch()
+            };
+            claim(audit::now());
+            assert_eq!(post().status(), Status::Conflict, "{}", backend);
+            claim(audit::now() - idempotency::LEASE - 1);
+            assert_eq!(post().status(), Status::Ok, "{}", backend);
+            assert_eq!(post().status(), Status::Ok, "{}", backend);
+            let table = get_test_table(&server.client, "1016");
+            assert_eq!(table.items.len(), 2, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_answer_order_when_its_response_is_not_stored_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let pizza = server.item("pizza").create();
+            server.table().id(1017).items(&[pizza]).create();
+            server.fail_on("update", "idempotency_key");
+            let response = server
+                .client
+                .post("/api/v1/tables")
+                .header(ContentType::JSON)
+                .header(as_staff(SERVER))
+                .header(idempotency_key("test-key-1017"))
+                .body(server.order(1017).item("pasta").json())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table = get_test_table(&server.client, "1017");
+            assert_eq!(table.items.len(), 2, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_reject_idempotency_key_reused_for_different_request() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").create();
+        server.table().id(1014).items(&[pizza]).create();
+        let response = server
+            .client
+            .post("/api/v1/tables")
+            .header(ContentType::JSON)
+            .header(as_staff(SERVER))
+            .header(idempotency_key("test-key-1014"))
+            .body(server.order(1014).item("pasta").json())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = server
+            .client
+            .post("/api/v1/tables")
+            .header(ContentType::JSON)
+            .header(as_staff(SERVER))
+            .header(idempotency_key("test-key-1014"))
+            .body(server.order(1014).item("cake").json())
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let table = get_test_table(&server.client, "1014");
+        assert_eq!(table.items.len(), 2);
+    }
+
+    #[test]
+    fn should_tag_table_with_version() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1027).create();
+        let cake = server.item("cake").time_to_prepare(9).create();
+        server.table().id(1015).items(&[pizza, cake]).create();
+        let response = server
+            .client
+            .get("/api/v1/tables/1015")
+            .header(as_staff(SERVER))
+            .dispatch();
+        let version_tag = response.headers().get_one("ETag").unwrap().to_string();
+        assert_eq!(version_tag, "\"1\"");
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1015/1027", "sent back"))
+            .header(as_staff(SERVER))
+            .header(Header::new(etag::IF_MATCH_HEADER, "\"0\""))
+            .dispatch();
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1015/1027", "sent back"))
+            .header(as_staff(SERVER))
+            .header(Header::new(etag::IF_MATCH_HEADER, version_tag))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = server
+            .client
+            .get("/api/v1/tables/1015")
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.headers().get_one("ETag"), Some("\"2\""));
+    }
+
+    #[test]
+    fn should_not_add_items_with_stale_version() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1029).create();
+        server.table().id(1016).items(&[pizza]).version(3).create();
+        let response = server
+            .client
+            .post("/api/v1/tables")
+            .header(ContentType::JSON)
+            .header(as_staff(SERVER))
+            .header(Header::new(etag::IF_MATCH_HEADER, "\"2\""))
+            .body(server.order(1016).item("pasta").json())
+            .dispatch();
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        let table = get_test_table(&server.client, "1016");
+        assert_eq!(get_test_item_ids(&table), "1029");
+    }
+
+    #[test]
+    fn should_not_lose_items_with_concurrent_writers() {
+        let server = TestServer::new();
+        server.table().id(1017).create();
+        let writers = (0..8)
+            .map(|writer| {
+                let database = server.database().clone();
+                std::thread::spawn(move || {
+                    let tables = repository::sqlite::SqliteRepository::new(database);
+                    for item in 0..5 {
+                        let item_ids = [(20000 + writer * 5 + item).to_string()];
+                        let added = service::add_to_table(
+                            &tables,
+                            RESTAURANT,
+                            "1017",
+                            &item_ids,
+                            None,
+                            &|_, _| Vec::new(),
+                        );
+                        assert!(added.is_ok());
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().expect("Writer panicked");
+        }
+        let table_data = database::find_table(&server.database().connect(), RESTAURANT, "1017")
+            .unwrap()
+            .unwrap();
+        let mut item_ids = database::split_item_ids(&table_data.item_ids);
+        item_ids.sort();
+        let expected = (20000..20040).map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(item_ids, expected);
+        assert_eq!(table_data.version, 41);
+    }
+
+    #[test]
+    fn should_echo_request_id() {
+        let server = TestServer::new();
+        let response = server
+            .client
+            .get("/api/v1/audit?from=0&to=0")
+            .header(as_staff(MANAGER))
+            .header(Header::new(logging::REQUEST_ID_HEADER, "till-3-retry"))
+            .dispatch();
+        assert_eq!(
+            response.headers().get_one(logging::REQUEST_ID_HEADER),
+            Some("till-3-retry")
+        );
+        let response = server.client.get("/api/v1/tables").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let request_id = response.headers().get_one(logging::REQUEST_ID_HEADER);
+        assert_eq!(request_id.map(|id| id.len()), Some(16));
+    }
+
+    #[test]
+    fn should_expose_metrics() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").create();
+        server.table().id(1018).items(&[pizza]).create();
+        let response = server
+            .client
+            .get("/api/v1/tables/1018")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = server.client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(body.contains(
+            "http_requests_total{method=\"GET\",route=\"/api/v1/tables/<table>\",status=\"200\"} 1"
+        ));
+        assert!(body.contains("# TYPE http_request_duration_seconds histogram"));
+        assert!(body.contains("restaurant_open_tables{restaurant_id=\"1\"} 1"));
+        assert!(body.contains("restaurant_items_ordered_total "));
+        assert!(body.contains("restaurant_items_voided_total "));
+        assert!(body.contains("restaurant_average_preparation_minutes "));
+    }
+
+    #[test]
+    fn should_report_business_metrics_by_restaurant_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let pizza = server.item("pizza").time_to_prepare(30).create();
+            let cake = server.item("cake").time_to_prepare(5).create();
+            server.table().id(1040).items(&[pizza, cake]).create();
+            server.execute(&format!(
+                "update item set fired_at = 1000, ready_at = 1600 where id = {};",
+                pizza
+            ));
+            let soup = server.item("soup").restaurant(OTHER_RESTAURANT).create();
+            server
+                .table()
+                .restaurant(OTHER_RESTAURANT)
+                .items(&[soup])
+                .create();
+            let mut response = server.client.get("/metrics").dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let body = response.body_string().unwrap();
+            for line in &[
+                "restaurant_open_tables{restaurant_id=\"1\"} 1",
+                "restaurant_open_tables{restaurant_id=\"2\"} 1",
+                "restaurant_average_preparation_minutes{restaurant_id=\"1\"} 10",
+                "restaurant_average_preparation_minutes{restaurant_id=\"2\"} 0",
+            ] {
+                assert!(body.contains(line), "{} {}", backend, line);
+            }
+        }
+    }
+
+    #[test]
+    fn should_report_health_without_staff() {
+        let server = TestServer::new();
+        let mut response = server.client.get("/health").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        let report = serde_json::from_str::<data::HealthReport>(&body).unwrap();
+        assert_eq!(report.status, health::UP);
+    }
+
+    #[test]
+    fn should_be_ready_once_database_is_set_up_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let mut response = server.client.get("/ready").dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let body = response.body_string().unwrap();
+            let report = serde_json::from_str::<data::HealthReport>(&body).unwrap();
+            assert_eq!(report.status, health::UP, "{}", backend);
+            let store = match backend {
+                "postgres" => "postgres",
+                _ => "database",
+            };
+            for name in &[store, "schema", "query"] {
+                assert_eq!(report.components[*name].status, health::UP, "{}", backend);
+            }
+        }
+    }
+
+    #[test]
+    fn should_not_be_ready_when_postgres_is_behind_on_migrations() {
+        let server = match TestServer::postgres() {
+            Some(server) => server,
+            None => return,
+        };
+        let sqlite = std::path::Path::new(server.database().path());
+        assert!(!sqlite.exists());
+        server.execute("update schema_migration set version = 1;");
+        let mut response = server.client.get("/ready").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body = response.body_string().unwrap();
+        let report = serde_json::from_str::<data::HealthReport>(&body).unwrap();
+        assert_eq!(report.components["postgres"].status, health::UP);
+        assert_eq!(report.components["schema"].status, health::DOWN);
+    }
+
+    #[test]
+    fn should_serve_docs_page_without_another_host() {
+        let server = TestServer::new();
+        let mut response = server.client.get("/api/v1/docs").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let page = response.body_string().unwrap();
+        assert!(!page.contains("//"), "docs page loads from another host");
+        for (path, content_type) in [
+            ("/api/v1/docs/swagger-ui.css", ContentType::CSS),
+            ("/api/v1/docs/swagger-ui-bundle.js", ContentType::JavaScript),
+        ] {
+            assert!(page.contains(path), "docs page does not load {}", path);
+            let response = server.client.get(path).dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", path);
+            assert_eq!(response.content_type(), Some(content_type), "{}", path);
+        }
+    }
+
+    #[test]
+    fn should_keep_postgres_connections_open_between_requests() {
+        let server = match TestServer::postgres() {
+            Some(server) => server,
+            None => return,
+        };
+        for _ in 0..5 {
+            let response = server.order(1).item("pizza").item("cake").place();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        let workers = server.client.rocket().config().workers as i64;
+        let open = server.postgres_connections();
+        assert!(open >= 1 && open <= workers, "{} connections open", open);
+    }
+
+    #[test]
+    fn should_match_spec_to_routes() {
+        let server = TestServer::new();
+        let mut routes: Vec<(String, String, String)> = server
+            .client
+            .rocket()
+            .routes()
+            .map(|route| {
+                (
+                    route.method.as_str().to_lowercase(),
+                    openapi::openapi_path(route.uri.path()),
+                    route.name.unwrap_or_default().to_string(),
+                )
+            })
+            .collect();
+        routes.sort();
+        let mut response = server.client.get("/api/v1/openapi.json").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let document: serde_json::Value =
+            serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        let mut documented: Vec<(String, String, String)> = Vec::new();
+        for (path, operations) in document["paths"].as_object().unwrap() {
+            for (method, operation) in operations.as_object().unwrap() {
+                let id = operation["operationId"].as_str().unwrap().to_string();
+                documented.push((method.clone(), path.clone(), id));
+            }
+        }
+        documented.sort();
+        assert_eq!(routes, documented);
+    }
+
+    #[test]
+    fn should_keep_v1_responses_unchanged() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").id(1031).create();
+        let cake = server.item("cake").id(1032).time_to_prepare(9).create();
+        let soup = server.item("soup").id(1033).time_to_prepare(7).create();
+        server.table().id(1019).items(&[pizza, cake, soup]).create();
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1019/1033", "sent back"))
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = server
+            .client
+            .get("/api/v1/tables/1019")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(
+            response.body_string().unwrap(),
+            "{\"id\":1019,\"items\":[{\"id\":1031,\"time_to_prepare\":5,\"name\":\"pizza\"},{\"id\":1032,\"time_to_prepare\":9,\"name\":\"cake\"}]}"
+        );
+        let mut response = server
+            .client
+            .get("/api/v1/tables/1019/1032")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(
+            response.body_string().unwrap(),
+            "{\"id\":1032,\"time_to_prepare\":9,\"name\":\"cake\"}"
+        );
+        let mut response = server
+            .client
+            .get("/api/v1/tables")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(
+            response.body_string().unwrap(),
+            "[{\"id\":1019,\"items\":[{\"id\":1031,\"time_to_prepare\":5,\"name\":\"pizza\"},{\"id\":1032,\"time_to_prepare\":9,\"name\":\"cake\"}]}]"
         );
     }
 
-    fn get_item_json(table_id: String, items: Vec<String>) -> String {
-        let request = data::ItemRequest {
-            table_id: table_id,
-            items: items,
+    #[test]
+    fn should_show_voided_items_in_v2() {
+        let server = TestServer::new();
+        let pizza = server.item("pizza").create();
+        let cake = server.item("cake").id(1035).time_to_prepare(9).create();
+        server.table().id(1020).items(&[pizza, cake]).create();
+        let response = server
+            .client
+            .delete(void_path("/api/v1/tables/1020/1035", "sent back"))
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = server
+            .client
+            .get("/api/v2/tables/1020")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("\"2\""));
+        let body = response.body_string().unwrap();
+        let table = serde_json::from_str::<api::v2::Table>(&body).expect("Failed to convert json.");
+        assert_eq!(table.status, api::v2::TableStatus::Open);
+        assert_eq!(table.order.items.len(), 2);
+        assert_eq!(table.order.items[0].status, api::v2::ItemStatus::Ordered);
+        assert_eq!(table.order.items[1].status, api::v2::ItemStatus::Voided);
+        let void = table.order.items[1].void.as_ref().expect("Void details.");
+        assert_eq!(void.reason, "sent back");
+        let response = server
+            .client
+            .get("/api/v2/tables/not-a-number")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = server
+            .client
+            .get("/api/v2/tables/404404")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn should_not_share_data_between_servers() {
+        let first = TestServer::new();
+        let second = TestServer::new();
+        let pizza = first.item("pizza").id(1036).create();
+        first.table().id(1021).items(&[pizza]).create();
+        assert_eq!(get_test_table(&first.client, "1021").items.len(), 1);
+        let response = second
+            .client
+            .get("/api/v2/tables/1021")
+            .header(as_staff(COOK))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_ne!(first.database().path(), second.database().path());
+    }
+
+    /// Builds a server with `config` and returns the fairings that stopped it launching.
+    fn failed_fairings(config: rocket::config::ConfigBuilder) -> Vec<&'static str> {
+        let config = config.finalize().expect("Failed to build test config.");
+        let error = match Client::new(build_server(rocket::custom(config))) {
+            Ok(_) => panic!("Launched without a database."),
+            Err(error) => error,
         };
-        serde_json::to_string(&request).unwrap()
+        match error.kind() {
+            rocket::error::LaunchErrorKind::FailedFairings(failed) => failed.clone(),
+            kind => panic!("Failed to launch for another reason: {}", kind),
+        }
+    }
+
+    #[test]
+    fn should_not_launch_when_postgres_cannot_be_set_up() {
+        let config = rocket::Config::build(rocket::config::Environment::Development)
+            .extra("database", ":memory:")
+            .extra("database_backend", "postgres")
+            .extra("database_url", "host=/nonexistent user=postgres");
+        assert_eq!(failed_fairings(config), vec!["Repositories"]);
+    }
+
+    #[test]
+    fn should_not_launch_when_sqlite_cannot_be_set_up() {
+        let config = rocket::Config::build(rocket::config::Environment::Development)
+            .extra("database", "/nonexistent/data.sqlite");
+        assert_eq!(failed_fairings(config), vec!["Repositories"]);
+    }
+
+    #[test]
+    fn should_order_void_and_restore_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let response = server.order(1).item("pizza").item("cake").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let tables = get_v2_tables(&server.client);
+            assert_eq!(tables.len(), 1, "{}", backend);
+            let table = &tables[0];
+            assert_eq!(table.version, 1, "{}", backend);
+            let pizza = table.order.items[0].id;
+            let path = format!("/api/v1/tables/{}/{}", table.id, pizza);
+            let attempts = [("\"0\"", Status::PreconditionFailed), ("\"1\"", Status::Ok)];
+            for (version, status) in attempts {
+                let response = server
+                    .client
+                    .delete(void_path(&path, "sent back"))
+                    .header(as_staff(SERVER))
+                    .header(Header::new(etag::IF_MATCH_HEADER, version))
+                    .dispatch();
+                assert_eq!(response.status(), status, "{}", backend);
+            }
+            let tables = get_v2_tables(&server.client);
+            let table = &tables[0];
+            assert_eq!(table.version, 2, "{}", backend);
+            let statuses = table
+                .order
+                .items
+                .iter()
+                .map(|item| (item.name.as_str(), &item.status))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                statuses,
+                vec![
+                    ("cake", &api::v2::ItemStatus::Ordered),
+                    ("pizza", &api::v2::ItemStatus::Voided)
+                ],
+                "{}",
+                backend
+            );
+            let response = server
+                .client
+                .post(format!("{}/restore", path))
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let tables = get_v2_tables(&server.client);
+            let table = &tables[0];
+            let names = table
+                .order
+                .items
+                .iter()
+                .filter(|item| item.status == api::v2::ItemStatus::Ordered)
+                .map(|item| item.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, vec!["cake", "pizza"], "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_leave_table_alone_when_void_or_restore_fails_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let pizza = server.item("pizza").create();
+            let cake = server.item("cake").create();
+            let table = server.table().items(&[pizza, cake]).create();
+            let cake_path = format!("/api/v1/tables/{}/{}", table, cake);
+            let response = server
+                .client
+                .delete(void_path(&cake_path, "sent back"))
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            server.fail_on("insert", "voided_item");
+            server.fail_on("delete", "voided_item");
+            let response = server
+                .client
+                .delete(void_path(
+                    &format!("/api/v1/tables/{}/{}", table, pizza),
+                    "cold",
+                ))
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(
+                response.status(),
+                Status::InternalServerError,
+                "{}",
+                backend
+            );
+            let response = server
+                .client
+                .post(format!("{}/restore", cake_path))
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(
+                response.status(),
+                Status::InternalServerError,
+                "{}",
+                backend
+            );
+            let tables = get_v2_tables(&server.client);
+            assert_eq!(tables[0].version, 2, "{}", backend);
+            let statuses = tables[0]
+                .order
+                .items
+                .iter()
+                .map(|item| (item.name.as_str(), &item.status))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                statuses,
+                vec![
+                    ("pizza", &api::v2::ItemStatus::Ordered),
+                    ("cake", &api::v2::ItemStatus::Voided)
+                ],
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn should_leave_table_alone_when_audit_log_fails_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let response = server
+                .order(1)
+                .item("soup")
+                .item_in_course("steak", 2)
+                .item("cake")
+                .place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table = get_v2_tables(&server.client).remove(0);
+            let item_path = |index: usize| {
+                format!(
+                    "/api/v1/tables/{}/{}",
+                    table.id, table.order.items[index].id
+                )
+            };
+            let response = server
+                .client
+                .delete(void_path(&item_path(2), "sent back"))
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let audit_entries = || {
+                let mut response = server
+                    .client
+                    .get("/api/v1/audit")
+                    .header(as_staff(MANAGER))
+                    .dispatch();
+                let body = response.body_string().unwrap();
+                serde_json::from_str::<Vec<data::AuditEntry>>(&body)
+                    .unwrap()
+                    .len()
+            };
+            let recorded = audit_entries();
+            server.fail_on("insert", "audit_log");
+            let responses = vec![
+                server.order(table.id).item("pie").place(),
+                server.order(table.id + 1).item("pie").place(),
+                server
+                    .client
+                    .delete(void_path(&item_path(0), "cold"))
+                    .header(as_staff(MANAGER))
+                    .dispatch(),
+                server
+                    .client
+                    .post(format!("{}/restore", item_path(2)))
+                    .header(as_staff(MANAGER))
+                    .dispatch(),
+                server
+                    .client
+                    .post(format!("/api/v1/tables/{}/courses/2/fire", table.id))
+                    .header(as_staff(SERVER))
+                    .dispatch(),
+            ];
+            for response in responses {
+                assert_eq!(
+                    response.status(),
+                    Status::InternalServerError,
+                    "{}",
+                    backend
+                );
+            }
+            let tables = get_v2_tables(&server.client);
+            assert_eq!(tables.len(), 1, "{}", backend);
+            assert_eq!(tables[0].version, 2, "{}", backend);
+            let statuses = tables[0]
+                .order
+                .items
+                .iter()
+                .map(|item| (item.name.as_str(), &item.status))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                statuses,
+                vec![
+                    ("soup", &api::v2::ItemStatus::Ordered),
+                    ("steak", &api::v2::ItemStatus::Held),
+                    ("cake", &api::v2::ItemStatus::Voided)
+                ],
+                "{}",
+                backend
+            );
+            assert_eq!(audit_entries(), recorded, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_not_lose_items_with_concurrent_writers_on_every_backend() {
+        for server in TestServer::all() {
+            let table_id = server
+                .tables()
+                .create(RESTAURANT, &[], &|_| Vec::new())
+                .unwrap()
+                .to_string();
+            let writers = (0..8)
+                .map(|writer| {
+                    let tables = server.tables();
+                    let items = server.items();
+                    let stock = server.stock();
+                    let table_id = table_id.clone();
+                    std::thread::spawn(move || {
+                        let menu = domain::Menu::default();
+                        for item in 0..5 {
+                            let lines = [domain::OrderLine {
+                                name: format!("dish {}-{}", writer, item),
+                                modifiers: Vec::new(),
+                                course: 1,
+                            }];
+                            let added = service::add_items(
+                                &*tables,
+                                &*items,
+                                &*stock,
+                                &menu,
+                                RESTAURANT,
+                                &table_id,
+                                &lines,
+                                audit::now(),
+                                None,
+                                &|_, _, _| Vec::new(),
+                            );
+                            assert!(added.is_ok());
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            for writer in writers {
+                writer.join().expect("Writer panicked");
+            }
+            let table = server.tables().find(RESTAURANT, &table_id).unwrap();
+            let table = table.unwrap();
+            assert_eq!(table.ordered_items().count(), 40, "{}", server.backend());
+            assert_eq!(table.version, 41, "{}", server.backend());
+        }
+    }
+
+    #[test]
+    fn should_keep_restaurants_apart_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let response = server.order(1).item("pizza").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let tables = get_v2_tables(&server.client);
+            let table = &tables[0];
+            let path = format!("/api/v1/tables/{}/{}", table.id, table.order.items[0].id);
+            let mut response = server
+                .client
+                .get("/api/v1/tables")
+                .header(as_staff(OTHER_SERVER))
+                .dispatch();
+            assert_eq!(response.body_string().unwrap(), "[]", "{}", backend);
+            let response = server
+                .client
+                .get(format!("/api/v2/tables/{}", table.id))
+                .header(as_staff(OTHER_SERVER))
+                .dispatch();
+            assert_eq!(response.status(), Status::NotFound, "{}", backend);
+            let response = server
+                .client
+                .delete(void_path(&path, "not ours"))
+                .header(as_staff(OTHER_MANAGER))
+                .dispatch();
+            assert_eq!(response.status(), Status::NotFound, "{}", backend);
+            let response = server.order(table.id).item("cake").by(OTHER_SERVER).place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let tables = get_v2_tables(&server.client);
+            assert_eq!(tables.len(), 1, "{}", backend);
+            assert_eq!(tables[0].version, 1, "{}", backend);
+            assert_eq!(tables[0].order.items.len(), 1, "{}", backend);
+            let mut response = server
+                .client
+                .get("/api/v1/tables")
+                .header(as_staff(OTHER_SERVER))
+                .dispatch();
+            let body = response.body_string().unwrap();
+            let theirs = serde_json::from_str::<Vec<data::Table>>(&body).unwrap();
+            assert_eq!(theirs.len(), 1, "{}", backend);
+            assert_eq!(theirs[0].items[0].name, "cake", "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_only_audit_own_restaurant() {
+        let server = TestServer::new();
+        let response = server.order(1022).item("pasta").place();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = server
+            .client
+            .get("/api/v1/audit")
+            .header(as_staff(OTHER_MANAGER))
+            .dispatch();
+        assert_eq!(response.body_string().unwrap(), "[]");
+        let mut response = server
+            .client
+            .get("/api/v1/audit")
+            .header(as_staff(MANAGER))
+            .dispatch();
+        let body = response.body_string().unwrap();
+        let entries = serde_json::from_str::<Vec<data::AuditEntry>>(&body).unwrap();
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn should_not_share_idempotency_keys_between_restaurants() {
+        let server = TestServer::new();
+        for staff in [SERVER, OTHER_SERVER] {
+            let response = server
+                .client
+                .post("/api/v1/tables")
+                .header(ContentType::JSON)
+                .header(as_staff(staff))
+                .header(idempotency_key("test-key-1023"))
+                .body(server.order(1023).item("pasta").json())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        for staff in [SERVER, OTHER_SERVER] {
+            let mut response = server
+                .client
+                .get("/api/v1/tables")
+                .header(as_staff(staff))
+                .dispatch();
+            let body = response.body_string().unwrap();
+            let tables = serde_json::from_str::<Vec<data::Table>>(&body).unwrap();
+            assert_eq!(tables.len(), 1);
+            assert_eq!(tables[0].items.len(), 1);
+        }
+    }
+
+    #[test]
+    fn should_get_restaurant_of_staff() {
+        let server = TestServer::new();
+        for (staff, restaurant_id) in [(MANAGER, 1), (OTHER_MANAGER, 2)] {
+            let mut response = server
+                .client
+                .get("/api/v1/restaurant")
+                .header(as_staff(staff))
+                .dispatch();
+            let body = response.body_string().unwrap();
+            let restaurant = serde_json::from_str::<data::Restaurant>(&body).unwrap();
+            assert_eq!(restaurant.id, restaurant_id);
+        }
+    }
+
+    #[test]
+    fn should_forbid_staff_of_another_restaurant() {
+        let server = TestServer::new();
+        let response = server
+            .client
+            .get("/api/v1/tables")
+            .header(as_staff(SERVER))
+            .header(Header::new(auth::RESTAURANT_HEADER, "2"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = server
+            .client
+            .get("/api/v1/tables")
+            .header(as_staff(SERVER))
+            .header(Header::new(auth::RESTAURANT_HEADER, "1"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn should_list_menu_with_modifier_groups() {
+        let server = TestServer::new();
+        server
+            .menu_item("burger")
+            .price(1200)
+            .group("Cooking", 1, 1, &[("rare", 0), ("medium", 0)])
+            .group("Extras", 0, 2, &[("bacon", 200)])
+            .create();
+        server.menu_item("cake").category("Desserts").create();
+        let mut response = server
+            .client
+            .get("/api/v1/menu")
+            .header(as_staff(COOK))
+            .dispatch();
+        let body = response.body_string().unwrap();
+        let menu = serde_json::from_str::<Vec<data::MenuCategory>>(&body).unwrap();
+        let categories = menu
+            .iter()
+            .map(|category| category.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(categories, vec!["Mains", "Desserts"]);
+        let burger = &menu[0].items[0];
+        assert_eq!(burger.price, 1200);
+        assert_eq!(burger.modifier_groups[0].min_selected, 1);
+        assert_eq!(burger.modifier_groups[0].modifiers.len(), 2);
+        assert_eq!(burger.modifier_groups[1].modifiers[0].price_delta, 200);
+        let mut response = server
+            .client
+            .get("/api/v1/menu")
+            .header(as_staff(OTHER_SERVER))
+            .dispatch();
+        assert_eq!(response.body_string().unwrap(), "[]");
+    }
+
+    #[test]
+    fn should_not_order_menu_items_that_ran_out() {
+        let server = TestServer::new();
+        let burger = server.menu_item("burger").create();
+        let path = format!("/api/v1/menu/{}/availability", burger);
+        let attempts = [
+            (SERVER, "{\"available\": false}", Status::Forbidden),
+            (
+                COOK,
+                "{\"available\": false, \"available_at\": 1}",
+                Status::BadRequest,
+            ),
+            (COOK, "{\"available\": false}", Status::Ok),
+        ];
+        for (staff, body, status) in attempts {
+            let response = server
+                .client
+                .put(path.clone())
+                .header(ContentType::JSON)
+                .header(as_staff(staff))
+                .body(body)
+                .dispatch();
+            assert_eq!(response.status(), status, "{}", body);
+        }
+        let menu = get_menu(&server.client);
+        assert!(!menu[0].items[0].available);
+        let mut response = server.order(1).item("burger").place();
+        assert_eq!(response.status(), Status::Conflict);
+        assert_eq!(response.body_string().unwrap(), "burger is not available.");
+        let response = server
+            .client
+            .put("/api/v1/menu/404404/availability")
+            .header(ContentType::JSON)
+            .header(as_staff(COOK))
+            .body("{\"available\": true}")
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        server.execute("update menu_item set available_at = 1;");
+        let menu = get_menu(&server.client);
+        assert!(menu[0].items[0].available);
+        assert_eq!(menu[0].items[0].available_at, None);
+        let response = server.order(1).item("burger").place();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn should_schedule_and_price_the_menu_by_time_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let day = 24 * 60;
+            let minute = (audit::now() / 60).rem_euclid(day);
+            let (open, closed) = ((minute + day - 60) % day, (minute + 120) % day);
+            server
+                .menu_item("pancakes")
+                .category("Breakfast")
+                .price(800)
+                .create();
+            server
+                .menu_item("soda")
+                .category("Drinks")
+                .price(450)
+                .create();
+            server.execute(&format!(
+                "insert into menu_schedule (category_id, starts, ends)
+                    select id, {}, {} from menu_category where name = 'Breakfast';
+                insert into price_rule
+                    (restaurant_id, name, category_id, starts, ends, percent_off)
+                    select 1, 'happy hour', id, {}, {}, 20
+                    from menu_category where name = 'Drinks';",
+                closed,
+                (closed + 60) % day,
+                open,
+                (open + 120) % day
+            ));
+            let menu = get_menu(&server.client);
+            let breakfast = &menu[0];
+            assert_eq!(breakfast.schedules.len(), 1, "{}", backend);
+            assert!(!breakfast.items[0].available, "{}", backend);
+            let available_at = breakfast.items[0].available_at.unwrap();
+            assert!(available_at > audit::now(), "{}", backend);
+            let soda = &menu[1].items[0];
+            let prices = (soda.price, soda.regular_price);
+            assert_eq!(prices, (360, Some(450)), "{}", backend);
+            let response = server.order(1).item("pancakes").place();
+            assert_eq!(response.status(), Status::Conflict, "{}", backend);
+            let response = server.order(1).item("soda").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            server.execute("delete from price_rule;");
+            let soda = &get_menu(&server.client)[1].items[0];
+            let prices = (soda.price, soda.regular_price);
+            assert_eq!(prices, (450, None), "{}", backend);
+            assert_eq!(get_bill(&server.client, 1).total, 360, "{}", backend);
+            server.execute(&format!(
+                "update menu_schedule set starts = {}, ends = {};",
+                open,
+                (open + 120) % day
+            ));
+            let response = server.order(1).item("pancakes").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            assert_eq!(get_bill(&server.client, 1).total, 1160, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_order_modifiers_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            server
+                .menu_item("burger")
+                .group("Cooking", 1, 1, &[("rare", 0), ("medium", 0)])
+                .group("Extras", 0, 2, &[("bacon", 200), ("cheese", 100)])
+                .create();
+            let rejected = [
+                server.order(1).item("burger"),
+                server.order(1).item_with("burger", &["rare", "medium"]),
+                server.order(1).item_with("burger", &["rare", "gravy"]),
+                server.order(1).item_with("cake", &["bacon"]),
+            ];
+            for order in rejected {
+                let response = order.place();
+                assert_eq!(response.status(), Status::BadRequest, "{}", backend);
+            }
+            assert!(get_v2_tables(&server.client).is_empty(), "{}", backend);
+            let response = server
+                .order(1)
+                .item_with("burger", &["medium", "bacon"])
+                .item("cake")
+                .place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let tables = get_v2_tables(&server.client);
+            let items = &tables[0].order.items;
+            let chosen = items[0]
+                .modifiers
+                .iter()
+                .map(|modifier| (modifier.name.as_str(), modifier.price_delta))
+                .collect::<Vec<_>>();
+            assert_eq!(chosen, vec![("medium", 0), ("bacon", 200)], "{}", backend);
+            assert!(items[1].modifiers.is_empty(), "{}", backend);
+            let table = get_test_table(&server.client, &tables[0].id.to_string());
+            assert_eq!(table.items[0].modifiers.len(), 2, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_order_bundles_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let fries = server.menu_item("fries").price(400).create();
+            let soda = server.menu_item("soda").price(300).create();
+            server
+                .menu_item("lunch")
+                .price(600)
+                .components(&[fries, soda])
+                .create();
+            let response = server.order(1).item("lunch").item("soda").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let tables = get_v2_tables(&server.client);
+            let items = &tables[0].order.items;
+            assert_eq!(items.len(), 2, "{}", backend);
+            let lunch = &items[0];
+            let components = lunch
+                .components
+                .iter()
+                .map(|component| component.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(components, vec!["fries", "soda"], "{}", backend);
+            let tickets = get_kitchen(&server.client)
+                .into_iter()
+                .map(|ticket| (ticket.name, ticket.bundle))
+                .collect::<Vec<_>>();
+            let expected = vec![
+                (String::from("fries"), Some(String::from("lunch"))),
+                (String::from("soda"), Some(String::from("lunch"))),
+                (String::from("soda"), None),
+            ];
+            assert_eq!(tickets, expected, "{}", backend);
+            let mut response = server
+                .client
+                .get(format!("/api/v2/tables/{}/bill", tables[0].id))
+                .header(as_staff(SERVER))
+                .dispatch();
+            let body = response.body_string().unwrap();
+            let bill = serde_json::from_str::<api::v2::Bill>(&body).unwrap();
+            assert_eq!(bill.lines.len(), 2, "{}", backend);
+            let line = &bill.lines[0];
+            assert_eq!(line.price, 600, "{}", backend);
+            assert_eq!(line.components, vec!["fries", "soda"], "{}", backend);
+            assert_eq!(bill.total, 900, "{}", backend);
+            let component = format!("/api/v1/tables/{}/{}", tables[0].id, lunch.components[0].id);
+            let bundle = format!("/api/v1/tables/{}/{}", tables[0].id, lunch.id);
+            let attempts = [(component, Status::NotFound), (bundle, Status::Ok)];
+            for (path, status) in attempts {
+                let response = server
+                    .client
+                    .delete(void_path(&path, "wrong table"))
+                    .header(as_staff(SERVER))
+                    .dispatch();
+                assert_eq!(response.status(), status, "{}", backend);
+            }
+            let tickets = get_kitchen(&server.client);
+            assert_eq!(tickets.len(), 1, "{}", backend);
+            assert_eq!(tickets[0].bundle, None, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_track_stock_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let beef = server.ingredient("beef", 2).low_stock(1).create();
+            server.menu_item("burger").uses(beef, 1).create();
+            let response = server.order(1).item("burger").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let left = &get_inventory(&server.client)[0];
+            assert_eq!((left.stock, left.low), (1, true), "{}", backend);
+            let mut response = server.order(1).item("burger").item("burger").place();
+            assert_eq!(response.status(), Status::Conflict, "{}", backend);
+            assert_eq!(
+                response.body_string().unwrap(),
+                "There is not enough beef to make it.",
+                "{}",
+                backend
+            );
+            let tables = get_v2_tables(&server.client);
+            assert_eq!(tables[0].order.items.len(), 1, "{}", backend);
+            let response = server
+                .client
+                .delete(void_path(
+                    &format!(
+                        "/api/v1/tables/{}/{}",
+                        tables[0].id, tables[0].order.items[0].id
+                    ),
+                    "dropped",
+                ))
+                .header(as_staff(SERVER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let left = &get_inventory(&server.client)[0];
+            assert_eq!((left.stock, left.low), (2, false), "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_give_back_what_was_used_when_the_recipe_changed_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let beef = server.ingredient("beef", 10).create();
+            let bun = server.ingredient("bun", 10).create();
+            let patty = server.menu_item("patty").uses(beef, 2).create();
+            server
+                .menu_item("burger")
+                .uses(bun, 1)
+                .components(&[patty])
+                .create();
+            let response = server.order(1).item("burger").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let stock = || {
+                get_inventory(&server.client)
+                    .iter()
+                    .map(|ingredient| ingredient.stock)
+                    .collect::<Vec<i32>>()
+            };
+            assert_eq!(stock(), vec![8, 9], "{}", backend);
+            server.execute("update recipe set quantity = 5;");
+            let table = &get_v2_tables(&server.client)[0];
+            let path = format!("/api/v1/tables/{}/{}", table.id, table.order.items[0].id);
+            let response = server
+                .client
+                .delete(void_path(&path, "dropped"))
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            assert_eq!(stock(), vec![10, 10], "{}", backend);
+            let response = server
+                .client
+                .post(format!("{}/restore", path))
+                .header(as_staff(MANAGER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            assert_eq!(stock(), vec![8, 9], "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_let_manager_manage_inventory_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let burger = server.menu_item("burger").create();
+            let send = |method: Method, path: &str, staff: &'static str, body: &str| {
+                server
+                    .client
+                    .req(method, path.to_string())
+                    .header(ContentType::JSON)
+                    .header(as_staff(staff))
+                    .body(body)
+                    .dispatch()
+            };
+            let beef = "{\"name\": \"beef\", \"stock\": 1, \"low_stock\": 0}";
+            let attempts = [
+                (SERVER, beef, Status::Forbidden),
+                (
+                    MANAGER,
+                    "{\"name\": \" \", \"stock\": 1, \"low_stock\": 0}",
+                    Status::BadRequest,
+                ),
+                (
+                    MANAGER,
+                    "{\"name\": \"beef\", \"stock\": -1, \"low_stock\": 0}",
+                    Status::BadRequest,
+                ),
+                (MANAGER, beef, Status::Ok),
+            ];
+            for (staff, body, status) in attempts {
+                let response = send(Method::Post, "/api/v1/inventory", staff, body);
+                assert_eq!(response.status(), status, "{} {}", backend, body);
+            }
+            let beef = get_inventory(&server.client)[0].id;
+            let restock = format!("/api/v1/inventory/{}/restock", beef);
+            let low_stock = format!("/api/v1/inventory/{}/low_stock", beef);
+            let recipe = format!("/api/v1/menu/{}/recipe", burger);
+            let uses = |ingredient_id: i32, quantity: i32| {
+                format!(
+                    "{{\"ingredient_id\": {}, \"quantity\": {}}}",
+                    ingredient_id, quantity
+                )
+            };
+            let two_beef = format!("{{\"lines\": [{}]}}", uses(beef, 2));
+            let twice = format!("{{\"lines\": [{}, {}]}}", uses(beef, 1), uses(beef, 1));
+            let unknown = format!("{{\"lines\": [{}]}}", uses(404404, 1));
+            let none = format!("{{\"lines\": [{}]}}", uses(beef, 0));
+            let attempts = [
+                (
+                    Method::Post,
+                    restock.as_str(),
+                    COOK,
+                    "{\"quantity\": 4}",
+                    Status::Forbidden,
+                ),
+                (
+                    Method::Post,
+                    restock.as_str(),
+                    MANAGER,
+                    "{\"quantity\": 0}",
+                    Status::BadRequest,
+                ),
+                (
+                    Method::Post,
+                    "/api/v1/inventory/404404/restock",
+                    MANAGER,
+                    "{\"quantity\": 4}",
+                    Status::NotFound,
+                ),
+                (
+                    Method::Post,
+                    restock.as_str(),
+                    MANAGER,
+                    "{\"quantity\": 4}",
+                    Status::Ok,
+                ),
+                (
+                    Method::Put,
+                    low_stock.as_str(),
+                    MANAGER,
+                    "{\"low_stock\": -1}",
+                    Status::BadRequest,
+                ),
+                (
+                    Method::Put,
+                    "/api/v1/inventory/404404/low_stock",
+                    MANAGER,
+                    "{\"low_stock\": 2}",
+                    Status::NotFound,
+                ),
+                (
+                    Method::Put,
+                    low_stock.as_str(),
+                    MANAGER,
+                    "{\"low_stock\": 2}",
+                    Status::Ok,
+                ),
+                (
+                    Method::Put,
+                    recipe.as_str(),
+                    COOK,
+                    two_beef.as_str(),
+                    Status::Forbidden,
+                ),
+                (
+                    Method::Put,
+                    recipe.as_str(),
+                    MANAGER,
+                    twice.as_str(),
+                    Status::BadRequest,
+                ),
+                (
+                    Method::Put,
+                    recipe.as_str(),
+                    MANAGER,
+                    unknown.as_str(),
+                    Status::BadRequest,
+                ),
+                (
+                    Method::Put,
+                    recipe.as_str(),
+                    MANAGER,
+                    none.as_str(),
+                    Status::BadRequest,
+                ),
+                (
+                    Method::Put,
+                    "/api/v1/menu/404404/recipe",
+                    MANAGER,
+                    two_beef.as_str(),
+                    Status::NotFound,
+                ),
+                (
+                    Method::Put,
+                    recipe.as_str(),
+                    MANAGER,
+                    two_beef.as_str(),
+                    Status::Ok,
+                ),
+            ];
+            for (method, path, staff, body, status) in attempts {
+                let response = send(method, path, staff, body);
+                assert_eq!(response.status(), status, "{} {} {}", backend, path, body);
+            }
+            let left = &get_inventory(&server.client)[0];
+            assert_eq!((left.stock, left.low_stock), (5, 2), "{}", backend);
+            let response = server.order(1).item("burger").item("burger").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let left = &get_inventory(&server.client)[0];
+            assert_eq!((left.stock, left.low), (1, true), "{}", backend);
+            let response = server.order(1).item("burger").place();
+            assert_eq!(response.status(), Status::Conflict, "{}", backend);
+            let entries = get_audit(&server.client, "");
+            let changes = entries
+                .iter()
+                .filter(|entry| entry.table_id.is_none())
+                .map(|entry| entry.action.as_str())
+                .collect::<Vec<&str>>();
+            let expected = vec!["add_ingredient", "restock", "set_low_stock", "set_recipe"];
+            assert_eq!(changes, expected, "{}", backend);
+            let restocked = entries[1].before.clone().unwrap();
+            let restocked = serde_json::from_value::<data::Ingredient>(restocked).unwrap();
+            assert_eq!(restocked.stock, 1, "{}", backend);
+            let recipe = entries[3].after.clone().unwrap();
+            let recipe = serde_json::from_value::<data::Recipe>(recipe).unwrap();
+            assert_eq!(recipe.lines[0].quantity, 2, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_warn_kitchen_about_allergies_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            server.menu_item("satay").allergens(&["peanuts"]).create();
+            server
+                .menu_item("salad")
+                .dietary(&["gluten free", "vegan"])
+                .create();
+            let menu = get_menu(&server.client);
+            assert_eq!(menu[0].items[0].allergens, vec!["peanuts"], "{}", backend);
+            let dietary = &menu[0].items[1].dietary;
+            assert_eq!(dietary, &vec!["gluten free", "vegan"], "{}", backend);
+            let response = server.order(1).item("satay").item("salad").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table_id = get_v2_tables(&server.client)[0].id;
+            let path = format!("/api/v2/tables/{}/allergies", table_id);
+            let missing = "/api/v2/tables/404404/allergies";
+            let declared = "{\"allergies\": [{\"seat\": 2, \"allergen\": \"peanuts\"}]}";
+            let blank = "{\"allergies\": [{\"allergen\": \" \"}]}";
+            let attempts = [
+                (missing, SERVER, declared, Status::NotFound),
+                (path.as_str(), COOK, declared, Status::Forbidden),
+                (path.as_str(), SERVER, blank, Status::BadRequest),
+                (path.as_str(), SERVER, declared, Status::Ok),
+            ];
+            for (path, staff, body, status) in attempts {
+                let response = server
+                    .client
+                    .put(path)
+                    .header(ContentType::JSON)
+                    .header(as_staff(staff))
+                    .body(body)
+                    .dispatch();
+                assert_eq!(response.status(), status, "{} {}", backend, body);
+            }
+            let mut response = server.client.get(path).header(as_staff(COOK)).dispatch();
+            let body = response.body_string().unwrap();
+            let allergies = serde_json::from_str::<api::v2::Allergies>(&body).unwrap();
+            assert_eq!(allergies.allergies[0].seat, Some(2), "{}", backend);
+            let warnings = get_kitchen(&server.client)
+                .into_iter()
+                .map(|ticket| ticket.allergy_warnings)
+                .collect::<Vec<_>>();
+            let warning = "satay contains peanuts, seat 2 is allergic to it.";
+            let expected = vec![vec![String::from(warning)], Vec::new()];
+            assert_eq!(warnings, expected, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_hold_courses_until_they_are_fired_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let response = server
+                .order(1)
+                .item("soup")
+                .item_in_course("steak", 2)
+                .place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table = &get_v2_tables(&server.client)[0];
+            let statuses = table
+                .order
+                .items
+                .iter()
+                .map(|item| (item.course, &item.status))
+                .collect::<Vec<_>>();
+            let expected = vec![
+                (1, &api::v2::ItemStatus::Ordered),
+                (2, &api::v2::ItemStatus::Held),
+            ];
+            assert_eq!(statuses, expected, "{}", backend);
+            assert_eq!(table.order.items[1].fired_at, None, "{}", backend);
+            let names = |tickets: Vec<api::v2::Ticket>| {
+                tickets
+                    .into_iter()
+                    .map(|ticket| ticket.name)
+                    .collect::<Vec<String>>()
+            };
+            let tickets = get_kitchen(&server.client);
+            assert_eq!(names(tickets), vec!["soup"], "{}", backend);
+            let path = format!("/api/v1/tables/{}/courses/2/fire", table.id);
+            let missing = format!("/api/v1/tables/{}/courses/3/fire", table.id);
+            let attempts = [
+                (path.as_str(), COOK, Status::Forbidden),
+                (missing.as_str(), SERVER, Status::NotFound),
+                (path.as_str(), SERVER, Status::Ok),
+                (path.as_str(), SERVER, Status::Conflict),
+            ];
+            for (path, staff, status) in attempts {
+                let response = server.client.post(path).header(as_staff(staff)).dispatch();
+                assert_eq!(response.status(), status, "{} {}", backend, path);
+            }
+            let tickets = get_kitchen(&server.client);
+            assert!(tickets[1].fired_at.is_some(), "{}", backend);
+            let expected = vec!["soup", "steak"];
+            assert_eq!(names(tickets), expected, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_change_table_version_when_course_is_fired_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let response = server
+                .order(1)
+                .item("soup")
+                .item_in_course("steak", 2)
+                .place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table_id = get_v2_tables(&server.client)[0].id;
+            let version_tag = || {
+                let response = server
+                    .client
+                    .get(format!("/api/v1/tables/{}", table_id))
+                    .header(as_staff(SERVER))
+                    .dispatch();
+                response.headers().get_one("ETag").unwrap().to_string()
+            };
+            assert_eq!(version_tag(), "\"1\"", "{}", backend);
+            let path = format!("/api/v1/tables/{}/courses/2/fire", table_id);
+            let response = server
+                .client
+                .post(path.clone())
+                .header(as_staff(SERVER))
+                .header(Header::new(etag::IF_MATCH_HEADER, "\"0\""))
+                .dispatch();
+            assert_eq!(response.status(), Status::PreconditionFailed, "{}", backend);
+            let response = server
+                .client
+                .post(path)
+                .header(as_staff(SERVER))
+                .header(Header::new(etag::IF_MATCH_HEADER, version_tag()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            assert_eq!(version_tag(), "\"2\"", "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_queue_tickets_at_stations_until_they_are_ready_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            server.menu_item("steak").station("grill").create();
+            server.menu_item("soda").station("bar").create();
+            let response = server.order(1).item("steak").item("soda").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let mut response = server
+                .client
+                .get("/api/v2/stations")
+                .header(as_staff(COOK))
+                .dispatch();
+            let body = response.body_string().unwrap();
+            let stations = serde_json::from_str::<Vec<api::v2::Station>>(&body).unwrap();
+            let names = stations
+                .iter()
+                .map(|station| station.name.as_str())
+                .collect::<Vec<&str>>();
+            assert_eq!(names, vec!["grill", "bar"], "{}", backend);
+            let grill = stations[0].id;
+            let station_tickets = |station: i32| {
+                let mut response = server
+                    .client
+                    .get(format!("/api/v2/stations/{}/tickets", station))
+                    .header(as_staff(COOK))
+                    .dispatch();
+                let body = response.body_string().unwrap();
+                serde_json::from_str::<Vec<api::v2::Ticket>>(&body).unwrap()
+            };
+            let tickets = station_tickets(grill);
+            assert_eq!(tickets.len(), 1, "{}", backend);
+            assert_eq!(tickets[0].name, "steak", "{}", backend);
+            let grilled = Some(String::from("grill"));
+            assert_eq!(tickets[0].station, grilled, "{}", backend);
+            let response = server
+                .client
+                .get("/api/v2/stations/404404/tickets")
+                .header(as_staff(COOK))
+                .dispatch();
+            assert_eq!(response.status(), Status::NotFound, "{}", backend);
+            let events = format!("/api/v2/stations/{}/events", grill);
+            let mut response = server
+                .client
+                .get(events.clone())
+                .header(as_staff(COOK))
+                .dispatch();
+            let event_stream = ContentType::new("text", "event-stream");
+            assert_eq!(response.content_type(), Some(event_stream), "{}", backend);
+            let body = response.body_string().unwrap();
+            assert!(body.starts_with("retry: 2000\n\n"), "{} {}", backend, body);
+            assert!(body.contains("id: 1\nevent: fired\n"), "{}", backend);
+            let table = &get_v2_tables(&server.client)[0];
+            assert!(!table.ready, "{}", backend);
+            let steak = format!(
+                "/api/v2/tables/{}/items/{}/ready",
+                table.id, table.order.items[0].id
+            );
+            let soda = format!(
+                "/api/v2/tables/{}/items/{}/ready",
+                table.id, table.order.items[1].id
+            );
+            let missing = format!("/api/v2/tables/{}/items/404404/ready", table.id);
+            let attempts = [
+                (steak.as_str(), SERVER, Status::Forbidden),
+                (missing.as_str(), COOK, Status::NotFound),
+                (steak.as_str(), COOK, Status::Ok),
+                (steak.as_str(), COOK, Status::Conflict),
+            ];
+            for (path, staff, status) in attempts {
+                let response = server.client.post(path).header(as_staff(staff)).dispatch();
+                assert_eq!(response.status(), status, "{} {}", backend, path);
+            }
+            assert!(!get_v2_tables(&server.client)[0].ready, "{}", backend);
+            assert!(station_tickets(grill).is_empty(), "{}", backend);
+            let mut response = server.client.post(soda).header(as_staff(COOK)).dispatch();
+            let body = response.body_string().unwrap();
+            let table = serde_json::from_str::<api::v2::Table>(&body).unwrap();
+            assert!(table.ready, "{}", backend);
+            let mut response = server
+                .client
+                .get(events.clone())
+                .header(as_staff(COOK))
+                .header(Header::new(kitchen::LAST_EVENT_ID_HEADER, "1"))
+                .dispatch();
+            let body = response.body_string().unwrap();
+            assert!(!body.contains("event: fired"), "{} {}", backend, body);
+            assert!(body.contains("event: ready"), "{} {}", backend, body);
+            let response = server
+                .client
+                .get(events)
+                .header(as_staff(COOK))
+                .header(Header::new(kitchen::LAST_EVENT_ID_HEADER, "soon"))
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_hold_station_events_until_the_wait_runs_out() {
+        let server = TestServer::configured(&[("kitchen_event_wait", 1.into())]);
+        server.menu_item("steak").station("grill").create();
+        server.order(1).item("steak").place();
+        let started = std::time::Instant::now();
+        let mut response = server
+            .client
+            .get("/api/v2/stations/1/events")
+            .header(as_staff(COOK))
+            .header(Header::new(kitchen::LAST_EVENT_ID_HEADER, "1"))
+            .dispatch();
+        assert_eq!(response.body_string().unwrap(), "retry: 2000\n\n");
+        assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn should_not_hold_station_events_by_default() {
+        let server = TestServer::new();
+        server.menu_item("steak").station("grill").create();
+        server.order(1).item("steak").place();
+        let started = std::time::Instant::now();
+        let mut response = server
+            .client
+            .get("/api/v2/stations/1/events")
+            .header(as_staff(COOK))
+            .header(Header::new(kitchen::LAST_EVENT_ID_HEADER, "1"))
+            .dispatch();
+        assert_eq!(response.body_string().unwrap(), "retry: 2000\n\n");
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn should_estimate_preparation_from_history_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            server.menu_item("steak").station("grill").create();
+            for _ in 0..estimate::MIN_SAMPLES {
+                let response = server.order(1).item("steak").place();
+                assert_eq!(response.status(), Status::Ok, "{}", backend);
+                for ticket in get_kitchen(&server.client) {
+                    if ticket.ready_at.is_none() {
+                        let path = format!(
+                            "/api/v2/tables/{}/items/{}/ready",
+                            ticket.table_id, ticket.item_id
+                        );
+                        let response = server.client.post(path).header(as_staff(COOK)).dispatch();
+                        assert_eq!(response.status(), Status::Ok, "{}", backend);
+                    }
+                }
+            }
+            let mut response = server
+                .client
+                .get("/api/v2/kitchen/estimates")
+                .header(as_staff(SERVER))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let body = response.body_string().unwrap();
+            let estimates = serde_json::from_str::<Vec<api::v2::PrepEstimate>>(&body).unwrap();
+            assert_eq!(estimates.len(), 1, "{}", backend);
+            assert_eq!(estimates[0].name, "steak", "{}", backend);
+            assert_eq!(estimates[0].samples, 3, "{}", backend);
+            let estimated = estimates[0].estimated_minutes;
+            assert!((5..=15).contains(&estimated), "{}", backend);
+            assert_eq!(estimates[0].median_minutes, 1, "{}", backend);
+            assert_eq!(estimates[0].current_minutes, Some(1), "{}", backend);
+            let response = server.order(1).item("steak").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let tickets = get_kitchen(&server.client);
+            let steak = tickets.last().unwrap();
+            assert_eq!(steak.preparation_minutes, 1, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_adjust_bills_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            server.menu_item("pizza").price(1000).create();
+            server.menu_item("soda").price(300).create();
+            server.execute(
+                "insert into promo_code (restaurant_id, code, item_name, kind, buy, get, max_uses)
+                    values (1, 'PIZZA3', 'pizza', 'buy_get', 2, 1, 1);
+                insert into promo_code (restaurant_id, code, kind, value, expires_at)
+                    values (1, 'OLD', 'percentage', 10, 1);",
+            );
+            let response = server
+                .order(1)
+                .item("pizza")
+                .item("pizza")
+                .item("pizza")
+                .item("soda")
+                .place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table = &get_v2_tables(&server.client)[0];
+            let soda = table.order.items[3].id;
+            let comp = |item_id: i32, reason: &str| {
+                let discount = serde_json::json!({ "kind": "comp" });
+                let request = serde_json::json!({
+                    "item_id": item_id,
+                    "discount": discount,
+                    "reason": reason,
+                });
+                request.to_string()
+            };
+            let too_much = serde_json::json!({
+                "discount": { "kind": "percentage", "value": 150 },
+                "reason": "everyone is hungry",
+            });
+            let attempts = [
+                (SERVER, comp(soda, "cold"), Status::Forbidden),
+                (MANAGER, comp(404404, "cold"), Status::NotFound),
+                (MANAGER, comp(soda, " "), Status::BadRequest),
+                (MANAGER, too_much.to_string(), Status::BadRequest),
+                (MANAGER, comp(soda, "cold"), Status::Ok),
+            ];
+            let path = format!("/api/v2/tables/{}/adjustments", table.id);
+            for (staff, body, status) in attempts {
+                let response = server
+                    .client
+                    .post(path.clone())
+                    .header(ContentType::JSON)
+                    .header(as_staff(staff))
+                    .body(&body)
+                    .dispatch();
+                assert_eq!(response.status(), status, "{} {}", backend, body);
+            }
+            let path = format!("/api/v2/tables/{}/promo_codes", table.id);
+            let missing = "/api/v2/tables/404404/promo_codes";
+            let attempts = [
+                (missing, "PIZZA3", Status::NotFound),
+                (path.as_str(), "NOPE", Status::BadRequest),
+                (path.as_str(), "OLD", Status::Conflict),
+                (path.as_str(), "PIZZA3", Status::Ok),
+                (path.as_str(), "PIZZA3", Status::Conflict),
+            ];
+            for (path, code, status) in attempts {
+                let response = server
+                    .client
+                    .post(path)
+                    .header(ContentType::JSON)
+                    .header(as_staff(SERVER))
+                    .body(format!("{{\"code\": \"{}\"}}", code))
+                    .dispatch();
+                assert_eq!(response.status(), status, "{} {}", backend, code);
+            }
+            let bill = get_bill(&server.client, table.id);
+            let amounts = bill
+                .adjustments
+                .iter()
+                .map(|adjustment| (adjustment.reason.as_str(), adjustment.amount))
+                .collect::<Vec<_>>();
+            let expected = vec![("cold", 300), ("Promo code PIZZA3", 1000)];
+            assert_eq!(amounts, expected, "{}", backend);
+            assert_eq!((bill.subtotal, bill.total), (3300, 2000), "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_redeem_promo_code_once_per_table_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            server.execute(
+                "insert into promo_code (restaurant_id, code, kind, value, max_uses)
+                    values (1, 'SPRING', 'percentage', 15, 5);",
+            );
+            let response = server.order(1).item("pizza").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table_id = get_v2_tables(&server.client)[0].id;
+            let adjustments = server.adjustments();
+            let promo_code = adjustments.find_promo_code(1, "SPRING").unwrap().unwrap();
+            let redeemed = domain::Adjustment {
+                id: 0,
+                target: promo_code.target.clone(),
+                discount: promo_code.discount.clone(),
+                reason: String::from("Promo code SPRING"),
+                code: Some(promo_code.code.clone()),
+                added_by: 9002,
+                added_at: 1700000000,
+            };
+            // Both checked the bill before either redeemed the code.
+            let redemptions = (0..2)
+                .map(|_| {
+                    let change = audit::Change {
+                        restaurant_id: 1,
+                        actor_id: 9002,
+                        action: "redeem_promo_code",
+                        table_id: Some(table_id),
+                        item_id: None,
+                        before: None,
+                        after: None,
+                    };
+                    adjustments
+                        .redeem(1, table_id, promo_code.id, &redeemed, change)
+                        .unwrap()
+                })
+                .collect::<Vec<domain::Redemption>>();
+            assert!(
+                matches!(redemptions[0], domain::Redemption::Redeemed(_)),
+                "{}",
+                backend
+            );
+            assert_eq!(
+                redemptions[1],
+                domain::Redemption::AlreadyOnBill,
+                "{}",
+                backend
+            );
+            let promo_code = adjustments.find_promo_code(1, "SPRING").unwrap().unwrap();
+            assert_eq!(promo_code.uses, 1, "{}", backend);
+            assert_eq!(
+                get_bill(&server.client, table_id).adjustments.len(),
+                1,
+                "{}",
+                backend
+            );
+            let entries = get_audit(&server.client, &format!("?table={}", table_id));
+            let redeemed = entries
+                .iter()
+                .filter(|entry| entry.action == "redeem_promo_code")
+                .count();
+            assert_eq!(redeemed, 1, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_only_let_manager_void_from_settled_bill_on_every_backend() {
+        for server in TestServer::all() {
+            let backend = server.backend();
+            let response = server.order(1).item("pizza").item("cake").place();
+            assert_eq!(response.status(), Status::Ok, "{}", backend);
+            let table = &get_v2_tables(&server.client)[0];
+            let settle = format!("/api/v2/tables/{}/bill/settle", table.id);
+            let attempts = [
+                (COOK, settle.as_str(), Status::Forbidden),
+                (
+                    SERVER,
+                    "/api/v2/tables/404404/bill/settle",
+                    Status::NotFound,
+                ),
+                (SERVER, settle.as_str(), Status::Ok),
+                (SERVER, settle.as_str(), Status::Conflict),
+            ];
+            for (staff, path, status) in attempts {
+                let response = server.client.post(path).header(as_staff(staff)).dispatch();
+                assert_eq!(response.status(), status, "{} {}", backend, staff);
+            }
+            let bill = get_bill(&server.client, table.id);
+            assert!(bill.paid_at.is_some(), "{}", backend);
+            let path = |item: &api::v2::Item| {
+                void_path(&format!("/api/v1/tables/{}/{}", table.id, item.id), "paid")
+            };
+            let attempts = [
+                (SERVER, path(&table.order.items[0]), Status::Forbidden),
+                (MANAGER, path(&table.order.items[0]), Status::Ok),
+            ];
+            for (staff, path, status) in attempts {
+                let response = server
+                    .client
+                    .delete(path)
+                    .header(as_staff(staff))
+                    .dispatch();
+                assert_eq!(response.status(), status, "{} {}", backend, staff);
+            }
+            let bill = get_bill(&server.client, table.id);
+            assert_eq!(bill.lines.len(), 1, "{}", backend);
+            let entries = get_audit(&server.client, &format!("?table={}", table.id));
+            let actions = entries
+                .iter()
+                .map(|entry| entry.action.as_str())
+                .collect::<Vec<&str>>();
+            let expected = vec![
+                "create_table",
+                "add_item",
+                "add_item",
+                "settle_bill",
+                "void_item",
+            ];
+            assert_eq!(actions, expected, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn should_reject_allergy_conflicts_when_configured() {
+        let server = TestServer::configured(&[("allergy_conflicts", "reject".into())]);
+        server.menu_item("satay").allergens(&["peanuts"]).create();
+        let response = server.order(1).item("salad").place();
+        assert_eq!(response.status(), Status::Ok);
+        let table_id = get_v2_tables(&server.client)[0].id;
+        let response = server
+            .client
+            .put(format!("/api/v2/tables/{}/allergies", table_id))
+            .header(ContentType::JSON)
+            .header(as_staff(SERVER))
+            .body("{\"allergies\": [{\"allergen\": \"Peanuts\"}]}")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = server.order(table_id).item("satay").place();
+        assert_eq!(response.status(), Status::Conflict);
+        assert_eq!(
+            response.body_string().unwrap(),
+            "satay contains Peanuts, someone at the table is allergic to it."
+        );
+        let response = server.order(table_id).item("salad").place();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn should_not_bill_missing_table() {
+        let server = TestServer::new();
+        let response = server
+            .client
+            .get("/api/v2/tables/404404/bill")
+            .header(as_staff(SERVER))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    fn get_menu(client: &Client) -> Vec<data::MenuCategory> {
+        let mut response = client.get("/api/v1/menu").header(as_staff(COOK)).dispatch();
+        let body = response.body_string().unwrap();
+        serde_json::from_str::<Vec<data::MenuCategory>>(&body).expect("Failed to convert json.")
+    }
+
+    fn get_bill(client: &Client, table_id: i32) -> api::v2::Bill {
+        let path = format!("/api/v2/tables/{}/bill", table_id);
+        let mut response = client.get(path).header(as_staff(SERVER)).dispatch();
+        let body = response.body_string().unwrap();
+        serde_json::from_str::<api::v2::Bill>(&body).expect("Failed to convert json.")
+    }
+
+    fn get_inventory(client: &Client) -> Vec<data::Ingredient> {
+        let mut response = client
+            .get("/api/v1/inventory")
+            .header(as_staff(COOK))
+            .dispatch();
+        let body = response.body_string().unwrap();
+        serde_json::from_str::<Vec<data::Ingredient>>(&body).expect("Failed to convert json.")
+    }
+
+    fn get_kitchen(client: &Client) -> Vec<api::v2::Ticket> {
+        let mut response = client
+            .get("/api/v2/kitchen")
+            .header(as_staff(COOK))
+            .dispatch();
+        let body = response.body_string().unwrap();
+        serde_json::from_str::<Vec<api::v2::Ticket>>(&body).expect("Failed to convert json.")
+    }
+
+    fn get_audit(client: &Client, query: &str) -> Vec<data::AuditEntry> {
+        let mut response = client
+            .get(format!("/api/v1/audit{}", query))
+            .header(as_staff(MANAGER))
+            .dispatch();
+        let body = response.body_string().unwrap();
+        serde_json::from_str::<Vec<data::AuditEntry>>(&body).expect("Failed to convert json.")
+    }
+
+    fn get_v2_tables(client: &Client) -> Vec<api::v2::Table> {
+        let mut response = client
+            .get("/api/v2/tables")
+            .header(as_staff(COOK))
+            .dispatch();
+        let body = response.body_string().unwrap();
+        serde_json::from_str::<Vec<api::v2::Table>>(&body).expect("Failed to convert json.")
+    }
+
+    fn get_test_table(client: &Client, table: &str) -> data::Table {
+        let mut response = client
+            .get(format!("/api/v1/tables/{}", table))
+            .header(as_staff(MANAGER))
+            .dispatch();
+        let body = response.body_string().unwrap();
+        serde_json::from_str::<data::Table>(&body).expect("Failed to convert json.")
+    }
+
+    fn get_test_item_ids(table: &data::Table) -> String {
+        table
+            .items
+            .iter()
+            .map(|item| item.id.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    fn as_staff(id: &'static str) -> Header<'static> {
+        Header::new(auth::STAFF_HEADER, id)
+    }
+
+    fn idempotency_key(key: &'static str) -> Header<'static> {
+        Header::new(idempotency::IDEMPOTENCY_HEADER, key)
+    }
+
+    fn void_path(path: &str, reason: &str) -> String {
+        format!("{}?reason={}", path, Uri::percent_encode(reason))
     }
 }
@@ -0,0 +1,620 @@
+use crate::audit;
+use crate::database;
+use crate::domain;
+
+/// A menu item as `(id, name, price, available, available_at, station_id)`.
+pub type ItemColumns = (i32, String, i32, bool, Option<i64>, Option<i32>);
+
+/// One row of the menu query, a category with at most one item, group and modifier of it.
+pub struct MenuRow {
+    pub category_id: i32,
+    pub category_name: String,
+    pub item: Option<ItemColumns>,
+    pub group: Option<(i32, String, i32, i32)>,
+    pub modifier: Option<(i32, String, i32)>,
+}
+
+/// Everything a restaurant's menu is put together from, as each backend reads it. The parts
+/// are laid out the same way as what the queries below return.
+pub struct MenuRows {
+    pub rows: Vec<MenuRow>,
+    pub components: Vec<(i32, String)>,
+    pub recipes: Vec<(i32, domain::RecipeLine)>,
+    pub tags: Vec<(i32, String, String)>,
+    pub schedules: Vec<(i32, domain::Window)>,
+    pub price_rules: Vec<domain::PriceRule>,
+    pub utc_offset: i64,
+}
+
+/// The names of the items in each bundle as `(bundle_id, name)`, in the bundle's order.
+fn components(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Vec<(i32, String)>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select bundle_component.bundle_id, menu_item.name
+            from bundle_component
+            join menu_item on menu_item.id = bundle_component.menu_item_id
+            where menu_item.restaurant_id = $1
+            order by bundle_component.bundle_id, bundle_component.position;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| Ok((row.get(0)?, row.get(1)?)));
+    match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<(i32, String)>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// What each menu item is made of as `(menu_item_id, recipe line)`.
+fn recipes(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Vec<(i32, domain::RecipeLine)>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select recipe.menu_item_id, recipe.ingredient_id, recipe.quantity
+            from recipe
+            join ingredient on ingredient.id = recipe.ingredient_id
+            where ingredient.restaurant_id = $1
+            order by recipe.menu_item_id, recipe.ingredient_id;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok((
+            row.get(0)?,
+            domain::RecipeLine {
+                ingredient_id: row.get(1)?,
+                quantity: row.get(2)?,
+            },
+        ))
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<(i32, domain::RecipeLine)>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+pub const ALLERGEN: &str = "allergen";
+pub const DIETARY: &str = "dietary";
+
+/// The tags of each menu item as `(menu_item_id, kind, tag)`.
+fn tags(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Vec<(i32, String, String)>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select menu_item_tag.menu_item_id, menu_item_tag.kind, menu_item_tag.tag
+            from menu_item_tag
+            join menu_item on menu_item.id = menu_item_tag.menu_item_id
+            where menu_item.restaurant_id = $1
+            order by menu_item_tag.menu_item_id, menu_item_tag.tag;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<(i32, String, String)>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// The schedules of each category as `(category_id, window)`.
+fn schedules(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Vec<(i32, domain::Window)>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select menu_schedule.category_id, menu_schedule.days,
+                menu_schedule.starts, menu_schedule.ends
+            from menu_schedule
+            join menu_category on menu_category.id = menu_schedule.category_id
+            where menu_category.restaurant_id = $1
+            order by menu_schedule.category_id, menu_schedule.starts;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok((
+            row.get(0)?,
+            domain::Window {
+                days: row.get(1)?,
+                starts: row.get(2)?,
+                ends: row.get(3)?,
+            },
+        ))
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<(i32, domain::Window)>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// All of a restaurant's price rules, whether they are active or not.
+fn price_rules(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+) -> Result<Vec<domain::PriceRule>, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select id, name, category_id, menu_item_id, days, starts, ends,
+                valid_from, valid_until, percent_off
+            from price_rule where restaurant_id = $1 order by id;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        Ok(domain::PriceRule {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            category_id: row.get(2)?,
+            menu_item_id: row.get(3)?,
+            window: domain::Window {
+                days: row.get(4)?,
+                starts: row.get(5)?,
+                ends: row.get(6)?,
+            },
+            valid_from: row.get(7)?,
+            valid_until: row.get(8)?,
+            percent_off: row.get(9)?,
+        })
+    });
+    match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<domain::PriceRule>>>()
+            .map_err(|e| format!("Failed with error: {}", e)),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// How many seconds ahead of UTC the restaurant's clocks are, schedules and price rules
+/// are in its time.
+fn utc_offset(connection: &rusqlite::Connection, restaurant_id: i32) -> Result<i64, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select utc_offset from restaurant where id = $1;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| row.get::<_, i64>(0));
+    match results {
+        Ok(mut rows) => match rows.next() {
+            Some(Ok(utc_offset)) => Ok(utc_offset),
+            Some(Err(e)) => Err(format!("Failed with error: {}", e)),
+            None => Ok(0),
+        },
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+/// Whether an item in a category with these schedules can be ordered at `now`. Outside
+/// all of them it is not available until the next one opens, a category without schedules
+/// is always open.
+fn scheduled(schedules: &[domain::Window], now: i64, utc_offset: i64) -> domain::Availability {
+    let local = now + utc_offset;
+    if schedules.is_empty() || schedules.iter().any(|window| window.contains(local)) {
+        return domain::Availability::Available;
+    }
+    let until = schedules
+        .iter()
+        .filter_map(|window| window.next_start(local))
+        .min();
+    domain::Availability::Unavailable {
+        until: until.map(|until| until - utc_offset),
+    }
+}
+
+/// The price of a menu item with the biggest discount of the active rules that apply to
+/// it taken off.
+fn discounted(rules: &[domain::PriceRule], category_id: i32, item: &domain::MenuItem) -> i32 {
+    let percent_off = rules
+        .iter()
+        .filter(|rule| rule.applies_to(category_id, item.id))
+        .map(|rule| rule.percent_off.clamp(0, 100))
+        .max()
+        .unwrap_or(0);
+    item.regular_price - item.regular_price * percent_off / 100
+}
+
+/// Loads a restaurant's menu as it is now, everything in the order it was added.
+pub fn load(connection: &rusqlite::Connection, restaurant_id: i32) -> Result<domain::Menu, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "select menu_category.id, menu_category.name,
+                menu_item.id, menu_item.name, menu_item.price,
+                menu_item.available, menu_item.available_at,
+                modifier_group.id, modifier_group.name,
+                modifier_group.min_selected, modifier_group.max_selected,
+                modifier.id, modifier.name, modifier.price_delta,
+                coalesce(menu_item.station_id, menu_category.station_id)
+            from menu_category
+            left join menu_item on menu_item.category_id = menu_category.id
+                and menu_item.restaurant_id = menu_category.restaurant_id
+            left join modifier_group on modifier_group.menu_item_id = menu_item.id
+            left join modifier on modifier.group_id = modifier_group.id
+            where menu_category.restaurant_id = $1
+            order by menu_category.id, menu_item.id, modifier_group.id, modifier.id;",
+    );
+    let results = statement.query_map([&restaurant_id], |row| {
+        let item_id: Option<i32> = row.get(2)?;
+        let group_id: Option<i32> = row.get(7)?;
+        let modifier_id: Option<i32> = row.get(11)?;
+        Ok(MenuRow {
+            category_id: row.get(0)?,
+            category_name: row.get(1)?,
+            item: match item_id {
+                Some(id) => Some((
+                    id,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(14)?,
+                )),
+                None => None,
+            },
+            group: match group_id {
+                Some(id) => Some((id, row.get(8)?, row.get(9)?, row.get(10)?)),
+                None => None,
+            },
+            modifier: match modifier_id {
+                Some(id) => Some((id, row.get(12)?, row.get(13)?)),
+                None => None,
+            },
+        })
+    });
+    let rows = match results {
+        Ok(rows) => rows
+            .collect::<rusqlite::Result<Vec<MenuRow>>>()
+            .map_err(|e| format!("Failed with error: {}", e))?,
+        Err(e) => return Err(format!("Failed with error: {}", e)),
+    };
+    let rows = MenuRows {
+        rows,
+        components: components(connection, restaurant_id)?,
+        recipes: recipes(connection, restaurant_id)?,
+        tags: tags(connection, restaurant_id)?,
+        schedules: schedules(connection, restaurant_id)?,
+        price_rules: price_rules(connection, restaurant_id)?,
+        utc_offset: utc_offset(connection, restaurant_id)?,
+    };
+    Ok(assemble(rows, audit::now()))
+}
+
+/// Puts a menu together from its rows as it is at `now`, everything in the order it was
+/// added.
+pub fn assemble(rows: MenuRows, now: i64) -> domain::Menu {
+    let mut menu = domain::Menu::default();
+    for row in rows.rows {
+        if menu.categories.last().map(|category| category.id) != Some(row.category_id) {
+            menu.categories.push(domain::Category {
+                id: row.category_id,
+                name: row.category_name,
+                schedules: Vec::new(),
+                items: Vec::new(),
+            });
+        }
+        let category = menu.categories.last_mut().expect("Category was added.");
+        let items = &mut category.items;
+        let (item_id, name, price, available, available_at, station_id) = match row.item {
+            Some(item) => item,
+            None => continue,
+        };
+        if items.last().map(|item| item.id) != Some(item_id) {
+            items.push(domain::MenuItem {
+                id: item_id,
+                name,
+                price,
+                regular_price: price,
+                modifier_groups: Vec::new(),
+                components: Vec::new(),
+                recipe: Vec::new(),
+                availability: domain::Availability::at(available, available_at, now),
+                allergens: Vec::new(),
+                dietary: Vec::new(),
+                station_id,
+            });
+        }
+        let groups = &mut items.last_mut().expect("Item was added.").modifier_groups;
+        let (group_id, name, min_selected, max_selected) = match row.group {
+            Some(group) => group,
+            None => continue,
+        };
+        if groups.last().map(|group| group.id) != Some(group_id) {
+            groups.push(domain::ModifierGroup {
+                id: group_id,
+                name,
+                min_selected,
+                max_selected,
+                modifiers: Vec::new(),
+            });
+        }
+        if let Some((id, name, price_delta)) = row.modifier {
+            let group = groups.last_mut().expect("Group was added.");
+            group.modifiers.push(domain::Modifier {
+                id,
+                name,
+                price_delta,
+            });
+        }
+    }
+    for (bundle_id, name) in rows.components {
+        if let Some(bundle) = find_item(&mut menu, bundle_id) {
+            bundle.components.push(name);
+        }
+    }
+    for (item_id, line) in rows.recipes {
+        if let Some(item) = find_item(&mut menu, item_id) {
+            item.recipe.push(line);
+        }
+    }
+    for (item_id, kind, tag) in rows.tags {
+        match (find_item(&mut menu, item_id), kind.as_str()) {
+            (Some(item), ALLERGEN) => item.allergens.push(tag),
+            (Some(item), DIETARY) => item.dietary.push(tag),
+            _ => warn!(
+                "Ignoring tag {} of kind {} on menu item {}.",
+                tag, kind, item_id
+            ),
+        }
+    }
+    for (category_id, window) in rows.schedules {
+        let category = menu
+            .categories
+            .iter_mut()
+            .find(|category| category.id == category_id);
+        if let Some(category) = category {
+            category.schedules.push(window);
+        }
+    }
+    let utc_offset = rows.utc_offset;
+    let rules = rows
+        .price_rules
+        .into_iter()
+        .filter(|rule| rule.is_active(now, utc_offset))
+        .collect::<Vec<domain::PriceRule>>();
+    for category in menu.categories.iter_mut() {
+        let open = scheduled(&category.schedules, now, utc_offset);
+        for item in category.items.iter_mut() {
+            if item.availability == domain::Availability::Available {
+                item.availability = open.clone();
+            }
+            item.price = discounted(&rules, category.id, item);
+        }
+    }
+    let bundles = menu
+        .categories
+        .iter()
+        .flat_map(|category| category.items.iter())
+        .filter(|item| !item.components.is_empty())
+        .map(|bundle| {
+            let allergens = bundle
+                .components
+                .iter()
+                .filter_map(|component| menu.item(component))
+                .flat_map(|component| component.allergens.iter().cloned())
+                .collect::<Vec<String>>();
+            (bundle.id, allergens)
+        })
+        .collect::<Vec<_>>();
+    for (bundle_id, allergens) in bundles {
+        if let Some(bundle) = find_item(&mut menu, bundle_id) {
+            bundle.allergens.extend(allergens);
+            bundle.allergens.sort();
+            bundle.allergens.dedup();
+        }
+    }
+    menu
+}
+
+fn find_item(menu: &mut domain::Menu, item_id: i32) -> Option<&mut domain::MenuItem> {
+    menu.categories
+        .iter_mut()
+        .flat_map(|category| category.items.iter_mut())
+        .find(|item| item.id == item_id)
+}
+
+/// Marks a menu item as available or not, returns false when the restaurant has no such
+/// item.
+pub fn set_availability(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    item_id: i32,
+    available: bool,
+    available_at: Option<i64>,
+) -> Result<bool, String> {
+    let mut statement = database::build_statement(
+        connection,
+        "update menu_item set available = $1, available_at = $2
+            where id = $3 and restaurant_id = $4;",
+    );
+    let updated = statement.execute(rusqlite::params![
+        available,
+        available_at,
+        item_id,
+        restaurant_id
+    ]);
+    match updated {
+        Ok(updated) => Ok(updated == 1),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+pub fn set_recipe(
+    connection: &mut rusqlite::Connection,
+    restaurant_id: i32,
+    item_id: i32,
+    recipe: &[domain::RecipeLine],
+) -> Result<bool, String> {
+    let transaction = connection
+        .transaction()
+        .map_err(|e| format!("Failed to set recipe: {}", e))?;
+    let found = transaction
+        .query_row(
+            "select count(*) from menu_item where id = $1 and restaurant_id = $2;",
+            rusqlite::params![item_id, restaurant_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|e| format!("Failed to set recipe: {}", e))?;
+    if found == 0 {
+        return Ok(false);
+    }
+    transaction
+        .execute(
+            "delete from recipe where menu_item_id = $1;",
+            rusqlite::params![item_id],
+        )
+        .map_err(|e| format!("Failed to set recipe: {}", e))?;
+    for line in recipe {
+        transaction
+            .execute(
+                "insert into recipe (menu_item_id, ingredient_id, quantity) values ($1, $2, $3);",
+                rusqlite::params![item_id, line.ingredient_id, line.quantity],
+            )
+            .map_err(|e| format!("Failed to set recipe: {}", e))?;
+    }
+    transaction
+        .commit()
+        .map_err(|e| format!("Failed to set recipe: {}", e))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+    /// The fifth of January 1970, a Monday.
+    const MONDAY: i64 = 4 * 24 * HOUR;
+
+    fn at(days_after_monday: i64, hour: i64, minute: i64) -> i64 {
+        MONDAY + days_after_monday * 24 * HOUR + hour * HOUR + minute * 60
+    }
+
+    fn window(days: i32, starts: i32, ends: i32) -> domain::Window {
+        domain::Window { days, starts, ends }
+    }
+
+    fn rule(
+        category_id: Option<i32>,
+        menu_item_id: Option<i32>,
+        percent_off: i32,
+    ) -> domain::PriceRule {
+        domain::PriceRule {
+            id: 1,
+            name: String::from("happy hour"),
+            category_id,
+            menu_item_id,
+            window: window(127, 16 * 60, 18 * 60),
+            valid_from: None,
+            valid_until: None,
+            percent_off,
+        }
+    }
+
+    fn soda() -> domain::MenuItem {
+        domain::MenuItem {
+            id: 5,
+            name: String::from("soda"),
+            price: 450,
+            regular_price: 450,
+            modifier_groups: Vec::new(),
+            components: Vec::new(),
+            recipe: Vec::new(),
+            availability: domain::Availability::Available,
+            allergens: Vec::new(),
+            dietary: Vec::new(),
+            station_id: None,
+        }
+    }
+
+    #[test]
+    fn should_only_be_available_while_a_schedule_is_open() {
+        let weekday_breakfast = [window(0b0011111, 7 * 60, 11 * 60)];
+        let available = domain::Availability::Available;
+        assert_eq!(scheduled(&weekday_breakfast, at(0, 8, 0), 0), available);
+        assert_eq!(scheduled(&[], at(0, 20, 0), 0), available);
+        let until_tuesday = domain::Availability::Unavailable {
+            until: Some(at(1, 7, 0)),
+        };
+        assert_eq!(
+            scheduled(&weekday_breakfast, at(0, 12, 0), 0),
+            until_tuesday
+        );
+        let until_monday = domain::Availability::Unavailable {
+            until: Some(at(7, 7, 0)),
+        };
+        assert_eq!(scheduled(&weekday_breakfast, at(4, 11, 0), 0), until_monday);
+        assert_eq!(scheduled(&weekday_breakfast, at(5, 8, 0), 0), until_monday);
+        let never = domain::Availability::Unavailable { until: None };
+        assert_eq!(
+            scheduled(&[window(0, 7 * 60, 11 * 60)], at(0, 8, 0), 0),
+            never
+        );
+    }
+
+    #[test]
+    fn should_keep_schedules_in_the_restaurants_time() {
+        let breakfast = [window(127, 7 * 60, 11 * 60)];
+        let available = domain::Availability::Available;
+        assert_eq!(scheduled(&breakfast, at(0, 5, 30), 2 * HOUR), available);
+        let until = domain::Availability::Unavailable {
+            until: Some(at(0, 5, 0)),
+        };
+        assert_eq!(scheduled(&breakfast, at(0, 4, 30), 2 * HOUR), until);
+    }
+
+    #[test]
+    fn should_run_windows_that_end_before_they_start_past_midnight() {
+        let monday_night = [window(0b0000001, 22 * 60, 2 * 60)];
+        let available = domain::Availability::Available;
+        assert_eq!(scheduled(&monday_night, at(0, 23, 0), 0), available);
+        assert_eq!(scheduled(&monday_night, at(1, 1, 0), 0), available);
+        let until = domain::Availability::Unavailable {
+            until: Some(at(7, 22, 0)),
+        };
+        assert_eq!(scheduled(&monday_night, at(1, 3, 0), 0), until);
+        assert_eq!(
+            scheduled(&monday_night, at(0, 1, 0), 0),
+            domain::Availability::Unavailable {
+                until: Some(at(0, 22, 0)),
+            }
+        );
+    }
+
+    #[test]
+    fn should_only_apply_price_rules_while_they_are_active() {
+        let happy_hour = rule(Some(2), None, 20);
+        assert!(happy_hour.is_active(at(2, 17, 0), 0));
+        assert!(!happy_hour.is_active(at(2, 18, 0), 0));
+        assert!(!happy_hour.is_active(at(2, 17, 0), 2 * HOUR));
+        let ended = domain::PriceRule {
+            valid_until: Some(at(2, 0, 0)),
+            ..happy_hour.clone()
+        };
+        assert!(!ended.is_active(at(2, 17, 0), 0));
+        let upcoming = domain::PriceRule {
+            valid_from: Some(at(3, 0, 0)),
+            ..happy_hour
+        };
+        assert!(!upcoming.is_active(at(2, 17, 0), 0));
+        assert!(upcoming.is_active(at(3, 17, 0), 0));
+    }
+
+    #[test]
+    fn should_take_the_biggest_discount_that_applies_off_the_regular_price() {
+        assert_eq!(discounted(&[], 2, &soda()), 450);
+        let drinks = rule(Some(2), None, 20);
+        assert_eq!(discounted(&[drinks.clone()], 2, &soda()), 360);
+        assert_eq!(discounted(&[drinks.clone()], 1, &soda()), 450);
+        let other_item = rule(None, Some(6), 50);
+        let soda_rule = rule(None, Some(5), 10);
+        let rules = [drinks, other_item, soda_rule];
+        assert_eq!(discounted(&rules, 2, &soda()), 360);
+        let everything = rule(None, None, 150);
+        assert_eq!(discounted(&[everything], 1, &soda()), 0);
+    }
+}
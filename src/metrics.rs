@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response, Rocket};
+
+use crate::repository;
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (index, bound) in BUCKETS.iter().enumerate() {
+            if seconds <= *bound {
+                self.buckets[index] += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Request counts and latencies per route, kept in memory since the server started.
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    durations: Mutex<BTreeMap<(String, String), Histogram>>,
+}
+
+impl Metrics {
+    pub fn record(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        *self
+            .requests
+            .lock()
+            .entry((method.to_string(), route.to_string(), status))
+            .or_insert(0) += 1;
+        self.durations
+            .lock()
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(seconds);
+    }
+
+    /// Renders everything in the Prometheus text exposition format.
+    pub fn render(&self, status: &dyn repository::StatusRepository) -> String {
+        let mut output = String::new();
+        self.render_requests(&mut output);
+        match status.business() {
+            Ok(business) => render_business(&business, &mut output),
+            Err(e) => warn!("Failed to collect business metrics: {}", e),
+        }
+        output
+    }
+
+    fn render_requests(&self, output: &mut String) {
+        let _ = writeln!(
+            output,
+            "# HELP http_requests_total Requests handled by route and status."
+        );
+        let _ = writeln!(output, "# TYPE http_requests_total counter");
+        for ((method, route, status), count) in self.requests.lock().iter() {
+            let _ = writeln!(
+                output,
+                "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                escape(method),
+                escape(route),
+                status,
+                count
+            );
+        }
+        let _ = writeln!(
+            output,
+            "# HELP http_request_duration_seconds Time taken to handle requests by route."
+        );
+        let _ = writeln!(output, "# TYPE http_request_duration_seconds histogram");
+        for ((method, route), histogram) in self.durations.lock().iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            for (index, bound) in BUCKETS.iter().enumerate() {
+                let _ = writeln!(
+                    output,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, histogram.buckets[index]
+                );
+            }
+            let _ = writeln!(
+                output,
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                output,
+                "http_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                output,
+                "http_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+    }
+}
+
+/// The numbers of one restaurant, read from the database when scraped so they survive
+/// restarts. Ordered and voided counts come from the append-only audit log so they only ever
+/// go up, the preparation time is how long the kitchen took from firing an item to having it
+/// ready.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Business {
+    pub restaurant_id: i32,
+    pub open_tables: i64,
+    pub items_ordered: i64,
+    pub items_voided: i64,
+    pub average_preparation: Option<f64>,
+}
+
+/// The numbers of every restaurant, in the order of their ids.
+pub fn business(connection: &rusqlite::Connection) -> Result<Vec<Business>, String> {
+    let mut statement = connection
+        .prepare(
+            "select restaurant.id,
+                (select count(*) from restaurant_table
+                    where restaurant_id = restaurant.id and items != ''),
+                (select count(*) from audit_log
+                    where restaurant_id = restaurant.id and action = 'add_item'),
+                (select count(*) from audit_log
+                    where restaurant_id = restaurant.id and action = 'void_item'),
+                (select avg((ready_at - fired_at) / 60.0) from item
+                    where restaurant_id = restaurant.id and ready_at is not null
+                        and id not in (select item_id from voided_item))
+            from restaurant order by restaurant.id;",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(Business {
+                restaurant_id: row.get(0)?,
+                open_tables: row.get(1)?,
+                items_ordered: row.get(2)?,
+                items_voided: row.get(3)?,
+                average_preparation: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn render_business(business: &[Business], output: &mut String) {
+    write_metric(
+        output,
+        "restaurant_open_tables",
+        "gauge",
+        "Tables with items on them.",
+        business,
+        |restaurant| restaurant.open_tables.to_string(),
+    );
+    write_metric(
+        output,
+        "restaurant_items_ordered_total",
+        "counter",
+        "Items added to tables.",
+        business,
+        |restaurant| restaurant.items_ordered.to_string(),
+    );
+    write_metric(
+        output,
+        "restaurant_items_voided_total",
+        "counter",
+        "Items voided from tables.",
+        business,
+        |restaurant| restaurant.items_voided.to_string(),
+    );
+    write_metric(
+        output,
+        "restaurant_average_preparation_minutes",
+        "gauge",
+        "Average minutes from firing an item to it being ready, for items not voided.",
+        business,
+        |restaurant| restaurant.average_preparation.unwrap_or(0.0).to_string(),
+    );
+}
+
+/// Writes one sample of the metric for each restaurant, labelled with its id.
+fn write_metric(
+    output: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    business: &[Business],
+    value: impl Fn(&Business) -> String,
+) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+    for restaurant in business {
+        let _ = writeln!(
+            output,
+            "{}{{restaurant_id=\"{}\"}} {}",
+            name,
+            restaurant.restaurant_id,
+            value(restaurant)
+        );
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+struct RequestStart(Instant);
+
+/// Times every request and counts it against the route that handled it.
+pub struct MetricsFairing {
+    metrics: Arc<Metrics>,
+}
+
+impl Default for MetricsFairing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsFairing {
+    pub fn new() -> MetricsFairing {
+        MetricsFairing {
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+}
+
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Metrics",
+            kind: Kind::Attach | Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        Ok(rocket.manage(self.metrics.clone()))
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        let route = match request.route() {
+            Some(route) => route.uri.path().to_string(),
+            None => String::from("unmatched"),
+        };
+        self.metrics.record(
+            request.method().as_str(),
+            &route,
+            response.status().code,
+            start.0.elapsed().as_secs_f64(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_fill_cumulative_buckets() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.02);
+        histogram.observe(3.0);
+        assert_eq!(histogram.buckets[0], 0);
+        assert_eq!(histogram.buckets[2], 1);
+        assert_eq!(histogram.buckets[BUCKETS.len() - 1], 2);
+        assert_eq!(histogram.count, 2);
+    }
+
+    #[test]
+    fn should_render_requests_by_route() {
+        let metrics = Metrics::default();
+        metrics.record("GET", "/api/v1/tables/<table>", 200, 0.003);
+        metrics.record("GET", "/api/v1/tables/<table>", 200, 0.2);
+        metrics.record("GET", "/api/v1/tables/<table>", 404, 0.001);
+        let mut output = String::new();
+        metrics.render_requests(&mut output);
+        assert!(output.contains(
+            "http_requests_total{method=\"GET\",route=\"/api/v1/tables/<table>\",status=\"200\"} 2"
+        ));
+        assert!(output.contains(
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/api/v1/tables/<table>\",le=\"0.005\"} 2"
+        ));
+        assert!(output.contains(
+            "http_request_duration_seconds_count{method=\"GET\",route=\"/api/v1/tables/<table>\"} 3"
+        ));
+    }
+
+    #[test]
+    fn should_escape_labels() {
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
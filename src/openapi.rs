@@ -0,0 +1,1855 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::api::v2;
+use crate::auth;
+use crate::data;
+use crate::domain;
+use crate::etag;
+use crate::health;
+use crate::idempotency;
+use crate::kitchen;
+use crate::logging;
+
+/// A `data` type sent or received as json, described so it can be put in the spec.
+pub trait Schema: Serialize {
+    const NAME: &'static str;
+
+    fn schema() -> Value;
+
+    fn example() -> Self;
+}
+
+pub enum Body {
+    Json(&'static str),
+    JsonList(&'static str),
+    JsonObject,
+    Text,
+    Html,
+    Css,
+    JavaScript,
+    EventStream,
+}
+
+pub struct Operation {
+    pub method: &'static str,
+    /// The path as it is written in the route attribute, without the query.
+    pub path: &'static str,
+    pub id: &'static str,
+    pub summary: &'static str,
+    /// Which `auth::Action` the route is guarded by, `None` when no staff id is needed.
+    pub action: Option<auth::Action>,
+    pub query: &'static [(&'static str, &'static str)],
+    pub headers: &'static [&'static str],
+    pub request: Option<Body>,
+    pub responses: &'static [(u16, &'static str)],
+    pub body: Body,
+}
+
+/// Every route mounted by the server. `should_match_spec_to_routes` fails when this and
+/// the routes drift apart.
+pub const OPERATIONS: &[Operation] = &[
+    Operation {
+        method: "get",
+        path: "/api/v1/restaurant",
+        id: "get_restaurant",
+        summary: "Gets the restaurant the staff member works at.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The restaurant.")],
+        body: Body::Json(data::Restaurant::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/menu",
+        id: "get_menu",
+        summary: "Lists the menu by category, with the modifier groups and price of each item.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The menu.")],
+        body: Body::JsonList(data::MenuCategory::NAME),
+    },
+    Operation {
+        method: "put",
+        path: "/api/v1/menu/<item>/availability",
+        id: "set_availability",
+        summary: "Marks a menu item as run out, until a time when one is given, or as back.",
+        action: Some(auth::Action::ChangeAvailability),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(data::AvailabilityRequest::NAME)),
+        responses: &[
+            (200, "The menu item with its availability."),
+            (400, "The time it is available again has passed."),
+            (404, "There is no such menu item."),
+        ],
+        body: Body::Json(data::MenuItem::NAME),
+    },
+    Operation {
+        method: "put",
+        path: "/api/v1/menu/<item>/recipe",
+        id: "set_recipe",
+        summary: "Replaces how much of each ingredient making one of a menu item uses.",
+        action: Some(auth::Action::ManageInventory),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(data::Recipe::NAME)),
+        responses: &[
+            (200, "The recipe."),
+            (
+                400,
+                "An ingredient is unknown or listed twice, or a quantity is not more than nothing.",
+            ),
+            (404, "There is no such menu item."),
+        ],
+        body: Body::Json(data::Recipe::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/inventory",
+        id: "get_inventory",
+        summary: "Lists the ingredients with how much of each is left.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The ingredients.")],
+        body: Body::JsonList(data::Ingredient::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v1/inventory",
+        id: "add_ingredient",
+        summary: "Adds an ingredient with the stock there is of it.",
+        action: Some(auth::Action::ManageInventory),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(data::IngredientRequest::NAME)),
+        responses: &[
+            (200, "The ingredient that was added."),
+            (400, "The name is blank or the stock is less than nothing."),
+        ],
+        body: Body::Json(data::Ingredient::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v1/inventory/<ingredient>/restock",
+        id: "restock",
+        summary: "Adds a delivery of an ingredient to its stock.",
+        action: Some(auth::Action::ManageInventory),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(data::RestockRequest::NAME)),
+        responses: &[
+            (200, "The ingredient with its new stock."),
+            (400, "The quantity is not more than nothing."),
+            (404, "There is no such ingredient."),
+        ],
+        body: Body::Json(data::Ingredient::NAME),
+    },
+    Operation {
+        method: "put",
+        path: "/api/v1/inventory/<ingredient>/low_stock",
+        id: "set_low_stock",
+        summary: "Changes how low an ingredient's stock gets before it is running low.",
+        action: Some(auth::Action::ManageInventory),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(data::LowStockRequest::NAME)),
+        responses: &[
+            (200, "The ingredient with its new threshold."),
+            (400, "The threshold is less than nothing."),
+            (404, "There is no such ingredient."),
+        ],
+        body: Body::Json(data::Ingredient::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/tables",
+        id: "get_all_tables",
+        summary: "Lists every table with the items on it.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The tables.")],
+        body: Body::JsonList(data::Table::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/tables/<table>",
+        id: "get_items_for_table",
+        summary: "Gets a table with its items, the ETag header has the table's version.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The table."), (404, "There is no such table.")],
+        body: Body::Json(data::Table::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/tables/<table>/<item>",
+        id: "get_item_for_table",
+        summary: "Gets an item if it is on the table.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[
+            (200, "The item."),
+            (404, "There is no such table or the item is not on it."),
+        ],
+        body: Body::Json(data::Item::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v1/tables",
+        id: "add_item",
+        summary: "Adds items to a table, creating the table when there is not one.",
+        action: Some(auth::Action::AddItems),
+        query: &[],
+        headers: &[idempotency::IDEMPOTENCY_HEADER, etag::IF_MATCH_HEADER],
+        request: Some(Body::Json(data::ItemRequest::NAME)),
+        responses: &[
+            (200, "The items were added."),
+            (400, "The modifiers chosen are not valid for the item."),
+            (
+                409,
+                "An item is not available, there is not enough stock to make it or someone at \
+                 the table is allergic to it, or the first request with this Idempotency-Key \
+                 is running.",
+            ),
+            (412, "The table changed since the If-Match version."),
+            (422, "The Idempotency-Key was used for a different request."),
+        ],
+        body: Body::Json(data::StatusMessage::NAME),
+    },
+    Operation {
+        method: "delete",
+        path: "/api/v1/tables/<table>/<item>",
+        id: "remove_item",
+        summary: "Voids an item on a table with a reason.",
+        action: Some(auth::Action::RemoveItems),
+        query: &[("reason", "string")],
+        headers: &[etag::IF_MATCH_HEADER],
+        request: None,
+        responses: &[
+            (200, "The item was voided."),
+            (400, "The reason was missing or blank."),
+            (
+                403,
+                "The bill was settled and the staff member is not a manager.",
+            ),
+            (404, "The item is not on the table."),
+            (412, "The table changed since the If-Match version."),
+        ],
+        body: Body::Json(data::StatusMessage::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v1/tables/<table>/<item>/restore",
+        id: "restore_item",
+        summary: "Puts a voided item back on its table.",
+        action: Some(auth::Action::RestoreItems),
+        query: &[],
+        headers: &[etag::IF_MATCH_HEADER],
+        request: None,
+        responses: &[
+            (200, "The item was restored."),
+            (404, "The item was not voided from the table."),
+            (409, "There is not enough stock to make the item again."),
+            (412, "The table changed since the If-Match version."),
+        ],
+        body: Body::Json(data::StatusMessage::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v1/tables/<table>/courses/<course>/fire",
+        id: "fire_course",
+        summary: "Sends the held items of a course to the kitchen.",
+        action: Some(auth::Action::AddItems),
+        query: &[],
+        headers: &[etag::IF_MATCH_HEADER],
+        request: None,
+        responses: &[
+            (200, "The course was fired."),
+            (404, "Nothing in the course is on the table."),
+            (409, "The course was already fired."),
+            (412, "The table changed since the If-Match version."),
+        ],
+        body: Body::Json(data::StatusMessage::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/audit",
+        id: "get_audit",
+        summary: "Searches the audit log of table, item, menu, allergy and inventory changes.",
+        action: Some(auth::Action::ViewAudit),
+        query: &[
+            ("table", "integer"),
+            ("actor", "integer"),
+            ("from", "integer"),
+            ("to", "integer"),
+        ],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The matching audit entries, oldest first.")],
+        body: Body::JsonList(data::AuditEntry::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/tables",
+        id: "get_tables",
+        summary: "Lists every table with its order, voided items included.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The tables.")],
+        body: Body::JsonList(v2::Table::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/tables/<table>",
+        id: "get_table",
+        summary: "Gets a table with its order, the ETag header has the table's version.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The table."), (404, "There is no such table.")],
+        body: Body::Json(v2::Table::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/tables/<table>/bill",
+        id: "get_bill",
+        summary: "Prices what is still ordered on a table with its adjustments taken off.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The bill."), (404, "There is no such table.")],
+        body: Body::Json(v2::Bill::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v2/tables/<table>/bill/settle",
+        id: "settle_bill",
+        summary: "Settles a table's bill once it has been paid.",
+        action: Some(auth::Action::SettleBills),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[
+            (200, "The settled bill."),
+            (404, "There is no such table."),
+            (409, "The bill was already settled."),
+        ],
+        body: Body::Json(v2::Bill::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v2/tables/<table>/adjustments",
+        id: "add_adjustment",
+        summary: "Gives a comp or discount on an item, the items with a name or the whole bill.",
+        action: Some(auth::Action::AdjustBills),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(v2::AdjustmentRequest::NAME)),
+        responses: &[
+            (200, "The bill with the adjustment."),
+            (400, "The discount is not valid or there is no reason."),
+            (404, "There is no such table or item on it."),
+        ],
+        body: Body::Json(v2::Bill::NAME),
+    },
+    Operation {
+        method: "post",
+        path: "/api/v2/tables/<table>/promo_codes",
+        id: "redeem_promo_code",
+        summary: "Redeems a promo code for a table.",
+        action: Some(auth::Action::AddItems),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(v2::PromoCodeRequest::NAME)),
+        responses: &[
+            (200, "The bill with the promo code."),
+            (400, "There is no such promo code."),
+            (404, "There is no such table."),
+            (409, "The code expired, was used up or is on the bill."),
+        ],
+        body: Body::Json(v2::Bill::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/tables/<table>/allergies",
+        id: "get_allergies",
+        summary: "Lists the allergies the guests at a table declared.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The allergies."), (404, "There is no such table.")],
+        body: Body::Json(v2::Allergies::NAME),
+    },
+    Operation {
+        method: "put",
+        path: "/api/v2/tables/<table>/allergies",
+        id: "set_allergies",
+        summary: "Replaces the allergies declared at a table, for a seat or the whole table.",
+        action: Some(auth::Action::AddItems),
+        query: &[],
+        headers: &[],
+        request: Some(Body::Json(v2::Allergies::NAME)),
+        responses: &[
+            (200, "The allergies now declared."),
+            (400, "An allergy has no allergen."),
+            (404, "There is no such table."),
+        ],
+        body: Body::Json(v2::Allergies::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/kitchen",
+        id: "get_kitchen",
+        summary: "Lists what the kitchen has to make, bundles split into their components, \
+                  with warnings about allergies at the table.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The tickets.")],
+        body: Body::JsonList(v2::Ticket::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/kitchen/estimates",
+        id: "get_estimates",
+        summary: "Compares how long each menu item made lately was estimated to take with how \
+                  long it took, with what it would be estimated at now.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The estimates by menu item name.")],
+        body: Body::JsonList(v2::PrepEstimate::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/stations",
+        id: "get_stations",
+        summary: "Lists the kitchen's stations.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The stations.")],
+        body: Body::JsonList(v2::Station::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/stations/<station>/tickets",
+        id: "get_station_tickets",
+        summary: "Lists the tickets a station still has to get ready.",
+        action: Some(auth::Action::ViewTables),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The tickets."), (404, "There is no such station.")],
+        body: Body::JsonList(v2::Ticket::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v2/stations/<station>/events",
+        id: "get_station_events",
+        summary: "Polls what happened at a station as server-sent events with \
+                  v2.KitchenEvent data, starting after the Last-Event-ID header or the after \
+                  query. Held until there is an event or kitchen_event_wait seconds pass, \
+                  none by default.",
+        action: Some(auth::Action::ViewTables),
+        query: &[("after", "integer")],
+        headers: &[kitchen::LAST_EVENT_ID_HEADER],
+        request: None,
+        responses: &[
+            (
+                200,
+                "The events so far, none when the wait ran out, the client reconnects for more.",
+            ),
+            (400, "The Last-Event-ID header is not a number."),
+            (404, "There is no such station."),
+        ],
+        body: Body::EventStream,
+    },
+    Operation {
+        method: "post",
+        path: "/api/v2/tables/<table>/items/<item>/ready",
+        id: "mark_ready",
+        summary: "Marks a ticket of a table ready, the table is ready once all its tickets are.",
+        action: Some(auth::Action::ChangeItemStatus),
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[
+            (200, "The table with the ticket ready."),
+            (404, "The ticket is not on the table."),
+            (409, "The item is held or already ready."),
+        ],
+        body: Body::Json(v2::Table::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/metrics",
+        id: "get_metrics",
+        summary: "Prometheus metrics for requests and each restaurant.",
+        action: None,
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "Metrics in the Prometheus text format.")],
+        body: Body::Text,
+    },
+    Operation {
+        method: "get",
+        path: "/health",
+        id: "get_health",
+        summary: "Liveness, answers as long as the server is running.",
+        action: None,
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The server is up.")],
+        body: Body::Json(data::HealthReport::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/ready",
+        id: "get_ready",
+        summary: "Readiness, checks the database is reachable, migrated and answering.",
+        action: None,
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[
+            (200, "Every component is up."),
+            (503, "A component is down."),
+        ],
+        body: Body::Json(data::HealthReport::NAME),
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/openapi.json",
+        id: "get_openapi",
+        summary: "This document.",
+        action: None,
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The OpenAPI document.")],
+        body: Body::JsonObject,
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/docs",
+        id: "get_docs",
+        summary: "A page for browsing this document.",
+        action: None,
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The documentation page.")],
+        body: Body::Html,
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/docs/swagger-ui.css",
+        id: "get_docs_css",
+        summary: "The Swagger UI stylesheet the documentation page uses.",
+        action: None,
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The stylesheet.")],
+        body: Body::Css,
+    },
+    Operation {
+        method: "get",
+        path: "/api/v1/docs/swagger-ui-bundle.js",
+        id: "get_docs_bundle",
+        summary: "The Swagger UI script the documentation page uses.",
+        action: None,
+        query: &[],
+        headers: &[],
+        request: None,
+        responses: &[(200, "The script.")],
+        body: Body::JavaScript,
+    },
+];
+
+/// Turns a route path like `/tables/<table>` into the OpenAPI form `/tables/{table}`.
+pub fn openapi_path(path: &str) -> String {
+    path.replace('<', "{").replace('>', "}")
+}
+
+fn path_parameters(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|segment| segment.starts_with('<') && segment.ends_with('>'))
+        .map(|segment| segment.trim_start_matches('<').trim_end_matches('>'))
+        .collect()
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn content(body: &Body) -> Value {
+    match body {
+        Body::Json(name) => json!({ "application/json": { "schema": reference(name) } }),
+        Body::JsonList(name) => json!({
+            "application/json": { "schema": { "type": "array", "items": reference(name) } }
+        }),
+        Body::JsonObject => json!({ "application/json": { "schema": { "type": "object" } } }),
+        Body::Text => json!({ "text/plain": { "schema": { "type": "string" } } }),
+        Body::Html => json!({ "text/html": { "schema": { "type": "string" } } }),
+        Body::Css => json!({ "text/css": { "schema": { "type": "string" } } }),
+        Body::JavaScript => json!({ "text/javascript": { "schema": { "type": "string" } } }),
+        Body::EventStream => json!({ "text/event-stream": { "schema": { "type": "string" } } }),
+    }
+}
+
+fn operation(operation: &Operation) -> Value {
+    let mut parameters = Vec::new();
+    for name in path_parameters(operation.path) {
+        parameters.push(json!({
+            "name": name,
+            "in": "path",
+            "required": true,
+            "schema": { "type": "string" },
+        }));
+    }
+    for (name, kind) in operation.query {
+        parameters.push(json!({
+            "name": name,
+            "in": "query",
+            "required": false,
+            "schema": { "type": kind },
+        }));
+    }
+    for name in operation.headers {
+        parameters.push(json!({
+            "name": name,
+            "in": "header",
+            "required": false,
+            "schema": { "type": "string" },
+        }));
+    }
+    let mut responses = Map::new();
+    for (code, description) in operation.responses {
+        let mut response = json!({ "description": description });
+        if *code < 300 {
+            response["content"] = content(&operation.body);
+        }
+        responses.insert(code.to_string(), response);
+    }
+    let mut value = json!({
+        "operationId": operation.id,
+        "summary": operation.summary,
+        "parameters": parameters,
+        "responses": responses,
+    });
+    if let Some(action) = operation.action {
+        require_staff(&mut value, action);
+    }
+    if let Some(request) = &operation.request {
+        value["requestBody"] = json!({ "required": true, "content": content(request) });
+    }
+    value
+}
+
+fn require_staff(value: &mut Value, action: auth::Action) {
+    let roles: Vec<&str> = auth::ROLES
+        .iter()
+        .filter(|role| role.can(action))
+        .map(|role| role.name())
+        .collect();
+    value["security"] = json!([{ "staff": [] }]);
+    value["parameters"]
+        .as_array_mut()
+        .expect("Parameters are a list.")
+        .push(json!({
+            "name": auth::RESTAURANT_HEADER,
+            "in": "header",
+            "required": false,
+            "description": "Checked against the restaurant the staff member works at.",
+            "schema": { "type": "integer" },
+        }));
+    value["description"] = json!(format!("Allowed for: {}.", roles.join(", ")));
+    value["responses"]["401"] = json!({ "description": "No staff id or an unknown one." });
+    value["responses"]["403"] = json!({
+        "description": "The staff member's role is not allowed, or they work at another restaurant.",
+    });
+}
+
+fn component<T: Schema>(schemas: &mut Map<String, Value>) {
+    let mut schema = T::schema();
+    schema["example"] = serde_json::to_value(T::example()).unwrap_or(Value::Null);
+    schemas.insert(T::NAME.to_string(), schema);
+}
+
+fn components() -> Map<String, Value> {
+    let mut schemas = Map::new();
+    component::<data::Restaurant>(&mut schemas);
+    component::<data::Modifier>(&mut schemas);
+    component::<data::ModifierGroup>(&mut schemas);
+    component::<data::MenuItem>(&mut schemas);
+    component::<data::Schedule>(&mut schemas);
+    component::<data::MenuCategory>(&mut schemas);
+    component::<data::Ingredient>(&mut schemas);
+    component::<data::IngredientRequest>(&mut schemas);
+    component::<data::RestockRequest>(&mut schemas);
+    component::<data::LowStockRequest>(&mut schemas);
+    component::<data::Recipe>(&mut schemas);
+    component::<data::RecipeLine>(&mut schemas);
+    component::<data::Item>(&mut schemas);
+    component::<data::Table>(&mut schemas);
+    component::<data::ItemOrder>(&mut schemas);
+    component::<data::ItemRequest>(&mut schemas);
+    component::<data::StatusMessage>(&mut schemas);
+    component::<data::AvailabilityRequest>(&mut schemas);
+    component::<data::Void>(&mut schemas);
+    component::<data::AuditEntry>(&mut schemas);
+    component::<data::ComponentHealth>(&mut schemas);
+    component::<data::HealthReport>(&mut schemas);
+    component::<v2::Void>(&mut schemas);
+    component::<v2::Modifier>(&mut schemas);
+    component::<v2::Component>(&mut schemas);
+    component::<v2::Item>(&mut schemas);
+    component::<v2::Order>(&mut schemas);
+    component::<v2::Table>(&mut schemas);
+    component::<v2::Ticket>(&mut schemas);
+    component::<v2::PrepEstimate>(&mut schemas);
+    component::<v2::Station>(&mut schemas);
+    component::<v2::KitchenEvent>(&mut schemas);
+    component::<v2::Allergy>(&mut schemas);
+    component::<v2::Allergies>(&mut schemas);
+    component::<v2::BillLine>(&mut schemas);
+    component::<v2::Discount>(&mut schemas);
+    component::<v2::Adjustment>(&mut schemas);
+    component::<v2::AdjustmentRequest>(&mut schemas);
+    component::<v2::PromoCodeRequest>(&mut schemas);
+    component::<v2::Bill>(&mut schemas);
+    schemas
+}
+
+/// Builds the OpenAPI 3 document for every route in `OPERATIONS`.
+pub fn document() -> Value {
+    let mut paths: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    for entry in OPERATIONS {
+        paths
+            .entry(openapi_path(entry.path))
+            .or_default()
+            .insert(entry.method.to_string(), operation(entry));
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Simple Restaurant API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": format!(
+                "Every response has an {} header, send one to follow a request through the logs.",
+                logging::REQUEST_ID_HEADER
+            ),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": components(),
+            "securitySchemes": {
+                "staff": { "type": "apiKey", "in": "header", "name": auth::STAFF_HEADER },
+            },
+        },
+    })
+}
+
+/// A page that loads Swagger UI and points it at the document. Swagger UI 5.17.14 is
+/// vendored in `static/swagger-ui` and served by the server itself.
+pub const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+    <title>Simple Restaurant API</title>
+    <link rel="stylesheet" href="/api/v1/docs/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="/api/v1/docs/swagger-ui-bundle.js"></script>
+    <script>
+        SwaggerUIBundle({ url: "/api/v1/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+</body>
+</html>
+"##;
+
+pub const SWAGGER_UI_CSS: &str = include_str!("../static/swagger-ui/swagger-ui.css");
+
+pub const SWAGGER_UI_BUNDLE: &str = include_str!("../static/swagger-ui/swagger-ui-bundle.js");
+
+fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn health_status() -> Value {
+    json!({ "type": "string", "enum": [health::UP, health::DOWN] })
+}
+
+fn discount_kind() -> Value {
+    json!({ "type": "string", "enum": domain::Discount::KINDS })
+}
+
+fn list_of(name: &str) -> Value {
+    json!({ "type": "array", "items": reference(name) })
+}
+
+/// An object schema, fields are required unless they are listed in `optional`.
+fn object(fields: &[(&str, Value)], optional: &[&str]) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for (name, schema) in fields {
+        let mut schema = schema.clone();
+        if optional.contains(name) {
+            schema["nullable"] = json!(true);
+        } else {
+            required.push(*name);
+        }
+        properties.insert(name.to_string(), schema);
+    }
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+impl Schema for data::Restaurant {
+    const NAME: &'static str = "Restaurant";
+
+    fn schema() -> Value {
+        object(&[("id", integer()), ("name", string())], &[])
+    }
+
+    fn example() -> Self {
+        data::Restaurant {
+            id: 1,
+            name: String::from("Restaurant"),
+        }
+    }
+}
+
+impl Schema for data::Modifier {
+    const NAME: &'static str = "Modifier";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("price_delta", integer()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::Modifier {
+            id: 4,
+            name: String::from("extra cheese"),
+            price_delta: 150,
+        }
+    }
+}
+
+impl Schema for data::ModifierGroup {
+    const NAME: &'static str = "ModifierGroup";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("min_selected", integer()),
+                ("max_selected", integer()),
+                ("modifiers", list_of(data::Modifier::NAME)),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::ModifierGroup {
+            id: 2,
+            name: String::from("Toppings"),
+            min_selected: 0,
+            max_selected: 3,
+            modifiers: vec![data::Modifier::example()],
+        }
+    }
+}
+
+impl Schema for data::MenuItem {
+    const NAME: &'static str = "MenuItem";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("price", integer()),
+                ("regular_price", integer()),
+                ("modifier_groups", list_of(data::ModifierGroup::NAME)),
+                ("components", json!({ "type": "array", "items": string() })),
+                ("available", json!({ "type": "boolean" })),
+                ("available_at", integer()),
+                ("allergens", json!({ "type": "array", "items": string() })),
+                ("dietary", json!({ "type": "array", "items": string() })),
+            ],
+            &[
+                "regular_price",
+                "components",
+                "available_at",
+                "allergens",
+                "dietary",
+            ],
+        )
+    }
+
+    fn example() -> Self {
+        data::MenuItem {
+            id: 7,
+            name: String::from("pizza lunch"),
+            price: 1040,
+            regular_price: Some(1300),
+            modifier_groups: vec![data::ModifierGroup::example()],
+            components: vec![String::from("pizza"), String::from("soda")],
+            available: false,
+            available_at: Some(1700003600),
+            allergens: vec![String::from("gluten"), String::from("milk")],
+            dietary: vec![String::from("vegetarian")],
+        }
+    }
+}
+
+impl Schema for data::Ingredient {
+    const NAME: &'static str = "Ingredient";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("stock", integer()),
+                ("low_stock", integer()),
+                ("low", json!({ "type": "boolean" })),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::Ingredient {
+            id: 5,
+            name: String::from("mozzarella"),
+            stock: 800,
+            low_stock: 1000,
+            low: true,
+        }
+    }
+}
+
+impl Schema for data::IngredientRequest {
+    const NAME: &'static str = "IngredientRequest";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("name", string()),
+                ("stock", integer()),
+                ("low_stock", integer()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::IngredientRequest {
+            name: String::from("mozzarella"),
+            stock: 5000,
+            low_stock: 1000,
+        }
+    }
+}
+
+impl Schema for data::RestockRequest {
+    const NAME: &'static str = "RestockRequest";
+
+    fn schema() -> Value {
+        object(&[("quantity", integer())], &[])
+    }
+
+    fn example() -> Self {
+        data::RestockRequest { quantity: 2500 }
+    }
+}
+
+impl Schema for data::LowStockRequest {
+    const NAME: &'static str = "LowStockRequest";
+
+    fn schema() -> Value {
+        object(&[("low_stock", integer())], &[])
+    }
+
+    fn example() -> Self {
+        data::LowStockRequest { low_stock: 1500 }
+    }
+}
+
+impl Schema for data::Recipe {
+    const NAME: &'static str = "Recipe";
+
+    fn schema() -> Value {
+        object(&[("lines", list_of(data::RecipeLine::NAME))], &[])
+    }
+
+    fn example() -> Self {
+        data::Recipe {
+            lines: vec![data::RecipeLine::example()],
+        }
+    }
+}
+
+impl Schema for data::RecipeLine {
+    const NAME: &'static str = "RecipeLine";
+
+    fn schema() -> Value {
+        object(
+            &[("ingredient_id", integer()), ("quantity", integer())],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::RecipeLine {
+            ingredient_id: 5,
+            quantity: 120,
+        }
+    }
+}
+
+impl Schema for data::Schedule {
+    const NAME: &'static str = "Schedule";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("days", json!({ "type": "array", "items": string() })),
+                ("starts", string()),
+                ("ends", string()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::Schedule {
+            days: vec![String::from("saturday"), String::from("sunday")],
+            starts: String::from("11:00"),
+            ends: String::from("15:00"),
+        }
+    }
+}
+
+impl Schema for data::MenuCategory {
+    const NAME: &'static str = "MenuCategory";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("schedules", list_of(data::Schedule::NAME)),
+                ("items", list_of(data::MenuItem::NAME)),
+            ],
+            &["schedules"],
+        )
+    }
+
+    fn example() -> Self {
+        data::MenuCategory {
+            id: 1,
+            name: String::from("Brunch"),
+            schedules: vec![data::Schedule::example()],
+            items: vec![data::MenuItem::example()],
+        }
+    }
+}
+
+impl Schema for data::Item {
+    const NAME: &'static str = "Item";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("time_to_prepare", integer()),
+                ("name", string()),
+                ("modifiers", list_of(data::Modifier::NAME)),
+                ("course", integer()),
+                ("held", json!({ "type": "boolean" })),
+            ],
+            &["modifiers", "course", "held"],
+        )
+    }
+
+    fn example() -> Self {
+        data::Item {
+            id: 12,
+            time_to_prepare: 8,
+            name: String::from("pizza"),
+            modifiers: vec![data::Modifier::example()],
+            course: Some(2),
+            held: Some(true),
+        }
+    }
+}
+
+impl Schema for data::Table {
+    const NAME: &'static str = "Table";
+
+    fn schema() -> Value {
+        object(
+            &[("id", integer()), ("items", list_of(data::Item::NAME))],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::Table {
+            id: 3,
+            items: vec![data::Item::example()],
+        }
+    }
+}
+
+impl Schema for data::ItemOrder {
+    const NAME: &'static str = "ItemOrder";
+
+    fn schema() -> Value {
+        let with_modifiers = object(
+            &[
+                ("name", string()),
+                ("modifiers", json!({ "type": "array", "items": string() })),
+                ("course", integer()),
+            ],
+            &["modifiers", "course"],
+        );
+        json!({ "oneOf": [string(), with_modifiers] })
+    }
+
+    fn example() -> Self {
+        data::ItemOrder::WithModifiers {
+            name: String::from("pizza"),
+            modifiers: vec![String::from("extra cheese")],
+            course: Some(2),
+        }
+    }
+}
+
+impl Schema for data::ItemRequest {
+    const NAME: &'static str = "ItemRequest";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("table_id", string()),
+                ("items", list_of(data::ItemOrder::NAME)),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::ItemRequest {
+            table_id: String::from("3"),
+            items: vec![
+                data::ItemOrder::example(),
+                data::ItemOrder::Name(String::from("salad")),
+            ],
+        }
+    }
+}
+
+impl Schema for data::StatusMessage {
+    const NAME: &'static str = "StatusMessage";
+
+    fn schema() -> Value {
+        object(&[("message", string())], &[])
+    }
+
+    fn example() -> Self {
+        data::StatusMessage {
+            message: String::from("2 rows inserted."),
+        }
+    }
+}
+
+impl Schema for data::AvailabilityRequest {
+    const NAME: &'static str = "AvailabilityRequest";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("available", json!({ "type": "boolean" })),
+                ("available_at", integer()),
+            ],
+            &["available_at"],
+        )
+    }
+
+    fn example() -> Self {
+        data::AvailabilityRequest {
+            available: false,
+            available_at: Some(1700003600),
+        }
+    }
+}
+
+impl Schema for data::Void {
+    const NAME: &'static str = "Void";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("reason", string()),
+                ("voided_by", integer()),
+                ("voided_at", integer()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        data::Void {
+            reason: String::from("sent back"),
+            voided_by: 2,
+            voided_at: 1700000000,
+        }
+    }
+}
+
+impl Schema for data::AuditEntry {
+    const NAME: &'static str = "AuditEntry";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("actor_id", integer()),
+                ("action", string()),
+                ("table_id", integer()),
+                ("item_id", integer()),
+                ("before", json!({ "type": "object" })),
+                ("after", json!({ "type": "object" })),
+                ("timestamp", integer()),
+            ],
+            &["table_id", "item_id", "before", "after"],
+        )
+    }
+
+    fn example() -> Self {
+        data::AuditEntry {
+            id: 1,
+            actor_id: 2,
+            action: String::from("void_item"),
+            table_id: Some(3),
+            item_id: Some(12),
+            before: serde_json::to_value(data::Item::example()).ok(),
+            after: serde_json::to_value(data::Void::example()).ok(),
+            timestamp: 1700000000,
+        }
+    }
+}
+
+impl Schema for data::ComponentHealth {
+    const NAME: &'static str = "ComponentHealth";
+
+    fn schema() -> Value {
+        object(
+            &[("status", health_status()), ("detail", string())],
+            &["detail"],
+        )
+    }
+
+    fn example() -> Self {
+        data::ComponentHealth {
+            status: String::from("up"),
+            detail: Some(String::from("Schema version 1.")),
+        }
+    }
+}
+
+impl Schema for data::HealthReport {
+    const NAME: &'static str = "HealthReport";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("status", health_status()),
+                (
+                    "components",
+                    json!({
+                        "type": "object",
+                        "additionalProperties": reference(data::ComponentHealth::NAME),
+                    }),
+                ),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        let mut components = BTreeMap::new();
+        components.insert(String::from("schema"), data::ComponentHealth::example());
+        data::HealthReport {
+            status: String::from("up"),
+            components,
+        }
+    }
+}
+
+impl Schema for v2::Void {
+    const NAME: &'static str = "v2.Void";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("reason", string()),
+                ("voided_by", integer()),
+                ("voided_at", integer()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Void {
+            reason: String::from("sent back"),
+            voided_by: 2,
+            voided_at: 1700000000,
+        }
+    }
+}
+
+impl Schema for v2::Modifier {
+    const NAME: &'static str = "v2.Modifier";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("price_delta", integer()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Modifier {
+            id: 4,
+            name: String::from("extra cheese"),
+            price_delta: 150,
+        }
+    }
+}
+
+impl Schema for v2::Component {
+    const NAME: &'static str = "v2.Component";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("preparation_minutes", integer()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Component {
+            id: 13,
+            name: String::from("soda"),
+            preparation_minutes: 5,
+        }
+    }
+}
+
+impl Schema for v2::Item {
+    const NAME: &'static str = "v2.Item";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("name", string()),
+                ("preparation_minutes", integer()),
+                ("price", integer()),
+                ("course", integer()),
+                (
+                    "status",
+                    json!({ "type": "string", "enum": ["ordered", "held", "voided"] }),
+                ),
+                ("fired_at", integer()),
+                ("void", reference(v2::Void::NAME)),
+                ("modifiers", list_of(v2::Modifier::NAME)),
+                ("components", list_of(v2::Component::NAME)),
+            ],
+            &["fired_at", "void", "modifiers", "components"],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Item {
+            id: 12,
+            name: String::from("pizza lunch"),
+            preparation_minutes: 8,
+            price: 1450,
+            course: 1,
+            status: v2::ItemStatus::Voided,
+            fired_at: Some(1700000000),
+            void: Some(v2::Void::example()),
+            modifiers: vec![v2::Modifier::example()],
+            components: vec![v2::Component::example()],
+        }
+    }
+}
+
+impl Schema for v2::Order {
+    const NAME: &'static str = "v2.Order";
+
+    fn schema() -> Value {
+        object(&[("items", list_of(v2::Item::NAME))], &[])
+    }
+
+    fn example() -> Self {
+        v2::Order {
+            items: vec![v2::Item::example()],
+        }
+    }
+}
+
+impl Schema for v2::Table {
+    const NAME: &'static str = "v2.Table";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("version", integer()),
+                (
+                    "status",
+                    json!({ "type": "string", "enum": ["open", "empty"] }),
+                ),
+                ("ready", json!({ "type": "boolean" })),
+                ("order", reference(v2::Order::NAME)),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Table {
+            id: 3,
+            version: 4,
+            status: v2::TableStatus::Empty,
+            ready: false,
+            order: v2::Order::example(),
+        }
+    }
+}
+
+impl Schema for v2::Ticket {
+    const NAME: &'static str = "v2.Ticket";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("table_id", integer()),
+                ("item_id", integer()),
+                ("name", string()),
+                ("preparation_minutes", integer()),
+                ("course", integer()),
+                ("station", string()),
+                ("fired_at", integer()),
+                ("ready_at", integer()),
+                ("modifiers", list_of(v2::Modifier::NAME)),
+                ("bundle", string()),
+                (
+                    "allergy_warnings",
+                    json!({ "type": "array", "items": string() }),
+                ),
+            ],
+            &[
+                "station",
+                "fired_at",
+                "ready_at",
+                "modifiers",
+                "bundle",
+                "allergy_warnings",
+            ],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Ticket {
+            table_id: 3,
+            item_id: 13,
+            name: String::from("pizza"),
+            preparation_minutes: 8,
+            course: 2,
+            station: Some(String::from("grill")),
+            fired_at: Some(1700000000),
+            ready_at: Some(1700000600),
+            modifiers: vec![v2::Modifier::example()],
+            bundle: Some(String::from("pizza lunch")),
+            allergy_warnings: vec![String::from(
+                "pizza contains gluten, seat 2 is allergic to it.",
+            )],
+        }
+    }
+}
+
+impl Schema for v2::PrepEstimate {
+    const NAME: &'static str = "v2.PrepEstimate";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("name", string()),
+                ("samples", integer()),
+                ("estimated_minutes", integer()),
+                ("median_minutes", integer()),
+                ("p90_minutes", integer()),
+                ("current_minutes", integer()),
+            ],
+            &["current_minutes"],
+        )
+    }
+
+    fn example() -> Self {
+        v2::PrepEstimate {
+            name: String::from("pizza"),
+            samples: 50,
+            estimated_minutes: 10,
+            median_minutes: 12,
+            p90_minutes: 18,
+            current_minutes: Some(13),
+        }
+    }
+}
+
+impl Schema for v2::Station {
+    const NAME: &'static str = "v2.Station";
+
+    fn schema() -> Value {
+        object(&[("id", integer()), ("name", string())], &[])
+    }
+
+    fn example() -> Self {
+        v2::Station {
+            id: 1,
+            name: String::from("grill"),
+        }
+    }
+}
+
+impl Schema for v2::KitchenEvent {
+    const NAME: &'static str = "v2.KitchenEvent";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("kind", json!({ "type": "string", "enum": kitchen::KINDS })),
+                ("table_id", integer()),
+                ("item_id", integer()),
+                ("name", string()),
+                ("at", integer()),
+            ],
+            &[],
+        )
+    }
+
+    fn example() -> Self {
+        v2::KitchenEvent {
+            id: 41,
+            kind: String::from(kitchen::READY),
+            table_id: 3,
+            item_id: 13,
+            name: String::from("pizza"),
+            at: 1700000600,
+        }
+    }
+}
+
+impl Schema for v2::Allergy {
+    const NAME: &'static str = "v2.Allergy";
+
+    fn schema() -> Value {
+        object(&[("seat", integer()), ("allergen", string())], &["seat"])
+    }
+
+    fn example() -> Self {
+        v2::Allergy {
+            seat: Some(2),
+            allergen: String::from("gluten"),
+        }
+    }
+}
+
+impl Schema for v2::Allergies {
+    const NAME: &'static str = "v2.Allergies";
+
+    fn schema() -> Value {
+        object(&[("allergies", list_of(v2::Allergy::NAME))], &[])
+    }
+
+    fn example() -> Self {
+        v2::Allergies {
+            allergies: vec![v2::Allergy::example()],
+        }
+    }
+}
+
+impl Schema for v2::BillLine {
+    const NAME: &'static str = "v2.BillLine";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("item_id", integer()),
+                ("name", string()),
+                ("price", integer()),
+                ("modifiers", list_of(v2::Modifier::NAME)),
+                ("components", json!({ "type": "array", "items": string() })),
+            ],
+            &["modifiers", "components"],
+        )
+    }
+
+    fn example() -> Self {
+        v2::BillLine {
+            item_id: 12,
+            name: String::from("pizza lunch"),
+            price: 1450,
+            modifiers: vec![v2::Modifier::example()],
+            components: vec![String::from("pizza"), String::from("soda")],
+        }
+    }
+}
+
+impl Schema for v2::Bill {
+    const NAME: &'static str = "v2.Bill";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("table_id", integer()),
+                ("lines", list_of(v2::BillLine::NAME)),
+                ("subtotal", integer()),
+                ("adjustments", list_of(v2::Adjustment::NAME)),
+                ("total", integer()),
+                ("paid_at", integer()),
+            ],
+            &["adjustments", "paid_at"],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Bill {
+            table_id: 3,
+            lines: vec![v2::BillLine::example()],
+            subtotal: 1450,
+            adjustments: vec![v2::Adjustment::example()],
+            total: 1305,
+            paid_at: Some(1700003600),
+        }
+    }
+}
+
+impl Schema for v2::Discount {
+    const NAME: &'static str = "v2.Discount";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("kind", discount_kind()),
+                ("value", integer()),
+                ("buy", integer()),
+                ("get", integer()),
+            ],
+            &["value", "buy", "get"],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Discount {
+            kind: String::from("percentage"),
+            value: Some(10),
+            buy: None,
+            get: None,
+        }
+    }
+}
+
+impl Schema for v2::Adjustment {
+    const NAME: &'static str = "v2.Adjustment";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("id", integer()),
+                ("item_id", integer()),
+                ("item_name", string()),
+                ("discount", reference(v2::Discount::NAME)),
+                ("reason", string()),
+                ("code", string()),
+                ("added_by", integer()),
+                ("added_at", integer()),
+                ("amount", integer()),
+            ],
+            &["item_id", "item_name", "code"],
+        )
+    }
+
+    fn example() -> Self {
+        v2::Adjustment {
+            id: 6,
+            item_id: Some(12),
+            item_name: Some(String::from("pizza lunch")),
+            discount: v2::Discount::example(),
+            reason: String::from("Promo code LUNCH10"),
+            code: Some(String::from("LUNCH10")),
+            added_by: 9002,
+            added_at: 1700000000,
+            amount: 145,
+        }
+    }
+}
+
+impl Schema for v2::AdjustmentRequest {
+    const NAME: &'static str = "v2.AdjustmentRequest";
+
+    fn schema() -> Value {
+        object(
+            &[
+                ("item_id", integer()),
+                ("item_name", string()),
+                ("discount", reference(v2::Discount::NAME)),
+                ("reason", string()),
+            ],
+            &["item_id", "item_name"],
+        )
+    }
+
+    fn example() -> Self {
+        v2::AdjustmentRequest {
+            item_id: Some(12),
+            item_name: None,
+            discount: v2::Discount {
+                kind: String::from("comp"),
+                value: None,
+                buy: None,
+                get: None,
+            },
+            reason: String::from("Waited too long"),
+        }
+    }
+}
+
+impl Schema for v2::PromoCodeRequest {
+    const NAME: &'static str = "v2.PromoCodeRequest";
+
+    fn schema() -> Value {
+        object(&[("code", string())], &[])
+    }
+
+    fn example() -> Self {
+        v2::PromoCodeRequest {
+            code: String::from("LUNCH10"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The properties of an object schema, or of the object in a `oneOf`.
+    fn properties(schema: &Value) -> Vec<&String> {
+        let object = match schema["oneOf"].as_array() {
+            Some(choices) => choices
+                .iter()
+                .find(|choice| choice["properties"].is_object()),
+            None => Some(schema),
+        };
+        object
+            .and_then(|object| object["properties"].as_object())
+            .map(|properties| properties.keys().collect())
+            .unwrap_or_default()
+    }
+
+    /// Fails when a field is added to or removed from a type without updating its schema.
+    #[test]
+    fn should_describe_every_field_of_every_schema() {
+        for (name, schema) in components() {
+            let example = schema["example"]
+                .as_object()
+                .unwrap_or_else(|| panic!("example for {} is not an object", name));
+            let fields: Vec<&String> = example.keys().collect();
+            assert_eq!(
+                fields,
+                properties(&schema),
+                "schema for {} is out of date",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn should_only_reference_known_schemas() {
+        let document = document().to_string();
+        let schemas = components();
+        for reference in document.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "{} is not a schema", name);
+        }
+    }
+
+    #[test]
+    fn should_convert_route_paths() {
+        assert_eq!(
+            openapi_path("/api/v1/tables/<table>/<item>"),
+            "/api/v1/tables/{table}/{item}"
+        );
+        assert_eq!(
+            path_parameters("/api/v1/tables/<table>/<item>/restore"),
+            vec!["table", "item"]
+        );
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::BTreeMap;
+
+use parking_lot::Mutex;
+
+use crate::audit;
+use crate::domain;
+use crate::repository::{ItemRepository, StockRepository, TableRepository};
+
+struct StoredTable {
+    restaurant_id: i32,
+    item_ids: Vec<String>,
+    version: i64,
+    paid_at: Option<i64>,
+}
+
+struct StoredItem {
+    restaurant_id: i32,
+    name: String,
+    time_to_prepare: i32,
+    modifiers: Vec<domain::Modifier>,
+    price: i32,
+    bundle_id: Option<i32>,
+    course: i32,
+    held: bool,
+    fired_at: Option<i64>,
+    station_id: Option<i32>,
+    ready_at: Option<i64>,
+    recipe: Vec<domain::RecipeLine>,
+}
+
+#[derive(Default)]
+struct Store {
+    tables: BTreeMap<i32, StoredTable>,
+    items: BTreeMap<i32, StoredItem>,
+    voids: BTreeMap<i32, (i32, domain::Void)>,
+    ingredients: BTreeMap<i32, (i32, domain::Ingredient)>,
+    next_table_id: i32,
+    next_item_id: i32,
+}
+
+/// Keeps everything in memory so the rules around tables can be tested without a database,
+/// it has no audit log so the changes it is given are dropped.
+#[derive(Default)]
+pub struct MemoryRepository {
+    store: Mutex<Store>,
+}
+
+impl MemoryRepository {
+    /// Adds a table with the given items, for setting up tests.
+    pub fn insert_table(&self, restaurant_id: i32, table_id: i32, item_ids: &[i32]) {
+        let mut store = self.store.lock();
+        store.tables.insert(
+            table_id,
+            StoredTable {
+                restaurant_id,
+                item_ids: item_ids.iter().map(|id| id.to_string()).collect(),
+                version: 1,
+                paid_at: None,
+            },
+        );
+        store.next_table_id = store.next_table_id.max(table_id);
+    }
+
+    /// Adds an ingredient with its stock, for setting up tests.
+    pub fn insert_ingredient(&self, restaurant_id: i32, ingredient: domain::Ingredient) {
+        let mut store = self.store.lock();
+        store
+            .ingredients
+            .insert(ingredient.id, (restaurant_id, ingredient));
+    }
+}
+
+impl Store {
+    fn item(&self, restaurant_id: i32, item_id: i32) -> Option<domain::Item> {
+        let item = self
+            .items
+            .get(&item_id)
+            .filter(|item| item.restaurant_id == restaurant_id)?;
+        let status = match self.voids.get(&item_id) {
+            Some((_, void)) => domain::ItemStatus::Voided(void.clone()),
+            None => domain::ItemStatus::Ordered,
+        };
+        let components = self
+            .items
+            .iter()
+            .filter(|(_, component)| component.bundle_id == Some(item_id))
+            .map(|(component_id, component)| domain::Item {
+                id: *component_id,
+                name: component.name.clone(),
+                time_to_prepare: component.time_to_prepare,
+                status: status.clone(),
+                modifiers: component.modifiers.clone(),
+                price: component.price,
+                components: Vec::new(),
+                course: component.course,
+                held: component.held,
+                fired_at: component.fired_at,
+                station_id: component.station_id,
+                ready_at: component.ready_at,
+            })
+            .collect();
+        Some(domain::Item {
+            id: item_id,
+            name: item.name.clone(),
+            time_to_prepare: item.time_to_prepare,
+            status,
+            modifiers: item.modifiers.clone(),
+            price: item.price,
+            components,
+            course: item.course,
+            held: item.held,
+            fired_at: item.fired_at,
+            station_id: item.station_id,
+            ready_at: item.ready_at,
+        })
+    }
+
+    fn load(&self, table_id: i32, table: &StoredTable) -> domain::Table {
+        let mut items: Vec<domain::Item> = table
+            .item_ids
+            .iter()
+            .filter_map(|id| id.parse::<i32>().ok())
+            .filter_map(|id| self.item(table.restaurant_id, id))
+            .filter(|item| item.status == domain::ItemStatus::Ordered)
+            .collect();
+        for (item_id, (voided_from, _)) in self.voids.iter() {
+            if *voided_from == table_id {
+                items.extend(self.item(table.restaurant_id, *item_id));
+            }
+        }
+        domain::Table {
+            id: table_id,
+            version: table.version,
+            paid_at: table.paid_at,
+            items,
+        }
+    }
+
+    /// The table to change, as long as nobody changed it since `version`.
+    fn table_at(
+        &mut self,
+        restaurant_id: i32,
+        table_id: &str,
+        version: i64,
+    ) -> Option<&mut StoredTable> {
+        let table_id = table_id.parse::<i32>().ok()?;
+        self.tables
+            .get_mut(&table_id)
+            .filter(|table| table.restaurant_id == restaurant_id && table.version == version)
+    }
+}
+
+impl TableRepository for MemoryRepository {
+    fn all(&self, restaurant_id: i32) -> Result<Vec<domain::Table>, String> {
+        let store = self.store.lock();
+        Ok(store
+            .tables
+            .iter()
+            .filter(|(_, table)| table.restaurant_id == restaurant_id)
+            .map(|(table_id, table)| store.load(*table_id, table))
+            .collect())
+    }
+
+    fn find(&self, restaurant_id: i32, table_id: &str) -> Result<Option<domain::Table>, String> {
+        let store = self.store.lock();
+        let table_id = match table_id.parse::<i32>() {
+            Ok(table_id) => table_id,
+            Err(_) => return Ok(None),
+        };
+        Ok(store
+            .tables
+            .get(&table_id)
+            .filter(|table| table.restaurant_id == restaurant_id)
+            .map(|table| store.load(table_id, table)))
+    }
+
+    fn create(
+        &self,
+        restaurant_id: i32,
+        item_ids: &[String],
+        _changes: &dyn Fn(i32) -> Vec<audit::Change>,
+    ) -> Result<i32, String> {
+        let mut store = self.store.lock();
+        store.next_table_id += 1;
+        let table_id = store.next_table_id;
+        store.tables.insert(
+            table_id,
+            StoredTable {
+                restaurant_id,
+                item_ids: item_ids.to_vec(),
+                version: 1,
+                paid_at: None,
+            },
+        );
+        Ok(table_id)
+    }
+
+    fn append_items(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[String],
+        version: i64,
+        _changes: Vec<audit::Change>,
+    ) -> Result<bool, String> {
+        let mut store = self.store.lock();
+        match store.table_at(restaurant_id, table_id, version) {
+            Some(table) => {
+                table.item_ids.extend(item_ids.iter().cloned());
+                table.version += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn void_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        void: &domain::Void,
+        version: i64,
+        _change: audit::Change,
+    ) -> Result<bool, String> {
+        let mut store = self.store.lock();
+        if store.item(restaurant_id, item_id).is_none() {
+            return Err(format!("Item {} was not ordered here.", item_id));
+        }
+        let table = match store.table_at(restaurant_id, table_id, version) {
+            Some(table) => table,
+            None => return Ok(false),
+        };
+        match table
+            .item_ids
+            .iter()
+            .position(|id| *id == item_id.to_string())
+        {
+            Some(position) => {
+                table.item_ids.remove(position);
+                table.version += 1;
+            }
+            None => return Ok(false),
+        }
+        let table_id = table_id
+            .parse::<i32>()
+            .map_err(|_| format!("Invalid table id {}.", table_id))?;
+        store.voids.insert(item_id, (table_id, void.clone()));
+        Ok(true)
+    }
+
+    fn restore_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        version: i64,
+        _change: audit::Change,
+    ) -> Result<bool, String> {
+        let mut store = self.store.lock();
+        let voided_here = match store.voids.get(&item_id) {
+            Some((voided_from, _)) => voided_from.to_string() == table_id,
+            None => false,
+        };
+        if !voided_here || store.item(restaurant_id, item_id).is_none() {
+            return Ok(false);
+        }
+        match store.table_at(restaurant_id, table_id, version) {
+            Some(table) => {
+                table.item_ids.push(item_id.to_string());
+                table.version += 1;
+            }
+            None => return Ok(false),
+        }
+        store.voids.remove(&item_id);
+        Ok(true)
+    }
+
+    fn fire(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[i32],
+        fired_at: i64,
+        version: i64,
+        _changes: Vec<audit::Change>,
+    ) -> Result<bool, String> {
+        let mut store = self.store.lock();
+        match store.table_at(restaurant_id, table_id, version) {
+            Some(table) => table.version += 1,
+            None => return Ok(false),
+        }
+        for (id, item) in store.items.iter_mut() {
+            let fired = item_ids
+                .iter()
+                .any(|item_id| *id == *item_id || item.bundle_id == Some(*item_id));
+            if fired && item.restaurant_id == restaurant_id && item.held {
+                item.held = false;
+                item.fired_at = Some(fired_at);
+            }
+        }
+        Ok(true)
+    }
+
+    fn ready(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        ready_at: i64,
+        version: i64,
+        _change: audit::Change,
+    ) -> Result<bool, String> {
+        let mut store = self.store.lock();
+        let readied = store
+            .items
+            .get(&item_id)
+            .filter(|item| item.restaurant_id == restaurant_id)
+            .map_or(false, |item| !item.held && item.ready_at.is_none());
+        match store.table_at(restaurant_id, table_id, version) {
+            Some(table) if readied => table.version += 1,
+            _ => return Ok(false),
+        }
+        if let Some(item) = store.items.get_mut(&item_id) {
+            item.ready_at = Some(ready_at);
+        }
+        Ok(true)
+    }
+
+    fn settle(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        paid_at: i64,
+        version: i64,
+    ) -> Result<bool, String> {
+        let mut store = self.store.lock();
+        match store.table_at(restaurant_id, table_id, version) {
+            Some(table) => {
+                table.paid_at = Some(paid_at);
+                table.version += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl ItemRepository for MemoryRepository {
+    fn create(
+        &self,
+        restaurant_id: i32,
+        new_item: &domain::NewItem,
+    ) -> Result<domain::Item, String> {
+        let mut store = self.store.lock();
+        store.next_item_id += 1;
+        let item_id = store.next_item_id;
+        store.items.insert(
+            item_id,
+            StoredItem {
+                restaurant_id,
+                name: new_item.name.clone(),
+                time_to_prepare: new_item.time_to_prepare,
+                modifiers: new_item.modifiers.clone(),
+                price: new_item.price,
+                bundle_id: new_item.bundle_id,
+                course: new_item.course,
+                held: new_item.held,
+                fired_at: new_item.fired_at,
+                station_id: new_item.station_id,
+                ready_at: None,
+                recipe: new_item.recipe.clone(),
+            },
+        );
+        Ok(store
+            .item(restaurant_id, item_id)
+            .expect("Item was just added."))
+    }
+
+    fn delete(&self, restaurant_id: i32, item_id: i32) -> Result<(), String> {
+        let mut store = self.store.lock();
+        if store.item(restaurant_id, item_id).is_some() {
+            store
+                .items
+                .retain(|id, item| *id != item_id && item.bundle_id != Some(item_id));
+        }
+        Ok(())
+    }
+
+    fn recipe(&self, restaurant_id: i32, item_id: i32) -> Result<Vec<domain::RecipeLine>, String> {
+        let store = self.store.lock();
+        Ok(store
+            .items
+            .iter()
+            .filter(|(id, item)| **id == item_id || item.bundle_id == Some(item_id))
+            .filter(|(_, item)| item.restaurant_id == restaurant_id)
+            .flat_map(|(_, item)| item.recipe.iter().cloned())
+            .collect())
+    }
+
+    fn find_voided(
+        &self,
+        restaurant_id: i32,
+        item_id: &str,
+        table_id: &str,
+    ) -> Result<Option<domain::Item>, String> {
+        let store = self.store.lock();
+        let item_id = match item_id.parse::<i32>() {
+            Ok(item_id) => item_id,
+            Err(_) => return Ok(None),
+        };
+        match store.voids.get(&item_id) {
+            Some((voided_from, _)) if voided_from.to_string() == table_id => {
+                Ok(store.item(restaurant_id, item_id))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn preparations(
+        &self,
+        restaurant_id: i32,
+        since: i64,
+    ) -> Result<Vec<domain::Preparation>, String> {
+        let store = self.store.lock();
+        let is_bundle = |id: &i32| store.items.values().any(|item| item.bundle_id == Some(*id));
+        let is_voided = |id: &i32, item: &StoredItem| {
+            store.voids.contains_key(id)
+                || item
+                    .bundle_id
+                    .map_or(false, |bundle_id| store.voids.contains_key(&bundle_id))
+        };
+        let mut preparations = store
+            .items
+            .iter()
+            .filter(|(id, item)| item.restaurant_id == restaurant_id && !is_bundle(id))
+            .filter(|(id, item)| item.ready_at.is_some() || !is_voided(id, item))
+            .filter_map(|(_, item)| {
+                let fired_at = item.fired_at.filter(|fired_at| *fired_at >= since)?;
+                Some(domain::Preparation {
+                    name: item.name.clone(),
+                    station_id: item.station_id,
+                    time_to_prepare: item.time_to_prepare,
+                    fired_at,
+                    ready_at: item.ready_at,
+                })
+            })
+            .collect::<Vec<domain::Preparation>>();
+        preparations.sort_by_key(|preparation| preparation.fired_at);
+        Ok(preparations)
+    }
+}
+
+impl StockRepository for MemoryRepository {
+    fn ingredients(&self, restaurant_id: i32) -> Result<Vec<domain::Ingredient>, String> {
+        let store = self.store.lock();
+        Ok(store
+            .ingredients
+            .values()
+            .filter(|(owner, _)| *owner == restaurant_id)
+            .map(|(_, ingredient)| ingredient.clone())
+            .collect())
+    }
+
+    fn take(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<bool, String> {
+        let mut store = self.store.lock();
+        let mut needed: BTreeMap<i32, i32> = BTreeMap::new();
+        for line in recipe {
+            *needed.entry(line.ingredient_id).or_default() += line.quantity;
+        }
+        let enough = needed.iter().all(|(ingredient_id, quantity)| {
+            match store.ingredients.get(ingredient_id) {
+                Some((owner, ingredient)) => {
+                    *owner == restaurant_id && ingredient.stock >= *quantity
+                }
+                None => false,
+            }
+        });
+        if !enough {
+            return Ok(false);
+        }
+        for (ingredient_id, quantity) in needed {
+            if let Some((_, ingredient)) = store.ingredients.get_mut(&ingredient_id) {
+                ingredient.stock -= quantity;
+            }
+        }
+        Ok(true)
+    }
+
+    fn give_back(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<(), String> {
+        let mut store = self.store.lock();
+        for line in recipe {
+            match store.ingredients.get_mut(&line.ingredient_id) {
+                Some((owner, ingredient)) if *owner == restaurant_id => {
+                    ingredient.stock += line.quantity
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn add_ingredient(
+        &self,
+        restaurant_id: i32,
+        name: &str,
+        stock: i32,
+        low_stock: i32,
+    ) -> Result<domain::Ingredient, String> {
+        let mut store = self.store.lock();
+        let id = store.ingredients.keys().next_back().map_or(1, |id| id + 1);
+        let ingredient = domain::Ingredient {
+            id,
+            name: name.to_string(),
+            stock,
+            low_stock,
+        };
+        store
+            .ingredients
+            .insert(id, (restaurant_id, ingredient.clone()));
+        Ok(ingredient)
+    }
+
+    fn restock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        quantity: i32,
+    ) -> Result<Option<domain::Ingredient>, String> {
+        let mut store = self.store.lock();
+        match store.ingredients.get_mut(&ingredient_id) {
+            Some((owner, ingredient)) if *owner == restaurant_id => {
+                ingredient.stock += quantity;
+                Ok(Some(ingredient.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn set_low_stock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        low_stock: i32,
+    ) -> Result<Option<domain::Ingredient>, String> {
+        let mut store = self.store.lock();
+        match store.ingredients.get_mut(&ingredient_id) {
+            Some((owner, ingredient)) if *owner == restaurant_id => {
+                ingredient.low_stock = low_stock;
+                Ok(Some(ingredient.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use rocket::fairing::AdHoc;
+use rocket::Rocket;
+
+use crate::audit;
+use crate::auth;
+use crate::data;
+use crate::database;
+use crate::domain;
+use crate::idempotency;
+use crate::metrics;
+
+#[cfg(test)]
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+/// Where tables and the ids of the items on them are kept.
+///
+/// A table lists item ids in the order they were added, the ids are kept as they were
+/// given even when there is no item with that id. Every call is for one restaurant and
+/// never sees or changes the tables of another one.
+pub trait TableRepository: Send + Sync {
+    fn all(&self, restaurant_id: i32) -> Result<Vec<domain::Table>, String>;
+
+    fn find(&self, restaurant_id: i32, table_id: &str) -> Result<Option<domain::Table>, String>;
+
+    /// Creates a table holding `item_ids` and records the changes `changes` gives for its id
+    /// in the audit log, in one transaction. Its id is picked by the repository.
+    fn create(
+        &self,
+        restaurant_id: i32,
+        item_ids: &[String],
+        changes: &dyn Fn(i32) -> Vec<audit::Change>,
+    ) -> Result<i32, String>;
+
+    /// Adds `item_ids` to the end of a table and records `changes` in the audit log, in one
+    /// transaction, if the table is still at `version`. Returns false when someone else
+    /// changed it first.
+    fn append_items(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[String],
+        version: i64,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String>;
+
+    /// Takes an item off a table, keeps it as voided from the table and records `change` in
+    /// the audit log, in one transaction, if the table is still at `version`. Returns false
+    /// when someone else changed it first.
+    fn void_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        void: &domain::Void,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String>;
+
+    /// Puts an item voided from a table back at the end of it, forgets the void and records
+    /// `change` in the audit log, in one transaction, if the table is still at `version`.
+    /// Returns false when someone else changed it first or the item is no longer voided
+    /// from it.
+    fn restore_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String>;
+
+    /// Sends held items of a table and their components to the kitchen at `fired_at` and
+    /// records `changes` in the audit log, in one transaction, if the table is still at
+    /// `version`. Returns false when someone else changed it first. Items that are not held
+    /// are left as they are.
+    fn fire(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[i32],
+        fired_at: i64,
+        version: i64,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String>;
+
+    /// Marks an item the kitchen was sent for a table as ready at `ready_at` and records
+    /// `change` in the audit log, in one transaction, if the table is still at `version`.
+    /// Returns false when someone else changed it first or the item was already ready or is
+    /// still held.
+    fn ready(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        ready_at: i64,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String>;
+
+    /// Settles a table's bill at `paid_at` if it is still at `version`, returns false when
+    /// someone else changed it first.
+    fn settle(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        paid_at: i64,
+        version: i64,
+    ) -> Result<bool, String>;
+}
+
+/// Where ordered items are kept, each belongs to the restaurant it was ordered in.
+pub trait ItemRepository: Send + Sync {
+    /// Orders an item with a copy of its price and the modifiers chosen for it, so later
+    /// changes to the menu do not change what was ordered.
+    fn create(
+        &self,
+        restaurant_id: i32,
+        new_item: &domain::NewItem,
+    ) -> Result<domain::Item, String>;
+
+    /// Deletes an item along with its modifiers and, for a bundle, its components.
+    fn delete(&self, restaurant_id: i32, item_id: i32) -> Result<(), String>;
+
+    /// What an item and, for a bundle, its components used from stock when they were
+    /// ordered. Nothing for an item that is not there.
+    fn recipe(&self, restaurant_id: i32, item_id: i32) -> Result<Vec<domain::RecipeLine>, String>;
+
+    /// Finds an item voided from `table_id`, with the void as its status.
+    fn find_voided(
+        &self,
+        restaurant_id: i32,
+        item_id: &str,
+        table_id: &str,
+    ) -> Result<Option<domain::Item>, String>;
+
+    /// What the kitchen was sent since `since`, oldest first, each component of a bundle on
+    /// its own. Voided items are left out unless they were ready before they were voided.
+    fn preparations(
+        &self,
+        restaurant_id: i32,
+        since: i64,
+    ) -> Result<Vec<domain::Preparation>, String>;
+}
+
+/// Where the stock of each ingredient is kept, it never goes below nothing.
+pub trait StockRepository: Send + Sync {
+    fn ingredients(&self, restaurant_id: i32) -> Result<Vec<domain::Ingredient>, String>;
+
+    /// Takes every line out of stock if there is enough for all of them, returns false and
+    /// takes nothing when there is not.
+    fn take(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<bool, String>;
+
+    /// Puts what was taken for `recipe` back into stock.
+    fn give_back(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<(), String>;
+
+    fn add_ingredient(
+        &self,
+        restaurant_id: i32,
+        name: &str,
+        stock: i32,
+        low_stock: i32,
+    ) -> Result<domain::Ingredient, String>;
+
+    /// Adds `quantity` to an ingredient's stock, `None` when the restaurant has no such
+    /// ingredient.
+    fn restock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        quantity: i32,
+    ) -> Result<Option<domain::Ingredient>, String>;
+
+    /// Changes when an ingredient is running low, `None` when the restaurant has no such
+    /// ingredient.
+    fn set_low_stock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        low_stock: i32,
+    ) -> Result<Option<domain::Ingredient>, String>;
+}
+
+/// Where each restaurant's menu is kept.
+pub trait MenuRepository: Send + Sync {
+    /// A restaurant's menu as it is now, everything in the order it was added.
+    fn menu(&self, restaurant_id: i32) -> Result<domain::Menu, String>;
+
+    /// Marks a menu item as available or not, returns false when the restaurant has no such
+    /// item.
+    fn set_availability(
+        &self,
+        restaurant_id: i32,
+        item_id: i32,
+        available: bool,
+        available_at: Option<i64>,
+    ) -> Result<bool, String>;
+
+    /// Replaces what making one of a menu item uses, returns false when the restaurant has
+    /// no such item.
+    fn set_recipe(
+        &self,
+        restaurant_id: i32,
+        item_id: i32,
+        recipe: &[domain::RecipeLine],
+    ) -> Result<bool, String>;
+}
+
+/// Where the restaurants and the staff working at them are kept.
+pub trait StaffRepository: Send + Sync {
+    /// Finds a staff member by the id they send, `None` when there is no one with a known
+    /// role under that id.
+    fn find(&self, staff_id: &str) -> Result<Option<auth::Staff>, String>;
+
+    fn restaurant(&self, restaurant_id: i32) -> Result<Option<domain::Restaurant>, String>;
+}
+
+/// Where the responses sent for idempotency keys are kept, a key is only ever used within
+/// one restaurant.
+pub trait IdempotencyRepository: Send + Sync {
+    /// Claims `key` for `request`, or finds out what happened the last time it was used.
+    /// Keys older than the window and claims older than the lease are forgotten first.
+    fn begin(
+        &self,
+        restaurant_id: i32,
+        key: &str,
+        request: &str,
+        window: &idempotency::Window,
+    ) -> Result<idempotency::Attempt, String>;
+
+    /// Stores the response sent for `key` so retries get exactly the same answer.
+    fn complete(&self, restaurant_id: i32, key: &str, response: &str) -> Result<(), String>;
+
+    /// Releases `key` after a failed request so the client can retry it.
+    fn abandon(&self, restaurant_id: i32, key: &str);
+}
+
+/// Where the append-only log of what staff changed is kept.
+pub trait AuditRepository: Send + Sync {
+    fn record(&self, change: audit::Change) -> Result<(), String>;
+
+    /// The entries matching `filter`, oldest first.
+    fn search(&self, filter: &audit::Filter) -> Result<Vec<data::AuditEntry>, String>;
+}
+
+/// Where the allergies declared at each table are kept.
+pub trait AllergyRepository: Send + Sync {
+    /// The allergies declared at each of a restaurant's tables, by table id.
+    fn all(&self, restaurant_id: i32) -> Result<BTreeMap<i32, Vec<domain::Allergy>>, String>;
+
+    /// Replaces the allergies declared at a table.
+    fn replace(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        allergies: &[domain::Allergy],
+    ) -> Result<(), String>;
+
+    fn find(&self, restaurant_id: i32, table_id: i32) -> Result<Vec<domain::Allergy>, String> {
+        Ok(self
+            .all(restaurant_id)?
+            .remove(&table_id)
+            .unwrap_or_default())
+    }
+}
+
+/// Where the adjustments on each table's bill and the promo codes they come from are kept.
+pub trait AdjustmentRepository: Send + Sync {
+    /// The adjustments on a table's bill, in the order they were added.
+    fn adjustments(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+    ) -> Result<Vec<domain::Adjustment>, String>;
+
+    /// Adds an adjustment to a table's bill and records the change in the audit log in one
+    /// transaction, and returns its id. The id it is given is ignored.
+    fn add(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        adjustment: &domain::Adjustment,
+        change: audit::Change,
+    ) -> Result<i32, String>;
+
+    fn find_promo_code(
+        &self,
+        restaurant_id: i32,
+        code: &str,
+    ) -> Result<Option<domain::PromoCode>, String>;
+
+    /// Counts a use of a promo code, adds the adjustment it gives to a table's bill and
+    /// records the change in the audit log in one transaction. The use is counted at the
+    /// time the adjustment is added.
+    fn redeem(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        promo_code_id: i32,
+        adjustment: &domain::Adjustment,
+        change: audit::Change,
+    ) -> Result<domain::Redemption, String>;
+}
+
+/// Where the kitchen's stations are kept with what happened to the tickets at each.
+pub trait KitchenRepository: Send + Sync {
+    /// The restaurant's stations in the order they were added.
+    fn stations(&self, restaurant_id: i32) -> Result<Vec<domain::Station>, String>;
+
+    /// Logs an event for each ticket of an item at the station it is made at, held items
+    /// have no tickets yet so nothing is logged for them.
+    fn record(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item: &domain::Item,
+        kind: &str,
+        at: i64,
+    ) -> Result<(), String>;
+
+    /// What happened at a station after the event with id `after`, oldest first.
+    fn events(
+        &self,
+        restaurant_id: i32,
+        station_id: i32,
+        after: i64,
+    ) -> Result<Vec<domain::KitchenEvent>, String>;
+
+    fn find_station(
+        &self,
+        restaurant_id: i32,
+        station_id: i32,
+    ) -> Result<Option<domain::Station>, String> {
+        Ok(self
+            .stations(restaurant_id)?
+            .into_iter()
+            .find(|station| station.id == station_id))
+    }
+}
+
+/// What `/ready` and `/metrics` read about everything that is kept.
+pub trait StatusRepository: Send + Sync {
+    /// Checks the store can be reached, is fully migrated and answers a query.
+    fn readiness(&self) -> data::HealthReport;
+
+    /// The numbers of every restaurant for the metrics.
+    fn business(&self) -> Result<Vec<metrics::Business>, String>;
+}
+
+pub type Tables = Arc<dyn TableRepository>;
+pub type Items = Arc<dyn ItemRepository>;
+pub type Stock = Arc<dyn StockRepository>;
+pub type Menus = Arc<dyn MenuRepository>;
+pub type StaffMembers = Arc<dyn StaffRepository>;
+pub type IdempotencyKeys = Arc<dyn IdempotencyRepository>;
+pub type AuditLog = Arc<dyn AuditRepository>;
+pub type Allergies = Arc<dyn AllergyRepository>;
+pub type Adjustments = Arc<dyn AdjustmentRepository>;
+pub type Kitchen = Arc<dyn KitchenRepository>;
+pub type Status = Arc<dyn StatusRepository>;
+
+/// Manages the repositories handlers read and change everything through. It is all kept in
+/// the sqlite file set with `database` unless `database_backend` is set to `postgres`, which
+/// uses the server at `database_url` and leaves the sqlite file alone.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_attach("Repositories", |rocket| {
+        let backend = rocket
+            .config()
+            .get_str("database_backend")
+            .unwrap_or("sqlite")
+            .to_string();
+        match backend.as_str() {
+            "sqlite" => {
+                let database = match database::open(rocket.config()) {
+                    Ok(database) => database,
+                    Err(e) => {
+                        error!("{}", e);
+                        return Err(rocket);
+                    }
+                };
+                Ok(manage(rocket, sqlite::SqliteRepository::new(database)))
+            }
+            "postgres" => {
+                let url = match rocket.config().get_str("database_url") {
+                    Ok(url) => url.to_string(),
+                    Err(_) => {
+                        error!("Set database_url to use the postgres backend.");
+                        return Err(rocket);
+                    }
+                };
+                // Enough connections for every worker to hold one by default.
+                let pool_size = rocket
+                    .config()
+                    .get_int("database_pool_size")
+                    .unwrap_or(rocket.config().workers as i64);
+                let repository = match postgres::PostgresRepository::new(&url, pool_size as u32) {
+                    Ok(repository) => repository,
+                    Err(e) => {
+                        error!("{}", e);
+                        return Err(rocket);
+                    }
+                };
+                match repository.setup() {
+                    Ok(message) => info!("{} Using postgres.", message),
+                    Err(e) => {
+                        error!("Failed to set up postgres: {}", e);
+                        return Err(rocket);
+                    }
+                }
+                Ok(manage(rocket, repository))
+            }
+            _ => {
+                error!(
+                    "Unknown database_backend {}, use sqlite or postgres.",
+                    backend
+                );
+                Err(rocket)
+            }
+        }
+    })
+}
+
+fn manage<R>(rocket: Rocket, repository: R) -> Rocket
+where
+    R: TableRepository
+        + ItemRepository
+        + StockRepository
+        + MenuRepository
+        + StaffRepository
+        + IdempotencyRepository
+        + AuditRepository
+        + AllergyRepository
+        + AdjustmentRepository
+        + KitchenRepository
+        + StatusRepository
+        + 'static,
+{
+    let repository = Arc::new(repository);
+    let tables: Tables = repository.clone();
+    let items: Items = repository.clone();
+    let stock: Stock = repository.clone();
+    let menus: Menus = repository.clone();
+    let staff: StaffMembers = repository.clone();
+    let idempotency_keys: IdempotencyKeys = repository.clone();
+    let audit_log: AuditLog = repository.clone();
+    let allergies: Allergies = repository.clone();
+    let adjustments: Adjustments = repository.clone();
+    let kitchen: Kitchen = repository.clone();
+    let status: Status = repository;
+    rocket
+        .manage(tables)
+        .manage(items)
+        .manage(stock)
+        .manage(menus)
+        .manage(staff)
+        .manage(idempotency_keys)
+        .manage(audit_log)
+        .manage(allergies)
+        .manage(adjustments)
+        .manage(kitchen)
+        .manage(status)
+}
@@ -0,0 +1,1743 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use ::postgres::error::SqlState;
+use ::postgres::{Client, GenericClient, NoTls, Row, Transaction};
+use r2d2_postgres::{r2d2, PostgresConnectionManager};
+
+use crate::adjustment;
+use crate::audit;
+use crate::auth;
+use crate::data;
+use crate::database;
+use crate::domain;
+use crate::health;
+use crate::idempotency;
+use crate::menu;
+use crate::metrics;
+use crate::repository::{
+    AdjustmentRepository, AllergyRepository, AuditRepository, IdempotencyRepository,
+    ItemRepository, KitchenRepository, MenuRepository, StaffRepository, StatusRepository,
+    StockRepository, TableRepository,
+};
+
+/// The same tables as the sqlite schema, with `serial` ids since postgres has no rowid.
+/// `schema_migration` stands in for sqlite's `user_version`.
+const SCHEMA: &str = "create table if not exists item (
+        id serial primary key,
+        name varchar(64) not null,
+        preperation_time integer not null
+    );
+    create table if not exists restaurant_table (
+        id serial primary key,
+        items text not null
+    );
+    create table if not exists staff (
+        id integer primary key,
+        name varchar(64) not null,
+        role varchar(16) not null
+    );
+    create table if not exists voided_item (
+        item_id integer primary key,
+        table_id integer not null,
+        reason text not null,
+        voided_by integer not null,
+        voided_at bigint not null
+    );
+    create table if not exists idempotency_key (
+        key varchar(255) primary key,
+        request text not null,
+        response text,
+        created_at bigint not null
+    );
+    create table if not exists audit_log (
+        id serial primary key,
+        actor_id integer not null,
+        action varchar(32) not null,
+        table_id integer not null,
+        item_id integer,
+        before text,
+        after text,
+        created_at bigint not null
+    );
+    create table if not exists schema_migration (
+        version integer not null
+    );";
+
+/// The sqlite migrations written for postgres, tracked with `schema_migration`. Ids that
+/// sqlite takes from the rowid are `serial` here, and times are `bigint`.
+pub const MIGRATIONS: &[&str] = &[
+    "alter table restaurant_table add column version integer not null default 1;
+    create or replace function audit_log_append_only() returns trigger as $$
+        begin
+            raise exception 'The audit log is append-only.';
+        end;
+    $$ language plpgsql;
+    create trigger audit_log_no_change before update or delete on audit_log
+        for each row execute function audit_log_append_only();",
+    // Everything that was there before belongs to the first restaurant.
+    "create table if not exists restaurant (
+        id serial primary key,
+        name varchar(64) not null
+    );
+    insert into restaurant (id, name) values (1, 'Restaurant');
+    select setval('restaurant_id_seq', 1);
+    alter table restaurant_table add column restaurant_id integer not null default 1;
+    alter table item add column restaurant_id integer not null default 1;
+    alter table staff add column restaurant_id integer not null default 1;
+    alter table audit_log add column restaurant_id integer not null default 1;",
+    "create table if not exists menu_category (
+        id serial primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null
+    );
+    create table if not exists menu_item (
+        id serial primary key,
+        restaurant_id integer not null,
+        category_id integer not null,
+        name varchar(64) not null,
+        price integer not null
+    );
+    create unique index if not exists menu_item_name on menu_item (restaurant_id, name);
+    create table if not exists modifier_group (
+        id serial primary key,
+        menu_item_id integer not null,
+        name varchar(64) not null,
+        min_selected integer not null default 0,
+        max_selected integer not null default 1
+    );
+    create table if not exists modifier (
+        id serial primary key,
+        group_id integer not null,
+        name varchar(64) not null,
+        price_delta integer not null default 0
+    );
+    create table if not exists item_modifier (
+        item_id integer not null,
+        position integer not null,
+        modifier_id integer not null,
+        name varchar(64) not null,
+        price_delta integer not null
+    );",
+    "create table if not exists bundle_component (
+        bundle_id integer not null,
+        position integer not null,
+        menu_item_id integer not null
+    );
+    alter table item add column price integer not null default 0;
+    alter table item add column bundle_item_id integer;",
+    "create table if not exists ingredient (
+        id serial primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null,
+        stock integer not null default 0,
+        low_stock integer not null default 0
+    );
+    create table if not exists recipe (
+        menu_item_id integer not null,
+        ingredient_id integer not null,
+        quantity integer not null
+    );",
+    "alter table menu_item add column available integer not null default 1;
+    alter table menu_item add column available_at bigint;",
+    "create table if not exists menu_item_tag (
+        menu_item_id integer not null,
+        kind varchar(16) not null,
+        tag varchar(64) not null
+    );
+    create table if not exists table_allergy (
+        restaurant_id integer not null,
+        table_id integer not null,
+        seat integer,
+        allergen varchar(64) not null
+    );",
+    "alter table restaurant add column utc_offset integer not null default 0;
+    create table if not exists menu_schedule (
+        category_id integer not null,
+        days integer not null default 127,
+        starts integer not null,
+        ends integer not null
+    );
+    create table if not exists price_rule (
+        id serial primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null,
+        category_id integer,
+        menu_item_id integer,
+        days integer not null default 127,
+        starts integer not null default 0,
+        ends integer not null default 1440,
+        valid_from bigint,
+        valid_until bigint,
+        percent_off integer not null
+    );",
+    "create table if not exists promo_code (
+        id serial primary key,
+        restaurant_id integer not null,
+        code varchar(32) not null,
+        item_name varchar(64),
+        kind varchar(16) not null,
+        value integer,
+        buy integer,
+        get integer,
+        max_uses integer,
+        uses integer not null default 0,
+        expires_at bigint
+    );
+    create unique index if not exists promo_code_code on promo_code (restaurant_id, code);
+    create table if not exists adjustment (
+        id serial primary key,
+        restaurant_id integer not null,
+        table_id integer not null,
+        item_id integer,
+        item_name varchar(64),
+        kind varchar(16) not null,
+        value integer,
+        buy integer,
+        get integer,
+        reason text not null,
+        code varchar(32),
+        added_by integer not null,
+        added_at bigint not null
+    );",
+    "alter table item add column course integer not null default 1;
+    alter table item add column held integer not null default 0;
+    alter table item add column fired_at bigint;",
+    "create table if not exists station (
+        id serial primary key,
+        restaurant_id integer not null,
+        name varchar(64) not null
+    );
+    alter table menu_category add column station_id integer;
+    alter table menu_item add column station_id integer;
+    alter table item add column station_id integer;
+    alter table item add column ready_at bigint;
+    create table if not exists kitchen_event (
+        id serial primary key,
+        restaurant_id integer not null,
+        station_id integer,
+        table_id integer not null,
+        item_id integer not null,
+        name varchar(64) not null,
+        kind varchar(16) not null,
+        at bigint not null
+    );",
+    "alter table audit_log alter column table_id drop not null;",
+    "create unique index if not exists adjustment_table_code on adjustment (table_id, code);",
+    "alter table restaurant_table add column paid_at bigint;",
+    "create table if not exists item_ingredient (
+        item_id integer not null,
+        ingredient_id integer not null,
+        quantity integer not null
+    );
+    insert into item_ingredient (item_id, ingredient_id, quantity)
+        select item.id, recipe.ingredient_id, recipe.quantity
+        from item
+        join menu_item on menu_item.name = item.name
+            and menu_item.restaurant_id = item.restaurant_id
+        join recipe on recipe.menu_item_id = menu_item.id;",
+];
+
+/// The `schema_migration` version a fully migrated database has.
+pub fn current_schema_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+/// How long a call waits for a connection from the pool, it is also how long an unreachable
+/// postgres takes to be reported.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Connection = r2d2::PooledConnection<PostgresConnectionManager<NoTls>>;
+
+/// Keeps everything in postgres, calls borrow a connection from a pool and give it back when
+/// they are done, so connections are opened once rather than for every query.
+pub struct PostgresRepository {
+    pool: r2d2::Pool<PostgresConnectionManager<NoTls>>,
+}
+
+impl PostgresRepository {
+    /// Connections are only opened when they are first needed, so this fails for a
+    /// `database_url` that cannot be parsed but not for a postgres that is down.
+    pub fn new(url: &str, pool_size: u32) -> Result<PostgresRepository, String> {
+        let config = url
+            .parse::<::postgres::Config>()
+            .map_err(|e| format!("Invalid database_url: {}", e))?;
+        let pool = r2d2::Pool::builder()
+            .max_size(pool_size)
+            .connection_timeout(CONNECTION_TIMEOUT)
+            .build_unchecked(PostgresConnectionManager::new(config, NoTls));
+        Ok(PostgresRepository { pool })
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        self.pool
+            .get()
+            .map_err(|e| format!("Cannot connect to database: {}", e))
+    }
+
+    /// Creates the tables and runs the migrations they have not had yet.
+    pub fn setup(&self) -> Result<String, String> {
+        let mut client = self.connect()?;
+        client
+            .batch_execute(SCHEMA)
+            .map_err(|e| format!("Could not run create table sql: {}", e))?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Could not start migration: {}", e))?;
+        transaction
+            .batch_execute("lock table schema_migration in exclusive mode;")
+            .map_err(|e| format!("Could not start migration: {}", e))?;
+        let version = transaction
+            .query_opt("select version from schema_migration;", &[])
+            .map_err(|e| format!("Could not read schema version: {}", e))?
+            .map(|row| row.get::<_, i32>(0));
+        if version.is_none() {
+            transaction
+                .execute("insert into schema_migration (version) values (0);", &[])
+                .map_err(|e| format!("Could not update schema version: {}", e))?;
+        }
+        let version = version.unwrap_or(0);
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            info!("Running migration {}.", index + 1);
+            transaction
+                .batch_execute(migration)
+                .map_err(|e| format!("Migration {} failed: {}", index + 1, e))?;
+            transaction
+                .execute(
+                    "update schema_migration set version = $1;",
+                    &[&(index as i32 + 1)],
+                )
+                .map_err(|e| format!("Could not update schema version: {}", e))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Could not commit migrations: {}", e))?;
+        Ok("Successfully created database tables.".into())
+    }
+
+    /// Items that are still on the table come first, in the order they were added,
+    /// followed by the ones voided from it.
+    fn load(client: &mut Client, restaurant_id: i32, row: &Row) -> Result<domain::Table, String> {
+        let table_id: i32 = row.get("id");
+        let mut items = Vec::new();
+        for item_id in database::split_item_ids(row.get("items")) {
+            let item_id = match item_id.parse::<i32>() {
+                Ok(item_id) => item_id,
+                Err(_) => continue,
+            };
+            let found = client
+                .query_opt(
+                    "select id, name, preperation_time, price, course, held, fired_at,
+                            station_id, ready_at from item
+                        where id = $1 and restaurant_id = $2
+                        and id not in (select item_id from voided_item);",
+                    &[&item_id, &restaurant_id],
+                )
+                .map_err(|e| format!("Failed with error: {}", e))?;
+            if let Some(item) = found {
+                items.push(fill_in(client, restaurant_id, ordered_item(&item))?);
+            }
+        }
+        for voided in find_voided_items(client, restaurant_id, table_id)? {
+            items.push(fill_in(client, restaurant_id, voided_item(&voided))?);
+        }
+        Ok(domain::Table {
+            id: table_id,
+            version: row.get::<_, i32>("version") as i64,
+            paid_at: row.get("paid_at"),
+            items,
+        })
+    }
+
+    /// Runs `change` on a table's item ids and writes them back if it is still at `version`,
+    /// then runs `also` and records `changes` in the audit log in the same transaction.
+    /// Nothing is written when either of them gives up.
+    fn update_items<F, G>(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        version: i64,
+        change: F,
+        also: G,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String>
+    where
+        F: FnOnce(&str) -> Option<String>,
+        G: FnOnce(&mut Transaction, i32) -> Result<bool, String>,
+    {
+        let table_id = match table_id.parse::<i32>() {
+            Ok(table_id) => table_id,
+            Err(_) => return Ok(false),
+        };
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to update table {}: {}", table_id, e))?;
+        let row = transaction
+            .query_opt(
+                "select items from restaurant_table
+                    where id = $1 and version = $2 and restaurant_id = $3 for update;",
+                &[&table_id, &(version as i32), &restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let items = match row.and_then(|row| change(row.get("items"))) {
+            Some(items) => items,
+            None => return Ok(false),
+        };
+        debug!("New items are {:?}", items);
+        let count = transaction
+            .execute(
+                "update restaurant_table set items = $1, version = version + 1
+                    where id = $2 and version = $3 and restaurant_id = $4;",
+                &[&items, &table_id, &(version as i32), &restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        // Dropping the transaction rolls back the new items when `also` gives up.
+        if count == 0 || !also(&mut transaction, table_id)? {
+            return Ok(false);
+        }
+        for change in changes.iter() {
+            insert_change(&mut transaction, change)?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to update table {}: {}", table_id, e))?;
+        changes.iter().for_each(audit::logged);
+        Ok(true)
+    }
+}
+
+fn ordered_item(row: &Row) -> domain::Item {
+    domain::Item {
+        id: row.get("id"),
+        name: row.get("name"),
+        time_to_prepare: row.get("preperation_time"),
+        status: domain::ItemStatus::Ordered,
+        modifiers: Vec::new(),
+        price: row.get("price"),
+        components: Vec::new(),
+        course: row.get("course"),
+        held: row.get::<_, i32>("held") != 0,
+        fired_at: row.get("fired_at"),
+        station_id: row.get("station_id"),
+        ready_at: row.get("ready_at"),
+    }
+}
+
+fn voided_item(row: &Row) -> domain::Item {
+    domain::Item {
+        status: domain::ItemStatus::Voided(domain::Void {
+            reason: row.get("reason"),
+            voided_by: row.get("voided_by"),
+            voided_at: row.get("voided_at"),
+        }),
+        ..ordered_item(row)
+    }
+}
+
+/// Fills in an item's modifiers and, for a bundle, its components with the same status.
+fn fill_in(
+    client: &mut Client,
+    restaurant_id: i32,
+    mut item: domain::Item,
+) -> Result<domain::Item, String> {
+    let rows = client
+        .query(
+            "select id, name, preperation_time, price, course, held, fired_at, station_id, ready_at
+                from item where bundle_item_id = $1 and restaurant_id = $2 order by id;",
+            &[&item.id, &restaurant_id],
+        )
+        .map_err(|e| format!("Failed with error: {}", e))?;
+    for row in rows.iter() {
+        let component = domain::Item {
+            status: item.status.clone(),
+            ..ordered_item(row)
+        };
+        item.components.push(with_modifiers(client, component)?);
+    }
+    with_modifiers(client, item)
+}
+
+/// Fills in the modifiers chosen for an item, in the order they were asked for.
+fn with_modifiers(client: &mut Client, mut item: domain::Item) -> Result<domain::Item, String> {
+    let rows = client
+        .query(
+            "select modifier_id, name, price_delta from item_modifier
+                where item_id = $1 order by position;",
+            &[&item.id],
+        )
+        .map_err(|e| format!("Failed with error: {}", e))?;
+    item.modifiers = rows
+        .iter()
+        .map(|row| domain::Modifier {
+            id: row.get("modifier_id"),
+            name: row.get("name"),
+            price_delta: row.get("price_delta"),
+        })
+        .collect();
+    Ok(item)
+}
+
+fn find_voided_items(
+    client: &mut Client,
+    restaurant_id: i32,
+    table_id: i32,
+) -> Result<Vec<Row>, String> {
+    client
+        .query(
+            "select item.id, item.name, item.preperation_time, item.price, item.course,
+                    item.held, item.fired_at, item.station_id, item.ready_at,
+                    voided_item.reason, voided_item.voided_by, voided_item.voided_at
+                from voided_item join item on item.id = voided_item.item_id
+                where voided_item.table_id = $1 and item.restaurant_id = $2
+                order by voided_item.voided_at, item.id;",
+            &[&table_id, &restaurant_id],
+        )
+        .map_err(|e| format!("Failed with error: {}", e))
+}
+
+impl TableRepository for PostgresRepository {
+    fn all(&self, restaurant_id: i32) -> Result<Vec<domain::Table>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select id, items, version, paid_at from restaurant_table
+                    where restaurant_id = $1 order by id;",
+                &[&restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        rows.iter()
+            .map(|row| PostgresRepository::load(&mut client, restaurant_id, row))
+            .collect()
+    }
+
+    fn find(&self, restaurant_id: i32, table_id: &str) -> Result<Option<domain::Table>, String> {
+        let table_id = match table_id.parse::<i32>() {
+            Ok(table_id) => table_id,
+            Err(_) => return Ok(None),
+        };
+        let mut client = self.connect()?;
+        let row = client
+            .query_opt(
+                "select id, items, version, paid_at from restaurant_table
+                    where id = $1 and restaurant_id = $2;",
+                &[&table_id, &restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        match row {
+            Some(row) => Ok(Some(PostgresRepository::load(
+                &mut client,
+                restaurant_id,
+                &row,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    fn create(
+        &self,
+        restaurant_id: i32,
+        item_ids: &[String],
+        changes: &dyn Fn(i32) -> Vec<audit::Change>,
+    ) -> Result<i32, String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to create table: {}", e))?;
+        let row = transaction
+            .query_one(
+                "insert into restaurant_table (items, restaurant_id) values ($1, $2) returning id;",
+                &[&database::join_item_ids(item_ids), &restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let id = row.get(0);
+        let changes = changes(id);
+        for change in changes.iter() {
+            insert_change(&mut transaction, change)?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to create table: {}", e))?;
+        changes.iter().for_each(audit::logged);
+        Ok(id)
+    }
+
+    fn append_items(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[String],
+        version: i64,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String> {
+        let new_item_ids = database::join_item_ids(item_ids);
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(database::append_item_ids(items, &new_item_ids)),
+            |_, _| Ok(true),
+            changes,
+        )
+    }
+
+    fn void_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        void: &domain::Void,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| database::remove_item_id(items, &item_id.to_string()),
+            |transaction, table_id| {
+                let voided = transaction
+                    .execute(
+                        "insert into voided_item (item_id, table_id, reason, voided_by, voided_at)
+                            select id, $2::integer, $3::text, $4::integer, $5::bigint from item
+                            where id = $1 and restaurant_id = $6;",
+                        &[
+                            &item_id,
+                            &table_id,
+                            &void.reason,
+                            &void.voided_by,
+                            &void.voided_at,
+                            &restaurant_id,
+                        ],
+                    )
+                    .map_err(|e| format!("Failed to void item {}: {}", item_id, e))?;
+                match voided {
+                    1 => Ok(true),
+                    _ => Err(format!("Item {} was not ordered here.", item_id)),
+                }
+            },
+            vec![change],
+        )
+    }
+
+    fn restore_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(database::append_item_ids(items, &item_id.to_string())),
+            |transaction, table_id| {
+                let unvoided = transaction
+                    .execute(
+                        "delete from voided_item where item_id = $1 and table_id = $2
+                            and item_id in (select id from item where restaurant_id = $3);",
+                        &[&item_id, &table_id, &restaurant_id],
+                    )
+                    .map_err(|e| format!("Failed to restore item {}: {}", item_id, e))?;
+                Ok(unvoided == 1)
+            },
+            vec![change],
+        )
+    }
+
+    fn fire(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[i32],
+        fired_at: i64,
+        version: i64,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(items.to_string()),
+            |transaction, _| {
+                transaction
+                    .execute(
+                        "update item set held = 0, fired_at = $1
+                            where (id = any($2) or bundle_item_id = any($2))
+                            and restaurant_id = $3 and held = 1;",
+                        &[&fired_at, &item_ids, &restaurant_id],
+                    )
+                    .map_err(|e| format!("Failed to fire items: {}", e))?;
+                Ok(true)
+            },
+            changes,
+        )
+    }
+
+    fn ready(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        ready_at: i64,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(items.to_string()),
+            |transaction, _| {
+                let updated = transaction
+                    .execute(
+                        "update item set ready_at = $1
+                            where id = $2 and restaurant_id = $3 and held = 0
+                            and ready_at is null;",
+                        &[&ready_at, &item_id, &restaurant_id],
+                    )
+                    .map_err(|e| format!("Failed to mark item {} ready: {}", item_id, e))?;
+                Ok(updated == 1)
+            },
+            vec![change],
+        )
+    }
+
+    fn settle(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        paid_at: i64,
+        version: i64,
+    ) -> Result<bool, String> {
+        let table_id = match table_id.parse::<i32>() {
+            Ok(table_id) => table_id,
+            Err(_) => return Ok(false),
+        };
+        let mut client = self.connect()?;
+        let count = client
+            .execute(
+                "update restaurant_table set paid_at = $1, version = version + 1
+                    where id = $2 and version = $3 and restaurant_id = $4;",
+                &[&paid_at, &table_id, &(version as i32), &restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(count > 0)
+    }
+}
+
+impl ItemRepository for PostgresRepository {
+    fn create(
+        &self,
+        restaurant_id: i32,
+        new_item: &domain::NewItem,
+    ) -> Result<domain::Item, String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to insert item: {}", e))?;
+        let row = transaction
+            .query_one(
+                "insert into item (name, preperation_time, restaurant_id, price, bundle_item_id,
+                        course, held, fired_at, station_id)
+                    values ($1, $2, $3, $4, $5, $6, $7, $8, $9) returning id;",
+                &[
+                    &new_item.name,
+                    &new_item.time_to_prepare,
+                    &restaurant_id,
+                    &new_item.price,
+                    &new_item.bundle_id,
+                    &new_item.course,
+                    &(new_item.held as i32),
+                    &new_item.fired_at,
+                    &new_item.station_id,
+                ],
+            )
+            .map_err(|e| format!("Failed to insert item: {}", e))?;
+        let item_id: i32 = row.get(0);
+        for (position, modifier) in new_item.modifiers.iter().enumerate() {
+            transaction
+                .execute(
+                    "insert into item_modifier (item_id, position, modifier_id, name, price_delta)
+                        values ($1, $2, $3, $4, $5);",
+                    &[
+                        &item_id,
+                        &(position as i32),
+                        &modifier.id,
+                        &modifier.name,
+                        &modifier.price_delta,
+                    ],
+                )
+                .map_err(|e| format!("Failed to insert item modifier: {}", e))?;
+        }
+        for line in new_item.recipe.iter() {
+            transaction
+                .execute(
+                    "insert into item_ingredient (item_id, ingredient_id, quantity)
+                        values ($1, $2, $3);",
+                    &[&item_id, &line.ingredient_id, &line.quantity],
+                )
+                .map_err(|e| format!("Failed to insert item ingredient: {}", e))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to insert item: {}", e))?;
+        Ok(domain::Item {
+            id: item_id,
+            name: new_item.name.clone(),
+            time_to_prepare: new_item.time_to_prepare,
+            status: domain::ItemStatus::Ordered,
+            modifiers: new_item.modifiers.clone(),
+            price: new_item.price,
+            components: Vec::new(),
+            course: new_item.course,
+            held: new_item.held,
+            fired_at: new_item.fired_at,
+            station_id: new_item.station_id,
+            ready_at: None,
+        })
+    }
+
+    fn delete(&self, restaurant_id: i32, item_id: i32) -> Result<(), String> {
+        let mut client = self.connect()?;
+        for details in &["item_modifier", "item_ingredient"] {
+            let deleted = client.execute(
+                format!(
+                    "delete from {} where item_id in (select id from item
+                        where (id = $1 or bundle_item_id = $1) and restaurant_id = $2);",
+                    details
+                )
+                .as_str(),
+                &[&item_id, &restaurant_id],
+            );
+            if let Err(e) = deleted {
+                return Err(format!("Failed to delete item {}: {}", item_id, e));
+            }
+        }
+        match client.execute(
+            "delete from item where (id = $1 or bundle_item_id = $1) and restaurant_id = $2;",
+            &[&item_id, &restaurant_id],
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to delete item {}: {}", item_id, e)),
+        }
+    }
+
+    fn recipe(&self, restaurant_id: i32, item_id: i32) -> Result<Vec<domain::RecipeLine>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select item_ingredient.ingredient_id, item_ingredient.quantity
+                    from item_ingredient join item on item.id = item_ingredient.item_id
+                    where (item.id = $1 or item.bundle_item_id = $1) and item.restaurant_id = $2
+                    order by item.id, item_ingredient.ingredient_id;",
+                &[&item_id, &restaurant_id],
+            )
+            .map_err(|e| format!("Failed to find the recipe of item {}: {}", item_id, e))?;
+        Ok(rows
+            .iter()
+            .map(|row| domain::RecipeLine {
+                ingredient_id: row.get("ingredient_id"),
+                quantity: row.get("quantity"),
+            })
+            .collect())
+    }
+
+    fn find_voided(
+        &self,
+        restaurant_id: i32,
+        item_id: &str,
+        table_id: &str,
+    ) -> Result<Option<domain::Item>, String> {
+        let table_id = match table_id.parse::<i32>() {
+            Ok(table_id) => table_id,
+            Err(_) => return Ok(None),
+        };
+        let mut client = self.connect()?;
+        let voided = find_voided_items(&mut client, restaurant_id, table_id)?
+            .iter()
+            .find(|row| row.get::<_, i32>("id").to_string() == item_id)
+            .map(voided_item);
+        match voided {
+            Some(item) => Ok(Some(fill_in(&mut client, restaurant_id, item)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn preparations(
+        &self,
+        restaurant_id: i32,
+        since: i64,
+    ) -> Result<Vec<domain::Preparation>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select name, station_id, preperation_time, fired_at, ready_at from item
+                where restaurant_id = $1 and fired_at >= $2
+                and id not in (select bundle_item_id from item where bundle_item_id is not null)
+                and (ready_at is not null
+                    or (id not in (select item_id from voided_item)
+                        and coalesce(bundle_item_id, 0) not in (select item_id from voided_item)))
+                order by fired_at, id;",
+                &[&restaurant_id, &since],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(rows
+            .iter()
+            .map(|row| domain::Preparation {
+                name: row.get("name"),
+                station_id: row.get("station_id"),
+                time_to_prepare: row.get("preperation_time"),
+                fired_at: row.get("fired_at"),
+                ready_at: row.get("ready_at"),
+            })
+            .collect())
+    }
+}
+
+impl StockRepository for PostgresRepository {
+    fn ingredients(&self, restaurant_id: i32) -> Result<Vec<domain::Ingredient>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select id, name, stock, low_stock from ingredient
+                    where restaurant_id = $1 order by id;",
+                &[&restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(rows.iter().map(ingredient).collect())
+    }
+
+    fn take(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<bool, String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to take stock: {}", e))?;
+        for line in recipe {
+            let taken = transaction
+                .execute(
+                    "update ingredient set stock = stock - $1
+                        where id = $2 and restaurant_id = $3 and stock >= $1;",
+                    &[&line.quantity, &line.ingredient_id, &restaurant_id],
+                )
+                .map_err(|e| format!("Failed to take stock: {}", e))?;
+            // Dropping the transaction rolls back what was taken so far.
+            if taken == 0 {
+                return Ok(false);
+            }
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to take stock: {}", e))?;
+        Ok(true)
+    }
+
+    fn give_back(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<(), String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to give back stock: {}", e))?;
+        for line in recipe {
+            transaction
+                .execute(
+                    "update ingredient set stock = stock + $1
+                        where id = $2 and restaurant_id = $3;",
+                    &[&line.quantity, &line.ingredient_id, &restaurant_id],
+                )
+                .map_err(|e| format!("Failed to give back stock: {}", e))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to give back stock: {}", e))
+    }
+
+    fn add_ingredient(
+        &self,
+        restaurant_id: i32,
+        name: &str,
+        stock: i32,
+        low_stock: i32,
+    ) -> Result<domain::Ingredient, String> {
+        let mut client = self.connect()?;
+        let row = client
+            .query_one(
+                "insert into ingredient (restaurant_id, name, stock, low_stock)
+                    values ($1, $2, $3, $4) returning id, name, stock, low_stock;",
+                &[&restaurant_id, &name, &stock, &low_stock],
+            )
+            .map_err(|e| format!("Failed to add ingredient: {}", e))?;
+        Ok(ingredient(&row))
+    }
+
+    fn restock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        quantity: i32,
+    ) -> Result<Option<domain::Ingredient>, String> {
+        let mut client = self.connect()?;
+        let row = client
+            .query_opt(
+                "update ingredient set stock = stock + $1 where id = $2 and restaurant_id = $3
+                    returning id, name, stock, low_stock;",
+                &[&quantity, &ingredient_id, &restaurant_id],
+            )
+            .map_err(|e| format!("Failed to restock: {}", e))?;
+        Ok(row.as_ref().map(ingredient))
+    }
+
+    fn set_low_stock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        low_stock: i32,
+    ) -> Result<Option<domain::Ingredient>, String> {
+        let mut client = self.connect()?;
+        let row = client
+            .query_opt(
+                "update ingredient set low_stock = $1 where id = $2 and restaurant_id = $3
+                    returning id, name, stock, low_stock;",
+                &[&low_stock, &ingredient_id, &restaurant_id],
+            )
+            .map_err(|e| format!("Failed to set low stock: {}", e))?;
+        Ok(row.as_ref().map(ingredient))
+    }
+}
+
+fn ingredient(row: &Row) -> domain::Ingredient {
+    domain::Ingredient {
+        id: row.get("id"),
+        name: row.get("name"),
+        stock: row.get("stock"),
+        low_stock: row.get("low_stock"),
+    }
+}
+
+/// Runs one of the queries a menu is put together from.
+fn menu_query(client: &mut Client, query: &str, restaurant_id: i32) -> Result<Vec<Row>, String> {
+    client
+        .query(query, &[&restaurant_id])
+        .map_err(|e| format!("Failed with error: {}", e))
+}
+
+impl MenuRepository for PostgresRepository {
+    fn menu(&self, restaurant_id: i32) -> Result<domain::Menu, String> {
+        let mut client = self.connect()?;
+        let rows = menu_query(
+            &mut client,
+            "select menu_category.id, menu_category.name,
+                    menu_item.id, menu_item.name, menu_item.price,
+                    menu_item.available, menu_item.available_at,
+                    modifier_group.id, modifier_group.name,
+                    modifier_group.min_selected, modifier_group.max_selected,
+                    modifier.id, modifier.name, modifier.price_delta,
+                    coalesce(menu_item.station_id, menu_category.station_id)
+                from menu_category
+                left join menu_item on menu_item.category_id = menu_category.id
+                    and menu_item.restaurant_id = menu_category.restaurant_id
+                left join modifier_group on modifier_group.menu_item_id = menu_item.id
+                left join modifier on modifier.group_id = modifier_group.id
+                where menu_category.restaurant_id = $1
+                order by menu_category.id, menu_item.id, modifier_group.id, modifier.id;",
+            restaurant_id,
+        )?;
+        let rows = rows
+            .iter()
+            .map(|row| menu::MenuRow {
+                category_id: row.get(0),
+                category_name: row.get(1),
+                item: row.get::<_, Option<i32>>(2).map(|id| {
+                    (
+                        id,
+                        row.get(3),
+                        row.get(4),
+                        row.get::<_, i32>(5) != 0,
+                        row.get(6),
+                        row.get(14),
+                    )
+                }),
+                group: row
+                    .get::<_, Option<i32>>(7)
+                    .map(|id| (id, row.get(8), row.get(9), row.get(10))),
+                modifier: row
+                    .get::<_, Option<i32>>(11)
+                    .map(|id| (id, row.get(12), row.get(13))),
+            })
+            .collect();
+        let components = menu_query(
+            &mut client,
+            "select bundle_component.bundle_id, menu_item.name
+                from bundle_component
+                join menu_item on menu_item.id = bundle_component.menu_item_id
+                where menu_item.restaurant_id = $1
+                order by bundle_component.bundle_id, bundle_component.position;",
+            restaurant_id,
+        )?;
+        let recipes = menu_query(
+            &mut client,
+            "select recipe.menu_item_id, recipe.ingredient_id, recipe.quantity
+                from recipe
+                join ingredient on ingredient.id = recipe.ingredient_id
+                where ingredient.restaurant_id = $1
+                order by recipe.menu_item_id, recipe.ingredient_id;",
+            restaurant_id,
+        )?;
+        let tags = menu_query(
+            &mut client,
+            "select menu_item_tag.menu_item_id, menu_item_tag.kind, menu_item_tag.tag
+                from menu_item_tag
+                join menu_item on menu_item.id = menu_item_tag.menu_item_id
+                where menu_item.restaurant_id = $1
+                order by menu_item_tag.menu_item_id, menu_item_tag.tag;",
+            restaurant_id,
+        )?;
+        let schedules = menu_query(
+            &mut client,
+            "select menu_schedule.category_id, menu_schedule.days,
+                    menu_schedule.starts, menu_schedule.ends
+                from menu_schedule
+                join menu_category on menu_category.id = menu_schedule.category_id
+                where menu_category.restaurant_id = $1
+                order by menu_schedule.category_id, menu_schedule.starts;",
+            restaurant_id,
+        )?;
+        let price_rules = menu_query(
+            &mut client,
+            "select id, name, category_id, menu_item_id, days, starts, ends,
+                    valid_from, valid_until, percent_off
+                from price_rule where restaurant_id = $1 order by id;",
+            restaurant_id,
+        )?;
+        let utc_offset = menu_query(
+            &mut client,
+            "select utc_offset from restaurant where id = $1;",
+            restaurant_id,
+        )?;
+        let rows = menu::MenuRows {
+            rows,
+            components: components
+                .iter()
+                .map(|row| (row.get(0), row.get(1)))
+                .collect(),
+            recipes: recipes
+                .iter()
+                .map(|row| {
+                    let line = domain::RecipeLine {
+                        ingredient_id: row.get(1),
+                        quantity: row.get(2),
+                    };
+                    (row.get(0), line)
+                })
+                .collect(),
+            tags: tags
+                .iter()
+                .map(|row| (row.get(0), row.get(1), row.get(2)))
+                .collect(),
+            schedules: schedules
+                .iter()
+                .map(|row| {
+                    let window = domain::Window {
+                        days: row.get(1),
+                        starts: row.get(2),
+                        ends: row.get(3),
+                    };
+                    (row.get(0), window)
+                })
+                .collect(),
+            price_rules: price_rules
+                .iter()
+                .map(|row| domain::PriceRule {
+                    id: row.get(0),
+                    name: row.get(1),
+                    category_id: row.get(2),
+                    menu_item_id: row.get(3),
+                    window: domain::Window {
+                        days: row.get(4),
+                        starts: row.get(5),
+                        ends: row.get(6),
+                    },
+                    valid_from: row.get(7),
+                    valid_until: row.get(8),
+                    percent_off: row.get(9),
+                })
+                .collect(),
+            utc_offset: utc_offset
+                .first()
+                .map_or(0, |row| row.get::<_, i32>(0) as i64),
+        };
+        Ok(menu::assemble(rows, audit::now()))
+    }
+
+    fn set_availability(
+        &self,
+        restaurant_id: i32,
+        item_id: i32,
+        available: bool,
+        available_at: Option<i64>,
+    ) -> Result<bool, String> {
+        let mut client = self.connect()?;
+        match client.execute(
+            "update menu_item set available = $1, available_at = $2
+                where id = $3 and restaurant_id = $4;",
+            &[&(available as i32), &available_at, &item_id, &restaurant_id],
+        ) {
+            Ok(updated) => Ok(updated == 1),
+            Err(e) => Err(format!("Failed with error: {}", e)),
+        }
+    }
+
+    fn set_recipe(
+        &self,
+        restaurant_id: i32,
+        item_id: i32,
+        recipe: &[domain::RecipeLine],
+    ) -> Result<bool, String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to set recipe: {}", e))?;
+        let found = transaction
+            .query_opt(
+                "select id from menu_item where id = $1 and restaurant_id = $2 for update;",
+                &[&item_id, &restaurant_id],
+            )
+            .map_err(|e| format!("Failed to set recipe: {}", e))?;
+        if found.is_none() {
+            return Ok(false);
+        }
+        transaction
+            .execute("delete from recipe where menu_item_id = $1;", &[&item_id])
+            .map_err(|e| format!("Failed to set recipe: {}", e))?;
+        for line in recipe {
+            transaction
+                .execute(
+                    "insert into recipe (menu_item_id, ingredient_id, quantity)
+                        values ($1, $2, $3);",
+                    &[&item_id, &line.ingredient_id, &line.quantity],
+                )
+                .map_err(|e| format!("Failed to set recipe: {}", e))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to set recipe: {}", e))?;
+        Ok(true)
+    }
+}
+
+impl StaffRepository for PostgresRepository {
+    fn find(&self, staff_id: &str) -> Result<Option<auth::Staff>, String> {
+        let staff_id = match staff_id.parse::<i32>() {
+            Ok(staff_id) => staff_id,
+            Err(_) => return Ok(None),
+        };
+        let mut client = self.connect()?;
+        let row = client
+            .query_opt(
+                "select id, name, role, restaurant_id from staff where id = $1;",
+                &[&staff_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(row.and_then(|row| {
+            auth::Role::from_name(row.get("role")).map(|role| auth::Staff {
+                id: row.get("id"),
+                name: row.get("name"),
+                role,
+                restaurant_id: row.get("restaurant_id"),
+            })
+        }))
+    }
+
+    fn restaurant(&self, restaurant_id: i32) -> Result<Option<domain::Restaurant>, String> {
+        let mut client = self.connect()?;
+        let row = client
+            .query_opt(
+                "select id, name from restaurant where id = $1;",
+                &[&restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(row.map(|row| domain::Restaurant {
+            id: row.get("id"),
+            name: row.get("name"),
+        }))
+    }
+}
+
+impl IdempotencyRepository for PostgresRepository {
+    fn begin(
+        &self,
+        restaurant_id: i32,
+        key: &str,
+        request: &str,
+        window: &idempotency::Window,
+    ) -> Result<idempotency::Attempt, String> {
+        let key = &idempotency::scoped(restaurant_id, key);
+        let now = audit::now();
+        let mut client = self.connect()?;
+        client
+            .execute(
+                "delete from idempotency_key
+                    where key = $1 and (created_at < $2 or (response is null and created_at < $3));",
+                &[key, &(now - window.0), &(now - idempotency::LEASE)],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let inserted = client
+            .execute(
+                "insert into idempotency_key (key, request, response, created_at)
+                    values ($1, $2, null, $3) on conflict do nothing;",
+                &[key, &request, &now],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        if inserted == 1 {
+            return Ok(idempotency::Attempt::New);
+        }
+        let row = client
+            .query_one(
+                "select request, response from idempotency_key where key = $1;",
+                &[key],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let stored_request: String = row.get("request");
+        match row.get::<_, Option<String>>("response") {
+            _ if stored_request != request => Ok(idempotency::Attempt::Mismatch),
+            Some(response) => Ok(idempotency::Attempt::Replay(response)),
+            None => Ok(idempotency::Attempt::InProgress),
+        }
+    }
+
+    fn complete(&self, restaurant_id: i32, key: &str, response: &str) -> Result<(), String> {
+        let key = &idempotency::scoped(restaurant_id, key);
+        let mut client = self.connect()?;
+        match client.execute(
+            "update idempotency_key set response = $1 where key = $2;",
+            &[&response, key],
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed with error: {}", e)),
+        }
+    }
+
+    fn abandon(&self, restaurant_id: i32, key: &str) {
+        let key = &idempotency::scoped(restaurant_id, key);
+        let released = self.connect().and_then(|mut client| {
+            client
+                .execute("delete from idempotency_key where key = $1;", &[key])
+                .map_err(|e| e.to_string())
+        });
+        if let Err(e) = released {
+            warn!("Failed to release idempotency key {}: {}", key, e);
+        }
+    }
+}
+
+impl AuditRepository for PostgresRepository {
+    fn record(&self, change: audit::Change) -> Result<(), String> {
+        let mut client = self.connect()?;
+        insert_change(&mut *client, &change)?;
+        audit::logged(&change);
+        Ok(())
+    }
+
+    fn search(&self, filter: &audit::Filter) -> Result<Vec<data::AuditEntry>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select id, actor_id, action, table_id, item_id, before, after, created_at
+                    from audit_log
+                    where restaurant_id = $1
+                    and ($2::integer is null or table_id = $2)
+                    and ($3::integer is null or actor_id = $3)
+                    and ($4::bigint is null or created_at >= $4)
+                    and ($5::bigint is null or created_at <= $5)
+                    order by id;",
+                &[
+                    &filter.restaurant_id,
+                    &filter.table_id,
+                    &filter.actor_id,
+                    &filter.from,
+                    &filter.to,
+                ],
+            )
+            .map_err(|_| String::from("Failed to fetch audit entries."))?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                audit::entry(database::AuditData {
+                    id: row.get::<_, i32>("id") as i64,
+                    actor_id: row.get("actor_id"),
+                    action: row.get("action"),
+                    table_id: row.get("table_id"),
+                    item_id: row.get("item_id"),
+                    before: row.get("before"),
+                    after: row.get("after"),
+                    created_at: row.get("created_at"),
+                })
+            })
+            .collect())
+    }
+}
+
+impl AllergyRepository for PostgresRepository {
+    fn all(&self, restaurant_id: i32) -> Result<BTreeMap<i32, Vec<domain::Allergy>>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select table_id, seat, allergen from table_allergy
+                    where restaurant_id = $1 order by table_id, seat, allergen;",
+                &[&restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let mut tables: BTreeMap<i32, Vec<domain::Allergy>> = BTreeMap::new();
+        for row in rows.iter() {
+            tables
+                .entry(row.get("table_id"))
+                .or_default()
+                .push(domain::Allergy {
+                    seat: row.get("seat"),
+                    allergen: row.get("allergen"),
+                });
+        }
+        Ok(tables)
+    }
+
+    fn replace(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        allergies: &[domain::Allergy],
+    ) -> Result<(), String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to record allergies: {}", e))?;
+        transaction
+            .execute(
+                "delete from table_allergy where restaurant_id = $1 and table_id = $2;",
+                &[&restaurant_id, &table_id],
+            )
+            .map_err(|e| format!("Failed to record allergies: {}", e))?;
+        for allergy in allergies {
+            transaction
+                .execute(
+                    "insert into table_allergy (restaurant_id, table_id, seat, allergen)
+                        values ($1, $2, $3, $4);",
+                    &[&restaurant_id, &table_id, &allergy.seat, &allergy.allergen],
+                )
+                .map_err(|e| format!("Failed to record allergies: {}", e))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to record allergies: {}", e))
+    }
+}
+
+impl AdjustmentRepository for PostgresRepository {
+    fn adjustments(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+    ) -> Result<Vec<domain::Adjustment>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select id, item_id, item_name, kind, value, buy, get, reason, code, added_by,
+                        added_at
+                    from adjustment where restaurant_id = $1 and table_id = $2 order by id;",
+                &[&restaurant_id, &table_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let mut adjustments = Vec::new();
+        for row in rows.iter() {
+            let parts = adjustment_row(row, row.get("item_id"));
+            adjustments.push(domain::Adjustment {
+                id: parts.id,
+                target: parts.target(),
+                discount: parts.discount()?,
+                reason: row.get("reason"),
+                code: row.get("code"),
+                added_by: row.get("added_by"),
+                added_at: row.get("added_at"),
+            });
+        }
+        Ok(adjustments)
+    }
+
+    fn add(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        adjustment: &domain::Adjustment,
+        change: audit::Change,
+    ) -> Result<i32, String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to add adjustment: {}", e))?;
+        let id = insert_adjustment(&mut transaction, restaurant_id, table_id, adjustment)
+            .map_err(|e| format!("Failed to add adjustment: {}", e))?;
+        insert_change(&mut transaction, &change)?;
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to add adjustment: {}", e))?;
+        audit::logged(&change);
+        Ok(id)
+    }
+
+    fn find_promo_code(
+        &self,
+        restaurant_id: i32,
+        code: &str,
+    ) -> Result<Option<domain::PromoCode>, String> {
+        let mut client = self.connect()?;
+        let row = client
+            .query_opt(
+                "select id, item_name, kind, value, buy, get, code, max_uses, uses, expires_at
+                    from promo_code where restaurant_id = $1 and code = $2;",
+                &[&restaurant_id, &code],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let parts = adjustment_row(&row, None);
+        Ok(Some(domain::PromoCode {
+            id: parts.id,
+            code: row.get("code"),
+            target: parts.target(),
+            discount: parts.discount()?,
+            max_uses: row.get("max_uses"),
+            uses: row.get("uses"),
+            expires_at: row.get("expires_at"),
+        }))
+    }
+
+    fn redeem(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        promo_code_id: i32,
+        adjustment: &domain::Adjustment,
+        change: audit::Change,
+    ) -> Result<domain::Redemption, String> {
+        let mut client = self.connect()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| format!("Failed to redeem promo code: {}", e))?;
+        let used = transaction
+            .execute(
+                "update promo_code set uses = uses + 1
+                    where id = $1 and (max_uses is null or uses < max_uses)
+                    and (expires_at is null or expires_at > $2);",
+                &[&promo_code_id, &adjustment.added_at],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        // Dropping the transaction rolls back the use when the code is not redeemed.
+        if used == 0 {
+            return Ok(domain::Redemption::UsedUp);
+        }
+        let id = match insert_adjustment(&mut transaction, restaurant_id, table_id, adjustment) {
+            Ok(id) => id,
+            Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+                return Ok(domain::Redemption::AlreadyOnBill);
+            }
+            Err(e) => return Err(format!("Failed to add adjustment: {}", e)),
+        };
+        insert_change(&mut transaction, &change)?;
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to redeem promo code: {}", e))?;
+        audit::logged(&change);
+        Ok(domain::Redemption::Redeemed(id))
+    }
+}
+
+/// Writes a change to the audit log, with a client or in a transaction that is not
+/// committed yet.
+fn insert_change(client: &mut impl GenericClient, change: &audit::Change) -> Result<(), String> {
+    client
+        .execute(
+            "insert into audit_log
+                (actor_id, action, table_id, item_id, before, after, created_at, restaurant_id)
+                values ($1, $2, $3, $4, $5, $6, $7, $8);",
+            &[
+                &change.actor_id,
+                &change.action,
+                &change.table_id,
+                &change.item_id,
+                &change.before,
+                &change.after,
+                &audit::now(),
+                &change.restaurant_id,
+            ],
+        )
+        .map_err(|e| format!("Failed to record audit entry: {}", e))?;
+    Ok(())
+}
+
+fn insert_adjustment(
+    client: &mut impl GenericClient,
+    restaurant_id: i32,
+    table_id: i32,
+    adjustment: &domain::Adjustment,
+) -> Result<i32, ::postgres::Error> {
+    let (item_id, item_name) = adjustment::target_parts(&adjustment.target);
+    let (kind, value, buy, get) = adjustment.discount.parts();
+    let row = client.query_one(
+        "insert into adjustment (restaurant_id, table_id, item_id, item_name, kind, value,
+                buy, get, reason, code, added_by, added_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) returning id;",
+        &[
+            &restaurant_id,
+            &table_id,
+            &item_id,
+            &item_name,
+            &kind,
+            &value,
+            &buy,
+            &get,
+            &adjustment.reason,
+            &adjustment.code,
+            &adjustment.added_by,
+            &adjustment.added_at,
+        ],
+    )?;
+    Ok(row.get(0))
+}
+
+/// The target and discount columns of an adjustment or promo code row.
+fn adjustment_row(row: &Row, item_id: Option<i32>) -> adjustment::AdjustmentRow {
+    adjustment::AdjustmentRow {
+        id: row.get("id"),
+        item_id,
+        item_name: row.get("item_name"),
+        kind: row.get("kind"),
+        value: row.get("value"),
+        buy: row.get("buy"),
+        get: row.get("get"),
+    }
+}
+
+impl KitchenRepository for PostgresRepository {
+    fn stations(&self, restaurant_id: i32) -> Result<Vec<domain::Station>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select id, name from station where restaurant_id = $1 order by id;",
+                &[&restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(rows
+            .iter()
+            .map(|row| domain::Station {
+                id: row.get("id"),
+                name: row.get("name"),
+            })
+            .collect())
+    }
+
+    fn record(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item: &domain::Item,
+        kind: &str,
+        at: i64,
+    ) -> Result<(), String> {
+        if item.held {
+            return Ok(());
+        }
+        let table_id = table_id
+            .parse::<i32>()
+            .map_err(|_| format!("Invalid table id {}.", table_id))?;
+        let mut client = self.connect()?;
+        for ticket in item.kitchen_items() {
+            client
+                .execute(
+                    "insert into kitchen_event
+                        (restaurant_id, station_id, table_id, item_id, name, kind, at)
+                        values ($1, $2, $3, $4, $5, $6, $7);",
+                    &[
+                        &restaurant_id,
+                        &ticket.station_id,
+                        &table_id,
+                        &ticket.id,
+                        &ticket.name,
+                        &kind,
+                        &at,
+                    ],
+                )
+                .map_err(|e| format!("Failed to record kitchen event: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn events(
+        &self,
+        restaurant_id: i32,
+        station_id: i32,
+        after: i64,
+    ) -> Result<Vec<domain::KitchenEvent>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select id, kind, station_id, table_id, item_id, name, at from kitchen_event
+                    where restaurant_id = $1 and station_id = $2 and id > $3::bigint
+                    order by id;",
+                &[&restaurant_id, &station_id, &after],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(rows
+            .iter()
+            .map(|row| domain::KitchenEvent {
+                id: row.get::<_, i32>("id") as i64,
+                kind: row.get("kind"),
+                station_id: row.get("station_id"),
+                table_id: row.get("table_id"),
+                item_id: row.get("item_id"),
+                name: row.get("name"),
+                at: row.get("at"),
+            })
+            .collect())
+    }
+}
+
+impl StatusRepository for PostgresRepository {
+    fn readiness(&self) -> data::HealthReport {
+        match self.connect() {
+            Ok(mut client) => health::postgres_readiness(Ok(&mut client)),
+            Err(e) => health::postgres_readiness(Err(e)),
+        }
+    }
+
+    fn business(&self) -> Result<Vec<metrics::Business>, String> {
+        let mut client = self.connect()?;
+        let rows = client
+            .query(
+                "select restaurant.id,
+                    (select count(*) from restaurant_table
+                        where restaurant_id = restaurant.id and items != ''),
+                    (select count(*) from audit_log
+                        where restaurant_id = restaurant.id and action = 'add_item'),
+                    (select count(*) from audit_log
+                        where restaurant_id = restaurant.id and action = 'void_item'),
+                    (select avg((ready_at - fired_at) / 60.0)::float8 from item
+                        where restaurant_id = restaurant.id and ready_at is not null
+                            and id not in (select item_id from voided_item))
+                from restaurant order by restaurant.id;",
+                &[],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(rows
+            .iter()
+            .map(|row| metrics::Business {
+                restaurant_id: row.get(0),
+                open_tables: row.get(1),
+                items_ordered: row.get(2),
+                items_voided: row.get(3),
+                average_preparation: row.get(4),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,902 @@
+use std::collections::BTreeMap;
+
+use crate::adjustment;
+use crate::allergy;
+use crate::audit;
+use crate::auth;
+use crate::data;
+use crate::database;
+use crate::domain;
+use crate::health;
+use crate::idempotency;
+use crate::kitchen;
+use crate::menu;
+use crate::metrics;
+use crate::repository::{
+    AdjustmentRepository, AllergyRepository, AuditRepository, IdempotencyRepository,
+    ItemRepository, KitchenRepository, MenuRepository, StaffRepository, StatusRepository,
+    StockRepository, TableRepository,
+};
+
+/// Keeps everything in a sqlite database, a connection is opened for each call.
+pub struct SqliteRepository {
+    database: database::Database,
+}
+
+impl SqliteRepository {
+    pub fn new(database: database::Database) -> SqliteRepository {
+        SqliteRepository { database }
+    }
+
+    /// Items that are still on the table come first, in the order they were added,
+    /// followed by the ones voided from it.
+    fn load(
+        connection: &rusqlite::Connection,
+        restaurant_id: i32,
+        table_data: database::TableData,
+    ) -> Result<domain::Table, String> {
+        let mut items = Vec::new();
+        for item_id in database::split_item_ids(&table_data.item_ids) {
+            let found = database::find_ordered_item(connection, restaurant_id, &item_id)?;
+            if let Some(item_data) = found {
+                items.push(item(
+                    connection,
+                    restaurant_id,
+                    item_data,
+                    domain::ItemStatus::Ordered,
+                )?);
+            }
+        }
+        for voided in database::find_voided_items(connection, restaurant_id, table_data.id)? {
+            items.push(voided_item(connection, restaurant_id, voided)?);
+        }
+        Ok(domain::Table {
+            id: table_data.id,
+            version: table_data.version,
+            paid_at: table_data.paid_at,
+            items,
+        })
+    }
+
+    /// Runs `change` on a table's item ids and writes them back if it is still at `version`,
+    /// then runs `also` and records `changes` in the audit log in the same transaction.
+    /// Nothing is written when either of them gives up.
+    fn update_items<F, G>(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        version: i64,
+        change: F,
+        also: G,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String>
+    where
+        F: FnOnce(&str) -> Option<String>,
+        G: FnOnce(&rusqlite::Connection) -> Result<bool, String>,
+    {
+        let mut connection = self.database.connect();
+        let transaction = connection
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(|e| format!("Failed to update table {}: {}", table_id, e))?;
+        let table_data = match database::find_table(&transaction, restaurant_id, table_id)? {
+            Some(table_data) if table_data.version == version => table_data,
+            _ => return Ok(false),
+        };
+        let items = match change(&table_data.item_ids) {
+            Some(items) => items,
+            None => return Ok(false),
+        };
+        debug!("New items are {:?}", items);
+        let count =
+            database::update_table_items(&transaction, restaurant_id, table_id, &items, version)?;
+        // Dropping the transaction rolls back the new items when `also` gives up.
+        if count == 0 || !also(&transaction)? {
+            return Ok(false);
+        }
+        for change in changes.iter() {
+            audit::insert(&transaction, change)?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to update table {}: {}", table_id, e))?;
+        changes.iter().for_each(audit::logged);
+        Ok(true)
+    }
+}
+
+fn modifiers(
+    connection: &rusqlite::Connection,
+    item_id: i32,
+) -> Result<Vec<domain::Modifier>, String> {
+    Ok(database::find_item_modifiers(connection, item_id)?
+        .into_iter()
+        .map(|modifier_data| domain::Modifier {
+            id: modifier_data.id,
+            name: modifier_data.name,
+            price_delta: modifier_data.price_delta,
+        })
+        .collect())
+}
+
+/// Fills in an item's modifiers and, for a bundle, its components with the same status.
+fn item(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    item_data: database::ItemData,
+    status: domain::ItemStatus,
+) -> Result<domain::Item, String> {
+    let mut components = Vec::new();
+    for component in database::find_components(connection, restaurant_id, item_data.id)? {
+        components.push(domain::Item {
+            id: component.id,
+            name: component.name,
+            time_to_prepare: component.time_to_prepare,
+            status: status.clone(),
+            modifiers: modifiers(connection, component.id)?,
+            price: component.price,
+            components: Vec::new(),
+            course: component.course,
+            held: component.held,
+            fired_at: component.fired_at,
+            station_id: component.station_id,
+            ready_at: component.ready_at,
+        });
+    }
+    Ok(domain::Item {
+        id: item_data.id,
+        name: item_data.name,
+        time_to_prepare: item_data.time_to_prepare,
+        status,
+        modifiers: modifiers(connection, item_data.id)?,
+        price: item_data.price,
+        components,
+        course: item_data.course,
+        held: item_data.held,
+        fired_at: item_data.fired_at,
+        station_id: item_data.station_id,
+        ready_at: item_data.ready_at,
+    })
+}
+
+fn voided_item(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    voided: database::VoidedItemData,
+) -> Result<domain::Item, String> {
+    let status = domain::ItemStatus::Voided(domain::Void {
+        reason: voided.reason,
+        voided_by: voided.voided_by,
+        voided_at: voided.voided_at,
+    });
+    item(connection, restaurant_id, voided.item, status)
+}
+
+fn find_ingredient(
+    connection: &rusqlite::Connection,
+    restaurant_id: i32,
+    ingredient_id: i32,
+) -> Result<Option<domain::Ingredient>, String> {
+    let found = connection.query_row(
+        "select id, name, stock, low_stock from ingredient
+            where id = $1 and restaurant_id = $2;",
+        rusqlite::params![ingredient_id, restaurant_id],
+        |row| {
+            Ok(domain::Ingredient {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                stock: row.get(2)?,
+                low_stock: row.get(3)?,
+            })
+        },
+    );
+    match found {
+        Ok(ingredient) => Ok(Some(ingredient)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(format!("Failed with error: {}", e)),
+    }
+}
+
+impl TableRepository for SqliteRepository {
+    fn all(&self, restaurant_id: i32) -> Result<Vec<domain::Table>, String> {
+        let connection = &self.database.connect();
+        database::find_tables(connection, restaurant_id)?
+            .into_iter()
+            .map(|table_data| SqliteRepository::load(connection, restaurant_id, table_data))
+            .collect()
+    }
+
+    fn find(&self, restaurant_id: i32, table_id: &str) -> Result<Option<domain::Table>, String> {
+        let connection = &self.database.connect();
+        match database::find_table(connection, restaurant_id, table_id)? {
+            Some(table_data) => Ok(Some(SqliteRepository::load(
+                connection,
+                restaurant_id,
+                table_data,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    fn create(
+        &self,
+        restaurant_id: i32,
+        item_ids: &[String],
+        changes: &dyn Fn(i32) -> Vec<audit::Change>,
+    ) -> Result<i32, String> {
+        let mut connection = self.database.connect();
+        let transaction = connection
+            .transaction()
+            .map_err(|e| format!("Failed to create table: {}", e))?;
+        transaction
+            .execute(
+                "insert into restaurant_table (id, items, restaurant_id) values (null, $1, $2);",
+                rusqlite::params![database::join_item_ids(item_ids), restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        let id = transaction.last_insert_rowid() as i32;
+        let changes = changes(id);
+        for change in changes.iter() {
+            audit::insert(&transaction, change)?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to create table: {}", e))?;
+        changes.iter().for_each(audit::logged);
+        Ok(id)
+    }
+
+    fn append_items(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[String],
+        version: i64,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String> {
+        let new_item_ids = database::join_item_ids(item_ids);
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(database::append_item_ids(items, &new_item_ids)),
+            |_| Ok(true),
+            changes,
+        )
+    }
+
+    fn void_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        void: &domain::Void,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| database::remove_item_id(items, &item_id.to_string()),
+            |connection| {
+                let voided = connection
+                    .execute(
+                        "insert into voided_item (item_id, table_id, reason, voided_by, voided_at)
+                            select id, $1, $2, $3, $4 from item
+                            where id = $5 and restaurant_id = $6;",
+                        rusqlite::params![
+                            table_id,
+                            void.reason,
+                            void.voided_by,
+                            void.voided_at,
+                            item_id,
+                            restaurant_id,
+                        ],
+                    )
+                    .map_err(|e| format!("Failed to void item {}: {}", item_id, e))?;
+                match voided {
+                    1 => Ok(true),
+                    _ => Err(format!("Item {} was not ordered here.", item_id)),
+                }
+            },
+            vec![change],
+        )
+    }
+
+    fn restore_item(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(database::append_item_ids(items, &item_id.to_string())),
+            |connection| {
+                let unvoided = connection
+                    .execute(
+                        "delete from voided_item where item_id = $1 and table_id = $2
+                            and item_id in (select id from item where restaurant_id = $3);",
+                        rusqlite::params![item_id, table_id, restaurant_id],
+                    )
+                    .map_err(|e| format!("Failed to restore item {}: {}", item_id, e))?;
+                Ok(unvoided == 1)
+            },
+            vec![change],
+        )
+    }
+
+    fn fire(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_ids: &[i32],
+        fired_at: i64,
+        version: i64,
+        changes: Vec<audit::Change>,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(items.to_string()),
+            |connection| {
+                for item_id in item_ids {
+                    connection
+                        .execute(
+                            "update item set held = 0, fired_at = $1
+                                where (id = $2 or bundle_item_id = $2) and restaurant_id = $3
+                                and held = 1;",
+                            rusqlite::params![fired_at, item_id, restaurant_id],
+                        )
+                        .map_err(|e| format!("Failed to fire item {}: {}", item_id, e))?;
+                }
+                Ok(true)
+            },
+            changes,
+        )
+    }
+
+    fn ready(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item_id: i32,
+        ready_at: i64,
+        version: i64,
+        change: audit::Change,
+    ) -> Result<bool, String> {
+        self.update_items(
+            restaurant_id,
+            table_id,
+            version,
+            |items| Some(items.to_string()),
+            |connection| {
+                let updated = connection
+                    .execute(
+                        "update item set ready_at = $1
+                            where id = $2 and restaurant_id = $3 and held = 0
+                            and ready_at is null;",
+                        rusqlite::params![ready_at, item_id, restaurant_id],
+                    )
+                    .map_err(|e| format!("Failed to mark item {} ready: {}", item_id, e))?;
+                Ok(updated == 1)
+            },
+            vec![change],
+        )
+    }
+
+    fn settle(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        paid_at: i64,
+        version: i64,
+    ) -> Result<bool, String> {
+        let connection = self.database.connect();
+        let count = connection
+            .execute(
+                "update restaurant_table set paid_at = $1, version = version + 1
+                    where id = $2 and version = $3 and restaurant_id = $4;",
+                rusqlite::params![paid_at, table_id, version, restaurant_id],
+            )
+            .map_err(|e| format!("Failed with error: {}", e))?;
+        Ok(count > 0)
+    }
+}
+
+impl ItemRepository for SqliteRepository {
+    fn create(
+        &self,
+        restaurant_id: i32,
+        new_item: &domain::NewItem,
+    ) -> Result<domain::Item, String> {
+        let mut connection = self.database.connect();
+        let transaction = connection
+            .transaction()
+            .map_err(|e| format!("Failed to insert item: {}", e))?;
+        transaction
+            .execute(
+                "insert into item (id, name, preperation_time, restaurant_id, price,
+                        bundle_item_id, course, held, fired_at, station_id)
+                    values (null, $1, $2, $3, $4, $5, $6, $7, $8, $9);",
+                rusqlite::params![
+                    new_item.name,
+                    new_item.time_to_prepare,
+                    restaurant_id,
+                    new_item.price,
+                    new_item.bundle_id,
+                    new_item.course,
+                    new_item.held,
+                    new_item.fired_at,
+                    new_item.station_id
+                ],
+            )
+            .map_err(|e| format!("Failed to insert item: {}", e))?;
+        let item_id = transaction.last_insert_rowid() as i32;
+        for (position, modifier) in new_item.modifiers.iter().enumerate() {
+            transaction
+                .execute(
+                    "insert into item_modifier (item_id, position, modifier_id, name, price_delta)
+                        values ($1, $2, $3, $4, $5);",
+                    rusqlite::params![
+                        item_id,
+                        position as i32,
+                        modifier.id,
+                        modifier.name,
+                        modifier.price_delta
+                    ],
+                )
+                .map_err(|e| format!("Failed to insert item modifier: {}", e))?;
+        }
+        for line in new_item.recipe.iter() {
+            transaction
+                .execute(
+                    "insert into item_ingredient (item_id, ingredient_id, quantity)
+                        values ($1, $2, $3);",
+                    rusqlite::params![item_id, line.ingredient_id, line.quantity],
+                )
+                .map_err(|e| format!("Failed to insert item ingredient: {}", e))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to insert item: {}", e))?;
+        Ok(domain::Item {
+            id: item_id,
+            name: new_item.name.clone(),
+            time_to_prepare: new_item.time_to_prepare,
+            status: domain::ItemStatus::Ordered,
+            modifiers: new_item.modifiers.clone(),
+            price: new_item.price,
+            components: Vec::new(),
+            course: new_item.course,
+            held: new_item.held,
+            fired_at: new_item.fired_at,
+            station_id: new_item.station_id,
+            ready_at: None,
+        })
+    }
+
+    fn delete(&self, restaurant_id: i32, item_id: i32) -> Result<(), String> {
+        let connection = &self.database.connect();
+        for details in &["item_modifier", "item_ingredient"] {
+            let mut statement = database::build_statement(
+                connection,
+                &format!(
+                    "delete from {} where item_id in (select id from item
+                        where (id = $1 or bundle_item_id = $1) and restaurant_id = $2);",
+                    details
+                ),
+            );
+            if let Err(e) = statement.execute([&item_id, &restaurant_id]) {
+                return Err(format!("Failed to delete item {}: {}", item_id, e));
+            }
+        }
+        let mut statement = database::build_statement(
+            connection,
+            "delete from item where (id = $1 or bundle_item_id = $1) and restaurant_id = $2;",
+        );
+        match statement.execute([&item_id, &restaurant_id]) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to delete item {}: {}", item_id, e)),
+        }
+    }
+
+    fn recipe(&self, restaurant_id: i32, item_id: i32) -> Result<Vec<domain::RecipeLine>, String> {
+        let connection = &self.database.connect();
+        let mut statement = database::build_statement(
+            connection,
+            "select item_ingredient.ingredient_id, item_ingredient.quantity
+                from item_ingredient join item on item.id = item_ingredient.item_id
+                where (item.id = $1 or item.bundle_item_id = $1) and item.restaurant_id = $2
+                order by item.id, item_ingredient.ingredient_id;",
+        );
+        let rows = statement
+            .query_map([&item_id, &restaurant_id], |row| {
+                Ok(domain::RecipeLine {
+                    ingredient_id: row.get(0)?,
+                    quantity: row.get(1)?,
+                })
+            })
+            .map_err(|e| format!("Failed to find the recipe of item {}: {}", item_id, e))?;
+        rows.collect::<rusqlite::Result<Vec<domain::RecipeLine>>>()
+            .map_err(|e| format!("Failed to find the recipe of item {}: {}", item_id, e))
+    }
+
+    fn find_voided(
+        &self,
+        restaurant_id: i32,
+        item_id: &str,
+        table_id: &str,
+    ) -> Result<Option<domain::Item>, String> {
+        let connection = &self.database.connect();
+        let table_id = match table_id.parse::<i32>() {
+            Ok(table_id) => table_id,
+            Err(_) => return Ok(None),
+        };
+        let voided = database::find_voided_items(connection, restaurant_id, table_id)?;
+        match voided
+            .into_iter()
+            .find(|voided| voided.item.id.to_string() == item_id)
+        {
+            Some(voided) => Ok(Some(voided_item(connection, restaurant_id, voided)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn preparations(
+        &self,
+        restaurant_id: i32,
+        since: i64,
+    ) -> Result<Vec<domain::Preparation>, String> {
+        let connection = &self.database.connect();
+        let mut statement = database::build_statement(
+            connection,
+            "select name, station_id, preperation_time, fired_at, ready_at from item
+            where restaurant_id = $1 and fired_at >= $2
+            and id not in (select bundle_item_id from item where bundle_item_id is not null)
+            and (ready_at is not null
+                or (id not in (select item_id from voided_item)
+                    and coalesce(bundle_item_id, 0) not in (select item_id from voided_item)))
+            order by fired_at, id;",
+        );
+        let results = statement.query_map(rusqlite::params![restaurant_id, since], |row| {
+            Ok(domain::Preparation {
+                name: row.get(0)?,
+                station_id: row.get(1)?,
+                time_to_prepare: row.get(2)?,
+                fired_at: row.get(3)?,
+                ready_at: row.get(4)?,
+            })
+        });
+        match results {
+            Ok(rows) => rows
+                .collect::<rusqlite::Result<Vec<domain::Preparation>>>()
+                .map_err(|e| format!("Failed with error: {}", e)),
+            Err(e) => Err(format!("Failed with error: {}", e)),
+        }
+    }
+}
+
+impl StockRepository for SqliteRepository {
+    fn ingredients(&self, restaurant_id: i32) -> Result<Vec<domain::Ingredient>, String> {
+        let connection = &self.database.connect();
+        let mut statement = database::build_statement(
+            connection,
+            "select id, name, stock, low_stock from ingredient
+                where restaurant_id = $1 order by id;",
+        );
+        let results = statement.query_map([&restaurant_id], |row| {
+            Ok(domain::Ingredient {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                stock: row.get(2)?,
+                low_stock: row.get(3)?,
+            })
+        });
+        match results {
+            Ok(rows) => rows
+                .collect::<rusqlite::Result<Vec<domain::Ingredient>>>()
+                .map_err(|e| format!("Failed with error: {}", e)),
+            Err(e) => Err(format!("Failed with error: {}", e)),
+        }
+    }
+
+    fn take(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<bool, String> {
+        let mut connection = self.database.connect();
+        let transaction = connection
+            .transaction()
+            .map_err(|e| format!("Failed to take stock: {}", e))?;
+        for line in recipe {
+            let taken = transaction
+                .execute(
+                    "update ingredient set stock = stock - $1
+                        where id = $2 and restaurant_id = $3 and stock >= $1;",
+                    rusqlite::params![line.quantity, line.ingredient_id, restaurant_id],
+                )
+                .map_err(|e| format!("Failed to take stock: {}", e))?;
+            // Dropping the transaction rolls back what was taken so far.
+            if taken == 0 {
+                return Ok(false);
+            }
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to take stock: {}", e))?;
+        Ok(true)
+    }
+
+    fn give_back(&self, restaurant_id: i32, recipe: &[domain::RecipeLine]) -> Result<(), String> {
+        let mut connection = self.database.connect();
+        let transaction = connection
+            .transaction()
+            .map_err(|e| format!("Failed to give back stock: {}", e))?;
+        for line in recipe {
+            transaction
+                .execute(
+                    "update ingredient set stock = stock + $1
+                        where id = $2 and restaurant_id = $3;",
+                    rusqlite::params![line.quantity, line.ingredient_id, restaurant_id],
+                )
+                .map_err(|e| format!("Failed to give back stock: {}", e))?;
+        }
+        transaction
+            .commit()
+            .map_err(|e| format!("Failed to give back stock: {}", e))
+    }
+
+    fn add_ingredient(
+        &self,
+        restaurant_id: i32,
+        name: &str,
+        stock: i32,
+        low_stock: i32,
+    ) -> Result<domain::Ingredient, String> {
+        let connection = self.database.connect();
+        connection
+            .execute(
+                "insert into ingredient (restaurant_id, name, stock, low_stock)
+                    values ($1, $2, $3, $4);",
+                rusqlite::params![restaurant_id, name, stock, low_stock],
+            )
+            .map_err(|e| format!("Failed to add ingredient: {}", e))?;
+        Ok(domain::Ingredient {
+            id: connection.last_insert_rowid() as i32,
+            name: name.to_string(),
+            stock,
+            low_stock,
+        })
+    }
+
+    fn restock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        quantity: i32,
+    ) -> Result<Option<domain::Ingredient>, String> {
+        let connection = self.database.connect();
+        connection
+            .execute(
+                "update ingredient set stock = stock + $1 where id = $2 and restaurant_id = $3;",
+                rusqlite::params![quantity, ingredient_id, restaurant_id],
+            )
+            .map_err(|e| format!("Failed to restock: {}", e))?;
+        find_ingredient(&connection, restaurant_id, ingredient_id)
+    }
+
+    fn set_low_stock(
+        &self,
+        restaurant_id: i32,
+        ingredient_id: i32,
+        low_stock: i32,
+    ) -> Result<Option<domain::Ingredient>, String> {
+        let connection = self.database.connect();
+        connection
+            .execute(
+                "update ingredient set low_stock = $1 where id = $2 and restaurant_id = $3;",
+                rusqlite::params![low_stock, ingredient_id, restaurant_id],
+            )
+            .map_err(|e| format!("Failed to set low stock: {}", e))?;
+        find_ingredient(&connection, restaurant_id, ingredient_id)
+    }
+}
+
+impl MenuRepository for SqliteRepository {
+    fn menu(&self, restaurant_id: i32) -> Result<domain::Menu, String> {
+        menu::load(&self.database.connect(), restaurant_id)
+    }
+
+    fn set_availability(
+        &self,
+        restaurant_id: i32,
+        item_id: i32,
+        available: bool,
+        available_at: Option<i64>,
+    ) -> Result<bool, String> {
+        menu::set_availability(
+            &self.database.connect(),
+            restaurant_id,
+            item_id,
+            available,
+            available_at,
+        )
+    }
+
+    fn set_recipe(
+        &self,
+        restaurant_id: i32,
+        item_id: i32,
+        recipe: &[domain::RecipeLine],
+    ) -> Result<bool, String> {
+        menu::set_recipe(&mut self.database.connect(), restaurant_id, item_id, recipe)
+    }
+}
+
+impl StaffRepository for SqliteRepository {
+    fn find(&self, staff_id: &str) -> Result<Option<auth::Staff>, String> {
+        auth::find_staff(&self.database.connect(), staff_id)
+    }
+
+    fn restaurant(&self, restaurant_id: i32) -> Result<Option<domain::Restaurant>, String> {
+        let found = database::find_restaurant(&self.database.connect(), restaurant_id)?;
+        Ok(found.map(|restaurant_data| domain::Restaurant {
+            id: restaurant_data.id,
+            name: restaurant_data.name,
+        }))
+    }
+}
+
+impl IdempotencyRepository for SqliteRepository {
+    fn begin(
+        &self,
+        restaurant_id: i32,
+        key: &str,
+        request: &str,
+        window: &idempotency::Window,
+    ) -> Result<idempotency::Attempt, String> {
+        idempotency::begin(
+            &self.database.connect(),
+            restaurant_id,
+            key,
+            request,
+            window,
+        )
+    }
+
+    fn complete(&self, restaurant_id: i32, key: &str, response: &str) -> Result<(), String> {
+        idempotency::complete(&self.database.connect(), restaurant_id, key, response)
+    }
+
+    fn abandon(&self, restaurant_id: i32, key: &str) {
+        idempotency::abandon(&self.database.connect(), restaurant_id, key)
+    }
+}
+
+impl AuditRepository for SqliteRepository {
+    fn record(&self, change: audit::Change) -> Result<(), String> {
+        audit::record(&self.database.connect(), change)
+    }
+
+    fn search(&self, filter: &audit::Filter) -> Result<Vec<data::AuditEntry>, String> {
+        audit::search(&self.database.connect(), filter)
+    }
+}
+
+impl AllergyRepository for SqliteRepository {
+    fn all(&self, restaurant_id: i32) -> Result<BTreeMap<i32, Vec<domain::Allergy>>, String> {
+        allergy::all(&self.database.connect(), restaurant_id)
+    }
+
+    fn replace(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        allergies: &[domain::Allergy],
+    ) -> Result<(), String> {
+        allergy::replace(
+            &mut self.database.connect(),
+            restaurant_id,
+            table_id,
+            allergies,
+        )
+    }
+}
+
+impl AdjustmentRepository for SqliteRepository {
+    fn adjustments(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+    ) -> Result<Vec<domain::Adjustment>, String> {
+        adjustment::find(&self.database.connect(), restaurant_id, table_id)
+    }
+
+    fn add(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        adjustment: &domain::Adjustment,
+        change: audit::Change,
+    ) -> Result<i32, String> {
+        adjustment::add(
+            &mut self.database.connect(),
+            restaurant_id,
+            table_id,
+            adjustment,
+            change,
+        )
+    }
+
+    fn find_promo_code(
+        &self,
+        restaurant_id: i32,
+        code: &str,
+    ) -> Result<Option<domain::PromoCode>, String> {
+        adjustment::find_promo_code(&self.database.connect(), restaurant_id, code)
+    }
+
+    fn redeem(
+        &self,
+        restaurant_id: i32,
+        table_id: i32,
+        promo_code_id: i32,
+        adjustment: &domain::Adjustment,
+        change: audit::Change,
+    ) -> Result<domain::Redemption, String> {
+        adjustment::redeem(
+            &mut self.database.connect(),
+            restaurant_id,
+            table_id,
+            promo_code_id,
+            adjustment,
+            change,
+        )
+    }
+}
+
+impl KitchenRepository for SqliteRepository {
+    fn stations(&self, restaurant_id: i32) -> Result<Vec<domain::Station>, String> {
+        kitchen::stations(&self.database.connect(), restaurant_id)
+    }
+
+    fn record(
+        &self,
+        restaurant_id: i32,
+        table_id: &str,
+        item: &domain::Item,
+        kind: &str,
+        at: i64,
+    ) -> Result<(), String> {
+        kitchen::record(
+            &self.database.connect(),
+            restaurant_id,
+            table_id,
+            item,
+            kind,
+            at,
+        )
+    }
+
+    fn events(
+        &self,
+        restaurant_id: i32,
+        station_id: i32,
+        after: i64,
+    ) -> Result<Vec<domain::KitchenEvent>, String> {
+        kitchen::events(&self.database.connect(), restaurant_id, station_id, after)
+    }
+}
+
+impl StatusRepository for SqliteRepository {
+    fn readiness(&self) -> data::HealthReport {
+        health::readiness(self.database.open_existing())
+    }
+
+    fn business(&self) -> Result<Vec<metrics::Business>, String> {
+        metrics::business(&self.database.open_existing()?)
+    }
+}
@@ -0,0 +1,2140 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+
+use crate::audit;
+use crate::domain::{self, Error};
+use crate::estimate;
+use crate::repository::{ItemRepository, StockRepository, TableRepository};
+
+/// Makes the audit entries for items put on a table from the id of the table, whether it
+/// was created for them and the items.
+pub type AddChanges<'a> = dyn Fn(i32, bool, &[domain::Item]) -> Vec<audit::Change> + 'a;
+
+fn precondition_failed(table_id: &str) -> Error {
+    Error::PreconditionFailed(format!(
+        "Table {} has been changed by someone else.",
+        table_id
+    ))
+}
+
+fn item_not_on_table(table_id: &str, item_id: &str) -> Error {
+    Error::NotFound(format!("Item {} is not on table {}.", item_id, table_id))
+}
+
+fn course_not_on_table(table_id: &str, course: i32) -> Error {
+    Error::NotFound(format!("Course {} is not on table {}.", course, table_id))
+}
+
+/// Checks the modifiers asked for on an item against its modifier groups and returns them.
+/// Items that are not on the menu can be ordered, but without modifiers.
+pub fn choose_modifiers(
+    menu: &domain::Menu,
+    line: &domain::OrderLine,
+) -> Result<Vec<domain::Modifier>, Error> {
+    let menu_item = match menu.item(&line.name) {
+        Some(menu_item) => menu_item,
+        None if line.modifiers.is_empty() => return Ok(Vec::new()),
+        None => {
+            return Err(Error::Invalid(format!(
+                "{} is not on the menu so it has no modifiers.",
+                line.name
+            )))
+        }
+    };
+    let mut chosen = Vec::new();
+    for name in &line.modifiers {
+        let modifier = menu_item
+            .modifier_groups
+            .iter()
+            .flat_map(|group| group.modifiers.iter())
+            .find(|modifier| &modifier.name == name);
+        match modifier {
+            Some(modifier) => chosen.push(modifier.clone()),
+            None => {
+                return Err(Error::Invalid(format!(
+                    "{} is not a modifier of {}.",
+                    name, line.name
+                )))
+            }
+        }
+    }
+    for group in &menu_item.modifier_groups {
+        let count = chosen
+            .iter()
+            .filter(|modifier| group.modifiers.contains(modifier))
+            .count() as i32;
+        if count < group.min_selected {
+            return Err(Error::Invalid(format!(
+                "{} needs at least {} from {}.",
+                line.name, group.min_selected, group.name
+            )));
+        }
+        if count > group.max_selected {
+            return Err(Error::Invalid(format!(
+                "{} takes at most {} from {}.",
+                line.name, group.max_selected, group.name
+            )));
+        }
+    }
+    Ok(chosen)
+}
+
+/// Refuses an item that has run out, or a bundle with a component that has. Items that are
+/// not on the menu are always available.
+fn check_available(menu: &domain::Menu, name: &str) -> Result<(), Error> {
+    let menu_item = match menu.item(name) {
+        Some(menu_item) => menu_item,
+        None => return Ok(()),
+    };
+    let components = menu_item
+        .components
+        .iter()
+        .filter_map(|component| menu.item(component));
+    for item in std::iter::once(menu_item).chain(components) {
+        match item.availability {
+            domain::Availability::Available => {}
+            domain::Availability::Unavailable { until: None } => {
+                return Err(Error::Conflict(format!("{} is not available.", item.name)))
+            }
+            domain::Availability::Unavailable { until: Some(until) } => {
+                return Err(Error::Conflict(format!(
+                    "{} is not available until {}.",
+                    item.name, until
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A warning for each allergy at the table that the item with this name has the allergen
+/// of, for the kitchen. Items that are not on the menu have no allergens.
+pub fn allergy_warnings(
+    menu: &domain::Menu,
+    allergies: &[domain::Allergy],
+    name: &str,
+) -> Vec<String> {
+    let allergens = match menu.item(name) {
+        Some(menu_item) => &menu_item.allergens,
+        None => return Vec::new(),
+    };
+    allergies
+        .iter()
+        .filter(|allergy| {
+            allergens
+                .iter()
+                .any(|allergen| allergen.eq_ignore_ascii_case(&allergy.allergen))
+        })
+        .map(|allergy| match allergy.seat {
+            Some(seat) => format!(
+                "{} contains {}, seat {} is allergic to it.",
+                name, allergy.allergen, seat
+            ),
+            None => format!(
+                "{} contains {}, someone at the table is allergic to it.",
+                name, allergy.allergen
+            ),
+        })
+        .collect()
+}
+
+/// Refuses an order with an item someone at the table is allergic to.
+pub fn check_allergies(
+    menu: &domain::Menu,
+    allergies: &[domain::Allergy],
+    lines: &[domain::OrderLine],
+) -> Result<(), Error> {
+    let conflict = lines
+        .iter()
+        .flat_map(|line| allergy_warnings(menu, allergies, &line.name))
+        .next();
+    match conflict {
+        Some(warning) => Err(Error::Conflict(warning)),
+        None => Ok(()),
+    }
+}
+
+/// Takes what a recipe uses out of stock, or says which ingredients there is not enough of.
+/// Ingredients left running low are logged.
+fn take_stock(
+    stock: &dyn StockRepository,
+    restaurant_id: i32,
+    recipe: &[domain::RecipeLine],
+) -> Result<(), Error> {
+    if recipe.is_empty() {
+        return Ok(());
+    }
+    let mut needed: BTreeMap<i32, i32> = BTreeMap::new();
+    for line in recipe {
+        *needed.entry(line.ingredient_id).or_default() += line.quantity;
+    }
+    if !stock.take(restaurant_id, recipe)? {
+        let short = stock
+            .ingredients(restaurant_id)?
+            .into_iter()
+            .filter(|ingredient| {
+                needed
+                    .get(&ingredient.id)
+                    .map_or(false, |quantity| ingredient.stock < *quantity)
+            })
+            .map(|ingredient| ingredient.name)
+            .collect::<Vec<String>>();
+        return Err(Error::Conflict(format!(
+            "There is not enough {} to make it.",
+            short.join(", ")
+        )));
+    }
+    for ingredient in stock.ingredients(restaurant_id)? {
+        if needed.contains_key(&ingredient.id) && ingredient.is_low() {
+            warn!(
+                "{} is running low, {} left.",
+                ingredient.name, ingredient.stock
+            );
+        }
+    }
+    Ok(())
+}
+
+fn give_back_stock(stock: &dyn StockRepository, restaurant_id: i32, recipe: &[domain::RecipeLine]) {
+    if let Err(e) = stock.give_back(restaurant_id, recipe) {
+        warn!("Failed to give back stock {:?}: {}", recipe, e);
+    }
+}
+
+/// Orders new items and puts them on a table. Nothing is ordered unless the modifiers of
+/// every line are valid, every item is available and there is the stock to make all of
+/// them, the stock is given back and the items are deleted again if they could not all be
+/// put on the table. Items that are not held are fired at `ordered_at`. Returns the id of
+/// the table they were put on, whether it was created for them and the items. What
+/// `changes` makes of the same is recorded in the audit log with the items on the table.
+#[allow(clippy::too_many_arguments)]
+pub fn add_items(
+    tables: &dyn TableRepository,
+    items: &dyn ItemRepository,
+    stock: &dyn StockRepository,
+    menu: &domain::Menu,
+    restaurant_id: i32,
+    table_id: &str,
+    lines: &[domain::OrderLine],
+    ordered_at: i64,
+    expected_version: Option<i64>,
+    changes: &AddChanges,
+) -> Result<(i32, bool, Vec<domain::Item>), Error> {
+    if lines.iter().any(|line| line.course < 1) {
+        return Err(Error::Invalid("Courses start at 1.".into()));
+    }
+    let modifiers = lines
+        .iter()
+        .map(|line| choose_modifiers(menu, line))
+        .collect::<Result<Vec<_>, Error>>()?;
+    for line in lines {
+        check_available(menu, &line.name)?;
+    }
+    let recipe = lines
+        .iter()
+        .flat_map(|line| menu.recipe(&line.name))
+        .collect::<Vec<domain::RecipeLine>>();
+    let preparations = items.preparations(restaurant_id, ordered_at - estimate::HISTORY_SECONDS)?;
+    let estimator = estimate::Estimator::new(&preparations, ordered_at);
+    take_stock(stock, restaurant_id, &recipe)?;
+    let mut new_items = Vec::new();
+    let mut ordered = Ok(());
+    for (line, modifiers) in lines.iter().zip(modifiers) {
+        match order_item(
+            items,
+            menu,
+            &estimator,
+            restaurant_id,
+            line,
+            modifiers,
+            ordered_at,
+        ) {
+            Ok(item) => new_items.push(item),
+            Err(e) => {
+                ordered = Err(e);
+                break;
+            }
+        }
+    }
+    let added = ordered.and_then(|_| {
+        let item_ids = new_items
+            .iter()
+            .map(|item| item.id.to_string())
+            .collect::<Vec<String>>();
+        add_to_table(
+            tables,
+            restaurant_id,
+            table_id,
+            &item_ids,
+            expected_version,
+            &|id, created| changes(id, created, &new_items),
+        )
+    });
+    match added {
+        Ok((table_id, created)) => Ok((table_id, created, new_items)),
+        Err(e) => {
+            for item in new_items.iter() {
+                if let Err(e) = items.delete(restaurant_id, item.id) {
+                    warn!("Failed to delete unused item {}: {}", item.id, e);
+                }
+            }
+            give_back_stock(stock, restaurant_id, &recipe);
+            Err(e)
+        }
+    }
+}
+
+/// Creates one ordered item, priced from the menu with its modifiers, items that are not on
+/// the menu cost nothing. It is estimated to take as long as it took lately at its station,
+/// or 5 to 15 minutes until it was made often enough to tell. A bundle gets a component for
+/// each item in it, the components cost nothing and the bundle takes as long as the slowest
+/// of them. Items in the first course go to the kitchen at `ordered_at`, later ones are
+/// held. Each is sent to the station of its menu item.
+fn order_item(
+    items: &dyn ItemRepository,
+    menu: &domain::Menu,
+    estimator: &estimate::Estimator,
+    restaurant_id: i32,
+    line: &domain::OrderLine,
+    modifiers: Vec<domain::Modifier>,
+    ordered_at: i64,
+) -> Result<domain::Item, Error> {
+    let mut random = rand::thread_rng();
+    let mut prep_minutes = |name: &str| {
+        estimator
+            .estimate(name, menu.station(name))
+            .unwrap_or_else(|| random.gen_range(5..=15))
+    };
+    let menu_item = menu.item(&line.name);
+    let components = menu_item.map_or(&[][..], |menu_item| &menu_item.components[..]);
+    let times = components
+        .iter()
+        .map(|name| prep_minutes(name))
+        .collect::<Vec<i32>>();
+    let time_to_prepare = match times.iter().max() {
+        Some(slowest) => *slowest,
+        None => prep_minutes(&line.name),
+    };
+    let price = menu_item.map_or(0, |menu_item| menu_item.price)
+        + modifiers
+            .iter()
+            .map(|modifier| modifier.price_delta)
+            .sum::<i32>();
+    let held = line.course > 1;
+    let fired_at = if held { None } else { Some(ordered_at) };
+    let mut item = items.create(
+        restaurant_id,
+        &domain::NewItem {
+            name: line.name.clone(),
+            time_to_prepare,
+            price,
+            modifiers,
+            bundle_id: None,
+            course: line.course,
+            held,
+            fired_at,
+            station_id: menu.station(&line.name),
+            recipe: menu_item.map_or(Vec::new(), |menu_item| menu_item.recipe.clone()),
+        },
+    )?;
+    for (name, time_to_prepare) in components.iter().zip(times) {
+        let component = domain::NewItem {
+            name: name.clone(),
+            time_to_prepare,
+            price: 0,
+            modifiers: Vec::new(),
+            bundle_id: Some(item.id),
+            course: line.course,
+            held,
+            fired_at,
+            station_id: menu.station(name),
+            recipe: menu
+                .item(name)
+                .map_or(Vec::new(), |menu_item| menu_item.recipe.clone()),
+        };
+        match items.create(restaurant_id, &component) {
+            Ok(component) => item.components.push(component),
+            Err(e) => {
+                if let Err(e) = items.delete(restaurant_id, item.id) {
+                    warn!("Failed to delete unused item {}: {}", item.id, e);
+                }
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(item)
+}
+
+/// Appends items to a table, creating it if the restaurant has no such table, and returns
+/// the id of the table and whether it was created. What `changes` makes of the same is
+/// recorded in the audit log in the same write. Without an `expected_version` a write that
+/// races with another one is retried so neither loses its items.
+pub fn add_to_table(
+    tables: &dyn TableRepository,
+    restaurant_id: i32,
+    table_id: &str,
+    item_ids: &[String],
+    expected_version: Option<i64>,
+    changes: &dyn Fn(i32, bool) -> Vec<audit::Change>,
+) -> Result<(i32, bool), Error> {
+    loop {
+        let table = match tables.find(restaurant_id, table_id)? {
+            Some(table) => table,
+            None if expected_version.is_some() => return Err(precondition_failed(table_id)),
+            None => {
+                let created = tables.create(restaurant_id, item_ids, &|id| changes(id, true))?;
+                info!("Created table {} for table {}.", created, table_id);
+                return Ok((created, true));
+            }
+        };
+        if expected_version.map_or(false, |version| version != table.version) {
+            return Err(precondition_failed(table_id));
+        }
+        let appended = tables.append_items(
+            restaurant_id,
+            table_id,
+            item_ids,
+            table.version,
+            changes(table.id, false),
+        )?;
+        if appended {
+            return Ok((table.id, false));
+        }
+        if expected_version.is_some() {
+            return Err(precondition_failed(table_id));
+        }
+        info!(
+            "Table {} changed while adding items, trying again.",
+            table_id
+        );
+    }
+}
+
+/// Takes an item off a table and keeps it as voided, returning the item as it was ordered.
+/// What `change` makes of the table's id and the item is recorded in the audit log in the
+/// same write. What it used from stock when it was ordered is put back. Items on a settled
+/// bill can only be voided when `may_void_paid` is set.
+#[allow(clippy::too_many_arguments)]
+pub fn void_item(
+    tables: &dyn TableRepository,
+    items: &dyn ItemRepository,
+    stock: &dyn StockRepository,
+    restaurant_id: i32,
+    table_id: &str,
+    item_id: &str,
+    void: &domain::Void,
+    expected_version: Option<i64>,
+    may_void_paid: bool,
+    change: &dyn Fn(i32, &domain::Item) -> audit::Change,
+) -> Result<domain::Item, Error> {
+    if void.reason.trim().is_empty() {
+        return Err(Error::Invalid(
+            "A reason is required to void an item.".into(),
+        ));
+    }
+    let (removed, recipe) = loop {
+        let table = match tables.find(restaurant_id, table_id)? {
+            Some(table) => table,
+            None => return Err(item_not_on_table(table_id, item_id)),
+        };
+        debug!("Found table {:?}", table);
+        if expected_version.map_or(false, |version| version != table.version) {
+            return Err(precondition_failed(table_id));
+        }
+        let item = match table.ordered_item(item_id) {
+            Some(item) => item.clone(),
+            None => return Err(item_not_on_table(table_id, item_id)),
+        };
+        if table.paid_at.is_some() && !may_void_paid {
+            return Err(Error::Forbidden(format!(
+                "The bill of table {} is settled, its items cannot be voided.",
+                table_id
+            )));
+        }
+        let recipe = items.recipe(restaurant_id, item.id)?;
+        let voided = tables.void_item(
+            restaurant_id,
+            table_id,
+            item.id,
+            void,
+            table.version,
+            change(table.id, &item),
+        )?;
+        if voided {
+            break (item, recipe);
+        }
+        if expected_version.is_some() {
+            return Err(precondition_failed(table_id));
+        }
+        info!(
+            "Table {} changed while removing items, trying again.",
+            table_id
+        );
+    };
+    give_back_stock(stock, restaurant_id, &recipe);
+    info!("Updated table {} and removed item {}.", table_id, item_id);
+    Ok(removed)
+}
+
+/// Puts a voided item back on the table it was voided from, returning the item and the
+/// void that was undone. What `change` makes of the table's id, the item and the void is
+/// recorded in the audit log in the same write. What it used from stock when it was ordered
+/// is taken out again, so it cannot be restored when there is not enough left.
+#[allow(clippy::too_many_arguments)]
+pub fn restore_item(
+    tables: &dyn TableRepository,
+    items: &dyn ItemRepository,
+    stock: &dyn StockRepository,
+    restaurant_id: i32,
+    table_id: &str,
+    item_id: &str,
+    expected_version: Option<i64>,
+    change: &dyn Fn(i32, &domain::Item, &domain::Void) -> audit::Change,
+) -> Result<(domain::Item, domain::Void), Error> {
+    let mut item = match items.find_voided(restaurant_id, item_id, table_id)? {
+        Some(item) => item,
+        None => {
+            return Err(Error::NotFound(format!(
+                "Item {} is not voided on table {}.",
+                item_id, table_id
+            )))
+        }
+    };
+    let void = match item.status {
+        domain::ItemStatus::Voided(void) => void,
+        domain::ItemStatus::Ordered => {
+            return Err(Error::Internal(format!("Item {} is not voided.", item_id)))
+        }
+    };
+    item.status = domain::ItemStatus::Ordered;
+    let recipe = items.recipe(restaurant_id, item.id)?;
+    take_stock(stock, restaurant_id, &recipe)?;
+    let restored = put_back(
+        tables,
+        items,
+        restaurant_id,
+        table_id,
+        item_id,
+        item.id,
+        expected_version,
+        &|id| change(id, &item, &void),
+    );
+    if let Err(e) = restored {
+        give_back_stock(stock, restaurant_id, &recipe);
+        return Err(e);
+    }
+    info!("Restored item {} to table {}.", item_id, table_id);
+    Ok((item, void))
+}
+
+/// Puts a voided item back on its table and forgets the void, retrying a write that races
+/// with another one unless there is an `expected_version`.
+#[allow(clippy::too_many_arguments)]
+fn put_back(
+    tables: &dyn TableRepository,
+    items: &dyn ItemRepository,
+    restaurant_id: i32,
+    table_id: &str,
+    item_id: &str,
+    id: i32,
+    expected_version: Option<i64>,
+    change: &dyn Fn(i32) -> audit::Change,
+) -> Result<(), Error> {
+    let not_voided = || {
+        Error::NotFound(format!(
+            "Item {} is not voided on table {}.",
+            item_id, table_id
+        ))
+    };
+    loop {
+        let table = match tables.find(restaurant_id, table_id)? {
+            Some(table) => table,
+            None => return Err(not_voided()),
+        };
+        if expected_version.map_or(false, |version| version != table.version) {
+            return Err(precondition_failed(table_id));
+        }
+        let restored =
+            tables.restore_item(restaurant_id, table_id, id, table.version, change(table.id))?;
+        if restored {
+            return Ok(());
+        }
+        if expected_version.is_some() {
+            return Err(precondition_failed(table_id));
+        }
+        if items
+            .find_voided(restaurant_id, item_id, table_id)?
+            .is_none()
+        {
+            return Err(not_voided());
+        }
+        info!(
+            "Table {} changed while restoring items, trying again.",
+            table_id
+        );
+    }
+}
+
+/// Sends the held items of a course on a table to the kitchen, their preparation starts
+/// at `fired_at`. Returns the items of the course as they are now, what `changes` makes of
+/// the table's id and those items is recorded in the audit log in the same write. Without
+/// an `expected_version` a write that races with another one is retried.
+pub fn fire_course(
+    tables: &dyn TableRepository,
+    restaurant_id: i32,
+    table_id: &str,
+    course: i32,
+    fired_at: i64,
+    expected_version: Option<i64>,
+    changes: &dyn Fn(i32, &[domain::Item]) -> Vec<audit::Change>,
+) -> Result<Vec<domain::Item>, Error> {
+    loop {
+        let table = match tables.find(restaurant_id, table_id)? {
+            Some(table) => table,
+            None => return Err(course_not_on_table(table_id, course)),
+        };
+        if expected_version.map_or(false, |version| version != table.version) {
+            return Err(precondition_failed(table_id));
+        }
+        let in_course = table
+            .ordered_items()
+            .filter(|item| item.course == course)
+            .collect::<Vec<&domain::Item>>();
+        if in_course.is_empty() {
+            return Err(course_not_on_table(table_id, course));
+        }
+        let held = in_course
+            .iter()
+            .filter(|item| item.held)
+            .map(|item| item.id)
+            .collect::<Vec<i32>>();
+        if held.is_empty() {
+            return Err(Error::Conflict(format!(
+                "Course {} of table {} has already been fired.",
+                course, table_id
+            )));
+        }
+        let fired = in_course
+            .into_iter()
+            .cloned()
+            .map(|mut item| {
+                if item.held {
+                    item.held = false;
+                    item.fired_at = Some(fired_at);
+                    for component in item.components.iter_mut() {
+                        component.held = false;
+                        component.fired_at = Some(fired_at);
+                    }
+                }
+                item
+            })
+            .collect::<Vec<domain::Item>>();
+        let changes = changes(table.id, &fired);
+        if tables.fire(
+            restaurant_id,
+            table_id,
+            &held,
+            fired_at,
+            table.version,
+            changes,
+        )? {
+            info!("Fired course {} of table {}.", course, table_id);
+            return Ok(fired);
+        }
+        if expected_version.is_some() {
+            return Err(precondition_failed(table_id));
+        }
+        info!(
+            "Table {} changed while firing a course, trying again.",
+            table_id
+        );
+    }
+}
+
+/// Marks something the kitchen was sent for a table as ready at `ready_at`, a bundle is
+/// ready once each of its components is. What `change` makes of the ticket once it is ready
+/// is recorded in the audit log in the same write. Returns the table as it is now.
+pub fn mark_ready(
+    tables: &dyn TableRepository,
+    restaurant_id: i32,
+    table_id: &str,
+    item_id: &str,
+    ready_at: i64,
+    change: &dyn Fn(&domain::Ticket) -> audit::Change,
+) -> Result<domain::Table, Error> {
+    loop {
+        let table = match tables.find(restaurant_id, table_id)? {
+            Some(table) => table,
+            None => return Err(item_not_on_table(table_id, item_id)),
+        };
+        let held = table
+            .ordered_items()
+            .filter(|item| item.held)
+            .flat_map(|item| item.kitchen_items())
+            .any(|item| item.id.to_string() == item_id);
+        if held {
+            return Err(Error::Conflict(format!(
+                "Item {} has not been fired yet.",
+                item_id
+            )));
+        }
+        let tickets = table.tickets();
+        let ticket = match tickets
+            .iter()
+            .find(|ticket| ticket.item.id.to_string() == item_id)
+        {
+            Some(ticket) => ticket,
+            None => return Err(item_not_on_table(table_id, item_id)),
+        };
+        if ticket.item.ready_at.is_some() {
+            return Err(Error::Conflict(format!(
+                "Item {} is already ready.",
+                item_id
+            )));
+        }
+        let mut readied = ticket.item.clone();
+        readied.ready_at = Some(ready_at);
+        let change = change(&domain::Ticket {
+            item: &readied,
+            ..*ticket
+        });
+        if tables.ready(
+            restaurant_id,
+            table_id,
+            ticket.item.id,
+            ready_at,
+            table.version,
+            change,
+        )? {
+            break;
+        }
+        info!(
+            "Table {} changed while marking an item ready, trying again.",
+            table_id
+        );
+    }
+    info!("Item {} of table {} is ready.", item_id, table_id);
+    match tables.find(restaurant_id, table_id)? {
+        Some(table) => Ok(table),
+        None => Err(item_not_on_table(table_id, item_id)),
+    }
+}
+
+/// Settles a table's bill at `paid_at` and returns the table as it is then. A bill is only
+/// settled once.
+pub fn settle_bill(
+    tables: &dyn TableRepository,
+    restaurant_id: i32,
+    table_id: &str,
+    paid_at: i64,
+) -> Result<domain::Table, Error> {
+    loop {
+        let table = match tables.find(restaurant_id, table_id)? {
+            Some(table) => table,
+            None => return Err(Error::NotFound(format!("There is no table {}.", table_id))),
+        };
+        if table.paid_at.is_some() {
+            return Err(Error::Conflict(format!(
+                "The bill of table {} is already settled.",
+                table_id
+            )));
+        }
+        if tables.settle(restaurant_id, table_id, paid_at, table.version)? {
+            info!("Settled the bill of table {}.", table_id);
+            return Ok(domain::Table {
+                version: table.version + 1,
+                paid_at: Some(paid_at),
+                ..table
+            });
+        }
+        info!(
+            "Table {} changed while settling its bill, trying again.",
+            table_id
+        );
+    }
+}
+
+/// Refuses an adjustment for an item that is not on the table.
+pub fn check_target(table: &domain::Table, target: &domain::Target) -> Result<(), Error> {
+    if let domain::Target::Item(item_id) = target {
+        let item_id = item_id.to_string();
+        if table.ordered_item(&item_id).is_none() {
+            return Err(item_not_on_table(&table.id.to_string(), &item_id));
+        }
+    }
+    Ok(())
+}
+
+/// Refuses a promo code that has expired, has been used up or is already on the table.
+pub fn check_promo_code(
+    promo_code: &domain::PromoCode,
+    adjustments: &[domain::Adjustment],
+    now: i64,
+) -> Result<(), Error> {
+    let expired = promo_code
+        .expires_at
+        .map_or(false, |expires_at| expires_at <= now);
+    if expired {
+        return Err(Error::Conflict(format!(
+            "Promo code {} has expired.",
+            promo_code.code
+        )));
+    }
+    let used_up = promo_code
+        .max_uses
+        .map_or(false, |max_uses| promo_code.uses >= max_uses);
+    if used_up {
+        return Err(Error::Conflict(format!(
+            "Promo code {} has been used up.",
+            promo_code.code
+        )));
+    }
+    let redeemed = adjustments
+        .iter()
+        .any(|adjustment| adjustment.code.as_ref() == Some(&promo_code.code));
+    if redeemed {
+        return Err(Error::Conflict(format!(
+            "Promo code {} is already on the bill.",
+            promo_code.code
+        )));
+    }
+    Ok(())
+}
+
+/// Refuses a recipe with an ingredient the restaurant does not have, one that is listed
+/// twice or a quantity that is not more than nothing.
+pub fn check_recipe(
+    recipe: &[domain::RecipeLine],
+    ingredients: &[domain::Ingredient],
+) -> Result<(), Error> {
+    for (index, line) in recipe.iter().enumerate() {
+        if !ingredients
+            .iter()
+            .any(|ingredient| ingredient.id == line.ingredient_id)
+        {
+            return Err(Error::Invalid(format!(
+                "There is no ingredient {}.",
+                line.ingredient_id
+            )));
+        }
+        if line.quantity <= 0 {
+            return Err(Error::Invalid(format!(
+                "A recipe needs more than nothing of ingredient {}.",
+                line.ingredient_id
+            )));
+        }
+        if recipe[..index]
+            .iter()
+            .any(|before| before.ingredient_id == line.ingredient_id)
+        {
+            return Err(Error::Invalid(format!(
+                "Ingredient {} is in the recipe more than once.",
+                line.ingredient_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// How much each adjustment takes off a table's bill, in the order they were added. Each
+/// one takes off what the ones before it left of its lines, so a line never costs less
+/// than nothing.
+pub fn adjust(table: &domain::Table, adjustments: &[domain::Adjustment]) -> Vec<i32> {
+    let items = table.ordered_items().collect::<Vec<&domain::Item>>();
+    let mut left = items.iter().map(|item| item.price).collect::<Vec<i32>>();
+    let mut amounts = Vec::new();
+    for adjustment in adjustments {
+        let mut lines = (0..items.len())
+            .filter(|line| match &adjustment.target {
+                domain::Target::Bill => true,
+                domain::Target::Item(item_id) => items[*line].id == *item_id,
+                domain::Target::Named(name) => items[*line].name == *name,
+            })
+            .collect::<Vec<usize>>();
+        let mut off = vec![0; lines.len()];
+        match adjustment.discount {
+            domain::Discount::Percentage(percent) => {
+                for (off, line) in off.iter_mut().zip(&lines) {
+                    *off = left[*line] * percent / 100;
+                }
+            }
+            domain::Discount::Amount(mut cents) => {
+                for (off, line) in off.iter_mut().zip(&lines) {
+                    *off = left[*line].min(cents);
+                    cents -= *off;
+                }
+            }
+            domain::Discount::BuyGet { buy, get } => {
+                lines.sort_by_key(|line| std::cmp::Reverse(items[*line].price));
+                let group = (buy + get) as usize;
+                for start in (0..lines.len() / group).map(|group_index| group_index * group) {
+                    for free in start + buy as usize..start + group {
+                        off[free] = left[lines[free]];
+                    }
+                }
+            }
+            domain::Discount::Comp => {
+                for (off, line) in off.iter_mut().zip(&lines) {
+                    *off = left[*line];
+                }
+            }
+        }
+        for (off, line) in off.iter().zip(&lines) {
+            left[*line] -= off;
+        }
+        amounts.push(off.iter().sum());
+    }
+    amounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::memory::MemoryRepository;
+    use proptest::prelude::*;
+
+    const RESTAURANT: i32 = 1;
+    const OTHER_RESTAURANT: i32 = 2;
+    const TABLES: usize = 3;
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        /// Orders this many pizzas on a table.
+        Add(usize, usize),
+        /// Voids from a table one of the items ordered so far, on whichever table.
+        Void(usize, usize),
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            (0..TABLES, 1..4usize).prop_map(|(table, count)| Operation::Add(table, count)),
+            (0..TABLES, 0..64usize).prop_map(|(table, pick)| Operation::Void(table, pick)),
+        ]
+    }
+
+    fn void(reason: &str) -> domain::Void {
+        domain::Void {
+            reason: String::from(reason),
+            voided_by: 9002,
+            voided_at: 1700000000,
+        }
+    }
+
+    fn lines(names: &[&str]) -> Vec<domain::OrderLine> {
+        names
+            .iter()
+            .map(|name| domain::OrderLine {
+                name: name.to_string(),
+                modifiers: Vec::new(),
+                course: 1,
+            })
+            .collect()
+    }
+
+    fn pizza() -> domain::NewItem {
+        domain::NewItem {
+            name: String::from("pizza"),
+            time_to_prepare: 5,
+            price: 1100,
+            modifiers: Vec::new(),
+            bundle_id: None,
+            course: 1,
+            held: false,
+            fired_at: Some(1700000000),
+            station_id: None,
+            recipe: Vec::new(),
+        }
+    }
+
+    fn modifier(id: i32, name: &str, price_delta: i32) -> domain::Modifier {
+        domain::Modifier {
+            id,
+            name: String::from(name),
+            price_delta,
+        }
+    }
+
+    fn menu_item(
+        id: i32,
+        name: &str,
+        price: i32,
+        modifier_groups: Vec<domain::ModifierGroup>,
+        components: &[&str],
+    ) -> domain::MenuItem {
+        domain::MenuItem {
+            id,
+            name: String::from(name),
+            price,
+            regular_price: price,
+            modifier_groups,
+            components: components.iter().map(|name| name.to_string()).collect(),
+            recipe: Vec::new(),
+            availability: domain::Availability::Available,
+            allergens: Vec::new(),
+            dietary: Vec::new(),
+            station_id: None,
+        }
+    }
+
+    /// A burger that needs a cooking temperature and takes up to two extras, and a lunch
+    /// bundle of fries and a drink.
+    fn menu() -> domain::Menu {
+        let burger = menu_item(
+            1,
+            "burger",
+            1200,
+            vec![
+                domain::ModifierGroup {
+                    id: 1,
+                    name: String::from("Cooking"),
+                    min_selected: 1,
+                    max_selected: 1,
+                    modifiers: vec![modifier(1, "rare", 0), modifier(2, "medium", 0)],
+                },
+                domain::ModifierGroup {
+                    id: 2,
+                    name: String::from("Extras"),
+                    min_selected: 0,
+                    max_selected: 2,
+                    modifiers: vec![modifier(3, "bacon", 200), modifier(4, "cheese", 100)],
+                },
+            ],
+            &[],
+        );
+        domain::Menu {
+            categories: vec![domain::Category {
+                id: 1,
+                name: String::from("Mains"),
+                schedules: Vec::new(),
+                items: vec![
+                    burger,
+                    menu_item(2, "fries", 400, Vec::new(), &[]),
+                    menu_item(3, "soda", 300, Vec::new(), &[]),
+                    menu_item(4, "lunch", 600, Vec::new(), &["fries", "soda"]),
+                ],
+            }],
+        }
+    }
+
+    fn burger(modifiers: &[&str]) -> domain::OrderLine {
+        domain::OrderLine {
+            name: String::from("burger"),
+            modifiers: modifiers.iter().map(|name| name.to_string()).collect(),
+            course: 1,
+        }
+    }
+
+    /// A change to hand the memory repository, which has no audit log to record it in.
+    fn change() -> audit::Change {
+        audit::Change {
+            restaurant_id: RESTAURANT,
+            actor_id: 1,
+            action: "test",
+            table_id: None,
+            item_id: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    fn ordered_ids(repository: &MemoryRepository, table_id: &str) -> Vec<i32> {
+        repository
+            .find(RESTAURANT, table_id)
+            .unwrap()
+            .unwrap()
+            .ordered_items()
+            .map(|item| item.id)
+            .collect()
+    }
+
+    #[test]
+    fn should_add_items_to_existing_table() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let menu = domain::Menu::default();
+        let names = lines(&["pizza", "cake"]);
+        let (_, _, added) = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &names,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(added.len(), 2);
+        assert!(added
+            .iter()
+            .all(|item| (5..=15).contains(&item.time_to_prepare)));
+        assert_eq!(
+            ordered_ids(&repository, "3"),
+            vec![added[0].id, added[1].id]
+        );
+        assert_eq!(
+            repository.find(RESTAURANT, "3").unwrap().unwrap().version,
+            2
+        );
+    }
+
+    #[test]
+    fn should_refuse_stale_version() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let menu = domain::Menu::default();
+        let names = lines(&["pizza"]);
+        let result = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &names,
+            crate::audit::now(),
+            Some(7),
+            &|_, _, _| Vec::new(),
+        );
+        assert!(matches!(result, Err(Error::PreconditionFailed(_))));
+        assert!(ordered_ids(&repository, "3").is_empty());
+        assert_eq!(
+            repository.find(RESTAURANT, "3").unwrap().unwrap().version,
+            1
+        );
+    }
+
+    #[test]
+    fn should_void_and_restore_item() {
+        let repository = MemoryRepository::default();
+        let pizza = ItemRepository::create(&repository, RESTAURANT, &pizza()).unwrap();
+        repository.insert_table(RESTAURANT, 3, &[pizza.id]);
+        let item_id = pizza.id.to_string();
+        let voided = void_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &item_id,
+            &void("cold"),
+            None,
+            false,
+            &|_, _| change(),
+        );
+        assert_eq!(voided.unwrap(), pizza);
+        assert!(ordered_ids(&repository, "3").is_empty());
+        let (restored, undone) = restore_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &item_id,
+            None,
+            &|_, _, _| change(),
+        )
+        .unwrap();
+        assert_eq!(restored, pizza);
+        assert_eq!(undone.reason, "cold");
+        assert_eq!(ordered_ids(&repository, "3"), vec![pizza.id]);
+    }
+
+    #[test]
+    fn should_not_void_without_reason_or_from_another_table() {
+        let repository = MemoryRepository::default();
+        let pizza = ItemRepository::create(&repository, RESTAURANT, &pizza()).unwrap();
+        repository.insert_table(RESTAURANT, 3, &[pizza.id]);
+        repository.insert_table(RESTAURANT, 4, &[]);
+        let item_id = pizza.id.to_string();
+        let blank = void_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &item_id,
+            &void(" "),
+            None,
+            false,
+            &|_, _| change(),
+        );
+        assert!(matches!(blank, Err(Error::Invalid(_))));
+        let elsewhere = void_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "4",
+            &item_id,
+            &void("cold"),
+            None,
+            false,
+            &|_, _| change(),
+        );
+        assert!(matches!(elsewhere, Err(Error::NotFound(_))));
+        let restored = restore_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &item_id,
+            None,
+            &|_, _, _| change(),
+        );
+        assert!(matches!(restored, Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn should_only_void_from_settled_bill_when_allowed() {
+        let repository = MemoryRepository::default();
+        let pizza = ItemRepository::create(&repository, RESTAURANT, &pizza()).unwrap();
+        repository.insert_table(RESTAURANT, 3, &[pizza.id]);
+        let settled = settle_bill(&repository, RESTAURANT, "3", 1700000000).unwrap();
+        assert_eq!((settled.version, settled.paid_at), (2, Some(1700000000)));
+        let again = settle_bill(&repository, RESTAURANT, "3", 1700000060);
+        assert!(matches!(again, Err(Error::Conflict(_))));
+        let missing = settle_bill(&repository, RESTAURANT, "4", 1700000000);
+        assert!(matches!(missing, Err(Error::NotFound(_))));
+        let item_id = pizza.id.to_string();
+        let void_as = |may_void_paid: bool| {
+            void_item(
+                &repository,
+                &repository,
+                &repository,
+                RESTAURANT,
+                "3",
+                &item_id,
+                &void("cold"),
+                None,
+                may_void_paid,
+                &|_, _| change(),
+            )
+        };
+        assert!(matches!(void_as(false), Err(Error::Forbidden(_))));
+        assert_eq!(ordered_ids(&repository, "3"), vec![pizza.id]);
+        assert_eq!(void_as(true).unwrap(), pizza);
+        assert!(ordered_ids(&repository, "3").is_empty());
+    }
+
+    #[test]
+    fn should_not_touch_tables_of_another_restaurant() {
+        let repository = MemoryRepository::default();
+        let pizza = ItemRepository::create(&repository, RESTAURANT, &pizza()).unwrap();
+        repository.insert_table(RESTAURANT, 3, &[pizza.id]);
+        let item_id = pizza.id.to_string();
+        assert!(repository.find(OTHER_RESTAURANT, "3").unwrap().is_none());
+        let voided = void_item(
+            &repository,
+            &repository,
+            &repository,
+            OTHER_RESTAURANT,
+            "3",
+            &item_id,
+            &void("cold"),
+            None,
+            false,
+            &|_, _| change(),
+        );
+        assert!(matches!(voided, Err(Error::NotFound(_))));
+        let names = lines(&["cake"]);
+        add_items(
+            &repository,
+            &repository,
+            &repository,
+            &domain::Menu::default(),
+            OTHER_RESTAURANT,
+            "3",
+            &names,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(ordered_ids(&repository, "3"), vec![pizza.id]);
+        assert_eq!(
+            repository.find(RESTAURANT, "3").unwrap().unwrap().version,
+            1
+        );
+        assert_eq!(repository.all(OTHER_RESTAURANT).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn should_keep_chosen_modifiers_on_ordered_item() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let order = vec![burger(&["medium", "bacon"])];
+        let (_, _, added) = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu(),
+            RESTAURANT,
+            "3",
+            &order,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        let expected = vec![modifier(2, "medium", 0), modifier(3, "bacon", 200)];
+        assert_eq!(added[0].modifiers, expected);
+        let table = repository.find(RESTAURANT, "3").unwrap().unwrap();
+        assert_eq!(table.items[0].modifiers, expected);
+    }
+
+    #[test]
+    fn should_check_modifiers_against_groups() {
+        let menu = menu();
+        let rejected = [
+            burger(&[]),
+            burger(&["rare", "medium"]),
+            burger(&["rare", "bacon", "cheese", "bacon"]),
+            burger(&["well done"]),
+            domain::OrderLine {
+                name: String::from("pizza"),
+                modifiers: vec![String::from("bacon")],
+                course: 1,
+            },
+        ];
+        for line in rejected.iter() {
+            let chosen = choose_modifiers(&menu, line);
+            assert!(matches!(chosen, Err(Error::Invalid(_))), "{:?}", line);
+        }
+        let rare = choose_modifiers(&menu, &burger(&["rare"])).unwrap();
+        assert_eq!(rare, vec![modifier(1, "rare", 0)]);
+        let pizza = choose_modifiers(&menu, &lines(&["pizza"])[0]).unwrap();
+        assert!(pizza.is_empty());
+    }
+
+    #[test]
+    fn should_not_order_anything_when_a_line_is_invalid() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let order = vec![burger(&["rare"]), burger(&[])];
+        let result = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu(),
+            RESTAURANT,
+            "3",
+            &order,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        );
+        assert!(matches!(result, Err(Error::Invalid(_))));
+        assert!(ordered_ids(&repository, "3").is_empty());
+    }
+
+    #[test]
+    fn should_price_items_with_their_modifiers() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let mut order = lines(&["tea"]);
+        order.insert(0, burger(&["rare", "bacon", "cheese"]));
+        let (_, _, added) = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu(),
+            RESTAURANT,
+            "3",
+            &order,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(added[0].price, 1500);
+        assert_eq!(added[1].price, 0);
+    }
+
+    #[test]
+    fn should_order_bundle_as_one_item_with_components() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let (_, _, added) = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu(),
+            RESTAURANT,
+            "3",
+            &lines(&["lunch"]),
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        let table = repository.find(RESTAURANT, "3").unwrap().unwrap();
+        assert_eq!(ordered_ids(&repository, "3"), vec![added[0].id]);
+        let lunch = &table.items[0];
+        assert_eq!(lunch.price, 600);
+        let components = lunch
+            .components
+            .iter()
+            .map(|component| (component.name.as_str(), component.price))
+            .collect::<Vec<_>>();
+        assert_eq!(components, vec![("fries", 0), ("soda", 0)]);
+        let times = lunch.components.iter().map(|item| item.time_to_prepare);
+        assert_eq!(Some(lunch.time_to_prepare), times.max());
+        for (ticket, component) in table.tickets().iter().zip(lunch.components.iter()) {
+            assert_eq!(ticket.item, component);
+            assert_eq!(ticket.bundle, Some(lunch));
+        }
+        assert_eq!(table.tickets().len(), 2);
+    }
+
+    #[test]
+    fn should_hold_later_courses_until_they_are_fired() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let mut courses = lines(&["soup", "lunch"]);
+        courses[1].course = 2;
+        add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu(),
+            RESTAURANT,
+            "3",
+            &courses,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        let table = repository.find(RESTAURANT, "3").unwrap().unwrap();
+        assert!(!table.items[0].held && table.items[0].fired_at.is_some());
+        assert!(table.items[1].held && table.items[1].fired_at.is_none());
+        assert!(table.items[1].components.iter().all(|item| item.held));
+        let tickets = table.tickets();
+        assert_eq!(tickets.len(), 1);
+        assert_eq!(tickets[0].item.name, "soup");
+
+        let stale = fire_course(
+            &repository,
+            RESTAURANT,
+            "3",
+            2,
+            1700000000,
+            Some(table.version - 1),
+            &|_, _| Vec::new(),
+        );
+        assert!(matches!(stale, Err(Error::PreconditionFailed(_))));
+        let fired = fire_course(
+            &repository,
+            RESTAURANT,
+            "3",
+            2,
+            1700000000,
+            Some(table.version),
+            &|_, _| Vec::new(),
+        );
+        assert_eq!(fired.unwrap().len(), 1);
+        let version = table.version;
+        let table = repository.find(RESTAURANT, "3").unwrap().unwrap();
+        assert_eq!(table.version, version + 1);
+        assert_eq!(table.items[1].fired_at, Some(1700000000));
+        let names = table
+            .tickets()
+            .iter()
+            .map(|ticket| ticket.item.name.clone())
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["soup", "fries", "soda"]);
+
+        let again = fire_course(
+            &repository,
+            RESTAURANT,
+            "3",
+            2,
+            1700000100,
+            None,
+            &|_, _| Vec::new(),
+        );
+        let message = "Course 2 of table 3 has already been fired.";
+        assert_eq!(again, Err(Error::Conflict(String::from(message))));
+        let missing = fire_course(
+            &repository,
+            RESTAURANT,
+            "3",
+            3,
+            1700000100,
+            None,
+            &|_, _| Vec::new(),
+        );
+        assert!(matches!(missing, Err(Error::NotFound(_))));
+        let elsewhere = fire_course(
+            &repository,
+            OTHER_RESTAURANT,
+            "3",
+            1,
+            1700000100,
+            None,
+            &|_, _| Vec::new(),
+        );
+        assert!(matches!(elsewhere, Err(Error::NotFound(_))));
+        let invalid = lines(&["soup"])
+            .into_iter()
+            .map(|line| domain::OrderLine { course: 0, ..line })
+            .collect::<Vec<_>>();
+        let refused = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu(),
+            RESTAURANT,
+            "3",
+            &invalid,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        );
+        assert!(matches!(refused, Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn should_route_items_to_stations_and_ready_tables_once_all_are_ready() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let mut menu = menu();
+        for item in menu.categories[0].items.iter_mut() {
+            item.station_id = match item.name.as_str() {
+                "fries" => Some(1),
+                "soda" => Some(2),
+                _ => None,
+            };
+        }
+        let mut courses = lines(&["lunch", "fries"]);
+        courses[1].course = 2;
+        add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &courses,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        let table = repository.find(RESTAURANT, "3").unwrap().unwrap();
+        let stations = table
+            .tickets()
+            .iter()
+            .map(|ticket| (ticket.item.name.clone(), ticket.item.station_id))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (String::from("fries"), Some(1)),
+            (String::from("soda"), Some(2)),
+        ];
+        assert_eq!(stations, expected);
+        assert!(!table.is_ready());
+        let fries = table.items[0].components[0].id.to_string();
+        let soda = table.items[0].components[1].id.to_string();
+        let bundle = table.items[0].id.to_string();
+        let held = table.items[1].id.to_string();
+        let ready = |table_id: &str, item_id: &str, ready_at: i64| {
+            mark_ready(
+                &repository,
+                RESTAURANT,
+                table_id,
+                item_id,
+                ready_at,
+                &|_| change(),
+            )
+        };
+
+        let version = table.version;
+        let table = ready("3", &fries, 1700000300).unwrap();
+        assert_eq!(table.version, version + 1);
+        assert_eq!(table.items[0].components[0].ready_at, Some(1700000300));
+        assert!(!table.is_ready());
+        let message = format!("Item {} is already ready.", fries);
+        assert_eq!(
+            ready("3", &fries, 1700000400),
+            Err(Error::Conflict(message))
+        );
+        let message = format!("Item {} has not been fired yet.", held);
+        assert_eq!(ready("3", &held, 1700000400), Err(Error::Conflict(message)));
+        for (table_id, item_id) in [("3", bundle.as_str()), ("3", "404"), ("4", soda.as_str())] {
+            let missing = ready(table_id, item_id, 1700000400);
+            assert!(matches!(missing, Err(Error::NotFound(_))), "{}", item_id);
+        }
+        assert!(ready("3", &soda, 1700000400).unwrap().is_ready());
+    }
+
+    #[test]
+    fn should_estimate_items_from_how_long_they_took_and_how_busy_the_kitchen_is() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let menu = domain::Menu::default();
+        let order = |name: &str| {
+            let (_, _, added) = add_items(
+                &repository,
+                &repository,
+                &repository,
+                &menu,
+                RESTAURANT,
+                "3",
+                &lines(&[name]),
+                crate::audit::now(),
+                None,
+                &|_, _, _| Vec::new(),
+            )
+            .unwrap();
+            added.into_iter().next().unwrap()
+        };
+        for minutes in [18, 20, 30] {
+            let steak = order("steak");
+            let ready_at = steak.fired_at.unwrap() + minutes * 60;
+            let item_id = steak.id.to_string();
+            mark_ready(&repository, RESTAURANT, "3", &item_id, ready_at, &|_| {
+                change()
+            })
+            .unwrap();
+        }
+        assert_eq!(order("steak").time_to_prepare, 20);
+        let soup = order("soup");
+        assert!((5..=15).contains(&soup.time_to_prepare));
+        assert_eq!(order("steak").time_to_prepare, 22);
+    }
+
+    #[test]
+    fn should_void_whole_bundle() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let (_, _, added) = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu(),
+            RESTAURANT,
+            "3",
+            &lines(&["lunch"]),
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        let component_id = added[0].components[0].id.to_string();
+        let component = void_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &component_id,
+            &void("cold"),
+            None,
+            false,
+            &|_, _| change(),
+        );
+        assert!(matches!(component, Err(Error::NotFound(_))));
+        let bundle_id = added[0].id.to_string();
+        void_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &bundle_id,
+            &void("cold"),
+            None,
+            false,
+            &|_, _| change(),
+        )
+        .unwrap();
+        let table = repository.find(RESTAURANT, "3").unwrap().unwrap();
+        assert!(table.tickets().is_empty());
+        assert!(table.items[0]
+            .components
+            .iter()
+            .all(|component| component.status != domain::ItemStatus::Ordered));
+    }
+
+    fn ingredient(id: i32, name: &str, stock: i32) -> domain::Ingredient {
+        domain::Ingredient {
+            id,
+            name: String::from(name),
+            stock,
+            low_stock: 1,
+        }
+    }
+
+    /// The menu with a burger made from beef and fries made from potatoes.
+    fn menu_with_recipes() -> domain::Menu {
+        let mut menu = menu();
+        for item in menu.categories[0].items.iter_mut() {
+            let (ingredient_id, quantity) = match item.name.as_str() {
+                "burger" => (1, 1),
+                "fries" => (2, 200),
+                _ => continue,
+            };
+            item.recipe.push(domain::RecipeLine {
+                ingredient_id,
+                quantity,
+            });
+        }
+        menu
+    }
+
+    fn stock_left(repository: &MemoryRepository) -> Vec<i32> {
+        repository
+            .ingredients(RESTAURANT)
+            .unwrap()
+            .iter()
+            .map(|ingredient| ingredient.stock)
+            .collect()
+    }
+
+    #[test]
+    fn should_take_stock_when_ordering_and_give_it_back_when_voiding() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        repository.insert_ingredient(RESTAURANT, ingredient(1, "beef", 3));
+        repository.insert_ingredient(RESTAURANT, ingredient(2, "potatoes", 500));
+        let menu = menu_with_recipes();
+        let order = vec![burger(&["rare"]), lines(&["lunch"]).remove(0)];
+        let (_, _, added) = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &order,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(stock_left(&repository), vec![2, 300]);
+        let lunch_id = added[1].id.to_string();
+        void_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &lunch_id,
+            &void("cold"),
+            None,
+            false,
+            &|_, _| change(),
+        )
+        .unwrap();
+        assert_eq!(stock_left(&repository), vec![2, 500]);
+        restore_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &lunch_id,
+            None,
+            &|_, _, _| change(),
+        )
+        .unwrap();
+        assert_eq!(stock_left(&repository), vec![2, 300]);
+    }
+
+    #[test]
+    fn should_not_order_what_there_is_not_stock_for() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        repository.insert_ingredient(RESTAURANT, ingredient(1, "beef", 1));
+        repository.insert_ingredient(RESTAURANT, ingredient(2, "potatoes", 500));
+        let menu = menu_with_recipes();
+        let order = vec![burger(&["rare"]), burger(&["medium"])];
+        let result = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &order,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        );
+        assert_eq!(
+            result,
+            Err(Error::Conflict(String::from(
+                "There is not enough beef to make it."
+            )))
+        );
+        assert!(ordered_ids(&repository, "3").is_empty());
+        assert_eq!(stock_left(&repository), vec![1, 500]);
+        let stale = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &order[..1],
+            crate::audit::now(),
+            Some(7),
+            &|_, _, _| Vec::new(),
+        );
+        assert!(matches!(stale, Err(Error::PreconditionFailed(_))));
+        assert_eq!(stock_left(&repository), vec![1, 500]);
+    }
+
+    #[test]
+    fn should_not_restore_what_there_is_not_stock_for() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        repository.insert_ingredient(RESTAURANT, ingredient(1, "beef", 1));
+        let menu = menu_with_recipes();
+        let order = vec![burger(&["rare"])];
+        let (_, _, added) = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &order,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        let burger_id = added[0].id.to_string();
+        void_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &burger_id,
+            &void("dropped"),
+            None,
+            false,
+            &|_, _| change(),
+        )
+        .unwrap();
+        add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &order,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        )
+        .unwrap();
+        let restored = restore_item(
+            &repository,
+            &repository,
+            &repository,
+            RESTAURANT,
+            "3",
+            &burger_id,
+            None,
+            &|_, _, _| change(),
+        );
+        assert!(matches!(restored, Err(Error::Conflict(_))));
+        assert_eq!(stock_left(&repository), vec![0]);
+    }
+
+    #[test]
+    fn should_not_order_items_that_are_not_available() {
+        let repository = MemoryRepository::default();
+        repository.insert_table(RESTAURANT, 3, &[]);
+        let mut menu = menu();
+        for item in menu.categories[0].items.iter_mut() {
+            item.availability = match item.name.as_str() {
+                "burger" => domain::Availability::Unavailable { until: None },
+                "soda" => domain::Availability::Unavailable {
+                    until: Some(1700003600),
+                },
+                _ => continue,
+            };
+        }
+        let orders = [
+            (vec![burger(&["rare"])], "burger is not available."),
+            (lines(&["lunch"]), "soda is not available until 1700003600."),
+        ];
+        for (order, message) in orders {
+            let result = add_items(
+                &repository,
+                &repository,
+                &repository,
+                &menu,
+                RESTAURANT,
+                "3",
+                &order,
+                crate::audit::now(),
+                None,
+                &|_, _, _| Vec::new(),
+            );
+            assert_eq!(result, Err(Error::Conflict(String::from(message))));
+        }
+        assert!(ordered_ids(&repository, "3").is_empty());
+        let fries = lines(&["fries"]);
+        let added = add_items(
+            &repository,
+            &repository,
+            &repository,
+            &menu,
+            RESTAURANT,
+            "3",
+            &fries,
+            crate::audit::now(),
+            None,
+            &|_, _, _| Vec::new(),
+        );
+        assert!(added.is_ok());
+    }
+
+    #[test]
+    fn should_warn_about_allergens_someone_at_the_table_is_allergic_to() {
+        let mut menu = menu();
+        for item in menu.categories[0].items.iter_mut() {
+            item.allergens = match item.name.as_str() {
+                "burger" => vec![String::from("gluten"), String::from("sesame")],
+                "lunch" => vec![String::from("Peanuts")],
+                _ => continue,
+            };
+        }
+        let allergies = vec![
+            domain::Allergy {
+                seat: Some(2),
+                allergen: String::from("peanuts"),
+            },
+            domain::Allergy {
+                seat: None,
+                allergen: String::from("sesame"),
+            },
+        ];
+        assert_eq!(
+            allergy_warnings(&menu, &allergies, "burger"),
+            vec!["burger contains sesame, someone at the table is allergic to it."]
+        );
+        assert_eq!(
+            allergy_warnings(&menu, &allergies, "lunch"),
+            vec!["lunch contains peanuts, seat 2 is allergic to it."]
+        );
+        assert!(allergy_warnings(&menu, &allergies, "fries").is_empty());
+        assert!(allergy_warnings(&menu, &allergies, "pizza").is_empty());
+        let checked = check_allergies(&menu, &allergies, &lines(&["fries", "lunch"]));
+        assert_eq!(
+            checked,
+            Err(Error::Conflict(String::from(
+                "lunch contains peanuts, seat 2 is allergic to it."
+            )))
+        );
+        assert!(check_allergies(&menu, &[], &lines(&["lunch"])).is_ok());
+    }
+
+    fn ordered(id: i32, name: &str, price: i32) -> domain::Item {
+        domain::Item {
+            id,
+            name: String::from(name),
+            time_to_prepare: 10,
+            status: domain::ItemStatus::Ordered,
+            modifiers: Vec::new(),
+            price,
+            components: Vec::new(),
+            course: 1,
+            held: false,
+            fired_at: None,
+            station_id: None,
+            ready_at: None,
+        }
+    }
+
+    fn adjustment(target: domain::Target, discount: domain::Discount) -> domain::Adjustment {
+        domain::Adjustment {
+            id: 1,
+            target,
+            discount,
+            reason: String::from("birthday"),
+            code: None,
+            added_by: 9001,
+            added_at: 1700000000,
+        }
+    }
+
+    #[test]
+    fn should_take_adjustments_off_what_is_left_of_their_lines() {
+        let mut voided = ordered(5, "pizza", 1200);
+        voided.status = domain::ItemStatus::Voided(void("sent back"));
+        let table = domain::Table {
+            id: 3,
+            version: 1,
+            paid_at: None,
+            items: vec![
+                ordered(1, "pizza", 1100),
+                ordered(2, "pizza", 1000),
+                ordered(3, "pizza", 900),
+                ordered(4, "soda", 300),
+                voided,
+            ],
+        };
+        let pizza = domain::Target::Named(String::from("pizza"));
+        let adjustments = [
+            adjustment(pizza, domain::Discount::BuyGet { buy: 2, get: 1 }),
+            adjustment(domain::Target::Bill, domain::Discount::Percentage(10)),
+            adjustment(domain::Target::Item(4), domain::Discount::Comp),
+            adjustment(domain::Target::Bill, domain::Discount::Amount(5000)),
+            adjustment(domain::Target::Item(5), domain::Discount::Percentage(50)),
+        ];
+        assert_eq!(adjust(&table, &adjustments), vec![900, 240, 270, 1890, 0]);
+        let soda = domain::Target::Named(String::from("soda"));
+        let two_for_one = adjustment(soda, domain::Discount::BuyGet { buy: 1, get: 1 });
+        assert_eq!(adjust(&table, &[two_for_one]), vec![0]);
+        assert!(check_target(&table, &domain::Target::Item(4)).is_ok());
+        assert!(check_target(&table, &domain::Target::Item(5)).is_err());
+    }
+
+    #[test]
+    fn should_refuse_promo_codes_that_cannot_be_redeemed() {
+        let promo_code = domain::PromoCode {
+            id: 1,
+            code: String::from("SPRING"),
+            target: domain::Target::Bill,
+            discount: domain::Discount::Percentage(15),
+            max_uses: Some(2),
+            uses: 1,
+            expires_at: Some(1700000000),
+        };
+        assert!(check_promo_code(&promo_code, &[], 1600000000).is_ok());
+        let refused = [
+            (
+                promo_code.clone(),
+                1700000000,
+                "Promo code SPRING has expired.",
+            ),
+            (
+                domain::PromoCode {
+                    uses: 2,
+                    ..promo_code.clone()
+                },
+                1600000000,
+                "Promo code SPRING has been used up.",
+            ),
+        ];
+        for (promo_code, now, message) in refused {
+            let checked = check_promo_code(&promo_code, &[], now);
+            assert_eq!(checked, Err(Error::Conflict(String::from(message))));
+        }
+        let redeemed = domain::Adjustment {
+            code: Some(String::from("SPRING")),
+            ..adjustment(domain::Target::Bill, domain::Discount::Percentage(15))
+        };
+        let checked = check_promo_code(&promo_code, &[redeemed], 1600000000);
+        let message = "Promo code SPRING is already on the bill.";
+        assert_eq!(checked, Err(Error::Conflict(String::from(message))));
+    }
+
+    #[test]
+    fn should_refuse_recipes_that_cannot_be_made() {
+        let beef = domain::Ingredient {
+            id: 1,
+            name: String::from("beef"),
+            stock: 10,
+            low_stock: 2,
+        };
+        let line = |ingredient_id: i32, quantity: i32| domain::RecipeLine {
+            ingredient_id,
+            quantity,
+        };
+        assert_eq!(check_recipe(&[line(1, 2)], &[beef.clone()]), Ok(()));
+        let refused = [
+            (vec![line(2, 1)], "There is no ingredient 2."),
+            (
+                vec![line(1, 0)],
+                "A recipe needs more than nothing of ingredient 1.",
+            ),
+            (
+                vec![line(1, 1), line(1, 1)],
+                "Ingredient 1 is in the recipe more than once.",
+            ),
+        ];
+        for (recipe, message) in refused {
+            let checked = check_recipe(&recipe, &[beef.clone()]);
+            assert_eq!(checked, Err(Error::Invalid(String::from(message))));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn should_match_model_for_any_orders_and_voids(
+            operations in prop::collection::vec(operation(), 0..60)
+        ) {
+            let repository = MemoryRepository::default();
+            for table in 0..TABLES {
+                repository.insert_table(RESTAURANT, table as i32 + 1, &[]);
+            }
+            let mut model: Vec<Vec<i32>> = vec![Vec::new(); TABLES];
+            let mut ordered: Vec<i32> = Vec::new();
+            for operation in operations {
+                match operation {
+                    Operation::Add(table, count) => {
+                        let table_id = (table + 1).to_string();
+                        let (id, created, added) = add_items(
+                            &repository,
+                            &repository,
+                            &repository,
+                            &domain::Menu::default(),
+                            RESTAURANT,
+                            &table_id,
+                            &lines(&vec!["pizza"; count]),
+                            1700000000,
+                            None,
+                            &|_, _, _| Vec::new(),
+                        )
+                        .unwrap();
+                        prop_assert_eq!((id, created), (table as i32 + 1, false));
+                        let ids = added.iter().map(|item| item.id).collect::<Vec<i32>>();
+                        model[table].extend(ids.iter().cloned());
+                        ordered.extend(ids);
+                    }
+                    Operation::Void(_, _) if ordered.is_empty() => continue,
+                    Operation::Void(table, pick) => {
+                        let item_id = ordered[pick % ordered.len()];
+                        let voided = void_item(
+                            &repository,
+                            &repository,
+                            &repository,
+                            RESTAURANT,
+                            &(table + 1).to_string(),
+                            &item_id.to_string(),
+                            &void("sent back"),
+                            None,
+                            false,
+                            &|_, _| change(),
+                        );
+                        // Items voided already and items on another table are not on it.
+                        match model[table].iter().position(|id| *id == item_id) {
+                            Some(position) => {
+                                prop_assert_eq!(voided.map(|item| item.id), Ok(item_id));
+                                model[table].remove(position);
+                            }
+                            None => prop_assert!(matches!(voided, Err(Error::NotFound(_)))),
+                        }
+                    }
+                }
+                for (table, items) in model.iter().enumerate() {
+                    prop_assert_eq!(&ordered_ids(&repository, &(table + 1).to_string()), items);
+                }
+            }
+        }
+    }
+}
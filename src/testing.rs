@@ -0,0 +1,797 @@
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use rocket::config::{Config, Environment, Value};
+use rocket::http::{ContentType, Header};
+use rocket::local::{Client, LocalResponse};
+
+use crate::auth;
+use crate::data;
+use crate::database;
+use crate::menu;
+use crate::repository;
+
+pub const MANAGER: &str = "9001";
+pub const SERVER: &str = "9002";
+pub const COOK: &str = "9003";
+
+/// The restaurant every migrated database starts with, the test staff above work there.
+pub const RESTAURANT: i32 = 1;
+/// A second restaurant, with staff of its own, for checking nothing is shared between them.
+pub const OTHER_RESTAURANT: i32 = 2;
+pub const OTHER_MANAGER: &str = "9101";
+pub const OTHER_SERVER: &str = "9102";
+
+const STAFF: &str = "INSERT INTO restaurant (id, name) VALUES (2, 'Other Restaurant');
+    INSERT INTO staff (id, name, role, restaurant_id) VALUES (9001, 'Test Manager', 'manager', 1);
+    INSERT INTO staff (id, name, role, restaurant_id) VALUES (9002, 'Test Server', 'server', 1);
+    INSERT INTO staff (id, name, role, restaurant_id) VALUES (9003, 'Test Cook', 'cook', 1);
+    INSERT INTO staff (id, name, role, restaurant_id) VALUES (9101, 'Other Manager', 'manager', 2);
+    INSERT INTO staff (id, name, role, restaurant_id) VALUES (9102, 'Other Server', 'server', 2);";
+
+/// A server with a database of its own, so tests can pick any ids and run side by side.
+/// The database is a temporary file that is removed when the server is dropped.
+pub struct TestServer {
+    pub client: Client,
+    database: database::Database,
+    postgres: Option<TestPostgres>,
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestServer {
+    /// Starts a server on an empty database holding only the test staff.
+    pub fn new() -> TestServer {
+        TestServer::start(None, &[])
+    }
+
+    /// Starts a server on an empty database with some settings of its own, given as
+    /// `(name, value)` like they would be in Rocket.toml.
+    pub fn configured(settings: &[(&str, Value)]) -> TestServer {
+        TestServer::start(None, settings)
+    }
+
+    /// Starts a server that keeps tables and items in a new postgres database, or returns
+    /// `None` when postgres is skipped with `SKIP_POSTGRES=1`.
+    pub fn postgres() -> Option<TestServer> {
+        Some(TestServer::start(Some(TestPostgres::start()?), &[]))
+    }
+
+    /// Every backend there is to test against, sqlite always and postgres unless it is
+    /// skipped.
+    pub fn all() -> Vec<TestServer> {
+        let mut servers = vec![TestServer::new()];
+        servers.extend(TestServer::postgres());
+        servers
+    }
+
+    fn start(postgres: Option<TestPostgres>, settings: &[(&str, Value)]) -> TestServer {
+        let path = temporary_path("sqlite");
+        let path = path.to_str().expect("Temporary path is not unicode.");
+        let mut config = Config::build(Environment::Development).extra("database", path);
+        for (name, value) in settings {
+            config = config.extra(name, value.clone());
+        }
+        if let Some(postgres) = &postgres {
+            config = config
+                .extra("database_backend", "postgres")
+                .extra("database_url", postgres.url.as_str());
+        }
+        let config = config.finalize().expect("Failed to build test config.");
+        let rocket = crate::build_server(rocket::custom(config));
+        let server = TestServer {
+            client: Client::new(rocket).expect("Failed to start server"),
+            database: database::Database::new(path),
+            postgres,
+        };
+        server.execute(STAFF);
+        server
+    }
+
+    pub fn backend(&self) -> &'static str {
+        match self.postgres {
+            Some(_) => "postgres",
+            None => "sqlite",
+        }
+    }
+
+    pub fn tables(&self) -> repository::Tables {
+        let tables = self.client.rocket().state::<repository::Tables>();
+        tables.expect("No table repository.").clone()
+    }
+
+    pub fn items(&self) -> repository::Items {
+        let items = self.client.rocket().state::<repository::Items>();
+        items.expect("No item repository.").clone()
+    }
+
+    pub fn stock(&self) -> repository::Stock {
+        let stock = self.client.rocket().state::<repository::Stock>();
+        stock.expect("No stock repository.").clone()
+    }
+
+    pub fn adjustments(&self) -> repository::Adjustments {
+        let adjustments = self.client.rocket().state::<repository::Adjustments>();
+        adjustments.expect("No adjustment repository.").clone()
+    }
+
+    pub fn database(&self) -> &database::Database {
+        &self.database
+    }
+
+    /// Runs statements against whichever backend the server keeps everything in.
+    pub fn execute(&self, statements: &str) {
+        match &self.postgres {
+            Some(postgres) => postgres
+                .connect()
+                .batch_execute(statements)
+                .expect("Failed to run test statements."),
+            None => self
+                .database
+                .connect()
+                .execute_batch(statements)
+                .expect("Failed to run test statements."),
+        }
+    }
+
+    /// Makes every `operation`, like insert or delete, on the rows of `table` fail from now
+    /// on, to test what a write leaves behind when part of it fails.
+    pub fn fail_on(&self, operation: &str, table: &str) {
+        let statements = match &self.postgres {
+            Some(_) => format!(
+                "create or replace function fail() returns trigger language plpgsql as $$
+                    begin raise exception 'Failing on purpose.'; end; $$;
+                create trigger fail_{0}_{1} before {0} on {1}
+                    for each row execute function fail();",
+                operation, table
+            ),
+            None => format!(
+                "create trigger fail_{0}_{1} before {0} on {1}
+                    begin select raise(abort, 'Failing on purpose.'); end;",
+                operation, table
+            ),
+        };
+        self.execute(&statements);
+    }
+
+    /// How many connections the server has open to its postgres database, besides this one.
+    pub fn postgres_connections(&self) -> i64 {
+        let postgres = self.postgres.as_ref().expect("Not a postgres server.");
+        let row = postgres
+            .connect()
+            .query_one(
+                "select count(*) from pg_stat_activity
+                    where datname = current_database() and pid != pg_backend_pid();",
+                &[],
+            )
+            .expect("Failed to count connections.");
+        row.get(0)
+    }
+
+    /// Runs an insert against the server's backend and returns the id of the new row.
+    fn insert(&self, statement: &str, params: &[&dyn Param]) -> i32 {
+        match &self.postgres {
+            Some(postgres) => {
+                let statement = format!("{} returning id;", statement);
+                let params = params
+                    .iter()
+                    .map(|param| param.postgres())
+                    .collect::<Vec<_>>();
+                let row = postgres
+                    .connect()
+                    .query_one(statement.as_str(), &params)
+                    .unwrap_or_else(|e| panic!("Failed to run {}: {}", statement, e));
+                row.get(0)
+            }
+            None => {
+                let connection = self.database.connect();
+                let params = params
+                    .iter()
+                    .map(|param| param.sqlite())
+                    .collect::<Vec<_>>();
+                connection
+                    .execute(&format!("{};", statement), &params[..])
+                    .unwrap_or_else(|e| panic!("Failed to run {}: {}", statement, e));
+                connection.last_insert_rowid() as i32
+            }
+        }
+    }
+
+    /// Runs an insert for a row without an id against the server's backend.
+    fn insert_row(&self, statement: &str, params: &[&dyn Param]) {
+        match &self.postgres {
+            Some(postgres) => {
+                let params = params
+                    .iter()
+                    .map(|param| param.postgres())
+                    .collect::<Vec<_>>();
+                postgres
+                    .connect()
+                    .execute(statement, &params)
+                    .unwrap_or_else(|e| panic!("Failed to run {}: {}", statement, e));
+            }
+            None => {
+                let params = params
+                    .iter()
+                    .map(|param| param.sqlite())
+                    .collect::<Vec<_>>();
+                self.database
+                    .connect()
+                    .execute(statement, &params[..])
+                    .unwrap_or_else(|e| panic!("Failed to run {}: {}", statement, e));
+            }
+        }
+    }
+
+    /// The id of the row a query finds on the server's backend, if it finds one.
+    fn find_id(&self, query: &str, params: &[&dyn Param]) -> Option<i32> {
+        match &self.postgres {
+            Some(postgres) => {
+                let params = params
+                    .iter()
+                    .map(|param| param.postgres())
+                    .collect::<Vec<_>>();
+                let row = postgres
+                    .connect()
+                    .query_opt(query, &params)
+                    .unwrap_or_else(|e| panic!("Failed to run {}: {}", query, e));
+                row.map(|row| row.get(0))
+            }
+            None => {
+                let params = params
+                    .iter()
+                    .map(|param| param.sqlite())
+                    .collect::<Vec<_>>();
+                self.database
+                    .connect()
+                    .query_row(query, &params[..], |row| row.get(0))
+                    .ok()
+            }
+        }
+    }
+
+    pub fn item(&self, name: &str) -> ItemBuilder {
+        ItemBuilder {
+            server: self,
+            id: None,
+            name: name.to_string(),
+            time_to_prepare: 5,
+            restaurant_id: RESTAURANT,
+        }
+    }
+
+    pub fn table(&self) -> TableBuilder {
+        TableBuilder {
+            server: self,
+            id: None,
+            item_ids: String::new(),
+            version: 1,
+            restaurant_id: RESTAURANT,
+        }
+    }
+
+    /// An ingredient with `stock` of it left, it is never low unless `low_stock` is set.
+    pub fn ingredient(&self, name: &str, stock: i32) -> IngredientBuilder {
+        IngredientBuilder {
+            server: self,
+            name: name.to_string(),
+            stock,
+            low_stock: 0,
+            restaurant_id: RESTAURANT,
+        }
+    }
+
+    /// A menu item, in a category named `Mains` unless another one is given.
+    pub fn menu_item(&self, name: &str) -> MenuItemBuilder {
+        MenuItemBuilder {
+            server: self,
+            name: name.to_string(),
+            category: String::from("Mains"),
+            price: 1000,
+            groups: Vec::new(),
+            components: Vec::new(),
+            recipe: Vec::new(),
+            tags: Vec::new(),
+            station: None,
+            restaurant_id: RESTAURANT,
+        }
+    }
+
+    /// An order placed through the API, the way a server at the till would.
+    pub fn order(&self, table_id: i32) -> OrderBuilder {
+        OrderBuilder {
+            server: self,
+            table_id,
+            items: Vec::new(),
+            staff_id: SERVER,
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        for suffix in &["", "-journal", "-wal", "-shm"] {
+            let path = PathBuf::from(format!("{}{}", self.database.path(), suffix));
+            if path.exists() {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Failed to remove test database {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+}
+
+/// A value a fixture writes, which either backend can take.
+trait Param {
+    fn sqlite(&self) -> &dyn rusqlite::ToSql;
+
+    fn postgres(&self) -> &(dyn ::postgres::types::ToSql + Sync);
+}
+
+impl<T: rusqlite::ToSql + ::postgres::types::ToSql + Sync> Param for T {
+    fn sqlite(&self) -> &dyn rusqlite::ToSql {
+        self
+    }
+
+    fn postgres(&self) -> &(dyn ::postgres::types::ToSql + Sync) {
+        self
+    }
+}
+
+fn temporary_path(extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "restaurant-{}-{:016x}.{}",
+        std::process::id(),
+        rand::random::<u64>(),
+        extension
+    ))
+}
+
+/// A postgres database that only lives for one test. It is made on the server in
+/// `TEST_POSTGRES_URL` when that is set, a key=value connection string for a user that can
+/// create databases. Otherwise a server is started with `initdb` and `postgres` from the
+/// path, listening on a socket in a temporary directory. When neither works the test fails,
+/// unless `SKIP_POSTGRES=1` is set to run the sqlite tests alone.
+pub struct TestPostgres {
+    url: String,
+    server: Option<(Child, PathBuf)>,
+    dropped: Option<(String, String)>,
+}
+
+impl TestPostgres {
+    pub fn start() -> Option<TestPostgres> {
+        if std::env::var("SKIP_POSTGRES").map_or(false, |skip| skip == "1") {
+            return None;
+        }
+        match std::env::var("TEST_POSTGRES_URL") {
+            Ok(url) => Some(TestPostgres::create_database(&url)),
+            Err(_) => Some(TestPostgres::launch()),
+        }
+    }
+
+    fn create_database(url: &str) -> TestPostgres {
+        let name = format!("restaurant_test_{:016x}", rand::random::<u64>());
+        let mut client =
+            ::postgres::Client::connect(url, ::postgres::NoTls).expect("Failed to reach postgres.");
+        client
+            .batch_execute(&format!("create database {};", name))
+            .expect("Failed to create test database.");
+        TestPostgres {
+            url: format!("{} dbname={}", url, name),
+            server: None,
+            dropped: Some((url.to_string(), name)),
+        }
+    }
+
+    fn launch() -> TestPostgres {
+        let directory = temporary_path("postgres");
+        let data = directory.join("data");
+        let initialized = Command::new("initdb")
+            .arg("-D")
+            .arg(&data)
+            .args(["-U", "postgres", "-A", "trust"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if !initialized.map_or(false, |status| status.success()) {
+            let _ = std::fs::remove_dir_all(&directory);
+            panic!(
+                "No postgres to test against, set TEST_POSTGRES_URL, put initdb on the path \
+                 or set SKIP_POSTGRES=1 to only test sqlite."
+            );
+        }
+        let child = Command::new("postgres")
+            .arg("-D")
+            .arg(&data)
+            .arg("-k")
+            .arg(&directory)
+            .args(["-c", "listen_addresses=", "-c", "fsync=off"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start postgres.");
+        let postgres = TestPostgres {
+            url: format!("host={} user=postgres dbname=postgres", directory.display()),
+            server: Some((child, directory)),
+            dropped: None,
+        };
+        postgres.wait_until_ready();
+        postgres
+    }
+
+    fn connect(&self) -> ::postgres::Client {
+        ::postgres::Client::connect(&self.url, ::postgres::NoTls)
+            .expect("Failed to reach postgres.")
+    }
+
+    fn wait_until_ready(&self) {
+        for _ in 0..100 {
+            if ::postgres::Client::connect(&self.url, ::postgres::NoTls).is_ok() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("Postgres did not start at {}.", self.url);
+    }
+}
+
+impl Drop for TestPostgres {
+    fn drop(&mut self) {
+        if let Some((url, name)) = &self.dropped {
+            let dropped = ::postgres::Client::connect(url, ::postgres::NoTls)
+                .and_then(|mut client| client.batch_execute(&format!("drop database {};", name)));
+            if let Err(e) = dropped {
+                warn!("Failed to drop test database {}: {}", name, e);
+            }
+        }
+        if let Some((child, directory)) = &mut self.server {
+            let _ = child.kill();
+            let _ = child.wait();
+            if let Err(e) = std::fs::remove_dir_all(&directory) {
+                warn!("Failed to remove test postgres {:?}: {}", directory, e);
+            }
+        }
+    }
+}
+
+pub struct ItemBuilder<'a> {
+    server: &'a TestServer,
+    id: Option<i32>,
+    name: String,
+    time_to_prepare: i32,
+    restaurant_id: i32,
+}
+
+impl<'a> ItemBuilder<'a> {
+    pub fn id(mut self, id: i32) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn time_to_prepare(mut self, minutes: i32) -> Self {
+        self.time_to_prepare = minutes;
+        self
+    }
+
+    pub fn restaurant(mut self, restaurant_id: i32) -> Self {
+        self.restaurant_id = restaurant_id;
+        self
+    }
+
+    /// Inserts the item and returns its id.
+    pub fn create(self) -> i32 {
+        let restaurant_id = self.restaurant_id;
+        match self.id {
+            Some(id) => self.server.insert(
+                "insert into item (id, name, preperation_time, restaurant_id)
+                    values ($1, $2, $3, $4)",
+                &[&id, &self.name, &self.time_to_prepare, &restaurant_id],
+            ),
+            None => self.server.insert(
+                "insert into item (name, preperation_time, restaurant_id) values ($1, $2, $3)",
+                &[&self.name, &self.time_to_prepare, &restaurant_id],
+            ),
+        }
+    }
+}
+
+pub struct TableBuilder<'a> {
+    server: &'a TestServer,
+    id: Option<i32>,
+    item_ids: String,
+    version: i32,
+    restaurant_id: i32,
+}
+
+impl<'a> TableBuilder<'a> {
+    pub fn id(mut self, id: i32) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn items(self, item_ids: &[i32]) -> Self {
+        let item_ids = item_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>();
+        self.item_ids(&item_ids.join(","))
+    }
+
+    /// Sets the stored id list as it is, for ids that have no item.
+    pub fn item_ids(mut self, item_ids: &str) -> Self {
+        self.item_ids = item_ids.to_string();
+        self
+    }
+
+    pub fn version(mut self, version: i64) -> Self {
+        self.version = version as i32;
+        self
+    }
+
+    pub fn restaurant(mut self, restaurant_id: i32) -> Self {
+        self.restaurant_id = restaurant_id;
+        self
+    }
+
+    /// Inserts the table and returns its id.
+    pub fn create(self) -> i32 {
+        let restaurant_id = self.restaurant_id;
+        match self.id {
+            Some(id) => self.server.insert(
+                "insert into restaurant_table (id, items, version, restaurant_id)
+                    values ($1, $2, $3, $4)",
+                &[&id, &self.item_ids, &self.version, &restaurant_id],
+            ),
+            None => self.server.insert(
+                "insert into restaurant_table (items, version, restaurant_id) values ($1, $2, $3)",
+                &[&self.item_ids, &self.version, &restaurant_id],
+            ),
+        }
+    }
+}
+
+/// A modifier group as `(name, min_selected, max_selected, [(modifier, price_delta)])`.
+type GroupFixture = (String, i32, i32, Vec<(String, i32)>);
+
+pub struct MenuItemBuilder<'a> {
+    server: &'a TestServer,
+    name: String,
+    category: String,
+    price: i32,
+    groups: Vec<GroupFixture>,
+    components: Vec<i32>,
+    recipe: Vec<(i32, i32)>,
+    tags: Vec<(&'static str, String)>,
+    station: Option<String>,
+    restaurant_id: i32,
+}
+
+impl<'a> MenuItemBuilder<'a> {
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = category.to_string();
+        self
+    }
+
+    pub fn price(mut self, cents: i32) -> Self {
+        self.price = cents;
+        self
+    }
+
+    pub fn group(mut self, name: &str, min: i32, max: i32, modifiers: &[(&str, i32)]) -> Self {
+        let modifiers = modifiers
+            .iter()
+            .map(|(name, price_delta)| (name.to_string(), *price_delta))
+            .collect();
+        self.groups.push((name.to_string(), min, max, modifiers));
+        self
+    }
+
+    /// Makes the item a bundle of the menu items with these ids.
+    pub fn components(mut self, menu_item_ids: &[i32]) -> Self {
+        self.components = menu_item_ids.to_vec();
+        self
+    }
+
+    /// Makes one of the item use `quantity` of the ingredient with this id.
+    pub fn uses(mut self, ingredient_id: i32, quantity: i32) -> Self {
+        self.recipe.push((ingredient_id, quantity));
+        self
+    }
+
+    pub fn allergens(mut self, allergens: &[&str]) -> Self {
+        let tags = allergens
+            .iter()
+            .map(|tag| (menu::ALLERGEN, tag.to_string()));
+        self.tags.extend(tags);
+        self
+    }
+
+    pub fn dietary(mut self, diets: &[&str]) -> Self {
+        let tags = diets.iter().map(|tag| (menu::DIETARY, tag.to_string()));
+        self.tags.extend(tags);
+        self
+    }
+
+    /// Has the item made at the station with this name.
+    pub fn station(mut self, station: &str) -> Self {
+        self.station = Some(station.to_string());
+        self
+    }
+
+    pub fn restaurant(mut self, restaurant_id: i32) -> Self {
+        self.restaurant_id = restaurant_id;
+        self
+    }
+
+    /// Inserts the item with its groups, modifiers, components, recipe and tags, and its
+    /// category and station if they are new, and returns the item's id.
+    pub fn create(self) -> i32 {
+        let server = self.server;
+        let restaurant_id = self.restaurant_id;
+        let category_id = server
+            .find_id(
+                "select id from menu_category where restaurant_id = $1 and name = $2;",
+                &[&restaurant_id, &self.category],
+            )
+            .unwrap_or_else(|| {
+                server.insert(
+                    "insert into menu_category (restaurant_id, name) values ($1, $2)",
+                    &[&restaurant_id, &self.category],
+                )
+            });
+        let station_id = self.station.as_ref().map(|station| {
+            server
+                .find_id(
+                    "select id from station where restaurant_id = $1 and name = $2;",
+                    &[&restaurant_id, station],
+                )
+                .unwrap_or_else(|| {
+                    server.insert(
+                        "insert into station (restaurant_id, name) values ($1, $2)",
+                        &[&restaurant_id, station],
+                    )
+                })
+        });
+        let item_id = server.insert(
+            "insert into menu_item (restaurant_id, category_id, name, price, station_id)
+                values ($1, $2, $3, $4, $5)",
+            &[
+                &restaurant_id,
+                &category_id,
+                &self.name,
+                &self.price,
+                &station_id,
+            ],
+        );
+        for (name, min, max, modifiers) in self.groups {
+            let group_id = server.insert(
+                "insert into modifier_group (menu_item_id, name, min_selected, max_selected)
+                    values ($1, $2, $3, $4)",
+                &[&item_id, &name, &min, &max],
+            );
+            for (name, price_delta) in modifiers {
+                server.insert(
+                    "insert into modifier (group_id, name, price_delta) values ($1, $2, $3)",
+                    &[&group_id, &name, &price_delta],
+                );
+            }
+        }
+        for (position, component_id) in self.components.iter().enumerate() {
+            server.insert_row(
+                "insert into bundle_component (bundle_id, position, menu_item_id)
+                    values ($1, $2, $3);",
+                &[&item_id, &(position as i32), component_id],
+            );
+        }
+        for (ingredient_id, quantity) in self.recipe {
+            server.insert_row(
+                "insert into recipe (menu_item_id, ingredient_id, quantity) values ($1, $2, $3);",
+                &[&item_id, &ingredient_id, &quantity],
+            );
+        }
+        for (kind, tag) in self.tags {
+            server.insert_row(
+                "insert into menu_item_tag (menu_item_id, kind, tag) values ($1, $2, $3);",
+                &[&item_id, &kind, &tag],
+            );
+        }
+        item_id
+    }
+}
+
+pub struct IngredientBuilder<'a> {
+    server: &'a TestServer,
+    name: String,
+    stock: i32,
+    low_stock: i32,
+    restaurant_id: i32,
+}
+
+impl<'a> IngredientBuilder<'a> {
+    pub fn low_stock(mut self, low_stock: i32) -> Self {
+        self.low_stock = low_stock;
+        self
+    }
+
+    pub fn restaurant(mut self, restaurant_id: i32) -> Self {
+        self.restaurant_id = restaurant_id;
+        self
+    }
+
+    /// Inserts the ingredient and returns its id.
+    pub fn create(self) -> i32 {
+        self.server.insert(
+            "insert into ingredient (restaurant_id, name, stock, low_stock)
+                values ($1, $2, $3, $4)",
+            &[
+                &self.restaurant_id,
+                &self.name,
+                &self.stock,
+                &self.low_stock,
+            ],
+        )
+    }
+}
+
+pub struct OrderBuilder<'a> {
+    server: &'a TestServer,
+    table_id: i32,
+    items: Vec<data::ItemOrder>,
+    staff_id: &'static str,
+}
+
+impl<'a> OrderBuilder<'a> {
+    pub fn item(mut self, name: &str) -> Self {
+        self.items.push(data::ItemOrder::Name(name.to_string()));
+        self
+    }
+
+    pub fn item_with(mut self, name: &str, modifiers: &[&str]) -> Self {
+        self.items.push(data::ItemOrder::WithModifiers {
+            name: name.to_string(),
+            modifiers: modifiers.iter().map(|name| name.to_string()).collect(),
+            course: None,
+        });
+        self
+    }
+
+    /// Orders an item in a course, courses after the first are held until they are fired.
+    pub fn item_in_course(mut self, name: &str, course: i32) -> Self {
+        self.items.push(data::ItemOrder::WithModifiers {
+            name: name.to_string(),
+            modifiers: Vec::new(),
+            course: Some(course),
+        });
+        self
+    }
+
+    /// Places the order as someone other than `SERVER`.
+    pub fn by(mut self, staff_id: &'static str) -> Self {
+        self.staff_id = staff_id;
+        self
+    }
+
+    pub fn json(&self) -> String {
+        let request = data::ItemRequest {
+            table_id: self.table_id.to_string(),
+            items: self.items.clone(),
+        };
+        serde_json::to_string(&request).unwrap()
+    }
+
+    /// Posts the order, as `SERVER` unless another staff member was given.
+    pub fn place(self) -> LocalResponse<'a> {
+        self.server
+            .client
+            .post("/api/v1/tables")
+            .header(ContentType::JSON)
+            .header(Header::new(auth::STAFF_HEADER, self.staff_id))
+            .body(self.json())
+            .dispatch()
+    }
+}